@@ -0,0 +1,95 @@
+//! Schemaless key to bytes storage.
+//!
+//! Blobs live in row pages like table rows, but are indexed by their own
+//! btree whose root is kept in the catalog, so blob keys never collide with
+//! table composite keys or the raw u64 store. Each stored blob is prefixed
+//! with its key, checked on read like a table row's pk prefix.
+
+use crate::btree;
+use crate::catalog::Catalog;
+use crate::error::{InvError, InvResult};
+use crate::pager::Pager;
+use crate::rowstore::{RowPtr, RowStore, MAX_INLINE_ROW};
+use crate::types::PageId;
+
+/// Largest blob [`put_blob`] accepts.
+pub const MAX_BLOB_BYTES: usize = MAX_INLINE_ROW - 4;
+
+/// Store `bytes` under `key`, replacing any previous blob for the key.
+///
+/// A replaced blob's old bytes stay in their row page as dead space.
+pub(crate) fn put_blob(pager: &mut Pager, key: u32, bytes: &[u8]) -> InvResult<()> {
+    if bytes.len() > MAX_BLOB_BYTES {
+        return Err(InvError::Unsupported {
+            feature: "blob.too_large",
+        });
+    }
+    let mut cat = pager.read_catalog()?;
+    if cat.blob_root == 0 {
+        cat.blob_root = pager.allocate_btree_page()?.0;
+    }
+    let mut stored = Vec::with_capacity(4 + bytes.len());
+    stored.extend_from_slice(&key.to_le_bytes());
+    stored.extend_from_slice(bytes);
+    let (ptr, last) = RowStore::append_row(pager, cat.blob_last_row_page, &stored)?;
+    cat.blob_last_row_page = last;
+    cat.blob_root = btree::insert_u64(pager, PageId(cat.blob_root), key, ptr.pack())?.0;
+    pager.write_catalog(&cat)
+}
+
+/// Read the blob stored under `key`, if any.
+pub(crate) fn get_blob(pager: &mut Pager, key: u32) -> InvResult<Option<Vec<u8>>> {
+    let cat = pager.read_catalog()?;
+    if cat.blob_root == 0 {
+        return Ok(None);
+    }
+    let Some(raw_ptr) = btree::search_u64(pager, PageId(cat.blob_root), key)? else {
+        return Ok(None);
+    };
+    let ptr = RowPtr::unpack(raw_ptr);
+    ptr.validate()?;
+    let mut stored = RowStore::read_row(pager, ptr)?;
+    if stored.len() < 4 || stored[0..4] != key.to_le_bytes() {
+        return Err(InvError::Corruption {
+            context: "blob.key_mismatch",
+            details: format!("stored bytes at page {} do not belong to key {}", ptr.page_id, key),
+        });
+    }
+    stored.drain(..4);
+    Ok(Some(stored))
+}
+
+/// Every `(key, bytes)` pair in key order.
+pub(crate) fn scan_blobs(pager: &mut Pager) -> InvResult<Vec<(u32, Vec<u8>)>> {
+    let cat = pager.read_catalog()?;
+    if cat.blob_root == 0 {
+        return Ok(Vec::new());
+    }
+    let keys: Vec<u32> = btree::search::iter_from(pager, PageId(cat.blob_root), 0)?
+        .map(|entry| entry.map(|(key, _)| key))
+        .collect::<InvResult<_>>()?;
+    let mut blobs = Vec::with_capacity(keys.len());
+    for key in keys {
+        if let Some(bytes) = get_blob(pager, key)? {
+            blobs.push((key, bytes));
+        }
+    }
+    Ok(blobs)
+}
+
+/// `(key, pointer)` of every blob, in key order.
+pub(crate) fn entries(pager: &mut Pager, cat: &Catalog) -> InvResult<Vec<(u32, RowPtr)>> {
+    if cat.blob_root == 0 {
+        return Ok(Vec::new());
+    }
+    btree::search::iter_from(pager, PageId(cat.blob_root), 0)?
+        .map(|entry| entry.map(|(key, raw)| (key, RowPtr::unpack(raw))))
+        .collect()
+}
+
+/// Point `key`'s index entry at `ptr`, after its stored bytes moved. The
+/// caller is responsible for persisting `cat`.
+pub(crate) fn repoint(pager: &mut Pager, cat: &mut Catalog, key: u32, ptr: RowPtr) -> InvResult<()> {
+    cat.blob_root = btree::insert_u64(pager, PageId(cat.blob_root), key, ptr.pack())?.0;
+    Ok(())
+}
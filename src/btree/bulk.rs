@@ -0,0 +1,183 @@
+use crate::btree::insert::insert_u64;
+use crate::btree::node::{max_internal_keys, max_leaf_keys, InternalNode, LeafNode, Node};
+use crate::error::{InvError, InvResult};
+use crate::pager::Pager;
+use crate::types::PageId;
+
+const MAX_DEPTH: usize = 64;
+
+/// Build a tree of fully packed nodes from entries sorted by strictly
+/// ascending key, returning the new root.
+///
+/// `root` must be an empty leaf; it becomes the first leaf of the new tree.
+/// Leaves are filled to capacity left to right and linked through
+/// `next_leaf`, then internal levels are stacked until one node remains.
+pub fn bulk_load_u64(pager: &mut Pager, root: PageId, entries: &[(u32, u64)]) -> InvResult<PageId> {
+    let page_count = pager.page_count();
+    match Node::decode(pager.get_page(root)?, page_count)? {
+        Node::Leaf(leaf) if leaf.num_keys == 0 => {}
+        _ => {
+            return Err(InvError::InvalidArgument {
+                name: "root",
+                details: "bulk load requires an empty tree".to_string(),
+            })
+        }
+    }
+    if entries.windows(2).any(|w| w[0].0 >= w[1].0) {
+        return Err(InvError::InvalidArgument {
+            name: "entries",
+            details: "keys must be strictly ascending".to_string(),
+        });
+    }
+    if entries.is_empty() {
+        return Ok(root);
+    }
+
+    // (page, smallest key) of each node on the level being built.
+    let chunks: Vec<&[(u32, u64)]> = entries.chunks(max_leaf_keys()).collect();
+    let mut pages = vec![root];
+    for _ in 1..chunks.len() {
+        pages.push(pager.allocate_btree_page()?);
+    }
+    let mut level = Vec::with_capacity(chunks.len());
+    for (i, chunk) in chunks.iter().enumerate() {
+        let leaf = LeafNode {
+            num_keys: chunk.len() as u16,
+            next_leaf: pages.get(i + 1).copied().unwrap_or(PageId(0)),
+            keys: chunk.iter().map(|&(k, _)| k).collect(),
+            values: chunk.iter().map(|&(_, v)| v).collect(),
+        };
+        pager.encode_leaf_into_page(pages[i], &leaf)?;
+        level.push((pages[i], chunk[0].0));
+    }
+
+    while level.len() > 1 {
+        level = pack_internal(pager, &level, None)?;
+    }
+    Ok(level[0].0)
+}
+
+/// Append entries whose keys are all above the current maximum, returning the
+/// new root.
+///
+/// The rightmost leaf is filled first, then new leaves and internal entries
+/// are added along the right edge only; the rest of the tree is untouched.
+/// Batches that are not strictly ascending or do not start past the largest
+/// key fall back to one [`insert_u64`] per entry.
+pub fn append_u64(pager: &mut Pager, root: PageId, entries: &[(u32, u64)]) -> InvResult<PageId> {
+    if entries.is_empty() {
+        return Ok(root);
+    }
+    let page_count = pager.page_count();
+    let mut path = Vec::new();
+    let mut current = root;
+    let leaf = loop {
+        if path.len() > MAX_DEPTH {
+            return Err(InvError::Corruption {
+                context: "btree.depth",
+                details: format!("exceeded depth {}", MAX_DEPTH),
+            });
+        }
+        match Node::decode(pager.get_page(current)?, page_count)? {
+            Node::Leaf(leaf) => break leaf,
+            Node::Internal(internal) => {
+                let next = *internal.children.last().expect("internal node has children");
+                path.push((current, internal));
+                current = next;
+            }
+        }
+    };
+    // The rightmost leaf may be empty after deletes; its subtree still only
+    // admits keys at or above the deepest separator.
+    let appends = match (leaf.keys.last(), path.last().and_then(|(_, n)| n.keys.last())) {
+        (Some(&max), _) => entries[0].0 > max,
+        (None, Some(&sep)) => entries[0].0 >= sep,
+        (None, None) => true,
+    };
+    if !appends || entries.windows(2).any(|w| w[0].0 >= w[1].0) {
+        let mut root = root;
+        for &(key, value) in entries {
+            root = insert_u64(pager, root, key, value)?;
+        }
+        return Ok(root);
+    }
+
+    let mut all: Vec<(u32, u64)> = leaf.keys.iter().copied().zip(leaf.values.iter().copied()).collect();
+    all.extend_from_slice(entries);
+    let chunks: Vec<&[(u32, u64)]> = all.chunks(max_leaf_keys()).collect();
+    let mut pages = vec![current];
+    for _ in 1..chunks.len() {
+        pages.push(pager.allocate_btree_page()?);
+    }
+    let mut added = Vec::with_capacity(chunks.len() - 1);
+    for (i, chunk) in chunks.iter().enumerate() {
+        let node = LeafNode {
+            num_keys: chunk.len() as u16,
+            next_leaf: pages.get(i + 1).copied().unwrap_or(leaf.next_leaf),
+            keys: chunk.iter().map(|&(k, _)| k).collect(),
+            values: chunk.iter().map(|&(_, v)| v).collect(),
+        };
+        pager.encode_leaf_into_page(pages[i], &node)?;
+        if i > 0 {
+            added.push((pages[i], chunk[0].0));
+        }
+    }
+
+    // Hand the new right siblings up the right edge, splitting as needed.
+    while let Some((page, node)) = path.pop() {
+        if added.is_empty() {
+            return Ok(root);
+        }
+        let mut children: Vec<(PageId, u32)> = Vec::with_capacity(node.children.len() + added.len());
+        children.push((node.children[0], 0));
+        children.extend(node.children[1..].iter().copied().zip(node.keys.iter().copied()));
+        children.extend(added);
+        added = pack_internal(pager, &children, Some(page))?.split_off(1);
+    }
+    if added.is_empty() {
+        return Ok(root);
+    }
+    let mut level = vec![(root, 0)];
+    level.extend(added);
+    while level.len() > 1 {
+        level = pack_internal(pager, &level, None)?;
+    }
+    Ok(level[0].0)
+}
+
+/// Group `(child, smallest key)` pairs into internal nodes, returning the
+/// `(node, smallest key)` pair of each node built.
+///
+/// The first node is written to `first_page` when given; every other node
+/// gets a freshly allocated page.
+fn pack_internal(
+    pager: &mut Pager,
+    level: &[(PageId, u32)],
+    first_page: Option<PageId>,
+) -> InvResult<Vec<(PageId, u32)>> {
+    let mut groups: Vec<Vec<(PageId, u32)>> = level
+        .chunks(max_internal_keys() + 1)
+        .map(|c| c.to_vec())
+        .collect();
+    // An internal node needs at least two children.
+    let n = groups.len();
+    if n > 1 && groups[n - 1].len() == 1 {
+        let moved = groups[n - 2].pop().expect("full group");
+        groups[n - 1].insert(0, moved);
+    }
+    let mut next = Vec::with_capacity(groups.len());
+    for (i, group) in groups.into_iter().enumerate() {
+        let page = match first_page {
+            Some(page) if i == 0 => page,
+            _ => pager.allocate_btree_page()?,
+        };
+        let node = InternalNode {
+            num_keys: (group.len() - 1) as u16,
+            children: group.iter().map(|&(p, _)| p).collect(),
+            keys: group[1..].iter().map(|&(_, k)| k).collect(),
+        };
+        pager.encode_internal_into_page(page, &node)?;
+        next.push((page, group[0].1));
+    }
+    Ok(next)
+}
@@ -0,0 +1,239 @@
+use std::collections::HashSet;
+
+use crate::btree::node::{encode_into_page, max_internal_keys, max_leaf_keys, InternalNode, Node};
+use crate::btree::prune::prune_empty_leaves;
+use crate::btree::search::{find_leaf, route};
+use crate::error::{InvError, InvResult};
+use crate::pager::Pager;
+use crate::types::PageId;
+
+const MAX_DEPTH: usize = 64;
+
+/// Remove a key, returning the (possibly new) root and whether a key was removed.
+///
+/// Deletion is lazy: the key/value pair is removed from its leaf and the leaf
+/// re-encoded, but nodes are never merged or rebalanced, so a leaf may be left
+/// empty.
+pub fn delete_u64(pager: &mut Pager, root: PageId, key: u32) -> InvResult<(PageId, bool)> {
+    let mut current = root;
+    let mut depth = 0usize;
+    loop {
+        if depth > MAX_DEPTH {
+            return Err(InvError::Corruption {
+                context: "btree.depth",
+                details: format!("exceeded depth {}", MAX_DEPTH),
+            });
+        }
+        let page_count = pager.page_count();
+        let page = pager.get_page(current)?;
+        let mut node = Node::decode(page, page_count)?;
+        match &mut node {
+            Node::Leaf(leaf) => {
+                let Ok(idx) = leaf.keys.binary_search(&key) else {
+                    return Ok((root, false));
+                };
+                leaf.keys.remove(idx);
+                leaf.values.remove(idx);
+                leaf.num_keys -= 1;
+                encode_into_page(&node, pager.get_page_mut(current)?)?;
+                return Ok((root, true));
+            }
+            Node::Internal(internal) => {
+                let idx = internal
+                    .keys
+                    .iter()
+                    .position(|&k| key < k)
+                    .unwrap_or(internal.keys.len());
+                current = internal.children[idx];
+                depth += 1;
+            }
+        }
+    }
+}
+
+/// Remove every key in `[lo, hi)`, returning the new root, the number of
+/// keys removed and the pages no longer part of the tree.
+///
+/// Walks the leaf chain from the leaf covering `lo`, dropping the range from
+/// each leaf and stopping at the first key `>= hi`; only the boundary leaves
+/// keep keys. If any leaf was emptied, the tree is pruned as by
+/// [`prune_empty_leaves`], unlinking the emptied leaves and any internal node
+/// left with a single child. The caller installs the new root before handing
+/// the dropped pages to [`Pager::free_page`]. Nothing changes when
+/// `lo >= hi`.
+pub fn delete_range_u64(pager: &mut Pager, root: PageId, lo: u32, hi: u32) -> InvResult<(PageId, u64, Vec<PageId>)> {
+    if lo >= hi {
+        return Ok((root, 0, Vec::new()));
+    }
+    let mut current = find_leaf(pager, root, lo)?;
+    let mut visited = HashSet::new();
+    let mut removed = 0u64;
+    let mut emptied = false;
+    loop {
+        if !visited.insert(current.0) {
+            return Err(InvError::Corruption {
+                context: "btree.leaf_cycle",
+                details: format!("cycle detected at {}", current.0),
+            });
+        }
+        let page_count = pager.page_count();
+        let Node::Leaf(mut leaf) = Node::decode(pager.get_page(current)?, page_count)? else {
+            return Err(InvError::Corruption {
+                context: "btree.leaf_chain",
+                details: format!("page {} in the leaf chain is not a leaf", current.0),
+            });
+        };
+        let start = leaf.keys.partition_point(|&k| k < lo);
+        let end = leaf.keys.partition_point(|&k| k < hi);
+        let last = end < leaf.keys.len() || leaf.next_leaf.0 == 0;
+        if end > start {
+            leaf.keys.drain(start..end);
+            leaf.values.drain(start..end);
+            leaf.num_keys = leaf.keys.len() as u16;
+            removed += (end - start) as u64;
+            emptied |= leaf.num_keys == 0;
+            pager.encode_leaf_into_page(current, &leaf)?;
+        }
+        if last {
+            break;
+        }
+        current = leaf.next_leaf;
+    }
+    if !emptied {
+        return Ok((root, removed, Vec::new()));
+    }
+    let (root, dropped) = prune_empty_leaves(pager, root)?;
+    Ok((root, removed, dropped))
+}
+
+/// Smallest key count a node of capacity `max` may keep before a delete
+/// tries to merge it, for a fill ratio clamped to `0.0..=0.5`.
+///
+/// Half is the upper bound so that an underfull node and a sibling at the
+/// threshold always fit in one page; `0.0` (or NaN) disables merging.
+pub fn min_fill_keys(min_fill_ratio: f32, max: usize) -> usize {
+    let ratio = if min_fill_ratio > 0.0 { min_fill_ratio.min(0.5) } else { 0.0 };
+    (ratio * max as f32) as usize
+}
+
+/// Remove a key like [`delete_u64`], then merge nodes left below the
+/// minimum fill with an adjacent sibling.
+///
+/// The thresholds come from [`min_fill_keys`] against [`max_leaf_keys`] and
+/// [`max_internal_keys`]. A node is merged only with a sibling under the same
+/// parent, and only when both fit in one page; the right page of the pair is
+/// released to the free list and every merge is counted in
+/// [`Pager::btree_stats`]. A root left with a single child takes over the
+/// child's contents, so the root page never changes.
+pub fn delete_u64_rebalancing(
+    pager: &mut Pager,
+    root: PageId,
+    key: u32,
+    min_fill_ratio: f32,
+) -> InvResult<(PageId, bool)> {
+    let min_leaf = min_fill_keys(min_fill_ratio, max_leaf_keys());
+    let min_internal = min_fill_keys(min_fill_ratio, max_internal_keys());
+
+    let mut path: Vec<(PageId, InternalNode)> = Vec::new();
+    let mut current = root;
+    let mut leaf = loop {
+        if path.len() > MAX_DEPTH {
+            return Err(InvError::Corruption {
+                context: "btree.depth",
+                details: format!("exceeded depth {}", MAX_DEPTH),
+            });
+        }
+        let page_count = pager.page_count();
+        match Node::decode(pager.get_page(current)?, page_count)? {
+            Node::Leaf(leaf) => break leaf,
+            Node::Internal(internal) => {
+                let child = internal.children[route(&internal, key)];
+                path.push((current, internal));
+                current = child;
+            }
+        }
+    };
+    let Ok(idx) = leaf.keys.binary_search(&key) else {
+        return Ok((root, false));
+    };
+    leaf.keys.remove(idx);
+    leaf.values.remove(idx);
+    leaf.num_keys -= 1;
+    let mut underfull = (leaf.num_keys as usize) < min_leaf;
+    pager.encode_leaf_into_page(current, &leaf)?;
+
+    while let Some((parent_id, mut parent)) = path.pop() {
+        if !underfull {
+            break;
+        }
+        let idx = route(&parent, key);
+        let left = if idx + 1 < parent.children.len() { idx } else { idx.saturating_sub(1) };
+        if left + 1 >= parent.children.len() {
+            break;
+        }
+        let (left_id, right_id) = (parent.children[left], parent.children[left + 1]);
+        if !merge_siblings(pager, left_id, right_id, parent.keys[left])? {
+            break;
+        }
+        parent.keys.remove(left);
+        parent.children.remove(left + 1);
+        parent.num_keys -= 1;
+        pager.encode_internal_into_page(parent_id, &parent)?;
+        pager.free_page(right_id)?;
+        pager.note_merge();
+        underfull = (parent.num_keys as usize) < min_internal;
+    }
+
+    loop {
+        let page_count = pager.page_count();
+        let child = match Node::decode(pager.get_page(root)?, page_count)? {
+            Node::Internal(internal) if internal.num_keys == 0 => internal.children[0],
+            _ => break,
+        };
+        let page_count = pager.page_count();
+        let node = Node::decode(pager.get_page(child)?, page_count)?;
+        encode_into_page(&node, pager.get_page_mut(root)?)?;
+        pager.free_page(child)?;
+    }
+    Ok((root, true))
+}
+
+/// Fold the node at `right_id` into its left sibling at `left_id` if the
+/// union fits in one page, returning whether it did.
+///
+/// `separator` is the parent key between the two, pulled down between the
+/// children of merged internal nodes.
+fn merge_siblings(pager: &mut Pager, left_id: PageId, right_id: PageId, separator: u32) -> InvResult<bool> {
+    let page_count = pager.page_count();
+    let left = Node::decode(pager.get_page(left_id)?, page_count)?;
+    let right = Node::decode(pager.get_page(right_id)?, page_count)?;
+    match (left, right) {
+        (Node::Leaf(mut left), Node::Leaf(right)) => {
+            if left.keys.len() + right.keys.len() > max_leaf_keys() {
+                return Ok(false);
+            }
+            left.keys.extend(right.keys);
+            left.values.extend(right.values);
+            left.num_keys = left.keys.len() as u16;
+            left.next_leaf = right.next_leaf;
+            pager.encode_leaf_into_page(left_id, &left)?;
+        }
+        (Node::Internal(mut left), Node::Internal(right)) => {
+            if left.keys.len() + 1 + right.keys.len() > max_internal_keys() {
+                return Ok(false);
+            }
+            left.keys.push(separator);
+            left.keys.extend(right.keys);
+            left.children.extend(right.children);
+            left.num_keys = left.keys.len() as u16;
+            pager.encode_internal_into_page(left_id, &left)?;
+        }
+        _ => {
+            return Err(InvError::Corruption {
+                context: "btree.structure",
+                details: format!("siblings {} and {} differ in kind", left_id.0, right_id.0),
+            })
+        }
+    }
+    Ok(true)
+}
@@ -1,6 +1,6 @@
 use crate::btree::node::{encode_into_page, max_internal_keys, max_leaf_keys, Node};
 use crate::btree::split::{split_internal, split_leaf};
-use crate::error::InvResult;
+use crate::error::{InvError, InvResult};
 use crate::pager::Pager;
 use crate::types::PageId;
 
@@ -24,12 +24,12 @@ pub fn insert_u64(
         } => {
             // Need a new root
             let new_root_id = pager.allocate_btree_page()?;
-            let mut children = vec![root, right];
-            let mut keys = vec![promoted_key];
+            let children = vec![root, right];
+            let keys = vec![promoted_key];
             let internal = Node::Internal(crate::btree::node::InternalNode {
                 num_keys: keys.len() as u16,
-                children: children.drain(..).collect(),
-                keys: keys.drain(..).collect(),
+                children,
+                keys,
             });
             encode_into_page(&internal, pager.get_page_mut(new_root_id)?)?;
             Ok(new_root_id)
@@ -48,7 +48,7 @@ fn insert_into(pager: &mut Pager, page_id: PageId, key: u32, value: u64) -> InvR
                 Ok(idx) => {
                     leaf.values[idx] = value;
                     encode_into_page(&node, pager.get_page_mut(page_id)?)?;
-                    return Ok(InsertResult::NoSplit);
+                    Ok(InsertResult::NoSplit)
                 }
                 Err(pos) => {
                     leaf.keys.insert(pos, key);
@@ -105,3 +105,78 @@ fn insert_into(pager: &mut Pager, page_id: PageId, key: u32, value: u64) -> InvR
         }
     }
 }
+
+const MAX_DEPTH: usize = 64;
+
+/// Restructuring predicted by [`estimate_appends`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InsertEstimate {
+    pub leaf_splits: u64,
+    pub internal_splits: u64,
+    /// Pages allocated: one per split and one per new root.
+    pub new_pages: u64,
+}
+
+/// Estimate the splits `count` keys would cause if inserted one by one with
+/// [`insert_u64`], each above every key in the tree.
+///
+/// Such keys all land in the rightmost leaf, so only the key counts along
+/// the right edge of the tree matter: a node that overflows splits in half
+/// and passes one key up, and each split of the root adds a level. Keys
+/// that fall elsewhere or overwrite existing ones split less.
+/// # Errors
+/// - [`InvError::Corruption`] if a node on the right edge does not decode.
+pub fn estimate_appends(pager: &mut Pager, root: PageId, count: u64) -> InvResult<InsertEstimate> {
+    // Key counts along the right edge, root first.
+    let mut edge = Vec::new();
+    let mut current = root;
+    loop {
+        if edge.len() > MAX_DEPTH {
+            return Err(InvError::Corruption {
+                context: "btree.depth",
+                details: format!("exceeded depth {}", MAX_DEPTH),
+            });
+        }
+        let page_count = pager.page_count();
+        match Node::decode(pager.get_page(current)?, page_count)? {
+            Node::Leaf(leaf) => {
+                edge.push(leaf.num_keys as u64);
+                break;
+            }
+            Node::Internal(internal) => {
+                edge.push(internal.num_keys as u64);
+                current = *internal.children.last().expect("internal node has children");
+            }
+        }
+    }
+
+    let mut estimate = InsertEstimate::default();
+    let leaf_max = max_leaf_keys() as u64;
+    // A split leaf keeps the upper half of its keys on the right; a split
+    // internal node also promotes its middle key.
+    let mut incoming = overflows(edge.pop().unwrap_or(0), count, leaf_max, (leaf_max + 1).div_ceil(2));
+    estimate.leaf_splits = incoming;
+    let internal_max = max_internal_keys() as u64;
+    let internal_kept = (internal_max + 1).div_ceil(2) - 1;
+    while incoming > 0 {
+        // Past the old root, promoted keys fill a new root.
+        let keys = edge.pop().unwrap_or_else(|| {
+            estimate.new_pages += 1;
+            0
+        });
+        incoming = overflows(keys, incoming, internal_max, internal_kept);
+        estimate.internal_splits += incoming;
+    }
+    estimate.new_pages += estimate.leaf_splits + estimate.internal_splits;
+    Ok(estimate)
+}
+
+/// Splits of a node holding `keys` of `max` after `incoming` more, where
+/// each split leaves `kept` keys in the node receiving the next ones.
+fn overflows(keys: u64, incoming: u64, max: u64, kept: u64) -> u64 {
+    let first = (max + 1).saturating_sub(keys).max(1);
+    if incoming < first {
+        return 0;
+    }
+    1 + (incoming - first) / (max + 1 - kept)
+}
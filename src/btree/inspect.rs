@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+
+use crate::btree::node::Node;
+use crate::error::{InvError, InvResult};
+use crate::pager::Pager;
+use crate::types::PageId;
+
+const MAX_DEPTH: usize = 64;
+
+/// Depth below which [`dump_btree`] stops expanding internal nodes by default.
+pub const DEFAULT_DUMP_DEPTH: usize = 8;
+/// Number of nodes [`dump_btree`] decodes at most by default.
+pub const DEFAULT_DUMP_NODES: usize = 1024;
+
+/// Read-only snapshot of the btree's shape, from [`dump_btree`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BtreeDump {
+    pub root: NodeDump,
+    /// Whether the depth or node limit left some internal node unexpanded.
+    pub truncated: bool,
+}
+
+/// One decoded node of a [`BtreeDump`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeDump {
+    pub page: PageId,
+    pub kind: NodeDumpKind,
+    /// Dumps of the node's children, in order; empty for a leaf, and for an
+    /// internal node the limits kept from expanding.
+    pub children: Vec<NodeDump>,
+}
+
+/// What a [`NodeDump`] records about its node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NodeDumpKind {
+    Leaf { key_count: u16, next_leaf: PageId },
+    Internal { keys: Vec<u32>, child_pages: Vec<PageId> },
+}
+
+/// Dump the tree at `root`, expanding internal nodes down to `max_depth`
+/// (the root is depth 0) and decoding at most `max_nodes` nodes.
+///
+/// Nodes are visited depth first, so a node limit keeps the leftmost part
+/// of the tree. Child page ids of an unexpanded node are still listed.
+/// # Errors
+/// - [`InvError::Corruption`] if a page is reachable twice or the tree is
+///   deeper than any valid tree.
+pub fn dump_btree(pager: &mut Pager, root: PageId, max_depth: usize, max_nodes: usize) -> InvResult<BtreeDump> {
+    let mut budget = max_nodes.max(1) - 1;
+    let mut seen = HashSet::new();
+    let mut truncated = false;
+    let root = dump_node(pager, root, 0, max_depth, &mut budget, &mut seen, &mut truncated)?;
+    Ok(BtreeDump { root, truncated })
+}
+
+fn dump_node(
+    pager: &mut Pager,
+    page_id: PageId,
+    depth: usize,
+    max_depth: usize,
+    budget: &mut usize,
+    seen: &mut HashSet<u32>,
+    truncated: &mut bool,
+) -> InvResult<NodeDump> {
+    if depth > MAX_DEPTH {
+        return Err(InvError::Corruption {
+            context: "btree.depth",
+            details: format!("exceeded depth {}", MAX_DEPTH),
+        });
+    }
+    if !seen.insert(page_id.0) {
+        return Err(InvError::Corruption {
+            context: "btree.structure",
+            details: format!("page {} reachable twice", page_id.0),
+        });
+    }
+    let page_count = pager.page_count();
+    let internal = match Node::decode(pager.get_page(page_id)?, page_count)? {
+        Node::Leaf(leaf) => {
+            return Ok(NodeDump {
+                page: page_id,
+                kind: NodeDumpKind::Leaf {
+                    key_count: leaf.num_keys,
+                    next_leaf: leaf.next_leaf,
+                },
+                children: Vec::new(),
+            })
+        }
+        Node::Internal(internal) => internal,
+    };
+
+    let mut children = Vec::new();
+    if depth < max_depth {
+        for &child in &internal.children {
+            if *budget == 0 {
+                break;
+            }
+            *budget -= 1;
+            children.push(dump_node(pager, child, depth + 1, max_depth, budget, seen, truncated)?);
+        }
+    }
+    if children.len() < internal.children.len() {
+        *truncated = true;
+    }
+    Ok(NodeDump {
+        page: page_id,
+        kind: NodeDumpKind::Internal {
+            keys: internal.keys,
+            child_pages: internal.children,
+        },
+        children,
+    })
+}
@@ -2,6 +2,16 @@ pub mod node;
 pub mod search;
 pub mod insert;
 pub mod split;
+pub mod delete;
+pub mod repair;
+pub mod bulk;
+pub mod prune;
+pub mod inspect;
 
 pub use search::search_u64;
-pub use insert::insert_u64;
+pub use insert::{estimate_appends, insert_u64, InsertEstimate};
+pub use delete::{delete_range_u64, delete_u64};
+pub use repair::{repair_leaf_chain, RepairReport};
+pub use bulk::{append_u64, bulk_load_u64};
+pub use prune::prune_empty_leaves;
+pub use inspect::{dump_btree, BtreeDump, NodeDump, NodeDumpKind};
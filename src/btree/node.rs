@@ -1,14 +1,17 @@
 //! Decoding and validation for B-Tree nodes stored in page payloads.
 
-use crate::config::PAGE_SIZE;
+use crate::config::{usable_payload, PAGE_SIZE};
 use crate::error::{InvError, InvResult};
-use crate::page::Page;
+use crate::layout::{
+    NODE_ENTRIES, NODE_FLAGS, NODE_KIND, NODE_KIND_INTERNAL, NODE_KIND_LEAF, NODE_NEXT_LEAF, NODE_NUM_KEYS,
+    NODE_RESERVED, NODE_RESERVED2, PAGE_PAYLOAD,
+};
+use crate::page::{Page, PageKind};
 use crate::types::PageId;
 
-const PAYLOAD_BASE: usize = 16;
 
 /// Node type discriminator.
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NodeKind {
     Leaf,
     Internal,
@@ -40,17 +43,14 @@ pub enum Node {
 
 /// Maximum keys for leaf nodes based on page capacity.
 pub fn max_leaf_keys() -> usize {
-    // capacity after payload base
-    let capacity = PAGE_SIZE - PAYLOAD_BASE;
-    // leaf uses 16 bytes header + 12 bytes per key
-    (capacity.saturating_sub(16)) / 12
+    // 12 bytes per key: the u32 key and its u64 value
+    usable_payload(PageKind::Btree) / 12
 }
 
 /// Maximum keys for internal nodes based on page capacity.
 pub fn max_internal_keys() -> usize {
-    let capacity = PAGE_SIZE - PAYLOAD_BASE;
-    // internal uses 16 bytes header + 8*K + 4 bytes
-    (capacity.saturating_sub(20)) / 8
+    // 8 bytes per key plus one more child pointer
+    usable_payload(PageKind::Btree).saturating_sub(4) / 8
 }
 
 /// Construct an empty leaf node.
@@ -66,7 +66,7 @@ pub fn empty_leaf() -> Node {
 /// Encode a node back into a page payload, ensuring invariants.
 pub fn encode_into_page(node: &Node, page: &mut crate::page::Page) -> InvResult<()> {
     // Ensure page kind is btree (2)
-    if page.as_bytes().get(0).copied() != Some(2) {
+    if page.as_bytes().first().copied() != Some(2) {
         return Err(InvError::Corruption {
             context: "btree.page_kind",
             details: "page header not marked as btree".to_string(),
@@ -75,7 +75,7 @@ pub fn encode_into_page(node: &Node, page: &mut crate::page::Page) -> InvResult<
 
     let buf = page.as_bytes_mut();
     // zero payload
-    for b in &mut buf[PAYLOAD_BASE..] {
+    for b in &mut buf[PAGE_PAYLOAD..] {
         *b = 0;
     }
 
@@ -101,14 +101,14 @@ fn encode_leaf(leaf: &LeafNode, buf: &mut [u8]) -> InvResult<()> {
     }
     validate_sorted_unique(&leaf.keys, "btree.leaf.keys_order")?;
 
-    buf[PAYLOAD_BASE] = 1; // node_kind leaf
-    buf[PAYLOAD_BASE + 1] = 0; // node_flags
-    buf[PAYLOAD_BASE + 2..PAYLOAD_BASE + 4].copy_from_slice(&(leaf.num_keys).to_le_bytes());
-    buf[PAYLOAD_BASE + 4..PAYLOAD_BASE + 8].copy_from_slice(&0u32.to_le_bytes());
-    buf[PAYLOAD_BASE + 8..PAYLOAD_BASE + 12].copy_from_slice(&leaf.next_leaf.0.to_le_bytes());
-    buf[PAYLOAD_BASE + 12..PAYLOAD_BASE + 16].copy_from_slice(&0u32.to_le_bytes());
+    buf[NODE_KIND] = NODE_KIND_LEAF;
+    buf[NODE_FLAGS] = 0; // node_flags
+    buf[NODE_NUM_KEYS..NODE_NUM_KEYS + 2].copy_from_slice(&(leaf.num_keys).to_le_bytes());
+    buf[NODE_RESERVED..NODE_RESERVED + 4].copy_from_slice(&0u32.to_le_bytes());
+    buf[NODE_NEXT_LEAF..NODE_NEXT_LEAF + 4].copy_from_slice(&leaf.next_leaf.0.to_le_bytes());
+    buf[NODE_RESERVED2..NODE_RESERVED2 + 4].copy_from_slice(&0u32.to_le_bytes());
 
-    let keys_offset = PAYLOAD_BASE + 16;
+    let keys_offset = NODE_ENTRIES;
     let values_offset = keys_offset + 4 * k;
     for (i, key) in leaf.keys.iter().enumerate() {
         let offset = keys_offset + 4 * i;
@@ -137,14 +137,14 @@ fn encode_internal(internal: &InternalNode, buf: &mut [u8]) -> InvResult<()> {
     }
     validate_sorted_unique(&internal.keys, "btree.internal.keys_order")?;
 
-    buf[PAYLOAD_BASE] = 2; // node_kind internal
-    buf[PAYLOAD_BASE + 1] = 0;
-    buf[PAYLOAD_BASE + 2..PAYLOAD_BASE + 4].copy_from_slice(&(internal.num_keys).to_le_bytes());
-    buf[PAYLOAD_BASE + 4..PAYLOAD_BASE + 8].copy_from_slice(&0u32.to_le_bytes());
-    buf[PAYLOAD_BASE + 8..PAYLOAD_BASE + 12].copy_from_slice(&0u32.to_le_bytes());
-    buf[PAYLOAD_BASE + 12..PAYLOAD_BASE + 16].copy_from_slice(&0u32.to_le_bytes());
+    buf[NODE_KIND] = NODE_KIND_INTERNAL;
+    buf[NODE_FLAGS] = 0;
+    buf[NODE_NUM_KEYS..NODE_NUM_KEYS + 2].copy_from_slice(&(internal.num_keys).to_le_bytes());
+    buf[NODE_RESERVED..NODE_RESERVED + 4].copy_from_slice(&0u32.to_le_bytes());
+    buf[NODE_NEXT_LEAF..NODE_NEXT_LEAF + 4].copy_from_slice(&0u32.to_le_bytes());
+    buf[NODE_RESERVED2..NODE_RESERVED2 + 4].copy_from_slice(&0u32.to_le_bytes());
 
-    let children_offset = PAYLOAD_BASE + 16;
+    let children_offset = NODE_ENTRIES;
     for (i, child) in internal.children.iter().enumerate() {
         let offset = children_offset + 4 * i;
         buf[offset..offset + 4].copy_from_slice(&child.0.to_le_bytes());
@@ -161,23 +161,23 @@ impl Node {
     /// Decode and validate a B-Tree node from the page payload.
     pub fn decode(page: &Page, page_count: u32) -> InvResult<Self> {
         let buf = page.as_bytes();
-        if buf.len() < PAYLOAD_BASE + 16 {
+        if buf.len() < NODE_ENTRIES {
             return Err(InvError::Corruption {
                 context: "btree.leaf.size",
                 details: "payload too small".to_string(),
             });
         }
 
-        let node_kind_byte = read_u8(buf, PAYLOAD_BASE, "btree.leaf.size")?;
-        let node_flags = read_u8(buf, PAYLOAD_BASE + 1, "btree.leaf.size")?;
+        let node_kind_byte = read_u8(buf, NODE_KIND, "btree.leaf.size")?;
+        let node_flags = read_u8(buf, NODE_FLAGS, "btree.leaf.size")?;
         if node_flags != 0 {
             return Err(InvError::Unsupported {
                 feature: "btree.node_flags",
             });
         }
 
-        let num_keys = read_u16(buf, PAYLOAD_BASE + 2, "btree.leaf.size")?;
-        let reserved = read_u32(buf, PAYLOAD_BASE + 4, "btree.leaf.size")?;
+        let num_keys = read_u16(buf, NODE_NUM_KEYS, "btree.leaf.size")?;
+        let reserved = read_u32(buf, NODE_RESERVED, "btree.leaf.size")?;
         if reserved != 0 {
             return Err(InvError::Unsupported {
                 feature: "btree.reserved",
@@ -185,8 +185,8 @@ impl Node {
         }
 
         match node_kind_byte {
-            1 => decode_leaf(buf, num_keys, page_count),
-            2 => decode_internal(buf, num_keys, page_count),
+            NODE_KIND_LEAF => decode_leaf(buf, num_keys, page_count),
+            NODE_KIND_INTERNAL => decode_internal(buf, num_keys, page_count),
             _ => Err(InvError::Corruption {
                 context: "btree.node_kind",
                 details: format!("unknown kind {}", node_kind_byte),
@@ -197,7 +197,7 @@ impl Node {
 
 fn decode_leaf(buf: &[u8], num_keys: u16, page_count: u32) -> InvResult<Node> {
     let k = num_keys as usize;
-    let keys_offset = PAYLOAD_BASE + 16;
+    let keys_offset = NODE_ENTRIES;
     let values_offset = keys_offset
         .checked_add(4 * k)
         .ok_or(InvError::Corruption {
@@ -218,8 +218,8 @@ fn decode_leaf(buf: &[u8], num_keys: u16, page_count: u32) -> InvResult<Node> {
         });
     }
 
-    let next_leaf_raw = read_u32(buf, PAYLOAD_BASE + 8, "btree.leaf.size")?;
-    let reserved2 = read_u32(buf, PAYLOAD_BASE + 12, "btree.leaf.size")?;
+    let next_leaf_raw = read_u32(buf, NODE_NEXT_LEAF, "btree.leaf.size")?;
+    let reserved2 = read_u32(buf, NODE_RESERVED2, "btree.leaf.size")?;
     if reserved2 != 0 {
         return Err(InvError::Corruption {
             context: "btree.leaf.reserved2",
@@ -261,7 +261,7 @@ fn decode_leaf(buf: &[u8], num_keys: u16, page_count: u32) -> InvResult<Node> {
 
 fn decode_internal(buf: &[u8], num_keys: u16, page_count: u32) -> InvResult<Node> {
     let k = num_keys as usize;
-    let children_offset = PAYLOAD_BASE + 16;
+    let children_offset = NODE_ENTRIES;
     let keys_offset = children_offset
         .checked_add(4 * (k + 1))
         .ok_or(InvError::Corruption {
@@ -282,14 +282,14 @@ fn decode_internal(buf: &[u8], num_keys: u16, page_count: u32) -> InvResult<Node
         });
     }
 
-    let reserved2 = read_u32(buf, PAYLOAD_BASE + 8, "btree.internal.size")?;
+    let reserved2 = read_u32(buf, NODE_NEXT_LEAF, "btree.internal.size")?;
     if reserved2 != 0 {
         return Err(InvError::Corruption {
             context: "btree.internal.reserved2",
             details: format!("expected 0 got {}", reserved2),
         });
     }
-    let reserved3 = read_u32(buf, PAYLOAD_BASE + 12, "btree.internal.size")?;
+    let reserved3 = read_u32(buf, NODE_RESERVED2, "btree.internal.size")?;
     if reserved3 != 0 {
         return Err(InvError::Corruption {
             context: "btree.internal.reserved3",
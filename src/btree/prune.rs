@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+
+use crate::btree::node::{InternalNode, LeafNode, Node};
+use crate::btree::repair::repair_leaf_chain;
+use crate::error::{InvError, InvResult};
+use crate::pager::Pager;
+use crate::types::PageId;
+
+const MAX_DEPTH: usize = 64;
+
+/// Remove empty leaves and single-child internal nodes, returning the new root
+/// and the pages no longer part of the tree.
+///
+/// A child's separator is dropped along with the child; when the leftmost
+/// child goes, the next one simply takes over the keys below it. The leaf
+/// chain is relinked afterwards. A tree without any key collapses to a
+/// single empty leaf. The caller installs the new root before handing the
+/// dropped pages to [`Pager::free_page`].
+pub fn prune_empty_leaves(pager: &mut Pager, root: PageId) -> InvResult<(PageId, Vec<PageId>)> {
+    let mut freed = Vec::new();
+    let mut seen = HashSet::new();
+    let pruned = prune(pager, root, 0, &mut seen, &mut freed)?;
+    let new_root = match pruned {
+        Some(page) => page,
+        None => {
+            // Every leaf was empty: keep the leftmost one as the new root.
+            let leaf = freed
+                .iter()
+                .position(|&(_, was_leaf)| was_leaf)
+                .expect("an empty tree has at least one leaf");
+            let (page, _) = freed.remove(leaf);
+            let empty = LeafNode {
+                num_keys: 0,
+                next_leaf: PageId(0),
+                keys: Vec::new(),
+                values: Vec::new(),
+            };
+            pager.encode_leaf_into_page(page, &empty)?;
+            page
+        }
+    };
+    if !freed.is_empty() {
+        repair_leaf_chain(pager, new_root)?;
+    }
+    Ok((new_root, freed.into_iter().map(|(page, _)| page).collect()))
+}
+
+/// Prune the subtree at `page_id`, returning its replacement or `None` if it
+/// holds no key. Dropped pages are queued in `freed` with whether they were
+/// leaves.
+fn prune(
+    pager: &mut Pager,
+    page_id: PageId,
+    depth: usize,
+    seen: &mut HashSet<u32>,
+    freed: &mut Vec<(PageId, bool)>,
+) -> InvResult<Option<PageId>> {
+    if depth > MAX_DEPTH {
+        return Err(InvError::Corruption {
+            context: "btree.depth",
+            details: format!("exceeded depth {}", MAX_DEPTH),
+        });
+    }
+    if !seen.insert(page_id.0) {
+        return Err(InvError::Corruption {
+            context: "btree.structure",
+            details: format!("page {} reachable twice", page_id.0),
+        });
+    }
+    let page_count = pager.page_count();
+    let internal = match Node::decode(pager.get_page(page_id)?, page_count)? {
+        Node::Leaf(leaf) if leaf.num_keys == 0 => {
+            freed.push((page_id, true));
+            return Ok(None);
+        }
+        Node::Leaf(_) => return Ok(Some(page_id)),
+        Node::Internal(internal) => internal,
+    };
+
+    let mut children = Vec::with_capacity(internal.children.len());
+    let mut keys = Vec::with_capacity(internal.keys.len());
+    for (i, &child) in internal.children.iter().enumerate() {
+        let Some(kept) = prune(pager, child, depth + 1, seen, freed)? else {
+            continue;
+        };
+        if !children.is_empty() {
+            keys.push(internal.keys[i - 1]);
+        }
+        children.push(kept);
+    }
+    match children.len() {
+        0 => {
+            freed.push((page_id, false));
+            Ok(None)
+        }
+        1 => {
+            freed.push((page_id, false));
+            Ok(Some(children[0]))
+        }
+        _ => {
+            if children != internal.children {
+                let node = InternalNode {
+                    num_keys: keys.len() as u16,
+                    children,
+                    keys,
+                };
+                pager.encode_internal_into_page(page_id, &node)?;
+            }
+            Ok(Some(page_id))
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+
+use crate::btree::node::Node;
+use crate::error::{InvError, InvResult};
+use crate::pager::Pager;
+use crate::types::PageId;
+
+const MAX_DEPTH: usize = 64;
+
+/// Outcome of [`repair_leaf_chain`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Number of leaves reachable from the root.
+    pub leaves: u32,
+    /// Number of leaves whose `next_leaf` pointer was rewritten.
+    pub relinked: u32,
+}
+
+/// Rebuild the `next_leaf` chain from the tree structure.
+///
+/// Leaves are collected by an in-order walk from the root, which yields them
+/// in key order (empty leaves included), and each is linked to its successor.
+/// Keys and values are never modified; only leaves whose pointer is wrong are
+/// rewritten.
+pub fn repair_leaf_chain(pager: &mut Pager, root: PageId) -> InvResult<RepairReport> {
+    let mut leaves = Vec::new();
+    let mut seen = HashSet::new();
+    collect_leaves(pager, root, 0, &mut seen, &mut leaves)?;
+
+    let mut report = RepairReport {
+        leaves: leaves.len() as u32,
+        relinked: 0,
+    };
+    for (i, &(page_id, ref leaf)) in leaves.iter().enumerate() {
+        let next = leaves.get(i + 1).map_or(PageId(0), |(id, _)| *id);
+        if leaf.next_leaf == next {
+            continue;
+        }
+        let mut fixed = leaf.clone();
+        fixed.next_leaf = next;
+        pager.encode_leaf_into_page(page_id, &fixed)?;
+        report.relinked += 1;
+    }
+    Ok(report)
+}
+
+fn collect_leaves(
+    pager: &mut Pager,
+    page_id: PageId,
+    depth: usize,
+    seen: &mut HashSet<u32>,
+    out: &mut Vec<(PageId, crate::btree::node::LeafNode)>,
+) -> InvResult<()> {
+    if depth > MAX_DEPTH {
+        return Err(InvError::Corruption {
+            context: "btree.depth",
+            details: format!("exceeded depth {}", MAX_DEPTH),
+        });
+    }
+    if !seen.insert(page_id.0) {
+        return Err(InvError::Corruption {
+            context: "btree.structure",
+            details: format!("page {} reachable twice", page_id.0),
+        });
+    }
+    let page_count = pager.page_count();
+    let node = Node::decode(pager.get_page(page_id)?, page_count)?;
+    match node {
+        Node::Leaf(leaf) => out.push((page_id, leaf)),
+        Node::Internal(internal) => {
+            for child in internal.children {
+                collect_leaves(pager, child, depth + 1, seen, out)?;
+            }
+        }
+    }
+    Ok(())
+}
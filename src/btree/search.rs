@@ -1,4 +1,7 @@
-use crate::btree::node::Node;
+use std::collections::HashSet;
+use std::ops::Bound;
+
+use crate::btree::node::{InternalNode, LeafNode, Node, NodeKind};
 use crate::error::{InvError, InvResult};
 use crate::pager::Pager;
 use crate::types::PageId;
@@ -29,7 +32,7 @@ pub fn search_u64(pager: &mut Pager, root: PageId, key: u32) -> InvResult<Option
         let page = pager.get_page(current)?;
         let page_buf = page.as_bytes();
         let page_kind = page_buf
-            .get(0)
+            .first()
             .copied()
             .ok_or(InvError::Corruption {
                 context: "btree.page_kind",
@@ -49,14 +52,427 @@ pub fn search_u64(pager: &mut Pager, root: PageId, key: u32) -> InvResult<Option
                 Err(_) => return Ok(None),
             },
             Node::Internal(internal) => {
-                let idx = internal
-                    .keys
-                    .iter()
-                    .position(|&k| key < k)
-                    .unwrap_or(internal.keys.len());
+                current = internal.children[route(&internal, key)];
+                depth += 1;
+            }
+        }
+    }
+}
+
+/// Index of the child of `internal` whose key range covers `key`.
+pub(crate) fn route(internal: &InternalNode, key: u32) -> usize {
+    internal
+        .keys
+        .iter()
+        .position(|&k| key < k)
+        .unwrap_or(internal.keys.len())
+}
+
+/// Record the descent a lookup of `key` makes, root first.
+///
+/// Each internal node is listed with the child index followed; the final
+/// leaf with the index of `key`, or where it would be inserted if absent.
+pub fn explain_u64(pager: &mut Pager, root: PageId, key: u32) -> InvResult<Vec<(PageId, NodeKind, usize)>> {
+    let mut path = Vec::new();
+    let mut current = root;
+    loop {
+        if path.len() > MAX_DEPTH {
+            return Err(InvError::Corruption {
+                context: "btree.depth",
+                details: format!("exceeded depth {}", MAX_DEPTH),
+            });
+        }
+        let page_count = pager.page_count();
+        let page = pager.get_page(current)?;
+        match Node::decode(page, page_count)? {
+            Node::Leaf(leaf) => {
+                let idx = leaf.keys.binary_search(&key).unwrap_or_else(|idx| idx);
+                path.push((current, NodeKind::Leaf, idx));
+                return Ok(path);
+            }
+            Node::Internal(internal) => {
+                let idx = route(&internal, key);
+                path.push((current, NodeKind::Internal, idx));
                 current = internal.children[idx];
+            }
+        }
+    }
+}
+
+/// Descend from `root` to the leaf whose key range covers `key`.
+///
+/// Uses the same routing as [`search_u64`], so the returned leaf is where a
+/// lookup of `key` would land.
+pub fn find_leaf(pager: &mut Pager, root: PageId, key: u32) -> InvResult<PageId> {
+    let mut current = root;
+    let mut depth = 0usize;
+    loop {
+        if depth > MAX_DEPTH {
+            return Err(InvError::Corruption {
+                context: "btree.depth",
+                details: format!("exceeded depth {}", MAX_DEPTH),
+            });
+        }
+        let page_count = pager.page_count();
+        let page = pager.get_page(current)?;
+        match Node::decode(page, page_count)? {
+            Node::Leaf(_) => return Ok(current),
+            Node::Internal(internal) => {
+                current = internal.children[route(&internal, key)];
                 depth += 1;
             }
         }
     }
 }
+
+/// Visit entries with keys `>= start` in ascending order.
+///
+/// Descends to the leaf covering `start` and follows the `next_leaf` chain,
+/// invoking `f` for each entry until it returns `Ok(false)` or the chain ends.
+pub fn walk_from<F>(pager: &mut Pager, root: PageId, start: u32, mut f: F) -> InvResult<()>
+where
+    F: FnMut(u32, u64) -> InvResult<bool>,
+{
+    let mut current = find_leaf(pager, root, start)?;
+    let mut visited = HashSet::new();
+    loop {
+        if !visited.insert(current.0) {
+            return Err(InvError::Corruption {
+                context: "btree.leaf_cycle",
+                details: format!("cycle detected at {}", current.0),
+            });
+        }
+        let page_count = pager.page_count();
+        let page = pager.get_page(current)?;
+        let leaf = match Node::decode(page, page_count)? {
+            Node::Leaf(leaf) => leaf,
+            Node::Internal(_) => {
+                return Err(InvError::Corruption {
+                    context: "btree.leaf_cycle",
+                    details: "expected leaf during traversal".to_string(),
+                })
+            }
+        };
+        let first = leaf.keys.partition_point(|&k| k < start);
+        for (key, value) in leaf.keys[first..].iter().zip(&leaf.values[first..]) {
+            if !f(*key, *value)? {
+                return Ok(());
+            }
+        }
+        if leaf.next_leaf.0 == 0 {
+            return Ok(());
+        }
+        current = leaf.next_leaf;
+    }
+}
+
+/// Collect the entries with keys between `start` and `end`, in ascending order.
+///
+/// Reads the same leaves as a [`LeafCursor`] over the range; use the cursor
+/// to stream a large range instead of holding it in memory.
+pub fn range_u64(pager: &mut Pager, root: PageId, start: Bound<u32>, end: Bound<u32>) -> InvResult<Vec<(u32, u64)>> {
+    LeafCursor::new(pager, root, start, end)?.collect()
+}
+
+/// Streaming iterator over the entries with keys between two bounds, in
+/// ascending order.
+///
+/// Each leaf is decoded when the cursor reaches it, and the cursor moves on
+/// through `next_leaf` once the leaf is used up; it holds one leaf at a
+/// time. A chain longer than the file has pages must revisit a leaf, so the
+/// cursor yields [`InvError::Corruption`] past that many leaves instead of
+/// looping. The first error ends the iteration.
+pub struct LeafCursor<'a> {
+    pager: &'a mut Pager,
+    /// Leaf the cursor is on; `None` once the range is exhausted.
+    page: Option<PageId>,
+    /// Decoded entries of `page`, loaded on the first `next` that needs them.
+    leaf: Option<LeafNode>,
+    /// Position of the next entry within `leaf`.
+    index: usize,
+    start: u32,
+    end: Bound<u32>,
+    /// Leaves decoded so far.
+    steps: u32,
+}
+
+impl<'a> LeafCursor<'a> {
+    /// Position a cursor at the first key within `start` by descending to
+    /// its leaf.
+    pub fn new(pager: &'a mut Pager, root: PageId, start: Bound<u32>, end: Bound<u32>) -> InvResult<Self> {
+        let (start, page) = match start {
+            Bound::Included(key) => (key, Some(find_leaf(pager, root, key)?)),
+            Bound::Excluded(u32::MAX) => (u32::MAX, None),
+            Bound::Excluded(key) => (key + 1, Some(find_leaf(pager, root, key + 1)?)),
+            Bound::Unbounded => (0, Some(find_leaf(pager, root, 0)?)),
+        };
+        Ok(Self {
+            pager,
+            page,
+            leaf: None,
+            index: 0,
+            start,
+            end,
+            steps: 0,
+        })
+    }
+
+    /// Decode the leaf at `page_id` and skip to its first key `>= start`.
+    fn load(&mut self, page_id: PageId) -> InvResult<()> {
+        let page_count = self.pager.page_count();
+        self.steps += 1;
+        if self.steps > page_count {
+            return Err(InvError::Corruption {
+                context: "btree.leaf_cycle",
+                details: format!("leaf traversal exceeded {} pages", page_count),
+            });
+        }
+        let leaf = match Node::decode(self.pager.get_page(page_id)?, page_count)? {
+            Node::Leaf(leaf) => leaf,
+            Node::Internal(_) => {
+                return Err(InvError::Corruption {
+                    context: "btree.leaf_cycle",
+                    details: "expected leaf during traversal".to_string(),
+                })
+            }
+        };
+        self.index = leaf.keys.partition_point(|&k| k < self.start);
+        self.leaf = Some(leaf);
+        Ok(())
+    }
+}
+
+impl Iterator for LeafCursor<'_> {
+    type Item = InvResult<(u32, u64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let page_id = self.page?;
+            let Some(leaf) = &self.leaf else {
+                if let Err(e) = self.load(page_id) {
+                    self.page = None;
+                    return Some(Err(e));
+                }
+                continue;
+            };
+            if let (Some(&key), Some(&value)) = (leaf.keys.get(self.index), leaf.values.get(self.index)) {
+                let in_range = match self.end {
+                    Bound::Included(last) => key <= last,
+                    Bound::Excluded(last) => key < last,
+                    Bound::Unbounded => true,
+                };
+                if !in_range {
+                    self.page = None;
+                    return None;
+                }
+                self.index += 1;
+                return Some(Ok((key, value)));
+            }
+            self.page = (leaf.next_leaf.0 != 0).then_some(leaf.next_leaf);
+            self.leaf = None;
+        }
+    }
+}
+
+/// Count keys in `[lo, hi)` without materializing values.
+///
+/// Descends to the leaf covering `lo` and walks the `next_leaf` chain until a
+/// key `>= hi` is reached. Returns 0 when `lo >= hi`.
+pub fn count_range_u64(pager: &mut Pager, root: PageId, lo: u32, hi: u32) -> InvResult<u64> {
+    if lo >= hi {
+        return Ok(0);
+    }
+    let mut count = 0u64;
+    walk_from(pager, root, lo, |key, _| {
+        if key >= hi {
+            return Ok(false);
+        }
+        count += 1;
+        Ok(true)
+    })?;
+    Ok(count)
+}
+
+/// Count every key in the tree by summing `num_keys` along the leaf chain.
+pub fn count_keys_u64(pager: &mut Pager, root: PageId) -> InvResult<u64> {
+    let mut current = find_leaf(pager, root, 0)?;
+    let mut visited = HashSet::new();
+    let mut count = 0u64;
+    loop {
+        if !visited.insert(current.0) {
+            return Err(InvError::Corruption {
+                context: "btree.leaf_cycle",
+                details: format!("cycle detected at {}", current.0),
+            });
+        }
+        let page_count = pager.page_count();
+        let page = pager.get_page(current)?;
+        let leaf = match Node::decode(page, page_count)? {
+            Node::Leaf(leaf) => leaf,
+            Node::Internal(_) => {
+                return Err(InvError::Corruption {
+                    context: "btree.leaf_cycle",
+                    details: "expected leaf during traversal".to_string(),
+                })
+            }
+        };
+        count += leaf.num_keys as u64;
+        if leaf.next_leaf.0 == 0 {
+            return Ok(count);
+        }
+        current = leaf.next_leaf;
+    }
+}
+
+/// Look up a batch of keys, returning one result per key in input order.
+///
+/// A non-decreasing batch is resolved in a single walk of the leaf chain
+/// starting at its smallest key; any other batch probes the tree per key.
+pub fn search_many_u64(pager: &mut Pager, root: PageId, keys: &[u32]) -> InvResult<Vec<Option<u64>>> {
+    if !keys.windows(2).all(|w| w[0] <= w[1]) {
+        return keys.iter().map(|&key| search_u64(pager, root, key)).collect();
+    }
+    let mut out = vec![None; keys.len()];
+    let Some(&first) = keys.first() else {
+        return Ok(out);
+    };
+    let mut i = 0;
+    walk_from(pager, root, first, |key, value| {
+        while i < keys.len() && keys[i] < key {
+            i += 1;
+        }
+        while i < keys.len() && keys[i] == key {
+            out[i] = Some(value);
+            i += 1;
+        }
+        Ok(i < keys.len())
+    })?;
+    Ok(out)
+}
+
+/// Iterator over entries with keys `>= start` in ascending order.
+///
+/// Created by [`iter_from`]. Leaves are decoded one at a time while following
+/// the `next_leaf` chain; the first error is yielded and ends the iteration.
+pub struct LeafIter<'a> {
+    pager: &'a mut Pager,
+    next_page: Option<PageId>,
+    start: u32,
+    entries: std::vec::IntoIter<(u32, u64)>,
+    visited: HashSet<u32>,
+}
+
+/// Start iterating at the first key `>= start` by descending to its leaf.
+pub fn iter_from(pager: &mut Pager, root: PageId, start: u32) -> InvResult<LeafIter<'_>> {
+    let leaf = find_leaf(pager, root, start)?;
+    Ok(LeafIter {
+        pager,
+        next_page: Some(leaf),
+        start,
+        entries: Vec::new().into_iter(),
+        visited: HashSet::new(),
+    })
+}
+
+impl LeafIter<'_> {
+    fn load(&mut self, page_id: PageId) -> InvResult<()> {
+        if !self.visited.insert(page_id.0) {
+            return Err(InvError::Corruption {
+                context: "btree.leaf_cycle",
+                details: format!("cycle detected at {}", page_id.0),
+            });
+        }
+        let page_count = self.pager.page_count();
+        let page = self.pager.get_page(page_id)?;
+        let leaf = match Node::decode(page, page_count)? {
+            Node::Leaf(leaf) => leaf,
+            Node::Internal(_) => {
+                return Err(InvError::Corruption {
+                    context: "btree.leaf_cycle",
+                    details: "expected leaf during traversal".to_string(),
+                })
+            }
+        };
+        let first = leaf.keys.partition_point(|&k| k < self.start);
+        self.entries = leaf.keys[first..]
+            .iter()
+            .copied()
+            .zip(leaf.values[first..].iter().copied())
+            .collect::<Vec<_>>()
+            .into_iter();
+        self.next_page = (leaf.next_leaf.0 != 0).then_some(leaf.next_leaf);
+        Ok(())
+    }
+}
+
+impl Iterator for LeafIter<'_> {
+    type Item = InvResult<(u32, u64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(entry));
+            }
+            let page_id = self.next_page.take()?;
+            if let Err(e) = self.load(page_id) {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// Every node page of the tree under `root`, in depth-first order.
+pub fn tree_pages(pager: &mut Pager, root: PageId) -> InvResult<Vec<PageId>> {
+    let mut pages = Vec::new();
+    let mut stack = vec![(root, 0usize)];
+    let mut seen = HashSet::new();
+    while let Some((page_id, depth)) = stack.pop() {
+        if depth > MAX_DEPTH {
+            return Err(InvError::Corruption {
+                context: "btree.depth",
+                details: format!("exceeded depth {}", MAX_DEPTH),
+            });
+        }
+        if !seen.insert(page_id.0) {
+            return Err(InvError::Corruption {
+                context: "btree.structure",
+                details: format!("page {} reachable twice", page_id.0),
+            });
+        }
+        let page_count = pager.page_count();
+        pages.push(page_id);
+        if let Node::Internal(internal) = Node::decode(pager.get_page(page_id)?, page_count)? {
+            stack.extend(internal.children.iter().rev().map(|&c| (c, depth + 1)));
+        }
+    }
+    Ok(pages)
+}
+
+/// Count the nodes and leaf keys of the tree under `root`.
+pub fn tree_counts(pager: &mut Pager, root: PageId) -> InvResult<(u32, u64)> {
+    let mut pages = 0u32;
+    let mut keys = 0u64;
+    let mut stack = vec![(root, 0usize)];
+    let mut seen = HashSet::new();
+    while let Some((page_id, depth)) = stack.pop() {
+        if depth > MAX_DEPTH {
+            return Err(InvError::Corruption {
+                context: "btree.depth",
+                details: format!("exceeded depth {}", MAX_DEPTH),
+            });
+        }
+        if !seen.insert(page_id.0) {
+            return Err(InvError::Corruption {
+                context: "btree.structure",
+                details: format!("page {} reachable twice", page_id.0),
+            });
+        }
+        let page_count = pager.page_count();
+        pages += 1;
+        match Node::decode(pager.get_page(page_id)?, page_count)? {
+            Node::Leaf(leaf) => keys += leaf.keys.len() as u64,
+            Node::Internal(internal) => stack.extend(internal.children.iter().map(|&c| (c, depth + 1))),
+        }
+    }
+    Ok((pages, keys))
+}
@@ -18,13 +18,14 @@ pub fn split_leaf(
 
     let mid = total_keys / 2;
 
-    let mut right_keys = node.keys.split_off(mid);
-    let mut right_values = node.values.split_off(mid);
+    let right_keys = node.keys.split_off(mid);
+    let right_values = node.values.split_off(mid);
 
     let promoted_key = right_keys[0];
 
     let right_next = node.next_leaf;
     let right_page_id = pager.allocate_btree_page()?;
+    pager.note_split();
 
     let left_next = right_page_id;
     node.next_leaf = left_next;
@@ -32,8 +33,8 @@ pub fn split_leaf(
     let right_node = LeafNode {
         num_keys: right_keys.len() as u16,
         next_leaf: right_next,
-        keys: right_keys.drain(..).collect(),
-        values: right_values.drain(..).collect(),
+        keys: right_keys,
+        values: right_values,
     };
 
     node.num_keys = node.keys.len() as u16;
@@ -60,6 +61,8 @@ pub fn split_internal(
 
     let right_keys: Vec<u32> = node.keys.split_off(mid + 1);
     let right_children: Vec<PageId> = node.children.split_off(mid + 1);
+    // The promoted key moves up to the parent and stays in neither half.
+    node.keys.truncate(mid);
 
     let left_keys = node.keys.clone();
     let left_children = node.children.clone();
@@ -77,6 +80,7 @@ pub fn split_internal(
     };
 
     let right_page_id = pager.allocate_btree_page()?;
+    pager.note_split();
 
     pager.encode_internal_into_page(page_id, &left_node)?;
     pager.encode_internal_into_page(right_page_id, &right_node)?;
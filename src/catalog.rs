@@ -1,15 +1,60 @@
 //! Persistent catalog structures and encoding/decoding.
 
-use crate::config::PAGE_SIZE;
+use crate::config::{usable_payload, MAX_NAME_BYTES};
 use crate::encoding;
 use crate::error::{InvError, InvResult};
+use crate::layout::{self, read_u16_le, read_u32_le, CATALOG_ENTRIES, CATALOG_ENTRY_COUNT, CATALOG_MAGIC, CATALOG_NEXT_TABLE_ID, CATALOG_RESERVED};
+use crate::page::PageKind;
 use crate::schema::{ColType, Column, Schema};
 
+/// Catalog payload format version written by [`encode_catalog`].
+pub(crate) const CATALOG_VERSION: u16 = 3;
+
+/// Per-table flag: the table uses 64-bit pks (see [`TableDef::next_pk_u64`]).
+const TABLE_FLAG_PK_U64: u32 = 1;
+
+/// Per-table flag: rows are clustered (see [`TableDef::cluster`]).
+const TABLE_FLAG_CLUSTERED: u32 = 2;
+
+/// Per-table flag: rows are stored with a checksum (see [`TableDef::row_crc`]).
+const TABLE_FLAG_ROW_CRC: u32 = 4;
+
+/// Per-table flag: the entry records its first row page (see [`TableDef::first_row_page`]).
+const TABLE_FLAG_ROW_CHAIN: u32 = 8;
+
+/// Per-table flag: rows carry engine-generated keys (see [`TableDef::uuid_root`]).
+const TABLE_FLAG_UUID_KEY: u32 = 16;
+
+/// Per-table flag: rows are indexed in the table's own pk tree (see [`TableDef::pk_root`]).
+const TABLE_FLAG_PK_TREE: u32 = 32;
+
+const KNOWN_TABLE_FLAGS: u32 = TABLE_FLAG_PK_U64
+    | TABLE_FLAG_CLUSTERED
+    | TABLE_FLAG_ROW_CRC
+    | TABLE_FLAG_ROW_CHAIN
+    | TABLE_FLAG_UUID_KEY
+    | TABLE_FLAG_PK_TREE;
+
 /// Strongly typed table identifier.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct TableId(pub u32);
 
+/// Physical clustering of a table's rows by one column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClusterDef {
+    /// Index of the clustering column in the schema.
+    pub column: u32,
+    /// Meta page listing the table's row pages in cluster order.
+    pub directory_page: u32,
+}
+
 /// Table definition stored in the catalog.
+///
+/// The catalog keeps no index definitions: rows are found by pk through the
+/// shared btree (see [`crate::table::composite_key`]) or, for u64-pk tables,
+/// through [`TableDef::pk_root`], and by generated key through
+/// [`TableDef::uuid_root`]. There are no secondary indexes to list
+/// or drop.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TableDef {
     pub id: TableId,
@@ -17,12 +62,61 @@ pub struct TableDef {
     pub schema: Schema,
     pub next_pk: u32,
     pub last_row_page: u32,
+    /// Next pk for tables created with 64-bit pks; `None` for u32-pk tables.
+    pub next_pk_u64: Option<u64>,
+    /// Clustering of rows by a column; `None` for append-ordered tables.
+    pub cluster: Option<ClusterDef>,
+    /// Whether new rows are stored with a CRC-32 checked on every read.
+    pub row_crc: bool,
+    /// First page of the chain of row pages the table appends to, linked
+    /// through each page's next pointer; 0 until the first row. `None` for
+    /// tables created before row pages were chained.
+    pub first_row_page: Option<u32>,
+    /// Root of the btree mapping the generated keys of a table created with
+    /// [`crate::Db::create_table_with_uuid`] to pks; `None` for other tables.
+    pub uuid_root: Option<u32>,
+    /// Root of the index mapping the pks of a u64-pk table to its rows (see
+    /// [`crate::pktree`]); 0 until the first row. `None` for u32-pk tables,
+    /// whose rows are indexed in the shared btree.
+    pub pk_root: Option<u32>,
+}
+
+impl TableDef {
+    /// Whether the table was created with 64-bit pks.
+    pub fn has_u64_pk(&self) -> bool {
+        self.next_pk_u64.is_some()
+    }
+
+    /// How the table stores and indexes its rows, leaving out page
+    /// locations: its pk width, clustering, generated keys and row checksum
+    /// catalog flags, and its cluster column. Tables of equal kind lay out
+    /// the same rows alike.
+    pub(crate) fn kind(&self) -> (u32, Option<u32>) {
+        let mut flags = 0;
+        if self.has_u64_pk() {
+            flags |= TABLE_FLAG_PK_U64;
+        }
+        if self.cluster.is_some() {
+            flags |= TABLE_FLAG_CLUSTERED;
+        }
+        if self.row_crc {
+            flags |= TABLE_FLAG_ROW_CRC;
+        }
+        if self.uuid_root.is_some() {
+            flags |= TABLE_FLAG_UUID_KEY;
+        }
+        (flags, self.cluster.map(|c| c.column))
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Catalog {
     pub(crate) next_table_id: u32,
     pub(crate) tables: Vec<TableDef>,
+    /// Root of the blob btree (see [`crate::blob`]); 0 until the first blob.
+    pub(crate) blob_root: u32,
+    /// Row page receiving blob appends; 0 until the first blob.
+    pub(crate) blob_last_row_page: u32,
 }
 
 impl Catalog {
@@ -30,6 +124,8 @@ impl Catalog {
         Self {
             next_table_id: 1,
             tables: Vec::new(),
+            blob_root: 0,
+            blob_last_row_page: 0,
         }
     }
 
@@ -42,6 +138,14 @@ impl Catalog {
     }
 
     pub fn create_table(&mut self, name: &str, schema: &Schema) -> InvResult<TableId> {
+        self.add_table(name, schema, |_| {})
+    }
+
+    /// Append a new table entry shaped by `configure`.
+    ///
+    /// Fails without touching the catalog if the entry would not fit in the
+    /// catalog page, so callers never see a half-added table.
+    fn add_table(&mut self, name: &str, schema: &Schema, configure: impl FnOnce(&mut TableDef)) -> InvResult<TableId> {
         validate_table_name(name)?;
         if self.get_by_name(name).is_some() {
             return Err(InvError::InvalidArgument {
@@ -50,29 +154,115 @@ impl Catalog {
             });
         }
         let id = self.next_table_id;
-        self.next_table_id = self
-            .next_table_id
-            .checked_add(1)
-            .ok_or(InvError::Overflow {
-                context: "catalog.next_table_id",
-            })?;
+        let next_table_id = id.checked_add(1).ok_or(InvError::Overflow {
+            context: "catalog.next_table_id",
+        })?;
 
-        self.tables.push(TableDef {
+        let mut table = TableDef {
             id: TableId(id),
             name: name.to_string(),
             schema: schema.clone(),
             next_pk: 1,
             last_row_page: 0,
-        });
+            next_pk_u64: None,
+            cluster: None,
+            row_crc: false,
+            first_row_page: Some(0),
+            uuid_root: None,
+            pk_root: None,
+        };
+        configure(&mut table);
+        let mut entry = Vec::new();
+        encode_table_entry(&mut entry, &table)?;
+        if encode_catalog(self)?.len() + entry.len() > usable_payload(PageKind::Meta) {
+            return Err(InvError::Unsupported {
+                feature: "catalog.page_overflow",
+            });
+        }
+        self.next_table_id = next_table_id;
+        self.tables.push(table);
         Ok(TableId(id))
     }
+
+    /// Remove the last column from a table's schema.
+    ///
+    /// Only the trailing column can be dropped: rows store positional values,
+    /// so existing rows keep the dropped value as an extra trailing value that
+    /// reads skip until the rows are rewritten.
+    pub fn drop_column(&mut self, table_name: &str, column: &str) -> InvResult<()> {
+        let table = self
+            .tables
+            .iter_mut()
+            .find(|t| t.name == table_name)
+            .ok_or(InvError::InvalidArgument {
+                name: "table",
+                details: "not found".to_string(),
+            })?;
+        let idx = table
+            .schema
+            .columns
+            .iter()
+            .position(|c| c.name == column)
+            .ok_or(InvError::InvalidArgument {
+                name: "column",
+                details: format!("no column named {}", column),
+            })?;
+        if idx + 1 != table.schema.len() {
+            return Err(InvError::Unsupported {
+                feature: "column.drop_non_trailing",
+            });
+        }
+        if table.cluster.is_some_and(|c| c.column as usize == idx) {
+            return Err(InvError::InvalidArgument {
+                name: "column",
+                details: format!("cannot drop cluster column {}", column),
+            });
+        }
+        let mut columns = table.schema.columns.clone();
+        columns.pop();
+        let mut schema = Schema::new(columns).map_err(|_| InvError::InvalidArgument {
+            name: "column",
+            details: "cannot drop the only column".to_string(),
+        })?;
+        schema.reject_interior_nul.copy_from_slice(&table.schema.reject_interior_nul[..idx]);
+        table.schema = schema;
+        Ok(())
+    }
+
+    /// Create a table whose pks are 64-bit (allocated by `insert_row_u64`).
+    pub fn create_table_u64_pk(&mut self, name: &str, schema: &Schema) -> InvResult<TableId> {
+        self.add_table(name, schema, |table| {
+            table.next_pk_u64 = Some(1);
+            table.pk_root = Some(0);
+        })
+    }
+
+    /// Create a table whose rows are stored with a checksum.
+    pub fn create_table_with_row_crc(&mut self, name: &str, schema: &Schema) -> InvResult<TableId> {
+        self.add_table(name, schema, |table| table.row_crc = true)
+    }
+
+    /// Create a table whose rows are keyed by generated 16-byte keys indexed
+    /// in the btree at `uuid_root`.
+    ///
+    /// The caller allocates the btree page; the schema is not validated here.
+    pub fn create_table_with_uuid(&mut self, name: &str, schema: &Schema, uuid_root: u32) -> InvResult<TableId> {
+        self.add_table(name, schema, |table| table.uuid_root = Some(uuid_root))
+    }
+
+    /// Create a table whose rows are kept ordered by `cluster.column`.
+    ///
+    /// The caller allocates the directory page; the column is not validated here.
+    pub fn create_clustered_table(&mut self, name: &str, schema: &Schema, cluster: ClusterDef) -> InvResult<TableId> {
+        self.add_table(name, schema, |table| table.cluster = Some(cluster))
+    }
 }
 
 fn validate_table_name(name: &str) -> InvResult<()> {
-    if name.is_empty() || name.len() > 64 {
+    if name.is_empty() || name.len() > MAX_NAME_BYTES {
         return Err(InvError::InvalidArgument {
             name: "table.name",
-            details: "name must be 1..=64 chars".to_string(),
+            details: format!("name must be 1..={} chars", MAX_NAME_BYTES),
         });
     }
     if !name
@@ -92,8 +282,8 @@ pub fn encode_schema(schema: &Schema) -> InvResult<Vec<u8>> {
     let mut out = Vec::new();
     out.extend_from_slice(b"SCH1");
     encoding::write_var_u64(&mut out, schema.len() as u64);
-    for col in &schema.columns {
-        if col.name.len() > 64 {
+    for (idx, col) in schema.columns.iter().enumerate() {
+        if col.name.len() > MAX_NAME_BYTES {
             return Err(InvError::InvalidArgument {
                 name: "column.name",
                 details: "name too long".to_string(),
@@ -101,11 +291,32 @@ pub fn encode_schema(schema: &Schema) -> InvResult<Vec<u8>> {
         }
         encoding::write_bytes(&mut out, col.name.as_bytes());
         out.push(col_type_tag(&col.ty)?);
-        out.push(if col.nullable { 1 } else { 0 });
+        out.push(col.nullable as u8 | (schema.rejects_interior_nul(idx) as u8) << 1);
     }
     Ok(out)
 }
 
+/// Stable 64-bit digest of a schema's column names, types and flags.
+pub fn schema_digest(schema: &Schema) -> InvResult<u64> {
+    Ok(encoding::fnv1a64(&encode_schema(schema)?))
+}
+
+/// Stable 64-bit fingerprint of every table's name and [`schema_digest`].
+///
+/// Tables are hashed in name order and their ids are left out, since both
+/// follow creation order: two catalogs holding the same tables fingerprint
+/// alike however they were built.
+pub fn catalog_fingerprint(cat: &Catalog) -> InvResult<u64> {
+    let mut tables: Vec<&TableDef> = cat.tables.iter().collect();
+    tables.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut bytes = Vec::new();
+    for table in tables {
+        encoding::write_bytes(&mut bytes, table.name.as_bytes());
+        encoding::write_u64_le(&mut bytes, schema_digest(&table.schema)?);
+    }
+    Ok(encoding::fnv1a64(&bytes))
+}
+
 /// Decode schema bytes into a Schema instance.
 pub fn decode_schema(bytes: &[u8]) -> InvResult<Schema> {
     if bytes.len() < 4 || &bytes[0..4] != b"SCH1" {
@@ -117,8 +328,9 @@ pub fn decode_schema(bytes: &[u8]) -> InvResult<Schema> {
     let mut pos = 4;
     let col_count = encoding::read_var_u64(bytes, &mut pos)? as usize;
     let mut cols = Vec::with_capacity(col_count);
+    let mut reject_interior_nul = Vec::with_capacity(col_count);
     for _ in 0..col_count {
-        let name_bytes = encoding::read_bytes(bytes, &mut pos, 64)?;
+        let name_bytes = encoding::read_bytes(bytes, &mut pos, MAX_NAME_BYTES)?;
         let name = String::from_utf8(name_bytes).map_err(|e| InvError::Corruption {
             context: "schema.name.utf8",
             details: e.to_string(),
@@ -129,34 +341,34 @@ pub fn decode_schema(bytes: &[u8]) -> InvResult<Schema> {
         })?;
         pos += 1;
         let ty = tag_to_col_type(ty_tag)?;
+        // Bit 0 is nullability, bit 1 `reject_interior_nul`.
         let nullable_byte = *bytes.get(pos).ok_or(InvError::Corruption {
             context: "schema.nullable",
             details: "missing nullable byte".to_string(),
         })?;
         pos += 1;
-        let nullable = match nullable_byte {
-            0 => false,
-            1 => true,
-            _ => {
-                return Err(InvError::Corruption {
-                    context: "schema.nullable",
-                    details: format!("invalid nullable byte {}", nullable_byte),
-                })
-            }
-        };
+        if nullable_byte > 3 {
+            return Err(InvError::Corruption {
+                context: "schema.nullable",
+                details: format!("invalid nullable byte {}", nullable_byte),
+            });
+        }
         cols.push(Column {
             name,
             ty,
-            nullable,
+            nullable: nullable_byte & 1 != 0,
         });
+        reject_interior_nul.push(nullable_byte & 2 != 0);
     }
-    Schema::new(cols).map_err(|e| match e {
+    let mut schema = Schema::new(cols).map_err(|e| match e {
         InvError::InvalidArgument { .. } => InvError::Corruption {
             context: "schema.invalid",
             details: e.to_string(),
         },
         other => other,
-    })
+    })?;
+    schema.reject_interior_nul = reject_interior_nul;
+    Ok(schema)
 }
 
 fn col_type_tag(ty: &ColType) -> InvResult<u8> {
@@ -189,7 +401,7 @@ fn tag_to_col_type(tag: u8) -> InvResult<ColType> {
 pub fn encode_catalog(cat: &Catalog) -> InvResult<Vec<u8>> {
     let mut out = Vec::new();
     out.extend_from_slice(b"CAT1");
-    out.extend_from_slice(&1u16.to_le_bytes()); // version
+    out.extend_from_slice(&CATALOG_VERSION.to_le_bytes());
     let entry_count: u16 = cat
         .tables
         .len()
@@ -202,21 +414,12 @@ pub fn encode_catalog(cat: &Catalog) -> InvResult<Vec<u8>> {
     out.extend_from_slice(&0u32.to_le_bytes()); // reserved
 
     for table in &cat.tables {
-        out.extend_from_slice(&table.id.0.to_le_bytes());
-        encoding::write_bytes(&mut out, table.name.as_bytes());
-        let schema_bytes = encode_schema(&table.schema)?;
-        if schema_bytes.len() > 64 * 1024 {
-            return Err(InvError::Corruption {
-                context: "catalog.schema.too_large",
-                details: format!("schema bytes {}", schema_bytes.len()),
-            });
-        }
-        encoding::write_bytes(&mut out, &schema_bytes);
-        out.extend_from_slice(&table.next_pk.to_le_bytes());
-        out.extend_from_slice(&table.last_row_page.to_le_bytes());
+        encode_table_entry(&mut out, table)?;
     }
+    out.extend_from_slice(&cat.blob_root.to_le_bytes());
+    out.extend_from_slice(&cat.blob_last_row_page.to_le_bytes());
 
-    if out.len() > PAGE_SIZE - 16 {
+    if out.len() > usable_payload(PageKind::Meta) {
         return Err(InvError::Unsupported {
             feature: "catalog.page_overflow",
         });
@@ -225,36 +428,88 @@ pub fn encode_catalog(cat: &Catalog) -> InvResult<Vec<u8>> {
     Ok(out)
 }
 
+fn encode_table_entry(out: &mut Vec<u8>, table: &TableDef) -> InvResult<()> {
+    out.extend_from_slice(&table.id.0.to_le_bytes());
+    encoding::write_bytes(out, table.name.as_bytes());
+    let schema_bytes = encode_schema(&table.schema)?;
+    if schema_bytes.len() > 64 * 1024 {
+        return Err(InvError::Corruption {
+            context: "catalog.schema.too_large",
+            details: format!("schema bytes {}", schema_bytes.len()),
+        });
+    }
+    encoding::write_bytes(out, &schema_bytes);
+    out.extend_from_slice(&table.next_pk.to_le_bytes());
+    out.extend_from_slice(&table.last_row_page.to_le_bytes());
+    let mut flags = 0;
+    if table.has_u64_pk() {
+        flags |= TABLE_FLAG_PK_U64;
+    }
+    if table.cluster.is_some() {
+        flags |= TABLE_FLAG_CLUSTERED;
+    }
+    if table.row_crc {
+        flags |= TABLE_FLAG_ROW_CRC;
+    }
+    if table.first_row_page.is_some() {
+        flags |= TABLE_FLAG_ROW_CHAIN;
+    }
+    if table.uuid_root.is_some() {
+        flags |= TABLE_FLAG_UUID_KEY;
+    }
+    if table.pk_root.is_some() {
+        flags |= TABLE_FLAG_PK_TREE;
+    }
+    out.extend_from_slice(&flags.to_le_bytes());
+    if let Some(next) = table.next_pk_u64 {
+        out.extend_from_slice(&next.to_le_bytes());
+    }
+    if let Some(cluster) = table.cluster {
+        out.extend_from_slice(&cluster.column.to_le_bytes());
+        out.extend_from_slice(&cluster.directory_page.to_le_bytes());
+    }
+    if let Some(first) = table.first_row_page {
+        out.extend_from_slice(&first.to_le_bytes());
+    }
+    if let Some(root) = table.uuid_root {
+        out.extend_from_slice(&root.to_le_bytes());
+    }
+    if let Some(root) = table.pk_root {
+        out.extend_from_slice(&root.to_le_bytes());
+    }
+    Ok(())
+}
+
 /// Decode catalog payload bytes into Catalog struct.
 pub fn decode_catalog(payload: &[u8]) -> InvResult<Catalog> {
-    if payload.len() < 16 {
+    if payload.len() < CATALOG_ENTRIES {
         return Err(InvError::Corruption {
             context: "catalog.eof",
             details: "payload too small".to_string(),
         });
     }
-    if &payload[0..4] != b"CAT1" {
+    if &payload[CATALOG_MAGIC..CATALOG_MAGIC + 4] != b"CAT1" {
         return Err(InvError::Corruption {
             context: "catalog.magic",
             details: "invalid catalog magic".to_string(),
         });
     }
-    let version = u16::from_le_bytes([payload[4], payload[5]]);
-    if version != 1 {
+    let version = read_u16_le(payload, layout::CATALOG_VERSION);
+    if version == 0 || version > CATALOG_VERSION {
         return Err(InvError::Unsupported {
             feature: "catalog.version",
         });
     }
-    let entry_count = u16::from_le_bytes([payload[6], payload[7]]) as usize;
-    let next_table_id = u32::from_le_bytes([payload[8], payload[9], payload[10], payload[11]]);
-    let reserved = u32::from_le_bytes([payload[12], payload[13], payload[14], payload[15]]);
+    let entry_count = read_u16_le(payload, CATALOG_ENTRY_COUNT) as usize;
+    let next_table_id = read_u32_le(payload, CATALOG_NEXT_TABLE_ID);
+    let reserved = read_u32_le(payload, CATALOG_RESERVED);
     if reserved != 0 {
         return Err(InvError::Unsupported {
             feature: "catalog.reserved",
         });
     }
 
-    let mut pos = 16usize;
+    let mut pos = CATALOG_ENTRIES;
     let mut tables = Vec::with_capacity(entry_count);
     let mut name_set = std::collections::HashSet::new();
     let mut id_set = std::collections::HashSet::new();
@@ -272,12 +527,12 @@ pub fn decode_catalog(payload: &[u8]) -> InvResult<Catalog> {
             payload[pos + 3],
         ]);
         pos += 4;
-        let name_bytes = encoding::read_bytes(payload, &mut pos, 256)?;
+        let name_bytes = encoding::read_bytes(payload, &mut pos, MAX_NAME_BYTES)?;
         let name = String::from_utf8(name_bytes).map_err(|e| InvError::Corruption {
             context: "catalog.name",
             details: e.to_string(),
         })?;
-        if name.is_empty() || name.len() > 64 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        if name.is_empty() || name.len() > MAX_NAME_BYTES || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
             return Err(InvError::Corruption {
                 context: "catalog.name",
                 details: "invalid table name on disk".to_string(),
@@ -314,6 +569,113 @@ pub fn decode_catalog(payload: &[u8]) -> InvResult<Catalog> {
             });
         }
 
+        // Version 1 entries predate per-table flags.
+        let flags = if version >= 2 {
+            let bytes = payload.get(pos..pos + 4).ok_or(InvError::Corruption {
+                context: "catalog.eof",
+                details: "truncated table flags".to_string(),
+            })?;
+            pos += 4;
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        } else {
+            0
+        };
+        if flags & !KNOWN_TABLE_FLAGS != 0 {
+            return Err(InvError::Unsupported {
+                feature: "catalog.table_flags",
+            });
+        }
+        let next_pk_u64 = if flags & TABLE_FLAG_PK_U64 != 0 {
+            let bytes = payload.get(pos..pos + 8).ok_or(InvError::Corruption {
+                context: "catalog.eof",
+                details: "truncated u64 next_pk".to_string(),
+            })?;
+            pos += 8;
+            let mut raw = [0u8; 8];
+            raw.copy_from_slice(bytes);
+            let next = u64::from_le_bytes(raw);
+            if next < 1 {
+                return Err(InvError::Corruption {
+                    context: "catalog.next_pk",
+                    details: format!("invalid next_pk {}", next),
+                });
+            }
+            Some(next)
+        } else {
+            None
+        };
+        let cluster = if flags & TABLE_FLAG_CLUSTERED != 0 {
+            let bytes = payload.get(pos..pos + 8).ok_or(InvError::Corruption {
+                context: "catalog.eof",
+                details: "truncated cluster metadata".to_string(),
+            })?;
+            pos += 8;
+            let column = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            let directory_page = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+            if column as usize >= schema.len() || directory_page == 0 {
+                return Err(InvError::Corruption {
+                    context: "catalog.cluster",
+                    details: format!("invalid cluster column {} or directory {}", column, directory_page),
+                });
+            }
+            Some(ClusterDef { column, directory_page })
+        } else {
+            None
+        };
+        let first_row_page = if flags & TABLE_FLAG_ROW_CHAIN != 0 {
+            let bytes = payload.get(pos..pos + 4).ok_or(InvError::Corruption {
+                context: "catalog.eof",
+                details: "truncated first row page".to_string(),
+            })?;
+            pos += 4;
+            let first = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            if (first == 0) != (last_row_page == 0) {
+                return Err(InvError::Corruption {
+                    context: "catalog.first_row_page",
+                    details: format!("first row page {} with last row page {}", first, last_row_page),
+                });
+            }
+            Some(first)
+        } else {
+            None
+        };
+        let uuid_root = if flags & TABLE_FLAG_UUID_KEY != 0 {
+            let bytes = payload.get(pos..pos + 4).ok_or(InvError::Corruption {
+                context: "catalog.eof",
+                details: "truncated uuid root".to_string(),
+            })?;
+            pos += 4;
+            let root = read_u32_le(bytes, 0);
+            if root == 0 {
+                return Err(InvError::Corruption {
+                    context: "catalog.uuid_root",
+                    details: format!("table {} has no uuid root", name),
+                });
+            }
+            Some(root)
+        } else {
+            None
+        };
+        // Entries from before u64 pks got their own index keep them in the
+        // shared btree under 32-bit keys and decode without a pk root until
+        // `table::migrate_shared_u64_pks` moves them.
+        let pk_root = if flags & TABLE_FLAG_PK_TREE != 0 {
+            if next_pk_u64.is_none() {
+                return Err(InvError::Corruption {
+                    context: "catalog.pk_root",
+                    details: format!("table {} has a pk tree but u32 pks", name),
+                });
+            }
+            let bytes = payload.get(pos..pos + 4).ok_or(InvError::Corruption {
+                context: "catalog.eof",
+                details: "truncated pk root".to_string(),
+            })?;
+            pos += 4;
+            Some(read_u32_le(bytes, 0))
+        } else {
+            None
+        };
+
         if !id_set.insert(table_id) || !name_set.insert(name.clone()) {
             return Err(InvError::Corruption {
                 context: "catalog.duplicate",
@@ -327,11 +689,30 @@ pub fn decode_catalog(payload: &[u8]) -> InvResult<Catalog> {
             schema,
             next_pk,
             last_row_page,
+            next_pk_u64,
+            cluster,
+            row_crc: flags & TABLE_FLAG_ROW_CRC != 0,
+            first_row_page,
+            uuid_root,
+            pk_root,
         });
     }
 
+    // Version 3 appends the blob store state after the table entries.
+    let (blob_root, blob_last_row_page) = if version >= 3 {
+        let bytes = payload.get(pos..pos + 8).ok_or(InvError::Corruption {
+            context: "catalog.eof",
+            details: "truncated blob state".to_string(),
+        })?;
+        (read_u32_le(bytes, 0), read_u32_le(bytes, 4))
+    } else {
+        (0, 0)
+    };
+
     Ok(Catalog {
         next_table_id,
         tables,
+        blob_root,
+        blob_last_row_page,
     })
 }
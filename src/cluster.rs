@@ -0,0 +1,357 @@
+//! Clustered tables: row pages kept in the order of one integer column.
+//!
+//! A clustered table owns a directory page (meta kind, magic `CLD1`) listing
+//! its row pages sorted by fence key, the smallest cluster key a page holds.
+//! Rows inside a page are kept sorted by cluster key, so a range filter on
+//! the cluster column only reads the pages whose fences overlap the range.
+
+use crate::btree;
+use crate::catalog::{Catalog, ClusterDef, TableDef, TableId};
+use crate::config::{META_PAGE_KIND, PAGE_SIZE};
+use crate::error::{InvError, InvResult};
+use crate::layout;
+use crate::pager::Pager;
+use crate::row::{decode_row_lenient, Row, Value};
+use crate::rowstore::{RowPtr, RowStore};
+use crate::schema::{ColType, Schema};
+use crate::table::{self, composite_key};
+use crate::types::PageId;
+
+const DIRECTORY_MAGIC: &[u8; 4] = b"CLD1";
+const DIRECTORY_VERSION: u16 = 1;
+const DIRECTORY_ENTRIES_START: usize = layout::DIRECTORY_ENTRIES;
+const DIRECTORY_ENTRY_SIZE: usize = 12;
+const ROW_PAGE_CAPACITY: usize = PAGE_SIZE - layout::ROW_PAGE_DATA;
+
+/// Maximum number of row pages a clustered table can own.
+pub const MAX_DIRECTORY_ENTRIES: usize = (PAGE_SIZE - DIRECTORY_ENTRIES_START) / DIRECTORY_ENTRY_SIZE;
+
+/// Map an integer value to a u64 whose unsigned order matches the value order.
+///
+/// Returns `None` for non-integer values, which cannot be clustered on.
+pub fn cluster_key(value: &Value) -> Option<u64> {
+    match value {
+        Value::U32(v) => Some(*v as u64),
+        Value::U64(v) => Some(*v),
+        Value::I64(v) => Some((*v as u64) ^ (1 << 63)),
+        _ => None,
+    }
+}
+
+/// Create a clustered table, allocating its (empty) directory page.
+pub(crate) fn create_clustered_table(
+    pager: &mut Pager,
+    catalog: &mut Catalog,
+    name: &str,
+    schema: &Schema,
+    cluster_column: &str,
+) -> InvResult<TableId> {
+    let column = cluster_column_index(schema, cluster_column)?;
+    let id = catalog.create_clustered_table(
+        name,
+        schema,
+        ClusterDef {
+            column,
+            directory_page: 0,
+        },
+    )?;
+    let directory = pager.allocate_meta_page()?;
+    write_directory(pager, directory, &[])?;
+    if let Some(cluster) = catalog.tables.last_mut().and_then(|t| t.cluster.as_mut()) {
+        cluster.directory_page = directory.0;
+    }
+    Ok(id)
+}
+
+fn cluster_column_index(schema: &Schema, name: &str) -> InvResult<u32> {
+    let idx = schema
+        .columns
+        .iter()
+        .position(|c| c.name == name)
+        .ok_or(InvError::InvalidArgument {
+            name: "cluster_column",
+            details: format!("no column named {}", name),
+        })?;
+    let col = &schema.columns[idx];
+    if col.nullable || !matches!(col.ty, ColType::U32 | ColType::U64 | ColType::I64) {
+        return Err(InvError::InvalidArgument {
+            name: "cluster_column",
+            details: format!("column {} must be a non-nullable integer", name),
+        });
+    }
+    Ok(idx as u32)
+}
+
+/// Place a stored row (pk prefix included) at its sorted position and index it.
+///
+/// The target page is rewritten without its dead slots; if the rows no longer
+/// fit, the page is split and the new pages are added to the directory.
+pub(crate) fn insert_row(
+    pager: &mut Pager,
+    table: &TableDef,
+    cluster: ClusterDef,
+    key: u64,
+    composite: u32,
+    stored: Vec<u8>,
+) -> InvResult<()> {
+    let directory = PageId(cluster.directory_page);
+    let mut dir = read_directory(pager, directory)?;
+    if dir.is_empty() {
+        dir.push((pager.allocate_row_page()?.0, key));
+    }
+    let idx = dir.iter().rposition(|&(_, fence)| fence <= key).unwrap_or(0);
+    dir[idx].1 = dir[idx].1.min(key);
+    let page_id = PageId(dir[idx].0);
+
+    let mut rows = live_rows(pager, table, cluster, page_id)?;
+    let pos = rows.partition_point(|r| r.key <= key);
+    rows.insert(
+        pos,
+        PageRow {
+            composite,
+            key,
+            ptr: None,
+            bytes: stored,
+        },
+    );
+
+    let chunks = chunk_rows(&rows);
+    if dir.len() + chunks.len() - 1 > MAX_DIRECTORY_ENTRIES {
+        return Err(InvError::Unsupported {
+            feature: "cluster.directory_full",
+        });
+    }
+    let mut pages = vec![page_id];
+    for _ in 1..chunks.len() {
+        pages.push(pager.allocate_row_page()?);
+    }
+    for (i, (&(start, end), &page)) in chunks.iter().zip(&pages).enumerate() {
+        let chunk = &rows[start..end];
+        let bytes: Vec<&[u8]> = chunk.iter().map(|r| r.bytes.as_slice()).collect();
+        let ptrs = RowStore::rewrite_page(pager, page, &bytes)?;
+        for (row, ptr) in chunk.iter().zip(ptrs) {
+            if row.ptr != Some(ptr) {
+                let root = pager.root_page_id();
+                let new_root = btree::insert::insert_u64(pager, root, row.composite, ptr.pack())?;
+                if new_root != root {
+                    pager.set_root_page_id(new_root)?;
+                }
+            }
+        }
+        if i > 0 {
+            dir.insert(idx + i, (page.0, chunk[0].key));
+        }
+    }
+    write_directory(pager, directory, &dir)
+}
+
+/// Return the rows of a table whose `column` value lies in `[lo, hi]`.
+///
+/// When `column` is the table's cluster column only the overlapping pages are
+/// read and rows come back in cluster order; otherwise the table is scanned
+/// in pk order and filtered.
+pub(crate) fn scan_column_range(
+    pager: &mut Pager,
+    table: &TableDef,
+    column: &str,
+    lo: &Value,
+    hi: &Value,
+    strict: bool,
+) -> InvResult<Vec<(u32, Row)>> {
+    let idx = table
+        .schema
+        .columns
+        .iter()
+        .position(|c| c.name == column)
+        .ok_or(InvError::InvalidArgument {
+            name: "column",
+            details: format!("no column named {}", column),
+        })?;
+    let ty = &table.schema.columns[idx].ty;
+    let bound = |v: &Value| {
+        let matches_type = matches!(
+            (ty, v),
+            (ColType::U32, Value::U32(_)) | (ColType::U64, Value::U64(_)) | (ColType::I64, Value::I64(_))
+        );
+        cluster_key(v).filter(|_| matches_type).ok_or(InvError::InvalidArgument {
+            name: "range",
+            details: format!("bounds must be integers of column {}'s type", column),
+        })
+    };
+    let (lo, hi) = (bound(lo)?, bound(hi)?);
+
+    let Some(cluster) = table.cluster.filter(|c| c.column as usize == idx) else {
+        let rows = table::scan_rows(pager, table, strict)?;
+        return Ok(rows
+            .into_iter()
+            .filter(|(_, row)| cluster_key(&row[idx]).is_some_and(|k| lo <= k && k <= hi))
+            .collect());
+    };
+
+    let dir = read_directory(pager, PageId(cluster.directory_page))?;
+    // Equal keys may straddle a split, so start one page before the first
+    // fence that reaches `lo`.
+    let start = dir.partition_point(|&(_, fence)| fence < lo).saturating_sub(1);
+    let mut out = Vec::new();
+    for &(page, fence) in &dir[start..] {
+        if fence > hi {
+            break;
+        }
+        for row in live_rows(pager, table, cluster, PageId(page))? {
+            if row.key < lo || row.key > hi {
+                continue;
+            }
+            let pk = u32::from_le_bytes([row.bytes[0], row.bytes[1], row.bytes[2], row.bytes[3]]);
+            let mut decoded = Vec::with_capacity(table.schema.len());
+            table::decode_stored_row(table, &row.bytes[4..], strict, &mut decoded)?;
+            out.push((pk, decoded));
+        }
+    }
+    Ok(out)
+}
+
+/// A row slot of a clustered page together with its btree key and cluster key.
+struct PageRow {
+    composite: u32,
+    key: u64,
+    ptr: Option<RowPtr>,
+    bytes: Vec<u8>,
+}
+
+/// Collect the live rows of a clustered page in on-page (cluster) order.
+///
+/// A slot is live when the btree entry for its pk still points at it.
+fn live_rows(pager: &mut Pager, table: &TableDef, cluster: ClusterDef, page_id: PageId) -> InvResult<Vec<PageRow>> {
+    let mut rows = Vec::new();
+    for (ptr, bytes) in RowStore::page_rows(pager, page_id)? {
+        if bytes.len() < 4 {
+            return Err(InvError::Corruption {
+                context: "table.pk_mismatch",
+                details: "stored row too small".to_string(),
+            });
+        }
+        let pk = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let composite = composite_key(table.id.0, pk);
+        let root = pager.root_page_id();
+        if btree::search::search_u64(pager, root, composite)? != Some(ptr.pack()) {
+            continue;
+        }
+        let row = decode_row_lenient(&table.schema, &bytes[4..])?;
+        let key = cluster_key(&row[cluster.column as usize]).ok_or(InvError::Corruption {
+            context: "cluster.key",
+            details: format!("row {} has a non-integer cluster value", pk),
+        })?;
+        rows.push(PageRow {
+            composite,
+            key,
+            ptr: Some(ptr),
+            bytes,
+        });
+    }
+    Ok(rows)
+}
+
+/// Split sorted rows into page-sized `[start, end)` chunks.
+///
+/// An overflowing page is split roughly in half so both halves have room to
+/// grow; later chunks only appear when single rows are too large to pair up.
+fn chunk_rows(rows: &[PageRow]) -> Vec<(usize, usize)> {
+    let sizes: Vec<usize> = rows.iter().map(|r| 2 + r.bytes.len()).collect();
+    let total: usize = sizes.iter().sum();
+    if total <= ROW_PAGE_CAPACITY {
+        return vec![(0, rows.len())];
+    }
+    let half = total.div_ceil(2);
+    let mut chunks = Vec::new();
+    let (mut start, mut used) = (0usize, 0usize);
+    for (i, &size) in sizes.iter().enumerate() {
+        let full = used + size > ROW_PAGE_CAPACITY || (chunks.is_empty() && used >= half);
+        if used > 0 && full {
+            chunks.push((start, i));
+            start = i;
+            used = 0;
+        }
+        used += size;
+    }
+    chunks.push((start, rows.len()));
+    chunks
+}
+
+/// Decode a directory page into `(row page, fence key)` entries.
+pub(crate) fn decode_directory(buf: &[u8], page_count: u32) -> InvResult<Vec<(u32, u64)>> {
+    if buf.first() != Some(&META_PAGE_KIND) {
+        return Err(InvError::Corruption {
+            context: "cluster.directory",
+            details: format!("expected kind {} got {}", META_PAGE_KIND, buf.first().copied().unwrap_or(255)),
+        });
+    }
+    if &buf[layout::DIRECTORY_MAGIC..layout::DIRECTORY_MAGIC + 4] != DIRECTORY_MAGIC {
+        return Err(InvError::Corruption {
+            context: "cluster.directory",
+            details: "invalid directory magic".to_string(),
+        });
+    }
+    let version = layout::read_u16_le(buf, layout::DIRECTORY_VERSION);
+    if version != DIRECTORY_VERSION {
+        return Err(InvError::Unsupported {
+            feature: "cluster.directory_version",
+        });
+    }
+    let count = layout::read_u16_le(buf, layout::DIRECTORY_COUNT) as usize;
+    if count > MAX_DIRECTORY_ENTRIES {
+        return Err(InvError::Corruption {
+            context: "cluster.directory",
+            details: format!("entry count {} exceeds {}", count, MAX_DIRECTORY_ENTRIES),
+        });
+    }
+    let mut entries = Vec::with_capacity(count);
+    for i in 0..count {
+        let at = DIRECTORY_ENTRIES_START + i * DIRECTORY_ENTRY_SIZE;
+        let page = u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]]);
+        let mut fence = [0u8; 8];
+        fence.copy_from_slice(&buf[at + 4..at + 12]);
+        let fence = u64::from_le_bytes(fence);
+        if page == 0 || page >= page_count {
+            return Err(InvError::Corruption {
+                context: "cluster.directory",
+                details: format!("entry {} points at invalid page {}", i, page),
+            });
+        }
+        if entries.last().is_some_and(|&(_, prev)| prev > fence) {
+            return Err(InvError::Corruption {
+                context: "cluster.directory",
+                details: format!("entry {} fence out of order", i),
+            });
+        }
+        entries.push((page, fence));
+    }
+    Ok(entries)
+}
+
+pub(crate) fn read_directory(pager: &mut Pager, page_id: PageId) -> InvResult<Vec<(u32, u64)>> {
+    let page_count = pager.page_count();
+    decode_directory(pager.get_page(page_id)?.as_bytes(), page_count)
+}
+
+fn write_directory(pager: &mut Pager, page_id: PageId, entries: &[(u32, u64)]) -> InvResult<()> {
+    let page = pager.get_page_mut(page_id)?;
+    let buf = page.as_bytes_mut();
+    if buf.first() != Some(&META_PAGE_KIND) {
+        return Err(InvError::Corruption {
+            context: "cluster.directory",
+            details: "wrong page kind for directory".to_string(),
+        });
+    }
+    for b in &mut buf[layout::PAGE_PAYLOAD..] {
+        *b = 0;
+    }
+    buf[layout::DIRECTORY_MAGIC..layout::DIRECTORY_MAGIC + 4].copy_from_slice(DIRECTORY_MAGIC);
+    buf[layout::DIRECTORY_VERSION..layout::DIRECTORY_VERSION + 2].copy_from_slice(&DIRECTORY_VERSION.to_le_bytes());
+    buf[layout::DIRECTORY_COUNT..layout::DIRECTORY_COUNT + 2].copy_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (i, (page, fence)) in entries.iter().enumerate() {
+        let at = DIRECTORY_ENTRIES_START + i * DIRECTORY_ENTRY_SIZE;
+        buf[at..at + 4].copy_from_slice(&page.to_le_bytes());
+        buf[at + 4..at + 12].copy_from_slice(&fence.to_le_bytes());
+    }
+    Ok(())
+}
@@ -0,0 +1,201 @@
+//! Copying a database into a fresh, densely packed file.
+
+use crate::blob;
+use crate::btree;
+use crate::catalog;
+use crate::encoding;
+use crate::error::{InvError, InvResult};
+use crate::pager::Pager;
+use crate::pktree;
+use crate::row::encode_row;
+use crate::rowkey;
+use crate::rowstore::RowStore;
+use crate::table;
+
+/// Rows copied between two [`VacuumProgress`] reports counting rows.
+pub const PROGRESS_INTERVAL_ROWS: u64 = 1024;
+
+/// How far a [`crate::Db::vacuum_into`] has got.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VacuumProgress {
+    /// Tables whose rows have all been copied.
+    pub tables_done: u32,
+    /// Tables in the source database.
+    pub tables_total: u32,
+    /// Rows copied so far, over all tables.
+    pub rows_copied: u64,
+}
+
+/// Copy every table, live row and blob of `src` into the empty database `dst`.
+///
+/// Rows keep their pks and are re-encoded against the current schema, then
+/// appended in pk order so row pages fill up one after another. The index is
+/// built in one pass with [`btree::bulk_load_u64`]; only clustered tables,
+/// whose pages are laid out by cluster key, go through regular inserts.
+///
+/// `progress` is called after every table and every
+/// [`PROGRESS_INTERVAL_ROWS`] rows.
+pub(crate) fn compact_into(
+    dst: &mut Pager,
+    src: &mut Pager,
+    progress: &mut dyn FnMut(VacuumProgress),
+) -> InvResult<()> {
+    let src_cat = src.read_catalog()?;
+    let mut done = VacuumProgress {
+        tables_total: src_cat.tables.len() as u32,
+        ..VacuumProgress::default()
+    };
+    let mut cat = dst.read_catalog()?;
+    let mut entries = Vec::new();
+    let mut clustered = Vec::new();
+    for src_table in &src_cat.tables {
+        let id = table::create_table_like(dst, &mut cat, &src_table.name, src_table)?;
+        let rows = table::scan_all_rows(src, src_table, false)?;
+        let def = cat
+            .tables
+            .iter_mut()
+            .find(|t| t.id == id)
+            .expect("table was just created");
+        def.next_pk = src_table.next_pk;
+        def.next_pk_u64 = src_table.next_pk_u64;
+        if src_table.cluster.is_some() {
+            clustered.push((id, rows));
+            continue;
+        }
+        if def.uuid_root.is_some() {
+            let keys = rows
+                .iter()
+                .filter_map(|(pk, row)| Some((rowkey::row_key_prefix(&def.schema, row)?, *pk)))
+                .collect();
+            rowkey::index_keys(dst, def, keys)?;
+        }
+        let mut pk_entries = Vec::new();
+        for (pk, row) in rows {
+            let mut stored = if def.has_u64_pk() {
+                pk.to_le_bytes().to_vec()
+            } else {
+                (pk as u32).to_le_bytes().to_vec()
+            };
+            stored.extend_from_slice(&encode_row(&def.schema, &row)?);
+            let ptr = table::append_stored_row(dst, def, &stored)?;
+            if def.has_u64_pk() {
+                pk_entries.push((pk, ptr.pack()));
+            } else {
+                entries.push((table::composite_key(id.0, pk as u32), ptr.pack()));
+            }
+            note_row(&mut done, progress);
+        }
+        if def.has_u64_pk() {
+            def.pk_root = Some(pktree::bulk_load(dst, &pk_entries)?);
+        }
+        done.tables_done += 1;
+        progress(done);
+    }
+
+    entries.sort_unstable_by_key(|&(key, _)| key);
+    if let Some(w) = entries.windows(2).find(|w| w[0].0 == w[1].0) {
+        return Err(InvError::Corruption {
+            context: "compact.duplicate_key",
+            details: format!("btree key {} produced by two rows", w[0].0),
+        });
+    }
+    let root = dst.root_page_id();
+    let new_root = btree::bulk_load_u64(dst, root, &entries)?;
+    if new_root != root {
+        dst.set_root_page_id(new_root)?;
+    }
+
+    for (id, rows) in clustered {
+        let def = cat
+            .tables
+            .iter_mut()
+            .find(|t| t.id == id)
+            .expect("table was just created");
+        for (pk, row) in rows {
+            let encoded = encode_row(&def.schema, &row)?;
+            table::restore_row(dst, def, pk, &encoded)?;
+            note_row(&mut done, progress);
+        }
+        done.tables_done += 1;
+        progress(done);
+    }
+
+    let blobs = blob::scan_blobs(src)?;
+    if !blobs.is_empty() {
+        let mut entries = Vec::with_capacity(blobs.len());
+        for (key, bytes) in blobs {
+            let mut stored = key.to_le_bytes().to_vec();
+            stored.extend_from_slice(&bytes);
+            let (ptr, last) = RowStore::append_row(dst, cat.blob_last_row_page, &stored)?;
+            cat.blob_last_row_page = last;
+            entries.push((key, ptr.pack()));
+        }
+        let root = dst.allocate_btree_page()?;
+        cat.blob_root = btree::bulk_load_u64(dst, root, &entries)?.0;
+    }
+    dst.write_catalog(&cat)?;
+    dst.flush()
+}
+
+/// Count one copied row, reporting every [`PROGRESS_INTERVAL_ROWS`] rows.
+fn note_row(done: &mut VacuumProgress, progress: &mut dyn FnMut(VacuumProgress)) {
+    done.rows_copied += 1;
+    if done.rows_copied.is_multiple_of(PROGRESS_INTERVAL_ROWS) {
+        progress(*done);
+    }
+}
+
+/// Whether `a` and `b` hold the same tables, by name, schema and
+/// [`catalog::TableDef::kind`], and the same `(pk, row)` pairs in each,
+/// regardless of how either file is laid out.
+///
+/// Table order in the catalog and blobs are not compared.
+pub(crate) fn logically_equal(a: &mut Pager, b: &mut Pager) -> InvResult<bool> {
+    let mut a_tables = a.read_catalog()?.tables;
+    let mut b_tables = b.read_catalog()?.tables;
+    if a_tables.len() != b_tables.len() {
+        return Ok(false);
+    }
+    a_tables.sort_by(|x, y| x.name.cmp(&y.name));
+    b_tables.sort_by(|x, y| x.name.cmp(&y.name));
+    for (a_table, b_table) in a_tables.iter().zip(&b_tables) {
+        if a_table.name != b_table.name || a_table.schema != b_table.schema || a_table.kind() != b_table.kind() {
+            return Ok(false);
+        }
+        if table::scan_all_rows(a, a_table, false)? != table::scan_all_rows(b, b_table, false)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Hash of the logical contents of `pager`: the [`catalog::catalog_fingerprint`]
+/// followed by, per table, its name, its [`catalog::TableDef::kind`] and
+/// every `(pk, encoded row)` pair in pk order.
+///
+/// Tables are visited in name order like the fingerprint, and rows are
+/// re-encoded against the current schema, so databases that are
+/// [`logically_equal`] hash alike however their files are laid out. Blobs
+/// are not included.
+pub(crate) fn content_checksum(pager: &mut Pager) -> InvResult<u64> {
+    let cat = pager.read_catalog()?;
+    let mut hash = encoding::fnv1a64(&catalog::catalog_fingerprint(&cat)?.to_le_bytes());
+    let mut tables: Vec<_> = cat.tables.iter().collect();
+    tables.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut buf = Vec::new();
+    for table in tables {
+        buf.clear();
+        encoding::write_bytes(&mut buf, table.name.as_bytes());
+        let (flags, cluster_column) = table.kind();
+        encoding::write_var_u64(&mut buf, flags as u64);
+        encoding::write_var_u64(&mut buf, cluster_column.map_or(0, |column| column as u64 + 1));
+        hash = encoding::fnv1a64_extend(hash, &buf);
+        for (pk, row) in table::scan_all_rows(pager, table, false)? {
+            buf.clear();
+            encoding::write_u64_le(&mut buf, pk);
+            encoding::write_bytes(&mut buf, &encode_row(&table.schema, &row)?);
+            hash = encoding::fnv1a64_extend(hash, &buf);
+        }
+    }
+    Ok(hash)
+}
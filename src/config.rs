@@ -7,6 +7,27 @@ use crate::types::PageId;
 /// Logical page size in bytes for all database files (INV-1).
 pub const PAGE_SIZE: usize = 4096;
 
+/// Size of the per-page header every page but the file header starts with.
+pub const PAGE_HEADER_SIZE: usize = 16;
+
+/// Offset of the first row slot in a row page, past the page and row page
+/// headers.
+pub const ROW_PAGE_PAYLOAD_START: usize = 32;
+
+/// Bytes a page of `kind` offers its contents once its fixed headers are
+/// accounted for.
+///
+/// The file header and free pages hold only fixed fields, so they offer none.
+pub fn usable_payload(kind: crate::page::PageKind) -> usize {
+    use crate::page::PageKind;
+    match kind {
+        PageKind::Header | PageKind::Free => 0,
+        PageKind::Btree => PAGE_SIZE - crate::layout::NODE_ENTRIES,
+        PageKind::Meta => PAGE_SIZE - PAGE_HEADER_SIZE,
+        PageKind::Row => PAGE_SIZE - ROW_PAGE_PAYLOAD_START,
+    }
+}
+
 /// File magic header used to identify INVDB files (INV-4).
 pub const FILE_MAGIC: [u8; 8] = *b"INVDB\0\0\0";
 
@@ -27,12 +48,23 @@ pub const ROOT_PAGE_ID: PageId = PageId(1);
 /// Fixed page id for the catalog metadata page.
 pub const CATALOG_PAGE_ID: PageId = PageId(2);
 
+/// Maximum length in bytes of table and column names, enforced on both the
+/// encode and decode paths.
+pub const MAX_NAME_BYTES: usize = 64;
+
 /// Page kind for catalog/meta pages.
 pub const META_PAGE_KIND: u8 = 3;
 
 /// Page kind for row storage pages.
 pub const ROW_PAGE_KIND: u8 = 4;
 
+/// Row page version written to new row pages. Version 2 links the pages of
+/// a table through their next pointer; version 1 pages keep it zero.
+pub const ROW_PAGE_FORMAT_VERSION: u16 = 2;
+
+/// Page kind for pages released to the free list.
+pub const FREE_PAGE_KIND: u8 = 5;
+
 /// Validate a file format version against supported bounds.
 ///
 /// Returns [`InvError::InvalidVersion`] if the version is outside the
@@ -0,0 +1,223 @@
+//! Logical backup stream independent of page layout.
+//!
+//! Layout: magic `IDMP`, u16 version, then one record per table introduced by
+//! a `1` byte and terminated by a `0` byte. A table record holds its name,
+//! schema bytes (as [`crate::catalog::encode_schema`]), kind (plain, u64 pk,
+//! clustered followed by the cluster column, or generated keys, with the high
+//! bit set when rows carry checksums), next pk and row count, followed by
+//! `(pk, encoded row)` pairs. The key index of a table
+//! with generated keys is rebuilt from its rows on load. Integers are varints and
+//! byte strings are varint-length framed.
+
+use std::io::{Read, Write};
+
+use crate::catalog::{decode_schema, encode_schema};
+use crate::cluster;
+use crate::config::MAX_NAME_BYTES;
+use crate::encoding;
+use crate::error::{InvError, InvResult};
+use crate::pager::Pager;
+use crate::row::{decode_row_lenient, encode_row};
+use crate::rowkey;
+use crate::table;
+
+const DUMP_MAGIC: &[u8; 4] = b"IDMP";
+const DUMP_VERSION: u16 = 1;
+const MAX_SCHEMA_BYTES: usize = 64 * 1024;
+const MAX_ROW_BYTES: usize = 4 * 1024 * 1024;
+
+const KIND_PLAIN: u8 = 0;
+const KIND_PK_U64: u8 = 1;
+const KIND_CLUSTERED: u8 = 2;
+const KIND_UUID: u8 = 3;
+/// Or'd into the kind byte of a table whose rows carry checksums.
+const KIND_FLAG_ROW_CRC: u8 = 0x80;
+
+/// Write every table and row of the database to `out`.
+pub(crate) fn dump_all<W: Write>(pager: &mut Pager, out: &mut W) -> InvResult<()> {
+    let cat = pager.read_catalog()?;
+    let mut buf = Vec::new();
+    buf.extend_from_slice(DUMP_MAGIC);
+    buf.extend_from_slice(&DUMP_VERSION.to_le_bytes());
+    write_all(out, &mut buf)?;
+
+    for table in &cat.tables {
+        buf.push(1);
+        encoding::write_bytes(&mut buf, table.name.as_bytes());
+        encoding::write_bytes(&mut buf, &encode_schema(&table.schema)?);
+        let crc_flag = if table.row_crc { KIND_FLAG_ROW_CRC } else { 0 };
+        let rows: Vec<(u64, crate::row::Row)> = if table.has_u64_pk() {
+            buf.push(KIND_PK_U64 | crc_flag);
+            encoding::write_var_u64(&mut buf, table.next_pk_u64.unwrap_or(1));
+            table::scan_rows_u64(pager, table, false)?
+        } else {
+            match table.cluster {
+                Some(c) => {
+                    buf.push(KIND_CLUSTERED | crc_flag);
+                    encoding::write_var_u64(&mut buf, c.column as u64);
+                }
+                None if table.uuid_root.is_some() => buf.push(KIND_UUID | crc_flag),
+                None => buf.push(KIND_PLAIN | crc_flag),
+            }
+            encoding::write_var_u64(&mut buf, table.next_pk as u64);
+            table::scan_rows(pager, table, false)?
+                .into_iter()
+                .map(|(pk, row)| (pk as u64, row))
+                .collect()
+        };
+        encoding::write_var_u64(&mut buf, rows.len() as u64);
+        write_all(out, &mut buf)?;
+        for (pk, row) in rows {
+            encoding::write_var_u64(&mut buf, pk);
+            encoding::write_bytes(&mut buf, &encode_row(&table.schema, &row)?);
+            write_all(out, &mut buf)?;
+        }
+    }
+    buf.push(0);
+    write_all(out, &mut buf)
+}
+
+/// Recreate the tables and rows of a stream written by [`dump_all`], keeping pks.
+///
+/// The catalog is written once at the end; tables that already exist are
+/// rejected.
+pub(crate) fn load_all<R: Read>(pager: &mut Pager, input: &mut R) -> InvResult<()> {
+    let mut header = [0u8; 6];
+    read_exact(input, &mut header)?;
+    if &header[0..4] != DUMP_MAGIC {
+        return Err(InvError::Corruption {
+            context: "dump.magic",
+            details: "invalid dump magic".to_string(),
+        });
+    }
+    if u16::from_le_bytes([header[4], header[5]]) != DUMP_VERSION {
+        return Err(InvError::Unsupported {
+            feature: "dump.version",
+        });
+    }
+
+    let mut cat = pager.read_catalog()?;
+    loop {
+        match read_u8(input)? {
+            0 => break,
+            1 => {}
+            marker => {
+                return Err(InvError::Corruption {
+                    context: "dump.marker",
+                    details: format!("unexpected record marker {}", marker),
+                })
+            }
+        }
+        let name = String::from_utf8(read_framed(input, MAX_NAME_BYTES)?).map_err(|e| InvError::Corruption {
+            context: "dump.name",
+            details: e.to_string(),
+        })?;
+        let schema = decode_schema(&read_framed(input, MAX_SCHEMA_BYTES)?)?;
+        let kind = read_u8(input)?;
+        let row_crc = kind & KIND_FLAG_ROW_CRC != 0;
+        match kind & !KIND_FLAG_ROW_CRC {
+            KIND_PLAIN if row_crc => {
+                cat.create_table_with_row_crc(&name, &schema)?;
+            }
+            KIND_PLAIN => {
+                cat.create_table(&name, &schema)?;
+            }
+            KIND_PK_U64 => {
+                cat.create_table_u64_pk(&name, &schema)?;
+            }
+            KIND_CLUSTERED => {
+                let column = read_var(input)? as usize;
+                let column = schema.columns.get(column).ok_or(InvError::Corruption {
+                    context: "dump.cluster",
+                    details: format!("cluster column {} out of range", column),
+                })?;
+                cluster::create_clustered_table(pager, &mut cat, &name, &schema, &column.name)?;
+            }
+            KIND_UUID => {
+                rowkey::create_table_with_uuid(pager, &mut cat, &name, &schema)?;
+            }
+            kind => {
+                return Err(InvError::Corruption {
+                    context: "dump.kind",
+                    details: format!("unknown table kind {}", kind),
+                })
+            }
+        }
+        let next_pk = read_var(input)?;
+        let row_count = read_var(input)?;
+        let table = cat
+            .tables
+            .iter_mut()
+            .find(|t| t.name == name)
+            .expect("table was just created");
+        table.row_crc = row_crc;
+        let mut keys = Vec::new();
+        for _ in 0..row_count {
+            let pk = read_var(input)?;
+            let encoded = read_framed(input, MAX_ROW_BYTES)?;
+            table::restore_row(pager, table, pk, &encoded)?;
+            if table.uuid_root.is_some() {
+                if let Some(prefix) = rowkey::row_key_prefix(&table.schema, &decode_row_lenient(&table.schema, &encoded)?) {
+                    keys.push((prefix, pk));
+                }
+            }
+        }
+        if table.uuid_root.is_some() {
+            rowkey::index_keys(pager, table, keys)?;
+        }
+        if let Some(next) = table.next_pk_u64.as_mut() {
+            *next = (*next).max(next_pk);
+        } else {
+            table.next_pk = table.next_pk.max(u32::try_from(next_pk).unwrap_or(u32::MAX));
+        }
+    }
+    pager.write_catalog(&cat)
+}
+
+fn write_all<W: Write>(out: &mut W, buf: &mut Vec<u8>) -> InvResult<()> {
+    out.write_all(buf).map_err(|e| InvError::io("dump_write", e))?;
+    buf.clear();
+    Ok(())
+}
+
+fn read_exact<R: Read>(input: &mut R, buf: &mut [u8]) -> InvResult<()> {
+    input.read_exact(buf).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => InvError::Corruption {
+            context: "dump.eof",
+            details: "dump stream ended early".to_string(),
+        },
+        _ => InvError::io("dump_read", e),
+    })
+}
+
+fn read_u8<R: Read>(input: &mut R) -> InvResult<u8> {
+    let mut b = [0u8; 1];
+    read_exact(input, &mut b)?;
+    Ok(b[0])
+}
+
+fn read_var<R: Read>(input: &mut R) -> InvResult<u64> {
+    let mut raw = Vec::with_capacity(10);
+    loop {
+        let b = read_u8(input)?;
+        raw.push(b);
+        if b & 0x80 == 0 || raw.len() == 10 {
+            break;
+        }
+    }
+    let mut pos = 0;
+    encoding::read_var_u64(&raw, &mut pos)
+}
+
+fn read_framed<R: Read>(input: &mut R, limit: usize) -> InvResult<Vec<u8>> {
+    let len = read_var(input)? as usize;
+    if len > limit {
+        return Err(InvError::Corruption {
+            context: "dump.too_large",
+            details: format!("length {} exceeds {}", len, limit),
+        });
+    }
+    let mut buf = vec![0u8; len];
+    read_exact(input, &mut buf)?;
+    Ok(buf)
+}
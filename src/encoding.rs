@@ -90,6 +90,11 @@ pub fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
 
 /// Read a length-prefixed byte slice with a maximum length guard.
 pub fn read_bytes(input: &[u8], pos: &mut usize, max_len: usize) -> InvResult<Vec<u8>> {
+    read_bytes_ref(input, pos, max_len).map(|slice| slice.to_vec())
+}
+
+/// Borrow a length-prefixed byte slice with a maximum length guard, without copying.
+pub fn read_bytes_ref<'a>(input: &'a [u8], pos: &mut usize, max_len: usize) -> InvResult<&'a [u8]> {
     let len = read_var_u64(input, pos)? as usize;
     if len > max_len {
         return Err(InvError::Corruption {
@@ -105,7 +110,7 @@ pub fn read_bytes(input: &[u8], pos: &mut usize, max_len: usize) -> InvResult<Ve
     }
     let slice = &input[*pos..*pos + len];
     *pos += len;
-    Ok(slice.to_vec())
+    Ok(slice)
 }
 
 /// Write a UTF-8 string with length prefix.
@@ -121,3 +126,96 @@ pub fn read_string(input: &[u8], pos: &mut usize, max_len: usize) -> InvResult<S
         details: e.to_string(),
     })
 }
+
+/// Write a byte slice so that encoded outputs compare like the raw slices.
+///
+/// Each `0x00` is escaped as `0x00 0xFF` and the value is terminated by
+/// `0x00 0x00`; unlike [`write_bytes`], concatenations of encoded values sort
+/// component by component.
+pub fn write_order_preserving(out: &mut Vec<u8>, bytes: &[u8]) {
+    for &b in bytes {
+        out.push(b);
+        if b == 0 {
+            out.push(0xFF);
+        }
+    }
+    out.extend_from_slice(&[0, 0]);
+}
+
+/// Read a byte slice written by [`write_order_preserving`] with a maximum length guard.
+pub fn read_order_preserving(input: &[u8], pos: &mut usize, max_len: usize) -> InvResult<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let b = *input.get(*pos).ok_or(InvError::Corruption {
+            context: "encoding.ordered.eof",
+            details: "unexpected end of input before terminator".to_string(),
+        })?;
+        *pos += 1;
+        if b == 0 {
+            let next = *input.get(*pos).ok_or(InvError::Corruption {
+                context: "encoding.ordered.eof",
+                details: "unexpected end of input after 0x00".to_string(),
+            })?;
+            *pos += 1;
+            match next {
+                0x00 => return Ok(out),
+                0xFF => {}
+                other => {
+                    return Err(InvError::Corruption {
+                        context: "encoding.ordered.escape",
+                        details: format!("invalid escape byte {:#04x}", other),
+                    })
+                }
+            }
+        }
+        if out.len() == max_len {
+            return Err(InvError::Corruption {
+                context: "encoding.bytes.too_large",
+                details: format!("len exceeds max {}", max_len),
+            });
+        }
+        out.push(b);
+    }
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// CRC-32 (IEEE, as used by zlib) of `bytes`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc = CRC32_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// 64-bit FNV-1a hash of `bytes`.
+pub fn fnv1a64(bytes: &[u8]) -> u64 {
+    fnv1a64_extend(0xcbf2_9ce4_8422_2325, bytes)
+}
+
+/// Continue an FNV-1a hash of earlier input with `bytes`, so
+/// `fnv1a64_extend(fnv1a64(a), b) == fnv1a64(a ++ b)`.
+pub fn fnv1a64_extend(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
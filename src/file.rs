@@ -6,15 +6,60 @@ use std::path::{Path, PathBuf};
 
 use crate::config::PAGE_SIZE;
 use crate::error::{InvError, InvResult};
+use crate::options::RetryPolicy;
 use crate::types::PageId;
 
+/// Counters for the I/O calls issued against a database file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IoStats {
+    /// Number of page read calls.
+    pub reads: u64,
+    /// Number of write calls (a coalesced multi-page write counts once).
+    pub writes: u64,
+    /// Number of pages written across all write calls.
+    pub pages_written: u64,
+}
+
+/// Kind of page IO call, as seen by retry and fault injection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoOp {
+    /// A page read.
+    Read,
+    /// A single- or multi-page write.
+    Write,
+}
+
 /// Wrapper around the database file handle.
 #[derive(Debug)]
 pub struct DbFile {
     file: File,
     path: PathBuf,
+    stats: IoStats,
+    retry: RetryPolicy,
+    /// Staging buffer for every transfer once the handle bypasses the OS
+    /// cache, see [`DbFile::enable_direct_io`]; `None` for buffered IO.
+    direct: Option<Box<AlignedPage>>,
 }
 
+/// One page of memory aligned to the page size, as direct IO requires of
+/// its buffers.
+#[derive(Debug)]
+#[repr(C, align(4096))]
+struct AlignedPage([u8; PAGE_SIZE]);
+
+const _: () = assert!(std::mem::align_of::<AlignedPage>() == PAGE_SIZE);
+
+/// Open flag bypassing the OS page cache, where the platform has one.
+#[cfg(all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64")))]
+const DIRECT_IO_FLAG: Option<i32> = Some(0o40000);
+#[cfg(all(target_os = "linux", any(target_arch = "arm", target_arch = "aarch64")))]
+const DIRECT_IO_FLAG: Option<i32> = Some(0o200000);
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64")
+)))]
+const DIRECT_IO_FLAG: Option<i32> = None;
+
 impl DbFile {
     /// Create a new database file, truncating any existing file.
     pub fn create_new(path: &Path) -> InvResult<Self> {
@@ -28,6 +73,9 @@ impl DbFile {
         Ok(Self {
             file,
             path: path.to_path_buf(),
+            stats: IoStats::default(),
+            retry: RetryPolicy::none(),
+            direct: None,
         })
     }
 
@@ -41,6 +89,24 @@ impl DbFile {
         Ok(Self {
             file,
             path: path.to_path_buf(),
+            stats: IoStats::default(),
+            retry: RetryPolicy::none(),
+            direct: None,
+        })
+    }
+
+    /// Open an existing database file for read-only access.
+    pub fn open_read_only(path: &Path) -> InvResult<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .open(path)
+            .map_err(|e| InvError::io("open_read_only", e))?;
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            stats: IoStats::default(),
+            retry: RetryPolicy::none(),
+            direct: None,
         })
     }
 
@@ -49,17 +115,73 @@ impl DbFile {
         &self.path
     }
 
+    /// Retry page reads and writes that fail transiently according to `policy`.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+    }
+
+    /// Reopen the file so page IO bypasses the OS page cache (`O_DIRECT`),
+    /// returning whether it does.
+    ///
+    /// Falls back to buffered IO, returning false, on platforms without
+    /// direct IO and on file systems that refuse it. Transfers then go through
+    /// a page-aligned buffer, and multi-page writes are issued page by page.
+    /// # Errors
+    /// - [`InvError::Io`] if reopening fails for another reason.
+    pub fn enable_direct_io(&mut self, writable: bool) -> InvResult<bool> {
+        if self.direct.is_some() {
+            return Ok(true);
+        }
+        let Some(flag) = DIRECT_IO_FLAG else {
+            return Ok(false);
+        };
+        let mut options = OpenOptions::new();
+        options.read(true).write(writable);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::custom_flags(&mut options, flag);
+        match options.open(&self.path) {
+            Ok(file) => {
+                self.file = file;
+                self.direct = Some(Box::new(AlignedPage([0; PAGE_SIZE])));
+                Ok(true)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => Ok(false),
+            Err(e) => Err(InvError::io("open_direct", e)),
+        }
+    }
+
+    /// Whether page IO bypasses the OS page cache.
+    pub fn is_direct_io(&self) -> bool {
+        self.direct.is_some()
+    }
+
+    /// Return the I/O counters accumulated since the file was opened.
+    pub fn stats(&self) -> IoStats {
+        self.stats
+    }
+
     /// Read a full page into the provided buffer.
     pub fn read_page(&mut self, id: PageId, out: &mut [u8; PAGE_SIZE]) -> InvResult<()> {
+        self.stats.reads += 1;
         let offset = (id.0 as u64)
             .checked_mul(PAGE_SIZE as u64)
             .ok_or(InvError::Overflow {
                 context: "page offset overflow",
             })?;
-        self.file
-            .seek(SeekFrom::Start(offset))
-            .map_err(|e| InvError::io("seek_read", e))?;
-        match self.file.read_exact(out) {
+        let mut direct = self.direct.take();
+        let result = self.with_retry(IoOp::Read, |file| {
+            file.seek(SeekFrom::Start(offset))?;
+            match direct.as_deref_mut() {
+                Some(aligned) => {
+                    file.read_exact(&mut aligned.0)?;
+                    out.copy_from_slice(&aligned.0);
+                    Ok(())
+                }
+                None => file.read_exact(out),
+            }
+        });
+        self.direct = direct;
+        match result {
             Ok(()) => Ok(()),
             Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Err(InvError::Corruption {
                 context: "file.short_read",
@@ -71,17 +193,87 @@ impl DbFile {
 
     /// Write a full page from the provided buffer.
     pub fn write_page(&mut self, id: PageId, data: &[u8; PAGE_SIZE]) -> InvResult<()> {
-        let offset = (id.0 as u64)
+        self.write_pages(id, &[data])
+    }
+
+    /// Write consecutive pages starting at `start` with a single write call,
+    /// or one call per page under direct IO.
+    pub fn write_pages(&mut self, start: PageId, bufs: &[&[u8; PAGE_SIZE]]) -> InvResult<()> {
+        let offset = (start.0 as u64)
             .checked_mul(PAGE_SIZE as u64)
             .ok_or(InvError::Overflow {
                 context: "page offset overflow",
             })?;
+        if let Some(mut aligned) = self.direct.take() {
+            let mut result = Ok(());
+            for (i, buf) in bufs.iter().enumerate() {
+                aligned.0.copy_from_slice(&buf[..]);
+                result = self.with_retry(IoOp::Write, |file| {
+                    file.seek(SeekFrom::Start(offset + (i * PAGE_SIZE) as u64))?;
+                    file.write_all(&aligned.0)
+                });
+                self.stats.writes += 1;
+                self.stats.pages_written += 1;
+                if result.is_err() {
+                    break;
+                }
+            }
+            self.direct = Some(aligned);
+            return result.map_err(|e| InvError::io("write_page", e));
+        }
+        let mut joined = Vec::new();
+        let data: &[u8] = if let [single] = bufs {
+            &single[..]
+        } else {
+            joined.reserve(bufs.len() * PAGE_SIZE);
+            for buf in bufs {
+                joined.extend_from_slice(&buf[..]);
+            }
+            &joined
+        };
+        let result = self.with_retry(IoOp::Write, |file| {
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(data)
+        });
+        self.stats.writes += 1;
+        self.stats.pages_written += bufs.len() as u64;
+        result.map_err(|e| InvError::io("write_page", e))
+    }
+
+    /// Run one IO call, retrying transient failures per the retry policy.
+    fn with_retry<T>(
+        &mut self,
+        op: IoOp,
+        mut f: impl FnMut(&mut File) -> std::io::Result<T>,
+    ) -> std::io::Result<T> {
+        let mut retries = 0;
+        let mut delay = self.retry.backoff;
+        loop {
+            let result = match injected_fault(&self.path, op) {
+                Some(kind) => Err(std::io::Error::from(kind)),
+                None => f(&mut self.file),
+            };
+            match result {
+                Err(e) if self.retry.should_retry(e.kind(), retries) => {
+                    retries += 1;
+                    std::thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                }
+                other => return other,
+            }
+        }
+    }
+
+    /// Flush written data and metadata to stable storage.
+    pub fn sync(&mut self) -> InvResult<()> {
+        self.file.sync_all().map_err(|e| InvError::io("sync", e))
+    }
+
+    /// Shrink the file to its first `count` pages.
+    pub fn truncate_pages(&mut self, count: u32) -> InvResult<()> {
         self.file
-            .seek(SeekFrom::Start(offset))
-            .map_err(|e| InvError::io("seek_write", e))?;
-        self.file
-            .write_all(data)
-            .map_err(|e| InvError::io("write_page", e))
+            .set_len(count as u64 * PAGE_SIZE as u64)
+            .map_err(|e| InvError::io("truncate", e))
     }
 
     /// Return the current file length in bytes.
@@ -110,3 +302,13 @@ impl DbFile {
         Ok(pages as u32)
     }
 }
+
+#[cfg(any(test, feature = "testing"))]
+fn injected_fault(path: &Path, op: IoOp) -> Option<std::io::ErrorKind> {
+    crate::testing::take_io_fault(path, op)
+}
+
+#[cfg(not(any(test, feature = "testing")))]
+fn injected_fault(_path: &Path, _op: IoOp) -> Option<std::io::ErrorKind> {
+    None
+}
@@ -0,0 +1,128 @@
+//! Byte offsets of every on-disk field.
+//!
+//! Every multi-byte integer is stored little-endian, independent of the host.
+//! Offsets are absolute within a page, except the catalog fields, which are
+//! relative to the catalog payload starting at [`PAGE_PAYLOAD`].
+
+// Header page (page 0).
+
+/// `FILE_MAGIC`, 8 bytes.
+pub const HEADER_MAGIC: usize = 0;
+/// File format version, u16.
+pub const HEADER_VERSION: usize = 8;
+/// Page size the file was created with, u16.
+pub const HEADER_PAGE_SIZE: usize = 10;
+/// Root page of the index, u32.
+pub const HEADER_ROOT_PAGE_ID: usize = 12;
+/// Number of allocated pages, u32.
+pub const HEADER_PAGE_COUNT: usize = 16;
+/// Reserved u32, must be zero.
+pub const HEADER_RESERVED: usize = 20;
+/// First page of the free list, u32; 0 when no page is free.
+pub const HEADER_FREE_LIST: usize = 24;
+
+// Per-page header shared by every page except the file header.
+
+/// Page kind, u8.
+pub const PAGE_KIND: usize = 0;
+/// Page flags, u8, must be zero.
+pub const PAGE_FLAGS: usize = 1;
+/// Reserved u16, must be zero.
+pub const PAGE_RESERVED: usize = 2;
+/// Checksum slot, u32, must be zero.
+pub const PAGE_CRC32: usize = 4;
+/// Id of the page itself, u32.
+pub const PAGE_ID: usize = 8;
+/// Reserved u32, must be zero.
+pub const PAGE_RESERVED2: usize = 12;
+/// First byte after the per-page header.
+pub const PAGE_PAYLOAD: usize = crate::config::PAGE_HEADER_SIZE;
+
+// B-tree node pages.
+
+/// Node kind, u8: [`NODE_KIND_LEAF`] or [`NODE_KIND_INTERNAL`].
+pub const NODE_KIND: usize = PAGE_PAYLOAD;
+/// Node flags, u8, must be zero.
+pub const NODE_FLAGS: usize = PAGE_PAYLOAD + 1;
+/// Key count, u16.
+pub const NODE_NUM_KEYS: usize = PAGE_PAYLOAD + 2;
+/// Reserved u32, must be zero.
+pub const NODE_RESERVED: usize = PAGE_PAYLOAD + 4;
+/// Next leaf page id, u32 (reserved and zero in internal nodes).
+pub const NODE_NEXT_LEAF: usize = PAGE_PAYLOAD + 8;
+/// Reserved u32, must be zero.
+pub const NODE_RESERVED2: usize = PAGE_PAYLOAD + 12;
+/// Start of the keys (leaf) or children (internal) array.
+pub const NODE_ENTRIES: usize = PAGE_PAYLOAD + 16;
+
+/// [`NODE_KIND`] value of a leaf.
+pub const NODE_KIND_LEAF: u8 = 1;
+/// [`NODE_KIND`] value of an internal node.
+pub const NODE_KIND_INTERNAL: u8 = 2;
+
+// Row pages.
+
+/// `ROWP`, 4 bytes.
+pub const ROW_PAGE_MAGIC: usize = PAGE_PAYLOAD;
+/// Row page version, u16.
+pub const ROW_PAGE_VERSION: usize = PAGE_PAYLOAD + 4;
+/// Absolute offset of the first free byte, u16.
+pub const ROW_PAGE_FREE_OFFSET: usize = PAGE_PAYLOAD + 6;
+/// Next row page of the same table, u32; 0 ends the chain.
+pub const ROW_PAGE_NEXT: usize = PAGE_PAYLOAD + 8;
+/// Reserved u32, must be zero.
+pub const ROW_PAGE_RESERVED2: usize = PAGE_PAYLOAD + 12;
+/// First row slot: a u16 length followed by the stored bytes.
+pub const ROW_PAGE_DATA: usize = crate::config::ROW_PAGE_PAYLOAD_START;
+
+/// Slot length bit marking a slot whose bytes start with a CRC-32 of the rest.
+pub const ROW_SLOT_CRC_FLAG: u16 = 0x8000;
+/// Size of the CRC-32 leading a checksummed slot.
+pub const ROW_SLOT_CRC_LEN: usize = 4;
+
+// Free pages.
+
+/// Next page of the free list, u32; 0 ends the list.
+pub const FREE_PAGE_NEXT: usize = PAGE_PAYLOAD;
+
+// Cluster directory pages.
+
+/// `CLD1`, 4 bytes.
+pub const DIRECTORY_MAGIC: usize = PAGE_PAYLOAD;
+/// Directory version, u16.
+pub const DIRECTORY_VERSION: usize = PAGE_PAYLOAD + 4;
+/// Entry count, u16.
+pub const DIRECTORY_COUNT: usize = PAGE_PAYLOAD + 6;
+/// First `(page u32, fence u64)` entry.
+pub const DIRECTORY_ENTRIES: usize = PAGE_PAYLOAD + 16;
+
+// Catalog payload, relative to `PAGE_PAYLOAD` of the catalog page.
+
+/// `CAT1`, 4 bytes.
+pub const CATALOG_MAGIC: usize = 0;
+/// Catalog version, u16.
+pub const CATALOG_VERSION: usize = 4;
+/// Table count, u16.
+pub const CATALOG_ENTRY_COUNT: usize = 6;
+/// Next table id, u32.
+pub const CATALOG_NEXT_TABLE_ID: usize = 8;
+/// Reserved u32, must be zero.
+pub const CATALOG_RESERVED: usize = 12;
+/// First table entry.
+pub const CATALOG_ENTRIES: usize = 16;
+
+/// Read the little-endian u16 at `at`.
+pub(crate) fn read_u16_le(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+/// Read the little-endian u32 at `at`.
+pub(crate) fn read_u32_le(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+const _: () = assert!(HEADER_FREE_LIST + 4 <= crate::config::PAGE_SIZE);
+const _: () = assert!(PAGE_RESERVED2 + 4 == PAGE_PAYLOAD);
+const _: () = assert!(NODE_RESERVED2 + 4 == NODE_ENTRIES);
+const _: () = assert!(ROW_PAGE_RESERVED2 + 4 == ROW_PAGE_DATA);
+const _: () = assert!(ROW_PAGE_DATA == PAGE_PAYLOAD + 16);
@@ -1,7 +1,9 @@
 pub mod config;
+pub mod options;
 pub mod error;
 pub mod types;
 pub mod file;
+pub mod layout;
 pub mod page;
 pub mod pager;
 pub mod btree;
@@ -11,14 +13,31 @@ pub mod row;
 pub mod catalog;
 pub mod rowstore;
 pub mod table;
+pub mod cluster;
+pub mod merge;
+pub mod dump;
+pub mod compact;
+pub mod blob;
+pub mod rowkey;
+pub mod pktree;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 pub use error::{InvError, InvResult};
 pub use types::{DbVersion, Lsn, PageId, TxId};
 pub use schema::{Schema, Column, ColType};
-pub use row::{Row, Value, encode_row, decode_row};
-pub use catalog::{TableDef, TableId};
+pub use row::{CmpOp, QueryRow, Row, Value, value_tag, encode_index_key, infer_schema, encode_row, decode_row, decode_row_lenient, decode_row_reuse};
+pub use catalog::{ClusterDef, TableDef, TableId};
+pub use options::{DbOptions, LargeRowPolicy, RetryPolicy, ValidationLevel, WarmPolicy};
+pub use file::IoStats;
+pub use pager::{BtreeStats, FlushReport, PageAccess, PageAccessKind};
+pub use page::PageKind;
+pub use merge::{MergePolicy, MergeReport};
+pub use btree::{BtreeDump, InsertEstimate, NodeDump, NodeDumpKind, RepairReport};
+pub use table::{TableInfo, TableStorage};
+pub use compact::VacuumProgress;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::collections::HashSet;
 
 use crate::pager::Pager;
@@ -30,20 +49,38 @@ use crate::btree::node::Node;
 #[derive(Debug)]
 pub struct Db {
     pager: Pager,
+    options: DbOptions,
+    /// Whether every check of a full validation has run on this handle.
+    validation_complete: bool,
 }
 
 impl Db {
     /// Create a new database file at the given path.
     ///
+    /// The file is built next to `path` and renamed into place, so an
+    /// interrupted create leaves any existing file at `path` untouched.
+    ///
     /// Stable API: part of the supported surface.
     /// # Errors
     /// - [`InvError::InvalidArgument`] if the path is empty.
     /// - [`InvError::Unsupported`] if a WAL path is provided.
     pub fn create(path: impl AsRef<Path>) -> InvResult<Self> {
+        Self::create_with_options(path, DbOptions::default())
+    }
+
+    /// Create a new database file with explicit runtime options.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the path is empty.
+    /// - [`InvError::Unsupported`] if a WAL path is provided.
+    pub fn create_with_options(path: impl AsRef<Path>, options: DbOptions) -> InvResult<Self> {
         let path_buf = path.as_ref().to_path_buf();
         validate_path(&path_buf)?;
-        let pager = Pager::create(&path_buf)?;
-        Ok(Self { pager })
+        let mut pager = Pager::create(&path_buf)?;
+        pager.set_retry_policy(options.retry_policy.clone());
+        if options.direct_io {
+            pager.enable_direct_io()?;
+        }
+        Ok(Self { pager, options, validation_complete: true })
     }
 
     /// Open an existing database file.
@@ -53,11 +90,214 @@ impl Db {
     /// - [`InvError::InvalidArgument`] if the path is empty.
     /// - [`InvError::Unsupported`] if a WAL path is provided.
     pub fn open(path: impl AsRef<Path>) -> InvResult<Self> {
+        Self::open_with_options(path, DbOptions::default())
+    }
+
+    /// Open an existing database file with explicit runtime options.
+    ///
+    /// `options.validate_on_open` picks how much of the file is checked
+    /// before returning; see [`ValidationLevel`]. A full validation visits at
+    /// most `options.validate_page_budget` pages beyond the header, catalog
+    /// and root, skipping the checks it cannot afford; see
+    /// [`Db::was_validation_complete`]. Index pages are then read into the
+    /// cache per `options.warm_on_open`. A full validation first moves the
+    /// rows of u64-pk tables written before those tables had an index of
+    /// their own out of the shared btree into one; lighter levels leave that
+    /// to [`Db::check_integrity`], and such tables report
+    /// [`InvError::Unsupported`] until then.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the path is empty.
+    /// - [`InvError::Unsupported`] if a WAL path is provided.
+    /// - [`InvError::Unsupported`] if the reserved header field is non-zero,
+    ///   unless `options.ignore_unknown_header_reserved` is set.
+    /// - [`InvError::Corruption`] if a node read while warming does not decode.
+    pub fn open_with_options(path: impl AsRef<Path>, options: DbOptions) -> InvResult<Self> {
         let path_buf = path.as_ref().to_path_buf();
         validate_path(&path_buf)?;
-        let mut pager = Pager::open(&path_buf)?;
-        validate_database(&mut pager)?;
-        Ok(Self { pager })
+        let mut pager = if options.ignore_unknown_header_reserved {
+            Pager::open_allowing_header_reserved(&path_buf)?
+        } else {
+            Pager::open(&path_buf)?
+        };
+        pager.set_retry_policy(options.retry_policy.clone());
+        if options.direct_io {
+            pager.enable_direct_io()?;
+        }
+        let validation_complete = validate_on_open(&mut pager, &options)?;
+        warm_cache(&mut pager, options.warm_on_open)?;
+        Ok(Self { pager, options, validation_complete })
+    }
+
+    /// Flush, then reload the header and catalog from disk without closing
+    /// the file.
+    ///
+    /// Picks up changes other handles wrote to the same file, such as a new
+    /// root or page count; the cache is dropped and the file is validated
+    /// again per `options.validate_on_open` and warmed per
+    /// `options.warm_on_open`. Unflushed changes of this handle
+    /// are written first and override the other handles' header. A tool that replaces the file
+    /// by renaming another over its path is not seen: reopen by path instead.
+    /// # Errors
+    /// - [`InvError::Corruption`] if the reloaded file fails validation.
+    pub fn reopen(&mut self) -> InvResult<()> {
+        self.pager.reload()?;
+        self.validation_complete = validate_on_open(&mut self.pager, &self.options)?;
+        warm_cache(&mut self.pager, self.options.warm_on_open)?;
+        Ok(())
+    }
+
+    /// Whether every check of a full validation has run on this handle.
+    ///
+    /// False after opening with a [`ValidationLevel`] below `Full` or with a
+    /// `validate_page_budget` too small for the file, until
+    /// [`Db::check_integrity`] succeeds.
+    pub fn was_validation_complete(&self) -> bool {
+        self.validation_complete
+    }
+
+    /// Run the full structural validation that `open` performs by default.
+    ///
+    /// Meant for handles opened with a lighter [`ValidationLevel`]. Moves
+    /// u64-pk tables still indexed in the shared btree into their own index
+    /// first, as a full validation on open does.
+    /// # Errors
+    /// - [`InvError::Corruption`] describing the first inconsistency found.
+    pub fn check_integrity(&mut self) -> InvResult<()> {
+        crate::table::migrate_shared_u64_pks(&mut self.pager)?;
+        validate_database(&mut self.pager)?;
+        self.validation_complete = true;
+        Ok(())
+    }
+
+    /// Copy every table of the database at `src` into this one.
+    ///
+    /// `src` is opened read-only. Missing tables are created; tables that
+    /// already exist with the same schema get the source rows appended under
+    /// fresh pks or are skipped per `conflict`; tables whose schemas or kinds
+    /// differ are listed in [`MergeReport::conflicts`] and left alone. All
+    /// source rows are read and checked before any is written, so an error
+    /// reading `src` or a clashing generated key leaves this database as it
+    /// was; an error while writing the copied rows does not roll back.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the path is empty.
+    /// - [`InvError::Unsupported`] with feature `table.pk_u64_shared_index` if
+    ///   `src` holds a u64-pk table still indexed in the shared btree; opening
+    ///   it once with [`Db::open`] moves that table to its own index.
+    /// - Any error opening or validating `src`.
+    pub fn merge_from(&mut self, src: impl AsRef<Path>, conflict: MergePolicy) -> InvResult<MergeReport> {
+        let src_path = src.as_ref();
+        validate_path(src_path)?;
+        let mut src_pager = Pager::open_read_only(src_path)?;
+        src_pager.set_retry_policy(self.options.retry_policy.clone());
+        validate_database(&mut src_pager)?;
+        crate::merge::merge_into(&mut self.pager, &mut src_pager, conflict)
+    }
+
+    /// Write a compacted copy of this database to a new file at `dest`.
+    ///
+    /// Live rows are copied in pk order into densely packed row pages and the
+    /// index is bulk-loaded, so the copy carries no dead space. Tables keep
+    /// their names, schemas and pks. The copy is built in a temporary file in
+    /// [`DbOptions::scratch_dir`] and renamed to `dest` once complete, so
+    /// `dest` never holds a partial copy.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the path is empty or already exists.
+    /// - [`InvError::Io`] if the temporary file cannot be created or moved to
+    ///   `dest`.
+    pub fn compact_to(&mut self, dest: impl AsRef<Path>) -> InvResult<()> {
+        self.vacuum_into(dest, |_| {})
+    }
+
+    /// [`Db::compact_to`], calling `progress` after each table is copied and
+    /// every [`compact::PROGRESS_INTERVAL_ROWS`] rows copied.
+    ///
+    /// Counts in successive reports never decrease; the last report has
+    /// every table done. Blobs are copied after it.
+    /// # Errors
+    /// - As [`Db::compact_to`].
+    pub fn vacuum_into<F: FnMut(VacuumProgress)>(&mut self, dest: impl AsRef<Path>, mut progress: F) -> InvResult<()> {
+        let dest_path = dest.as_ref();
+        validate_path(dest_path)?;
+        if dest_path.exists() {
+            return Err(InvError::InvalidArgument {
+                name: "dest",
+                details: format!("{} already exists", dest_path.display()),
+            });
+        }
+        let scratch = self.scratch_path(dest_path);
+        let result = Pager::create(&scratch).and_then(|mut dest_pager| {
+            dest_pager.set_retry_policy(self.options.retry_policy.clone());
+            crate::compact::compact_into(&mut dest_pager, &mut self.pager, &mut progress)
+        });
+        if let Err(e) = result.and_then(|()| move_into_place(&scratch, dest_path)) {
+            let _ = std::fs::remove_file(&scratch);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// A fresh path in the scratch directory for an intermediate copy of `dest`.
+    fn scratch_path(&self, dest: &Path) -> PathBuf {
+        static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+        let dir = match &self.options.scratch_dir {
+            Some(dir) => dir.clone(),
+            None => self.pager.path().parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        let stem = dest.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        dir.join(format!(".{}.{}.{}.tmp", stem, std::process::id(), n))
+    }
+
+    /// Whether `other` holds the same tables, by name and schema, and the same
+    /// `(pk, row)` pairs in each.
+    ///
+    /// Physical layout is ignored, so a database equals its [`Db::compact_to`]
+    /// copy. Blobs are not compared.
+    pub fn logically_equals(&mut self, other: &mut Db) -> InvResult<bool> {
+        crate::compact::logically_equal(&mut self.pager, &mut other.pager)
+    }
+
+    /// Hash every table's name and schema into one value, stable across
+    /// handles and unaffected by the order tables were created in.
+    ///
+    /// See [`crate::catalog::catalog_fingerprint`].
+    pub fn catalog_fingerprint(&mut self) -> InvResult<u64> {
+        let cat = self.pager.read_catalog()?;
+        crate::catalog::catalog_fingerprint(&cat)
+    }
+
+    /// Hash the logical contents of the database: its tables' names and
+    /// schemas and every row's pk and values.
+    ///
+    /// The logical counterpart to comparing files byte for byte: databases
+    /// for which [`Db::logically_equals`] holds produce the same checksum,
+    /// whatever their fragmentation or page order. Blobs are not hashed.
+    pub fn content_checksum(&mut self) -> InvResult<u64> {
+        crate::compact::content_checksum(&mut self.pager)
+    }
+
+    /// Write a logical backup of every table and row to `out`.
+    ///
+    /// The stream records names, schemas and encoded rows with their pks, so
+    /// it does not depend on page layout and can be replayed with
+    /// [`Db::load_all`].
+    pub fn dump_all<W: std::io::Write>(&mut self, out: &mut W) -> InvResult<()> {
+        crate::dump::dump_all(&mut self.pager, out)
+    }
+
+    /// Recreate the tables and rows of a stream written by [`Db::dump_all`].
+    ///
+    /// Rows keep their original pks. Intended for a fresh database.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if a table in the stream already exists.
+    /// - [`InvError::Corruption`] if the stream is malformed or truncated.
+    pub fn load_all<R: std::io::Read>(&mut self, input: &mut R) -> InvResult<()> {
+        crate::dump::load_all(&mut self.pager, input)
+    }
+
+    /// Return the runtime options this handle was created or opened with.
+    pub fn options(&self) -> &DbOptions {
+        &self.options
     }
 
     /// Return the stored file format version.
@@ -70,6 +310,42 @@ impl Db {
         self.pager.path()
     }
 
+    /// Return the I/O counters accumulated by this handle.
+    pub fn io_stats(&self) -> IoStats {
+        self.pager.io_stats()
+    }
+
+    /// Whether this handle reads and writes pages with direct IO, as asked
+    /// for by `options.direct_io` and supported by the platform and file.
+    pub fn is_direct_io(&self) -> bool {
+        self.pager.is_direct_io()
+    }
+
+    /// The non-zero reserved header field this handle ignored when opening
+    /// or last reopening the file, as allowed by
+    /// `options.ignore_unknown_header_reserved`.
+    pub fn ignored_header_reserved(&self) -> Option<u32> {
+        self.pager.ignored_header_reserved()
+    }
+
+    /// Record every later read, modification and file IO of page `id`.
+    ///
+    /// A debugging aid for corruption that shows up on one page: each entry
+    /// of [`Db::take_page_log`] names the source location that asked for it.
+    pub fn watch_page(&mut self, id: PageId) {
+        self.pager.watch_page(id);
+    }
+
+    /// Return and clear the accesses recorded for watched pages.
+    pub fn take_page_log(&mut self) -> Vec<PageAccess> {
+        self.pager.take_page_log()
+    }
+
+    /// Return the btree split and merge counters accumulated by this handle.
+    pub fn btree_stats(&self) -> BtreeStats {
+        self.pager.btree_stats()
+    }
+
     /// Flush cached pages to disk.
     ///
     /// Stable API: part of the supported surface.
@@ -77,6 +353,29 @@ impl Db {
         self.pager.flush()
     }
 
+    /// Flush, then report the pages written along with their checksums as
+    /// read back from disk.
+    ///
+    /// The pages listed are exactly those modified since the last flush; see
+    /// [`FlushReport`].
+    /// # Errors
+    /// - [`InvError::Corruption`] if a page read back differs from what was
+    ///   written.
+    pub fn flush_reporting(&mut self) -> InvResult<FlushReport> {
+        self.pager.flush_reporting()
+    }
+
+    /// Persist one table's dirty pages and catalog entry, leaving other tables'
+    /// unflushed changes cached.
+    ///
+    /// Falls back to a full [`Db::flush`] when the shared btree was split since
+    /// the last flush.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not exist.
+    pub fn flush_table(&mut self, table_name: &str) -> InvResult<()> {
+        crate::table::flush_table(&mut self.pager, table_name)
+    }
+
     /// Read-only lookup of a u32 key returning an associated u64 value if present.
     ///
     /// Stable API: part of the supported surface.
@@ -97,649 +396,4271 @@ impl Db {
         Ok(())
     }
 
-    /// Create a new table and persist catalog.
+    /// Estimate the leaf and internal splits, and the pages they allocate,
+    /// that [`Db::put_u64`] would cause for `count` keys above every key in
+    /// the raw btree.
     ///
-    /// Stable API: part of the supported surface.
-    pub fn create_table(&mut self, name: &str, schema: &Schema) -> InvResult<TableId> {
-        let mut cat = self.pager.read_catalog()?;
-        let id = cat.create_table(name, schema)?;
-        self.pager.write_catalog(&cat)?;
-        Ok(id)
+    /// A planning aid for choosing between incremental inserts and a bulk
+    /// load such as [`Db::put_u64_batch`]; only the right edge of the tree
+    /// is read. Keys landing elsewhere cause fewer splits than estimated.
+    /// # Errors
+    /// - [`InvError::Corruption`] if the right edge of the tree is damaged.
+    pub fn estimate_inserts(&mut self, count: usize) -> InvResult<InsertEstimate> {
+        let root = self.pager.root_page_id();
+        crate::btree::estimate_appends(&mut self.pager, root, count as u64)
     }
 
-    /// Fetch a table definition by name.
+    /// Remove a raw mapping, returning whether the key was present.
     ///
-    /// Stable API: part of the supported surface.
-    pub fn get_table(&mut self, name: &str) -> InvResult<Option<TableDef>> {
-        let cat = self.pager.read_catalog()?;
-        Ok(cat.get_by_name(name).cloned())
+    /// Nodes left below `options.min_fill_ratio` are merged as in
+    /// [`Db::delete_row`]; with a ratio of `0.0` the key is only dropped from
+    /// its leaf, and deleting the last key leaves an empty root leaf.
+    /// # Errors
+    /// - [`InvError::Corruption`] if the tree structure is damaged.
+    pub fn delete_u64(&mut self, key: u32) -> InvResult<bool> {
+        let root = self.pager.root_page_id();
+        let (new_root, removed) =
+            crate::btree::delete::delete_u64_rebalancing(&mut self.pager, root, key, self.options.min_fill_ratio)?;
+        if new_root != root {
+            self.pager.set_root_page_id(new_root)?;
+        }
+        self.pager.forget_key_owner(key);
+        Ok(removed)
     }
 
-    /// List all table definitions.
+    /// Remove every raw mapping with a key in `[lo, hi)`, returning the
+    /// number removed.
     ///
-    /// Stable API: part of the supported surface.
-    pub fn list_tables(&mut self) -> InvResult<Vec<TableDef>> {
-        let cat = self.pager.read_catalog()?;
-        Ok(cat.list())
+    /// Leaves wholly inside the range are unlinked and moved to the free
+    /// list along with internal nodes left with a single child; the leaves
+    /// at `lo` and `hi` keep their keys outside the range. Returns 0 when
+    /// `lo >= hi`.
+    /// # Errors
+    /// - [`InvError::Corruption`] if the tree structure is damaged.
+    pub fn delete_u64_range(&mut self, lo: u32, hi: u32) -> InvResult<u64> {
+        let root = self.pager.root_page_id();
+        let (new_root, removed, dropped) = crate::btree::delete_range_u64(&mut self.pager, root, lo, hi)?;
+        if new_root != root {
+            self.pager.set_root_page_id(new_root)?;
+        }
+        for &page in &dropped {
+            self.pager.free_page(page)?;
+        }
+        Ok(removed)
     }
 
-    /// Insert a row into a table, returning the allocated primary key.
+    /// Look up a batch of raw keys, returning one result per key in input order.
     ///
-    /// Stable API: part of the supported surface.
-    pub fn insert_row(&mut self, table_name: &str, row: &Row) -> InvResult<u32> {
-        let mut cat = self.pager.read_catalog()?;
-        let pk = crate::table::insert_row(&mut self.pager, &mut cat, table_name, row)?;
-        self.pager.write_catalog(&cat)?;
-        Ok(pk)
+    /// Sorted batches are answered by one pass over the leaf chain instead of
+    /// a descent per key; unsorted batches fall back to individual lookups.
+    pub fn get_many_u64(&mut self, keys: &[u32]) -> InvResult<Vec<Option<u64>>> {
+        let root = self.pager.root_page_id();
+        crate::btree::search::search_many_u64(&mut self.pager, root, keys)
     }
 
-    /// Fetch a row by primary key.
+    /// Insert a batch of raw mappings, appending along the right edge of the tree
+    /// when the keys are strictly increasing and all above the current maximum.
     ///
-    /// Stable API: part of the supported surface.
-    pub fn get_row_by_pk(&mut self, table_name: &str, pk: u32) -> InvResult<Option<Row>> {
-        let cat = self.pager.read_catalog()?;
-        crate::table::get_row_by_pk(&mut self.pager, &cat, table_name, pk)
+    /// Any other batch is applied as one [`Db::put_u64`] per entry, so later
+    /// entries overwrite earlier ones with the same key.
+    pub fn put_u64_batch(&mut self, sorted: &[(u32, u64)]) -> InvResult<()> {
+        let root = self.pager.root_page_id();
+        let new_root = crate::btree::append_u64(&mut self.pager, root, sorted)?;
+        if new_root != root {
+            self.pager.set_root_page_id(new_root)?;
+        }
+        Ok(())
     }
 
-    /// Scan rows in primary key order (naive implementation).
+    /// Insert or overwrite a batch of raw mappings in any key order.
     ///
-    /// Stable API: part of the supported surface.
-    pub fn scan_table(&mut self, table_name: &str) -> InvResult<Vec<(u32, Row)>> {
-        let cat = self.pager.read_catalog()?;
-        crate::table::scan_table(&mut self.pager, &cat, table_name)
+    /// Each entry is a regular [`Db::put_u64`], but the header is written
+    /// once at the end of the batch instead of at every root split.
+    pub fn put_u64_batch_unsorted(&mut self, pairs: &[(u32, u64)]) -> InvResult<()> {
+        self.pager.begin_header_deferral();
+        let result = pairs.iter().try_for_each(|&(key, value)| self.put_u64(key, value));
+        let ended = self.pager.end_header_deferral();
+        result.and(ended)
     }
-}
 
-/// Validate caller-provided path arguments for Db operations.
-fn validate_path(path: &Path) -> InvResult<()> {
-    if path.as_os_str().is_empty() {
-        return Err(InvError::InvalidArgument {
-            name: "path",
-            details: "path must not be empty".to_string(),
-        });
+    /// Iterate over every raw key/value pair in ascending key order.
+    pub fn iter_u64(&mut self) -> InvResult<impl Iterator<Item = InvResult<(u32, u64)>> + '_> {
+        self.iter_u64_from(0)
     }
 
-    if path.extension().map_or(false, |ext| ext == "wal") {
-        return Err(InvError::Unsupported { feature: "wal" });
+    /// Iterate over raw key/value pairs with keys `>= start` in ascending order.
+    ///
+    /// Descends straight to the leaf covering `start`, so callers can resume a
+    /// previous iteration from the key after the last one they saw.
+    pub fn iter_u64_from(&mut self, start: u32) -> InvResult<impl Iterator<Item = InvResult<(u32, u64)>> + '_> {
+        let root = self.pager.root_page_id();
+        crate::btree::search::iter_from(&mut self.pager, root, start)
     }
 
-    Ok(())
-}
+    /// Visit every raw key/value pair in ascending key order without
+    /// collecting them.
+    ///
+    /// An error returned by `f` stops the walk and is returned as is.
+    pub fn for_each_u64<F>(&mut self, mut f: F) -> InvResult<()>
+    where
+        F: FnMut(u32, u64) -> InvResult<()>,
+    {
+        let root = self.pager.root_page_id();
+        crate::btree::search::walk_from(&mut self.pager, root, 0, |key, value| {
+            f(key, value)?;
+            Ok(true)
+        })
+    }
 
-fn validate_database(pager: &mut Pager) -> InvResult<()> {
-    let page_count = pager.page_count();
-    if page_count < 3 {
-        return Err(InvError::Corruption {
-            context: "catalog.missing",
-            details: format!("page_count {} too small", page_count),
-        });
+    /// List the nodes a lookup of `key` visits, root first, with the child
+    /// index taken at each internal node and the key index in the final leaf.
+    ///
+    /// The leaf index is where `key` is or would be inserted. Meant for
+    /// triaging unexpected lookup results.
+    pub fn explain_search(&mut self, key: u32) -> InvResult<Vec<(PageId, crate::btree::node::NodeKind, usize)>> {
+        let root = self.pager.root_page_id();
+        crate::btree::search::explain_u64(&mut self.pager, root, key)
     }
-    let root = pager.root_page_id();
-    if root.0 == 0 || root.0 >= page_count {
-        return Err(InvError::Corruption {
-            context: "header.root_page_id",
-            details: format!("root {} invalid for page_count {}", root.0, page_count),
-        });
+
+    /// Count raw keys in the half-open range `[lo, hi)`.
+    ///
+    /// Walks the leaf chain from the leaf covering `lo` without collecting
+    /// values; returns 0 when `lo >= hi`.
+    pub fn count_u64_range(&mut self, lo: u32, hi: u32) -> InvResult<u64> {
+        let root = self.pager.root_page_id();
+        crate::btree::search::count_range_u64(&mut self.pager, root, lo, hi)
     }
 
-    // Root btree validation
-    {
-        let root_page = pager.get_page(root)?;
-        let buf = root_page.as_bytes();
-        if buf.get(0) != Some(&2) {
-            return Err(InvError::Corruption {
-                context: "btree.page_kind",
-                details: format!("expected 2 got {}", buf.get(0).copied().unwrap_or(255)),
-            });
-        }
-        root_page.validate_header()?;
-        Node::decode(root_page, page_count)?;
+    /// Count the entries of the raw btree.
+    ///
+    /// Table rows live in the same btree, so their keys are included.
+    /// # Errors
+    /// - [`InvError::Corruption`] if the leaf chain loops back on itself.
+    pub fn u64_key_count(&mut self) -> InvResult<u64> {
+        let root = self.pager.root_page_id();
+        crate::btree::search::count_keys_u64(&mut self.pager, root)
     }
 
-    let cat = pager.read_catalog()?;
-    let mut ids = HashSet::new();
-    let mut names = HashSet::new();
-    for table in &cat.tables {
-        if table.id.0 == 0 {
-            return Err(InvError::Corruption {
-                context: "catalog.table_id",
-                details: "table id is 0".to_string(),
-            });
-        }
-        if !ids.insert(table.id.0) || !names.insert(table.name.clone()) {
-            return Err(InvError::Corruption {
-                context: "catalog.duplicate",
-                details: "duplicate table id or name".to_string(),
-            });
-        }
-        if table.next_pk < 1 {
-            return Err(InvError::Corruption {
-                context: "catalog.next_pk",
-                details: format!("invalid next_pk {}", table.next_pk),
-            });
-        }
-        if table.last_row_page != 0 && table.last_row_page >= page_count {
-            return Err(InvError::Corruption {
-                context: "catalog.last_row_page",
-                details: format!(
-                    "last_row_page {} >= page_count {}",
-                    table.last_row_page, page_count
-                ),
-            });
+    /// Rebuild a table's missing index entries from its row pages, returning
+    /// the number of entries restored.
+    ///
+    /// Meant for recovery when btree entries were lost but row pages are
+    /// intact. Rows deleted since their page was last compacted come back as
+    /// well, since their slots cannot be told apart from live ones.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not exist.
+    /// - [`InvError::Unsupported`] for clustered and u64-pk tables.
+    pub fn rebuild_table_index(&mut self, table_name: &str) -> InvResult<u64> {
+        let cat = self.pager.read_catalog()?;
+        let rebuilt = crate::table::rebuild_table_index(&mut self.pager, &cat, table_name)?;
+        self.pager.flush()?;
+        Ok(rebuilt)
+    }
+
+    /// Drop empty leaves and single-child internal nodes from the raw btree,
+    /// returning the number of pages moved to the free list.
+    ///
+    /// Deletes never merge nodes, so delete-heavy workloads leave empty leaves
+    /// behind; this unlinks them, relinks the leaf chain and flushes. Freed
+    /// pages are reused by later allocations.
+    /// # Errors
+    /// - [`InvError::Corruption`] if the tree structure is damaged.
+    pub fn compact_btree(&mut self) -> InvResult<u64> {
+        let root = self.pager.root_page_id();
+        let (new_root, dropped) = crate::btree::prune_empty_leaves(&mut self.pager, root)?;
+        if new_root != root {
+            self.pager.set_root_page_id(new_root)?;
         }
-        if table.schema.is_empty() {
-            return Err(InvError::Corruption {
-                context: "catalog.schema",
-                details: "schema empty".to_string(),
-            });
+        for &page in &dropped {
+            self.pager.free_page(page)?;
         }
+        self.pager.flush()?;
+        Ok(dropped.len() as u64)
     }
 
-    // Row page reachability (best-effort)
-    for table in &cat.tables {
-        if table.last_row_page != 0 {
-            let page = pager.get_page(PageId(table.last_row_page))?;
-            let buf = page.as_bytes();
-            if buf.get(0) != Some(&config::ROW_PAGE_KIND) {
-                return Err(InvError::Corruption {
-                    context: "rowpage.kind",
-                    details: format!(
-                        "expected {} got {}",
-                        config::ROW_PAGE_KIND,
-                        buf.get(0).copied().unwrap_or(255)
-                    ),
-                });
-            }
-            page.validate_header()?;
-            crate::rowstore::validate_row_page_header(buf)?;
+    /// Pages allocated in the file but unreachable from the header, catalog,
+    /// btrees, table row chains, blobs and free list, in ascending order.
+    ///
+    /// Such pages are left behind by crashes or bugs and waste space until
+    /// [`Db::compact_to`] copies the database. Nothing is changed. Row pages
+    /// whose every row was replaced or deleted count as orphans in tables
+    /// without a row page chain and in the blob store, since nothing points
+    /// at them any more.
+    /// # Errors
+    /// - [`InvError::Corruption`] if a btree, row chain or the free list is
+    ///   damaged.
+    pub fn find_orphan_pages(&mut self) -> InvResult<Vec<PageId>> {
+        let cat = self.pager.read_catalog()?;
+        crate::table::orphan_pages(&mut self.pager, &cat)
+    }
+
+    /// Move every page [`Db::find_orphan_pages`] reports to the free list,
+    /// returning the number reclaimed, then flush.
+    ///
+    /// All roots are walked before any page is freed, so a damaged structure
+    /// fails the call without reclaiming anything. Reclaimed pages are
+    /// handed out by later allocations, most recently freed first.
+    /// # Errors
+    /// - As [`Db::find_orphan_pages`].
+    /// - [`InvError::Unsupported`] on a read-only handle with orphans to free.
+    pub fn reclaim_orphans(&mut self) -> InvResult<u64> {
+        let orphans = self.find_orphan_pages()?;
+        for &page in &orphans {
+            self.pager.free_page(page)?;
         }
+        self.pager.flush()?;
+        Ok(orphans.len() as u64)
     }
 
-    validate_leaf_chain(pager, root, page_count)?;
+    /// The page id the next allocation will hand out: the head of the free
+    /// list, or a fresh page past the end of the file.
+    pub fn next_allocation_id(&self) -> PageId {
+        match self.pager.free_list_head() {
+            0 => PageId(self.pager.page_count()),
+            head => PageId(head),
+        }
+    }
 
-    Ok(())
+    /// Rebuild the btree leaf chain so every leaf links to its in-order successor.
+    ///
+    /// Fixes broken or cyclic `next_leaf` pointers left by corruption; keys
+    /// and values are not touched.
+    /// # Errors
+    /// - [`InvError::Corruption`] if the tree structure itself (not just the
+    ///   chain) is damaged.
+    pub fn repair_leaf_chain(&mut self) -> InvResult<RepairReport> {
+        let root = self.pager.root_page_id();
+        crate::btree::repair_leaf_chain(&mut self.pager, root)
+    }
+
+    /// Dump the btree's structure: separator keys and child ids of internal
+    /// nodes, key counts of leaves.
+    ///
+    /// Expands at most [`crate::btree::inspect::DEFAULT_DUMP_DEPTH`] levels
+    /// and [`crate::btree::inspect::DEFAULT_DUMP_NODES`] nodes; see
+    /// [`Db::dump_btree_limited`].
+    /// # Errors
+    /// - [`InvError::Corruption`] if the tree structure is damaged.
+    pub fn dump_btree(&mut self) -> InvResult<BtreeDump> {
+        self.dump_btree_limited(crate::btree::inspect::DEFAULT_DUMP_DEPTH, crate::btree::inspect::DEFAULT_DUMP_NODES)
+    }
+
+    /// [`Db::dump_btree`] expanding internal nodes down to `max_depth` and
+    /// decoding at most `max_nodes` nodes; [`BtreeDump::truncated`] tells
+    /// whether a limit was hit.
+    pub fn dump_btree_limited(&mut self, max_depth: usize, max_nodes: usize) -> InvResult<BtreeDump> {
+        let root = self.pager.root_page_id();
+        crate::btree::dump_btree(&mut self.pager, root, max_depth, max_nodes)
+    }
+
+    /// Create a new table and persist catalog.
+    ///
+    /// Stable API: part of the supported surface.
+    /// # Errors
+    /// - [`InvError::Unsupported`] (`catalog.page_overflow`) if the table's
+    ///   entry would not fit in the catalog page; nothing is changed.
+    pub fn create_table(&mut self, name: &str, schema: &Schema) -> InvResult<TableId> {
+        let mut cat = self.pager.read_catalog()?;
+        let id = cat.create_table(name, schema)?;
+        self.pager.write_catalog(&cat)?;
+        Ok(id)
+    }
+
+    /// Create a new table whose pks are `u64`, for tables expected to outgrow
+    /// the `u32` pk space.
+    ///
+    /// Rows of such a table are written with [`Db::insert_row_u64`] and read with
+    /// [`Db::get_row_by_pk_u64`]; the `u32` row methods reject it.
+    pub fn create_table_u64_pk(&mut self, name: &str, schema: &Schema) -> InvResult<TableId> {
+        let mut cat = self.pager.read_catalog()?;
+        let id = cat.create_table_u64_pk(name, schema)?;
+        self.pager.write_catalog(&cat)?;
+        Ok(id)
+    }
+
+    /// Create a new table whose rows are stored with a CRC-32.
+    ///
+    /// Every read verifies the checksum, so a damaged row fails with
+    /// [`InvError::Corruption`] (context `row.crc`) rather than a decode error
+    /// or a silently wrong value. Each row costs 4 extra bytes.
+    pub fn create_table_with_row_crc(&mut self, name: &str, schema: &Schema) -> InvResult<TableId> {
+        let mut cat = self.pager.read_catalog()?;
+        let id = cat.create_table_with_row_crc(name, schema)?;
+        self.pager.write_catalog(&cat)?;
+        Ok(id)
+    }
+
+    /// Create a table whose rows are kept physically ordered by `cluster_column`.
+    ///
+    /// Inserts are slower since each one rewrites its target page in order, but
+    /// [`Db::scan_column_range`] on the cluster column reads only the pages
+    /// covering the range.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if `cluster_column` is missing or not a
+    ///   non-nullable integer column, or the table name is invalid or taken.
+    pub fn create_clustered_table(&mut self, name: &str, schema: &Schema, cluster_column: &str) -> InvResult<TableId> {
+        let mut cat = self.pager.read_catalog()?;
+        let id = crate::cluster::create_clustered_table(&mut self.pager, &mut cat, name, schema, cluster_column)?;
+        self.pager.write_catalog(&cat)?;
+        Ok(id)
+    }
+
+    /// Create a table whose rows are keyed by engine-generated random 16-byte
+    /// keys instead of exposing sequential pks.
+    ///
+    /// The schema needs a non-nullable `Bytes` column named
+    /// [`rowkey::UUID_COLUMN`]; insert with [`Db::insert_row_uuid`] and read
+    /// with [`Db::get_row_by_uuid`]. Rows still get an internal pk. The key
+    /// index is rebuilt by [`Db::compact_to`] but not recorded by
+    /// [`Db::dump_all`], which restores such a table as a plain one.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the key column is missing or of the
+    ///   wrong type, or the table name is invalid or taken.
+    pub fn create_table_with_uuid(&mut self, name: &str, schema: &Schema) -> InvResult<TableId> {
+        let mut cat = self.pager.read_catalog()?;
+        let id = crate::rowkey::create_table_with_uuid(&mut self.pager, &mut cat, name, schema)?;
+        self.pager.write_catalog(&cat)?;
+        Ok(id)
+    }
+
+    /// Insert a row under a freshly generated key and return the key.
+    ///
+    /// The row's [`rowkey::UUID_COLUMN`] value must be `Null`; it is replaced
+    /// by the key. Plain inserts into such a table are rejected.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not generate keys or
+    ///   the row does not fit its schema.
+    pub fn insert_row_uuid(&mut self, table_name: &str, row: &Row) -> InvResult<[u8; 16]> {
+        let mut cat = self.pager.read_catalog()?;
+        let key = crate::rowkey::insert_row_uuid(&mut self.pager, &mut cat, table_name, row, self.options.large_row_policy)?;
+        self.pager.write_catalog(&cat)?;
+        Ok(key)
+    }
+
+    /// Fetch a row by the key [`Db::insert_row_uuid`] returned for it.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not exist or does
+    ///   not generate keys.
+    pub fn get_row_by_uuid(&mut self, table_name: &str, key: &[u8; 16]) -> InvResult<Option<Row>> {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        crate::rowkey::get_row_by_uuid(&mut self.pager, table, key, self.options.strict_reads)
+    }
+
+    /// Drop the last column of a table's schema.
+    ///
+    /// Existing rows are not touched: reads skip the dropped value, and
+    /// [`Db::rewrite_rows`] reclaims its bytes. Strict reads reject rows still
+    /// carrying the value until they are rewritten.
+    /// # Errors
+    /// - [`InvError::Unsupported`] if `column` is not the last column.
+    /// - [`InvError::InvalidArgument`] if the table or column does not exist,
+    ///   or the column is the table's only or cluster column.
+    pub fn drop_column(&mut self, table_name: &str, column: &str) -> InvResult<()> {
+        let mut cat = self.pager.read_catalog()?;
+        cat.drop_column(table_name, column)?;
+        self.pager.write_catalog(&cat)
+    }
+
+    /// Run `f` against one table with a single catalog read, writing the
+    /// catalog back only if the closure changed it.
+    ///
+    /// The `TableDef` passed to `f` is authoritative: it replaces the catalog's
+    /// copy of the table once `f` returns, so batch work such as repeated
+    /// [`crate::table::insert_into_table`] calls should go through it.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not exist.
+    /// - Any error returned by `f`; the catalog is not written in that case.
+    pub fn with_table<F, R>(&mut self, name: &str, f: F) -> InvResult<R>
+    where
+        F: FnOnce(&mut Pager, &mut crate::catalog::Catalog, &mut TableDef) -> InvResult<R>,
+    {
+        let mut cat = self.pager.read_catalog()?;
+        let before = crate::catalog::encode_catalog(&cat)?;
+        let mut table = cat.get_by_name(name).cloned().ok_or(InvError::InvalidArgument {
+            name: "table",
+            details: "not found".to_string(),
+        })?;
+        let out = f(&mut self.pager, &mut cat, &mut table)?;
+        if let Some(slot) = cat.tables.iter_mut().find(|t| t.id == table.id) {
+            *slot = table;
+        }
+        if crate::catalog::encode_catalog(&cat)? != before {
+            self.pager.write_catalog(&cat)?;
+        }
+        Ok(out)
+    }
+
+    /// Fetch a table definition by name.
+    ///
+    /// Stable API: part of the supported surface.
+    pub fn get_table(&mut self, name: &str) -> InvResult<Option<TableDef>> {
+        let cat = self.pager.read_catalog()?;
+        Ok(cat.get_by_name(name).cloned())
+    }
+
+    /// List all table definitions.
+    ///
+    /// Stable API: part of the supported surface.
+    pub fn list_tables(&mut self) -> InvResult<Vec<TableDef>> {
+        let cat = self.pager.read_catalog()?;
+        Ok(cat.list())
+    }
+
+    /// Number of tables in the catalog, without cloning their definitions.
+    pub fn count_tables(&mut self) -> InvResult<usize> {
+        Ok(self.pager.read_catalog()?.tables.len())
+    }
+
+    /// Call `f` with each table definition in [`Db::list_tables`] order,
+    /// borrowing the definitions instead of cloning them.
+    pub fn for_each_table<F: FnMut(&TableDef)>(&mut self, mut f: F) -> InvResult<()> {
+        let cat = self.pager.read_catalog()?;
+        cat.tables.iter().for_each(&mut f);
+        Ok(())
+    }
+
+    /// Export a table's schema as standalone deterministic bytes.
+    ///
+    /// The bytes use the same encoding as catalog storage and can be fed to
+    /// [`Db::create_table_from_schema_bytes`] on another database.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not exist.
+    pub fn export_table_schema(&mut self, table_name: &str) -> InvResult<Vec<u8>> {
+        let cat = self.pager.read_catalog()?;
+        let table = cat.get_by_name(table_name).ok_or(InvError::InvalidArgument {
+            name: "table",
+            details: "not found".to_string(),
+        })?;
+        crate::catalog::encode_schema(&table.schema)
+    }
+
+    /// Create a new table from schema bytes produced by [`Db::export_table_schema`].
+    /// # Errors
+    /// - [`InvError::Corruption`] if the bytes do not decode to a valid schema.
+    /// - [`InvError::InvalidArgument`] if the table name is invalid or taken.
+    pub fn create_table_from_schema_bytes(&mut self, name: &str, bytes: &[u8]) -> InvResult<TableId> {
+        let schema = crate::catalog::decode_schema(bytes)?;
+        self.create_table(name, &schema)
+    }
+
+    /// Insert a row into a table, returning the allocated primary key.
+    ///
+    /// Stable API: part of the supported surface.
+    pub fn insert_row(&mut self, table_name: &str, row: &Row) -> InvResult<u32> {
+        let mut cat = self.pager.read_catalog()?;
+        let pk = crate::table::insert_row_with_policy(&mut self.pager, &mut cat, table_name, row, self.options.large_row_policy)?;
+        self.pager.write_catalog(&cat)?;
+        Ok(pk)
+    }
+
+    /// Insert a row, returning its primary key and the row as stored.
+    ///
+    /// The returned row is decoded from the bytes just written, so it shows
+    /// the canonical form a later [`Db::get_row_by_pk`] would return.
+    pub fn insert_row_returning(&mut self, table_name: &str, row: &Row) -> InvResult<(u32, Row)> {
+        let mut cat = self.pager.read_catalog()?;
+        let inserted = crate::table::insert_row_returning(&mut self.pager, &mut cat, table_name, row, self.options.large_row_policy)?;
+        self.pager.write_catalog(&cat)?;
+        Ok(inserted)
+    }
+
+    /// Reserve `count` consecutive primary keys without inserting, returning
+    /// the first one.
+    ///
+    /// Later auto-allocated pks start after the block; fill it with
+    /// [`Db::insert_row_with_pk`].
+    /// # Errors
+    /// - [`InvError::Overflow`] if the block would run past the pk space.
+    pub fn reserve_pk_block(&mut self, table_name: &str, count: u32) -> InvResult<u32> {
+        let mut cat = self.pager.read_catalog()?;
+        let first = crate::table::reserve_pk_block(&mut cat, table_name, count)?;
+        self.pager.write_catalog(&cat)?;
+        Ok(first)
+    }
+
+    /// Insert a row under an explicit primary key.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if `pk` is out of range or already present.
+    pub fn insert_row_with_pk(&mut self, table_name: &str, pk: u32, row: &Row) -> InvResult<()> {
+        let mut cat = self.pager.read_catalog()?;
+        crate::table::insert_row_with_pk(&mut self.pager, &mut cat, table_name, pk, row, self.options.large_row_policy)?;
+        self.pager.write_catalog(&cat)
+    }
+
+    /// Replace a single column of an existing row.
+    ///
+    /// The rest of the row is kept as stored; the updated row is appended
+    /// like [`Db::rewrite_rows`] stores rows back, leaving the old bytes as
+    /// dead space.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table, column or row does not
+    ///   exist, or `value` does not match the column's type or nullability.
+    pub fn set_value(&mut self, table_name: &str, pk: u32, column: &str, value: Value) -> InvResult<()> {
+        let mut cat = self.pager.read_catalog()?;
+        crate::table::set_value(&mut self.pager, &mut cat, table_name, pk, column, value, self.options.large_row_policy)?;
+        self.pager.write_catalog(&cat)
+    }
+
+    /// Insert a row into a u64-pk table, returning the allocated primary key.
+    ///
+    /// Rows are indexed under their full pk, so any pks below `u64::MAX` can
+    /// be live at once.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not use u64 pks.
+    pub fn insert_row_u64(&mut self, table_name: &str, row: &Row) -> InvResult<u64> {
+        let mut cat = self.pager.read_catalog()?;
+        let pk = crate::table::insert_row_u64_with_policy(&mut self.pager, &mut cat, table_name, row, self.options.large_row_policy)?;
+        self.pager.write_catalog(&cat)?;
+        Ok(pk)
+    }
+
+    /// Fetch a row of a u64-pk table by primary key.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not use u64 pks.
+    pub fn get_row_by_pk_u64(&mut self, table_name: &str, pk: u64) -> InvResult<Option<Row>> {
+        let cat = self.pager.read_catalog()?;
+        crate::table::get_row_by_pk_u64(&mut self.pager, &cat, table_name, pk, self.options.strict_reads)
+    }
+
+    /// Fetch a row by primary key.
+    ///
+    /// Stable API: part of the supported surface.
+    pub fn get_row_by_pk(&mut self, table_name: &str, pk: u32) -> InvResult<Option<Row>> {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        crate::table::fetch_row(&mut self.pager, table, pk, self.options.strict_reads)
+    }
+
+    /// Store `bytes` under `key` in the schemaless blob store.
+    ///
+    /// Blobs have their own index, separate from tables and the raw u64
+    /// store, so any `u32` key is available. Storing under an existing key
+    /// replaces the blob. Blobs are kept by [`Db::compact_to`] but are not
+    /// part of [`Db::dump_all`] streams.
+    /// # Errors
+    /// - [`InvError::Unsupported`] if `bytes` exceeds [`blob::MAX_BLOB_BYTES`].
+    pub fn put_blob(&mut self, key: u32, bytes: &[u8]) -> InvResult<()> {
+        crate::blob::put_blob(&mut self.pager, key, bytes)
+    }
+
+    /// Read the blob stored under `key`, if any.
+    /// # Errors
+    /// - [`InvError::Corruption`] if the indexed bytes belong to another key.
+    pub fn get_blob(&mut self, key: u32) -> InvResult<Option<Vec<u8>>> {
+        crate::blob::get_blob(&mut self.pager, key)
+    }
+
+    /// Whether the table exists and has a row with this pk.
+    ///
+    /// Unlike [`Db::get_row_by_pk`], a missing table is not an error: it
+    /// yields `Ok(false)`, so callers can probe tables that may not exist yet.
+    /// # Errors
+    /// - [`InvError::Unsupported`] if the table uses 64-bit pks.
+    /// - [`InvError::Corruption`] if the stored row does not match `pk`.
+    pub fn row_present(&mut self, table_name: &str, pk: u32) -> InvResult<bool> {
+        let cat = self.pager.read_catalog()?;
+        crate::table::row_present(&mut self.pager, &cat, table_name, pk)
+    }
+
+    /// Scan rows in primary key order (naive implementation).
+    ///
+    /// Stable API: part of the supported surface.
+    pub fn scan_table(&mut self, table_name: &str) -> InvResult<Vec<(u32, Row)>> {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        crate::table::scan_rows(&mut self.pager, table, self.options.strict_reads)
+    }
+
+    /// Scan rows in primary key order, returning each with access by column name.
+    pub fn scan_table_named(&mut self, table_name: &str) -> InvResult<Vec<QueryRow>> {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        let schema = std::sync::Arc::new(table.schema.clone());
+        let rows = crate::table::scan_rows(&mut self.pager, table, self.options.strict_reads)?;
+        Ok(rows
+            .into_iter()
+            .map(|(pk, values)| QueryRow::new(pk, schema.clone(), values))
+            .collect())
+    }
+
+    /// Return the schema version a row was written under, or `None` if the
+    /// row does not exist.
+    ///
+    /// Schemas only change through [`Db::drop_column`], so the version is
+    /// the number of columns the row was stored with: rows written before a
+    /// drop report more columns than the current schema has until
+    /// [`Db::rewrite_rows`] stores them back. Only the row header is read.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not exist.
+    pub fn row_schema_version(&mut self, table_name: &str, pk: u32) -> InvResult<Option<u32>> {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        crate::table::row_schema_version(&mut self.pager, table, pk)
+    }
+
+    /// Return one column's value for every row, in pk order.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table or column does not exist.
+    pub fn column_values(&mut self, table_name: &str, column: &str) -> InvResult<Vec<Value>> {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        crate::table::column_values(&mut self.pager, table, column, self.options.strict_reads)
+    }
+
+    /// Return the rows whose integer `column` lies in the inclusive range `[lo, hi]`.
+    ///
+    /// On a clustered table filtered by its cluster column, rows come back in
+    /// column order and only overlapping pages are read; otherwise this is a
+    /// filtered full scan in pk order.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the column is missing or the bounds
+    ///   are not integers of the column's type.
+    pub fn scan_column_range(&mut self, table_name: &str, column: &str, lo: &Value, hi: &Value) -> InvResult<Vec<(u32, Row)>> {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        crate::cluster::scan_column_range(&mut self.pager, table, column, lo, hi, self.options.strict_reads)
+    }
+
+    /// List the pks, in ascending order, of rows whose `column` satisfies
+    /// `op` against `value`.
+    ///
+    /// Rows are decoded only up to the column, so this is cheaper than
+    /// filtering [`Db::scan_table`] when the column comes early in the
+    /// schema. Rows whose column is `Null` never match; see [`CmpOp::eval`].
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table or column does not exist,
+    ///   or `value` is not a non-null value of the column's type.
+    pub fn pks_where(&mut self, table_name: &str, column: &str, op: CmpOp, value: &Value) -> InvResult<Vec<u32>> {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        crate::table::pks_where(&mut self.pager, table, column, op, value, self.options.strict_reads)
+    }
+
+    /// List the live primary keys of a table in ascending order.
+    ///
+    /// Row bodies are not decoded; only the stored pk prefix is checked.
+    pub fn list_pks(&mut self, table_name: &str) -> InvResult<Vec<u32>> {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        crate::table::list_pks(&mut self.pager, table)
+    }
+
+    /// List the pks below the table's `next_pk` that have no live row.
+    ///
+    /// Scans skip such pks silently; this reports them, e.g. to audit deletes
+    /// or spot unexpected gaps.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not exist.
+    /// - [`InvError::Corruption`] if a probed row's stored pk does not match.
+    pub fn missing_pks(&mut self, table_name: &str) -> InvResult<Vec<u32>> {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        crate::table::missing_pks(&mut self.pager, table)
+    }
+
+    /// Return the minimum and maximum live primary keys of a table, or `None`
+    /// if it has no rows.
+    pub fn pk_range(&mut self, table_name: &str) -> InvResult<Option<(u32, u32)>> {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        crate::table::pk_range(&mut self.pager, table)
+    }
+
+    /// Fetch up to `n` pseudo-random rows without scanning the whole table.
+    ///
+    /// The sample is deterministic for a given `seed` and returned in pk order;
+    /// sparse tables may yield fewer than `n` rows.
+    pub fn sample_rows(&mut self, table_name: &str, n: usize, seed: u64) -> InvResult<Vec<(u32, Row)>> {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        crate::table::sample_rows(&mut self.pager, table, n, seed, self.options.strict_reads)
+    }
+
+    /// Decode up to `sample` pseudo-random rows strictly against the table schema.
+    ///
+    /// A cheap confidence check before trusting a reused file, short of a
+    /// full scan; rows are picked as by [`Db::sample_rows`] with a fixed seed.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not exist.
+    /// - [`InvError::Corruption`] for the first sampled row that does not
+    ///   decode canonically against the schema.
+    pub fn validate_table_rows(&mut self, table_name: &str, sample: usize) -> InvResult<()> {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        crate::table::sample_rows(&mut self.pager, table, sample, VALIDATION_SEED, true)?;
+        Ok(())
+    }
+
+    /// Return the bytes a table's rows and index entries use.
+    ///
+    /// Counts the used portion of every row page the table owns, dead slots
+    /// included, plus 12 bytes per btree entry. See [`Db::table_storage`] for
+    /// live and allocated figures.
+    ///
+    /// Ownership follows the table's row page chain. Tables created before
+    /// row pages were chained only own the pages of their live rows and their
+    /// append page, so a page whose rows were all deleted is not counted.
+    pub fn table_storage_bytes(&mut self, table_name: &str) -> InvResult<u64> {
+        Ok(self.table_storage(table_name)?.used_bytes)
+    }
+
+    /// Return the pages holding a table's rows, in the order they were
+    /// allocated to it.
+    ///
+    /// See [`crate::table::row_pages`].
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not exist.
+    /// - [`InvError::Unsupported`] for tables created before row pages were
+    ///   chained.
+    pub fn table_row_pages(&mut self, table_name: &str) -> InvResult<Vec<PageId>> {
+        let cat = self.pager.read_catalog()?;
+        let table = cat.get_by_name(table_name).ok_or(InvError::InvalidArgument {
+            name: "table",
+            details: "not found".to_string(),
+        })?;
+        crate::table::row_pages(&mut self.pager, table)
+    }
+
+    /// Estimate a table's row count from its row pages, without decoding rows.
+    ///
+    /// See [`crate::table::approx_row_count`] for how close the estimate is;
+    /// rows deleted since their page was last compacted are still counted.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not exist.
+    /// - [`InvError::Unsupported`] for tables created before row pages were
+    ///   chained.
+    pub fn approx_row_count(&mut self, table_name: &str) -> InvResult<u64> {
+        let cat = self.pager.read_catalog()?;
+        let table = cat.get_by_name(table_name).ok_or(InvError::InvalidArgument {
+            name: "table",
+            details: "not found".to_string(),
+        })?;
+        crate::table::approx_row_count(&mut self.pager, table)
+    }
+
+    /// Return the live, used and allocated storage of a table.
+    pub fn table_storage(&mut self, table_name: &str) -> InvResult<TableStorage> {
+        let cat = self.pager.read_catalog()?;
+        let table = cat.get_by_name(table_name).ok_or(InvError::InvalidArgument {
+            name: "table",
+            details: "not found".to_string(),
+        })?;
+        crate::table::table_storage(&mut self.pager, table)
+    }
+
+    /// Return a table's definition along with its owned pages, live rows and
+    /// storage, or `None` if it does not exist.
+    ///
+    /// Equivalent to [`Db::get_table`], [`Db::table_storage`] and counting
+    /// [`Db::list_pks`], but walks the table's rows once.
+    pub fn table_info(&mut self, name: &str) -> InvResult<Option<TableInfo>> {
+        let cat = self.pager.read_catalog()?;
+        let Some(table) = cat.get_by_name(name) else {
+            return Ok(None);
+        };
+        crate::table::table_info(&mut self.pager, table).map(Some)
+    }
+
+    /// Visit every row in primary key order without collecting them.
+    ///
+    /// The row passed to `f` is a reused buffer, so large scans avoid a fresh
+    /// allocation per row. An error returned by `f` stops the scan.
+    pub fn for_each_row<F>(&mut self, table_name: &str, f: F) -> InvResult<()>
+    where
+        F: FnMut(u32, &Row) -> InvResult<()>,
+    {
+        let cat = self.pager.read_catalog()?;
+        let table = crate::table::find_table(&cat, table_name)?;
+        crate::table::for_each_row(&mut self.pager, table, self.options.strict_reads, f)
+    }
+
+    /// Delete a row by primary key, returning whether it existed.
+    ///
+    /// The row's bytes remain as dead space in its row page until compaction,
+    /// except that a row page left without live rows is freed at once unless
+    /// it is the table's last. Btree nodes left below
+    /// [`DbOptions::min_fill_ratio`] are merged with a sibling when the pair
+    /// fits in one page.
+    pub fn delete_row(&mut self, table_name: &str, pk: u32) -> InvResult<bool> {
+        let mut cat = self.pager.read_catalog()?;
+        let before = cat.get_by_name(table_name).cloned();
+        let removed =
+            crate::table::delete_row_with_fill(&mut self.pager, &mut cat, table_name, pk, self.options.min_fill_ratio)?;
+        if cat.get_by_name(table_name) != before.as_ref() {
+            self.pager.write_catalog(&cat)?;
+        }
+        Ok(removed)
+    }
+
+    /// Classify a page by its kind byte without decoding its contents.
+    ///
+    /// Page 0 is always [`PageKind::Header`].
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if `id` is out of bounds.
+    /// - [`InvError::Corruption`] if the page has an unknown kind.
+    pub fn page_kind(&mut self, id: PageId) -> InvResult<PageKind> {
+        if id == config::HEADER_PAGE_ID {
+            return Ok(PageKind::Header);
+        }
+        let kind = self.pager.get_page(id)?.as_bytes()[0];
+        PageKind::from_byte(kind).ok_or_else(|| InvError::Corruption {
+            context: "page.kind",
+            details: format!("page {} has unexpected kind {}", id.0, kind),
+        })
+    }
+
+    /// Deeply validate a single page by decoding it as the kind it claims to be.
+    ///
+    /// The header page is re-read and checked like on open; btree pages are
+    /// decoded as nodes, row pages have their payload header checked, and the
+    /// catalog page is decoded as a catalog; other meta pages are decoded as
+    /// cluster directories.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if `id` is out of bounds.
+    /// - [`InvError::Corruption`] if the page fails validation or has an unknown kind.
+    pub fn validate_page(&mut self, id: PageId) -> InvResult<()> {
+        if id == config::HEADER_PAGE_ID {
+            return self.pager.validate_header_page();
+        }
+        let page_count = self.pager.page_count();
+        let page = self.pager.get_page(id)?;
+        page.validate_header()?;
+        let buf = page.as_bytes();
+        match buf[0] {
+            2 => {
+                Node::decode(page, page_count)?;
+            }
+            config::ROW_PAGE_KIND => crate::rowstore::validate_row_page_header(buf)?,
+            config::FREE_PAGE_KIND => {
+                let next = crate::layout::read_u32_le(buf, crate::layout::FREE_PAGE_NEXT);
+                if next == config::CATALOG_PAGE_ID.0 || next >= page_count {
+                    return Err(InvError::Corruption {
+                        context: "free_list.next",
+                        details: format!("page {} links to invalid page {}", id.0, next),
+                    });
+                }
+            }
+            config::META_PAGE_KIND if id == config::CATALOG_PAGE_ID => {
+                crate::catalog::decode_catalog(&buf[config::PAGE_HEADER_SIZE..])?;
+            }
+            config::META_PAGE_KIND => {
+                crate::cluster::decode_directory(buf, page_count)?;
+            }
+            kind => {
+                return Err(InvError::Corruption {
+                    context: "page.kind",
+                    details: format!("page {} has unexpected kind {}", id.0, kind),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Return a copy of a page's bytes, including unflushed changes.
+    ///
+    /// The page header is validated for every page but the file header, which
+    /// is read from disk since it is written there directly.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if `id` is out of bounds.
+    /// - [`InvError::Corruption`] if the page header is invalid.
+    pub fn read_page_bytes(&mut self, id: PageId) -> InvResult<[u8; config::PAGE_SIZE]> {
+        let mut out = [0u8; config::PAGE_SIZE];
+        if id == config::HEADER_PAGE_ID {
+            out.copy_from_slice(self.pager.read_page_from_disk(id)?.as_bytes());
+        } else {
+            let page = self.pager.get_page(id)?;
+            page.validate_header()?;
+            out.copy_from_slice(page.as_bytes());
+        }
+        Ok(out)
+    }
+
+    /// Re-encode every row of a table against its current schema, returning
+    /// how many rows were stored back.
+    ///
+    /// Rows still carrying values of dropped trailing columns are rewritten
+    /// without them; rows already in canonical form are left in place. The
+    /// old row bytes become dead space until their pages are compacted.
+    pub fn rewrite_rows(&mut self, table_name: &str) -> InvResult<u64> {
+        let mut cat = self.pager.read_catalog()?;
+        let rewritten = crate::table::rewrite_rows(&mut self.pager, &mut cat, table_name)?;
+        self.pager.write_catalog(&cat)?;
+        Ok(rewritten)
+    }
+
+    /// Rewrite a table's rows densely into as few row pages as possible,
+    /// returning how many row pages were freed.
+    ///
+    /// Narrower than [`Db::compact_to`]: only this table's row pages are
+    /// touched, and freed pages go to the free list rather than shrinking the
+    /// file. See [`crate::table::coalesce_row_pages`].
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if the table does not exist.
+    /// - [`InvError::Unsupported`] for clustered tables and tables whose row
+    ///   pages are not chained.
+    pub fn coalesce_row_pages(&mut self, table_name: &str) -> InvResult<u64> {
+        let mut cat = self.pager.read_catalog()?;
+        let freed = crate::table::coalesce_row_pages(&mut self.pager, &mut cat, table_name)?;
+        self.pager.write_catalog(&cat)?;
+        Ok(freed)
+    }
+
+    /// Compact a single row page, dropping dead row slots and sliding live rows
+    /// down so the page's free space is contiguous again.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if `page` is not a row page.
+    pub fn defragment_row_page(&mut self, page: PageId) -> InvResult<()> {
+        let kind = self.pager.get_page(page)?.as_bytes()[0];
+        if page == config::HEADER_PAGE_ID || kind != config::ROW_PAGE_KIND {
+            return Err(InvError::InvalidArgument {
+                name: "page_id",
+                details: format!("page {} is not a row page", page.0),
+            });
+        }
+        let mut cat = self.pager.read_catalog()?;
+        crate::table::defragment_row_page(&mut self.pager, &mut cat, page)?;
+        self.pager.write_catalog(&cat)
+    }
+}
+
+/// Rename a finished file to `dest`, copying through a sibling of `dest`
+/// when the two paths are on different filesystems.
+///
+/// The copy is staged as `dest` plus `.tmp`, so `dest` never holds a
+/// partial file.
+fn move_into_place(from: &Path, dest: &Path) -> InvResult<()> {
+    if std::fs::rename(from, dest).is_ok() {
+        return Ok(());
+    }
+    let mut staged = dest.as_os_str().to_owned();
+    staged.push(".tmp");
+    let staged = PathBuf::from(staged);
+    let moved = std::fs::copy(from, &staged)
+        .and_then(|_| std::fs::rename(&staged, dest))
+        .map_err(|e| InvError::io("move_into_place", e));
+    if moved.is_err() {
+        let _ = std::fs::remove_file(&staged);
+    }
+    moved?;
+    std::fs::remove_file(from).map_err(|e| InvError::io("remove_scratch", e))
+}
+
+/// Validate caller-provided path arguments for Db operations.
+fn validate_path(path: &Path) -> InvResult<()> {
+    if path.as_os_str().is_empty() {
+        return Err(InvError::InvalidArgument {
+            name: "path",
+            details: "path must not be empty".to_string(),
+        });
+    }
+
+    if path.extension().is_some_and(|ext| ext == "wal") {
+        return Err(InvError::Unsupported { feature: "wal" });
+    }
+
+    Ok(())
+}
+
+/// Seed of the row sample drawn by [`Db::validate_table_rows`].
+const VALIDATION_SEED: u64 = 0x5EED_F00D;
+
+/// Validate a freshly opened or reloaded file per `options.validate_on_open`,
+/// returning whether every check ran.
+fn validate_on_open(pager: &mut Pager, options: &DbOptions) -> InvResult<bool> {
+    match options.validate_on_open {
+        ValidationLevel::Full => {
+            crate::table::migrate_shared_u64_pks(pager)?;
+            validate_database_within(pager, &mut PageBudget::new(options.validate_page_budget))
+        }
+        ValidationLevel::Header => validate_header_and_root(pager).map(|()| false),
+        ValidationLevel::None => Ok(false),
+    }
+}
+
+/// Read the index nodes `policy` asks for into the cache, breadth first.
+fn warm_cache(pager: &mut Pager, policy: WarmPolicy) -> InvResult<()> {
+    let max_depth = match policy {
+        WarmPolicy::None => return Ok(()),
+        WarmPolicy::Root => 1,
+        WarmPolicy::Full => usize::MAX,
+    };
+    let page_count = pager.page_count();
+    let mut seen = HashSet::new();
+    let mut level = vec![pager.root_page_id()];
+    let mut depth = 0;
+    while !level.is_empty() {
+        let mut next = Vec::new();
+        for id in level {
+            // A page reachable twice is corruption; validation reports it.
+            if !seen.insert(id.0) {
+                continue;
+            }
+            if let Node::Internal(internal) = Node::decode(pager.get_page(id)?, page_count)? {
+                if depth < max_depth {
+                    next.extend(internal.children);
+                }
+            }
+        }
+        level = next;
+        depth += 1;
+    }
+    Ok(())
+}
+
+/// Page visits a validation may still spend; `None` is unlimited.
+struct PageBudget {
+    remaining: Option<u64>,
+    exhausted: bool,
+}
+
+impl PageBudget {
+    fn new(limit: Option<u64>) -> Self {
+        Self { remaining: limit, exhausted: false }
+    }
+
+    /// Claim one page visit, returning false once the budget is spent.
+    fn visit(&mut self) -> bool {
+        match &mut self.remaining {
+            None => true,
+            Some(0) => {
+                self.exhausted = true;
+                false
+            }
+            Some(n) => {
+                *n -= 1;
+                true
+            }
+        }
+    }
+}
+
+fn validate_database(pager: &mut Pager) -> InvResult<()> {
+    validate_database_within(pager, &mut PageBudget::new(None)).map(|_| ())
+}
+
+/// Run the full validation, skipping the row page, leaf chain and free list
+/// checks once `budget` runs out; returns whether every check ran.
+fn validate_database_within(pager: &mut Pager, budget: &mut PageBudget) -> InvResult<bool> {
+    validate_header_and_root(pager)?;
+    let page_count = pager.page_count();
+    let root = pager.root_page_id();
+
+    let cat = pager.read_catalog()?;
+    let mut ids = HashSet::new();
+    let mut names = HashSet::new();
+    for table in &cat.tables {
+        if table.id.0 == 0 {
+            return Err(InvError::Corruption {
+                context: "catalog.table_id",
+                details: "table id is 0".to_string(),
+            });
+        }
+        if !ids.insert(table.id.0) || !names.insert(table.name.clone()) {
+            return Err(InvError::Corruption {
+                context: "catalog.duplicate",
+                details: "duplicate table id or name".to_string(),
+            });
+        }
+        if table.next_pk < 1 {
+            return Err(InvError::Corruption {
+                context: "catalog.next_pk",
+                details: format!("invalid next_pk {}", table.next_pk),
+            });
+        }
+        if table.last_row_page != 0 && table.last_row_page >= page_count {
+            return Err(InvError::Corruption {
+                context: "catalog.last_row_page",
+                details: format!(
+                    "last_row_page {} >= page_count {}",
+                    table.last_row_page, page_count
+                ),
+            });
+        }
+        if table.first_row_page.is_some_and(|first| first >= page_count) {
+            return Err(InvError::Corruption {
+                context: "catalog.first_row_page",
+                details: format!("first_row_page {:?} >= page_count {}", table.first_row_page, page_count),
+            });
+        }
+        if table.uuid_root.is_some_and(|root| root >= page_count) {
+            return Err(InvError::Corruption {
+                context: "catalog.uuid_root",
+                details: format!("uuid_root {:?} >= page_count {}", table.uuid_root, page_count),
+            });
+        }
+        if table.schema.is_empty() {
+            return Err(InvError::Corruption {
+                context: "catalog.schema",
+                details: "schema empty".to_string(),
+            });
+        }
+    }
+
+    // Row page reachability (best-effort)
+    for table in &cat.tables {
+        if table.last_row_page != 0 && budget.visit() {
+            let page = pager.get_page(PageId(table.last_row_page))?;
+            let buf = page.as_bytes();
+            if buf.first() != Some(&config::ROW_PAGE_KIND) {
+                return Err(InvError::Corruption {
+                    context: "rowpage.kind",
+                    details: format!(
+                        "expected {} got {}",
+                        config::ROW_PAGE_KIND,
+                        buf.first().copied().unwrap_or(255)
+                    ),
+                });
+            }
+            page.validate_header()?;
+            crate::rowstore::validate_row_page_header(buf)?;
+        }
+    }
+
+    validate_leaf_chain(pager, root, page_count, budget)?;
+    if budget.visit() {
+        pager.free_pages()?;
+    }
+
+    Ok(!budget.exhausted)
+}
+
+
+fn validate_header_and_root(pager: &mut Pager) -> InvResult<()> {
+    let page_count = pager.page_count();
+    if page_count < 3 {
+        return Err(InvError::Corruption {
+            context: "catalog.missing",
+            details: format!("page_count {} too small", page_count),
+        });
+    }
+    let root = pager.root_page_id();
+    if root.0 == 0 || root.0 >= page_count {
+        return Err(InvError::Corruption {
+            context: "header.root_page_id",
+            details: format!("root {} invalid for page_count {}", root.0, page_count),
+        });
+    }
+
+    // Root btree validation
+    {
+        let root_page = pager.get_page(root)?;
+        let buf = root_page.as_bytes();
+        if buf.first() != Some(&2) {
+            return Err(InvError::Corruption {
+                context: "btree.page_kind",
+                details: format!("expected 2 got {}", buf.first().copied().unwrap_or(255)),
+            });
+        }
+        root_page.validate_header()?;
+        Node::decode(root_page, page_count)?;
+    }
+    Ok(())
+}
+
+fn validate_leaf_chain(
+    pager: &mut Pager,
+    root: PageId,
+    page_count: u32,
+    budget: &mut PageBudget,
+) -> InvResult<()> {
+    let start_leaf = find_leftmost_leaf(pager, root, page_count)?;
+    let mut current = start_leaf;
+    let mut steps = 0usize;
+    let mut visited = HashSet::new();
+    while current.0 != 0 && budget.visit() {
+        if steps > 10_000 {
+            return Err(InvError::Corruption {
+                context: "btree.leaf_cycle",
+                details: "leaf traversal exceeded limit".to_string(),
+            });
+        }
+        if !visited.insert(current.0) {
+            return Err(InvError::Corruption {
+                context: "btree.leaf_cycle",
+                details: format!("cycle detected at {}", current.0),
+            });
+        }
+        let page = pager.get_page(current)?;
+        let node = Node::decode(page, page_count)?;
+        let leaf = match node {
+            Node::Leaf(l) => l,
+            _ => {
+                return Err(InvError::Corruption {
+                    context: "btree.leaf_cycle",
+                    details: "expected leaf during traversal".to_string(),
+                })
+            }
+        };
+        let next = leaf.next_leaf.0;
+        if next == 0 {
+            break;
+        }
+        if next >= page_count {
+            return Err(InvError::Corruption {
+                context: "btree.leaf_cycle",
+                details: format!("invalid next_leaf {} from {}", next, current.0),
+            });
+        }
+        current = PageId(next);
+        steps += 1;
+    }
+    Ok(())
+}
+
+fn find_leftmost_leaf(pager: &mut Pager, mut current: PageId, page_count: u32) -> InvResult<PageId> {
+    let mut depth = 0usize;
+    loop {
+        if depth > 64 {
+            return Err(InvError::Corruption {
+                context: "btree.depth",
+                details: "exceeded max depth while finding leaf".to_string(),
+            });
+        }
+        let page = pager.get_page(current)?;
+        let node = Node::decode(page, page_count)?;
+        match node {
+            Node::Leaf(_) => return Ok(current),
+            Node::Internal(internal) => {
+                if internal.children.is_empty() {
+                    return Err(InvError::Corruption {
+                        context: "btree.internal.child",
+                        details: "internal node has no children".to_string(),
+                    });
+                }
+                current = internal.children[0];
+            }
+        }
+        depth += 1;
+    }
+}
+
+#[cfg(test)]
+impl Db {
+    fn pager_mut_for_tests(&mut self) -> &mut Pager {
+        &mut self.pager
+    }
 }
 
-fn validate_leaf_chain(pager: &mut Pager, root: PageId, page_count: u32) -> InvResult<()> {
-    let start_leaf = find_leftmost_leaf(pager, root, page_count)?;
-    let mut current = start_leaf;
-    let mut steps = 0usize;
-    let mut visited = HashSet::new();
-    while current.0 != 0 {
-        if steps > 10_000 {
-            return Err(InvError::Corruption {
-                context: "btree.leaf_cycle",
-                details: "leaf traversal exceeded limit".to_string(),
-            });
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::node::{self, Node};
+    use crate::btree::node::max_leaf_keys;
+    use crate::config::{MAX_SUPPORTED_VERSION, MIN_SUPPORTED_VERSION, PAGE_SIZE, ROOT_PAGE_ID};
+    use crate::rowstore::RowPtr;
+    use crate::table::composite_for_tests;
+    use crate::types::checked_page_index;
+    use std::collections::HashSet;
+    use std::fs::OpenOptions;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::path::PathBuf;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn validate_version_accepts_current() {
+        assert!(config::validate_version(config::FILE_FORMAT_VERSION).is_ok());
+    }
+
+    #[test]
+    fn validate_version_rejects_out_of_range() {
+        assert!(config::validate_version(0).is_err());
+        assert!(config::validate_version(MAX_SUPPORTED_VERSION.saturating_add(1)).is_err());
+        if MIN_SUPPORTED_VERSION > 0 {
+            assert!(config::validate_version(MIN_SUPPORTED_VERSION - 1).is_err());
+        }
+    }
+
+    #[test]
+    fn checked_page_index_overflow() {
+        let result = checked_page_index(u64::MAX);
+        assert!(matches!(
+            result,
+            Err(InvError::Overflow {
+                context: "page index exceeds u32::MAX"
+            })
+        ));
+    }
+
+    #[test]
+    fn create_rejects_empty_path() {
+        let err = Db::create("").unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::InvalidArgument { name: "path", .. }
+        ));
+    }
+
+    #[test]
+    fn open_rejects_wal_path() {
+        let err = Db::open("test.wal").unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Unsupported {
+                feature: "wal", ..
+            }
+        ));
+    }
+
+    fn unique_temp_path(name: &str) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        std::env::temp_dir().join(format!("invdb_{}_{}", name, nanos))
+    }
+
+    #[test]
+    fn create_creates_file_and_valid_header() {
+        let path = unique_temp_path("create_header");
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.flush().unwrap();
+        }
+        let meta = std::fs::metadata(&path).unwrap();
+        assert_eq!(meta.len(), (3 * PAGE_SIZE) as u64);
+
+        let db_open = Db::open(&path);
+        assert!(db_open.is_ok());
+    }
+
+    #[test]
+    fn open_rejects_bad_magic() {
+        let path = unique_temp_path("bad_magic");
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.flush().unwrap();
+        }
+
+        {
+            let mut f = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            f.seek(SeekFrom::Start(0)).unwrap();
+            f.write_all(&[0xFF]).unwrap();
+        }
+
+        let err = Db::open(&path).unwrap_err();
+        assert!(matches!(err, InvError::InvalidMagic { .. }));
+    }
+
+    #[test]
+    fn open_rejects_bad_page_size() {
+        let path = unique_temp_path("bad_page_size");
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.flush().unwrap();
+        }
+
+        {
+            let mut f = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            f.seek(SeekFrom::Start(10)).unwrap();
+            f.write_all(&[0x01, 0x00]).unwrap(); // set page_size to 1
+        }
+
+        let err = Db::open(&path).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Corruption {
+                context: "header.page_size",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn pager_get_page_validates_page_header() {
+        let path = unique_temp_path("page_header");
+        {
+            let mut db = Db::create(&path).unwrap();
+            {
+                let pager = db.pager_mut_for_tests();
+                let root_page = pager.get_page(ROOT_PAGE_ID).unwrap();
+                assert_eq!(root_page.as_bytes()[0], 2);
+
+                let root_page_mut = pager.get_page_mut(ROOT_PAGE_ID).unwrap();
+                root_page_mut.as_bytes_mut()[1] = 1; // set flags non-zero
+            }
+            db.flush().unwrap();
+        }
+
+        let err = Db::open(&path).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Unsupported {
+                feature: "page.flags"
+            }
+        ));
+    }
+
+    #[test]
+    fn search_empty_leaf_returns_none() {
+        let path = unique_temp_path("search_empty");
+        let mut db = Db::create(&path).unwrap();
+        let result = db.get_u64(10).unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn search_leaf_finds_value() {
+        let path = unique_temp_path("search_leaf");
+        {
+            let mut db = Db::create(&path).unwrap();
+            {
+                let pager = db.pager_mut_for_tests();
+                let root_id = pager.root_page_id();
+                let page = pager.get_page_mut(root_id).unwrap();
+                let buf = page.as_bytes_mut();
+                let base = 16;
+                buf[base] = 1; // leaf
+                buf[base + 1] = 0;
+                buf[base + 2..base + 4].copy_from_slice(&(3u16).to_le_bytes());
+                buf[base + 4..base + 8].copy_from_slice(&0u32.to_le_bytes());
+                buf[base + 8..base + 12].copy_from_slice(&0u32.to_le_bytes());
+                buf[base + 12..base + 16].copy_from_slice(&0u32.to_le_bytes());
+
+                let keys = [10u32, 20, 30];
+                let values = [1000u64, 2000, 3000];
+                let keys_offset = base + 16;
+                for (i, k) in keys.iter().enumerate() {
+                    let offset = keys_offset + 4 * i;
+                    buf[offset..offset + 4].copy_from_slice(&k.to_le_bytes());
+                }
+                let values_offset = keys_offset + 4 * keys.len();
+                for (i, v) in values.iter().enumerate() {
+                    let offset = values_offset + 8 * i;
+                    buf[offset..offset + 8].copy_from_slice(&v.to_le_bytes());
+                }
+            }
+            db.flush().unwrap();
+        }
+
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.get_u64(20).unwrap(), Some(2000));
+        assert_eq!(db.get_u64(25).unwrap(), None);
+        assert_eq!(db.get_u64(10).unwrap(), Some(1000));
+    }
+
+    #[test]
+    fn search_internal_traversal_basic() {
+        let path = unique_temp_path("search_internal");
+        {
+            let mut db = Db::create(&path).unwrap();
+            {
+                let pager = db.pager_mut_for_tests();
+                let child_a = pager.allocate_btree_page().unwrap();
+                let child_b = pager.allocate_btree_page().unwrap();
+
+                // root internal node with key 50 separating child_a and child_b
+                let root_id = pager.root_page_id();
+                let root_page = pager.get_page_mut(root_id).unwrap();
+                let buf = root_page.as_bytes_mut();
+                let base = 16;
+                buf[base] = 2; // internal
+                buf[base + 1] = 0;
+                buf[base + 2..base + 4].copy_from_slice(&(1u16).to_le_bytes());
+                buf[base + 4..base + 8].copy_from_slice(&0u32.to_le_bytes());
+                buf[base + 8..base + 12].copy_from_slice(&0u32.to_le_bytes()); // reserved2
+                buf[base + 12..base + 16].copy_from_slice(&0u32.to_le_bytes()); // reserved3
+                let children_offset = base + 16;
+                buf[children_offset..children_offset + 4]
+                    .copy_from_slice(&child_a.0.to_le_bytes());
+                buf[children_offset + 4..children_offset + 8]
+                    .copy_from_slice(&child_b.0.to_le_bytes());
+                let keys_offset = children_offset + 8;
+                buf[keys_offset..keys_offset + 4].copy_from_slice(&50u32.to_le_bytes());
+
+                // child_a leaf with key 10 -> 111
+                let child_a_page = pager.get_page_mut(child_a).unwrap();
+                let buf_a = child_a_page.as_bytes_mut();
+                buf_a[base] = 1;
+                buf_a[base + 1] = 0;
+                buf_a[base + 2..base + 4].copy_from_slice(&(1u16).to_le_bytes());
+                buf_a[base + 4..base + 8].copy_from_slice(&0u32.to_le_bytes());
+                buf_a[base + 8..base + 12].copy_from_slice(&0u32.to_le_bytes());
+                buf_a[base + 12..base + 16].copy_from_slice(&0u32.to_le_bytes());
+                let keys_offset_a = base + 16;
+                buf_a[keys_offset_a..keys_offset_a + 4].copy_from_slice(&10u32.to_le_bytes());
+                let values_offset_a = keys_offset_a + 4;
+                buf_a[values_offset_a..values_offset_a + 8]
+                    .copy_from_slice(&111u64.to_le_bytes());
+
+                // child_b leaf with key 60 -> 222
+                let child_b_page = pager.get_page_mut(child_b).unwrap();
+                let buf_b = child_b_page.as_bytes_mut();
+                buf_b[base] = 1;
+                buf_b[base + 1] = 0;
+                buf_b[base + 2..base + 4].copy_from_slice(&(1u16).to_le_bytes());
+                buf_b[base + 4..base + 8].copy_from_slice(&0u32.to_le_bytes());
+                buf_b[base + 8..base + 12].copy_from_slice(&0u32.to_le_bytes());
+                buf_b[base + 12..base + 16].copy_from_slice(&0u32.to_le_bytes());
+                let keys_offset_b = base + 16;
+                buf_b[keys_offset_b..keys_offset_b + 4].copy_from_slice(&60u32.to_le_bytes());
+                let values_offset_b = keys_offset_b + 4;
+                buf_b[values_offset_b..values_offset_b + 8]
+                    .copy_from_slice(&222u64.to_le_bytes());
+            }
+            db.flush().unwrap();
+        }
+
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.get_u64(10).unwrap(), Some(111));
+        assert_eq!(db.get_u64(60).unwrap(), Some(222));
+        assert_eq!(db.get_u64(55).unwrap(), None);
+    }
+
+    #[test]
+    fn node_validation_rejects_unsorted_keys() {
+        let path = unique_temp_path("unsorted_keys");
+        {
+            let mut db = Db::create(&path).unwrap();
+            {
+                let pager = db.pager_mut_for_tests();
+                let root_id = pager.root_page_id();
+                let page = pager.get_page_mut(root_id).unwrap();
+                let buf = page.as_bytes_mut();
+                let base = 16;
+                buf[base] = 1; // leaf
+                buf[base + 1] = 0;
+                buf[base + 2..base + 4].copy_from_slice(&(2u16).to_le_bytes());
+                buf[base + 4..base + 8].copy_from_slice(&0u32.to_le_bytes());
+                buf[base + 8..base + 12].copy_from_slice(&0u32.to_le_bytes());
+                buf[base + 12..base + 16].copy_from_slice(&0u32.to_le_bytes());
+                let keys_offset = base + 16;
+                let keys = [20u32, 10u32];
+                for (i, k) in keys.iter().enumerate() {
+                    let offset = keys_offset + 4 * i;
+                    buf[offset..offset + 4].copy_from_slice(&k.to_le_bytes());
+                }
+                let values_offset = keys_offset + 4 * keys.len();
+                let values = [1u64, 2u64];
+                for (i, v) in values.iter().enumerate() {
+                    let offset = values_offset + 8 * i;
+                    buf[offset..offset + 8].copy_from_slice(&v.to_le_bytes());
+                }
+            }
+            db.flush().unwrap();
+        }
+
+        let err = Db::open(&path).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Corruption {
+                context: "btree.leaf.keys_order",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn put_then_get_single() {
+        let path = unique_temp_path("put_single");
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.put_u64(10, 111).unwrap();
+            db.flush().unwrap();
+        }
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.get_u64(10).unwrap(), Some(111));
+    }
+
+    #[test]
+    fn overwrite_value_same_key() {
+        let path = unique_temp_path("overwrite_key");
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.put_u64(10, 111).unwrap();
+            db.put_u64(10, 222).unwrap();
+            db.flush().unwrap();
+        }
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.get_u64(10).unwrap(), Some(222));
+    }
+
+    #[test]
+    fn leaf_split_creates_multiple_pages() {
+        let path = unique_temp_path("leaf_split");
+        let max = max_leaf_keys();
+        {
+            let mut db = Db::create(&path).unwrap();
+            for k in 1..=(max as u32 + 5) {
+                db.put_u64(k, (k as u64) * 10).unwrap();
+            }
+            db.flush().unwrap();
+        }
+        let mut db = Db::open(&path).unwrap();
+        assert!(db.pager_mut_for_tests().page_count() > 2);
+        assert_eq!(db.get_u64(1).unwrap(), Some(10));
+        assert_eq!(db.get_u64((max as u32) / 2).unwrap(), Some(((max as u32) / 2) as u64 * 10));
+        assert_eq!(db.get_u64(max as u32 + 5).unwrap(), Some((max as u32 + 5) as u64 * 10));
+    }
+
+    #[test]
+    fn root_split_creates_internal_root() {
+        let path = unique_temp_path("root_split");
+        let max = max_leaf_keys();
+        {
+            let mut db = Db::create(&path).unwrap();
+            let upper = (max * 3) as u32;
+            for k in 1..=upper {
+                db.put_u64(k, (k as u64) * 2).unwrap();
+            }
+            db.flush().unwrap();
+        }
+        let mut db = Db::open(&path).unwrap();
+        let pager = db.pager_mut_for_tests();
+        let root = pager.root_page_id();
+        let page_count = pager.page_count();
+        let node = node::Node::decode(pager.get_page(root).unwrap(), page_count).unwrap();
+        match node {
+            Node::Internal(_) => {}
+            _ => panic!("expected internal root after splits"),
+        }
+        assert_eq!(db.get_u64(1).unwrap(), Some(2));
+        assert_eq!(db.get_u64((max * 2) as u32).unwrap(), Some((max * 2) as u64 * 2));
+        assert_eq!(db.get_u64((max * 3) as u32).unwrap(), Some((max * 3) as u64 * 2));
+    }
+
+    #[test]
+    fn persistence_of_root_page_id() {
+        let path = unique_temp_path("root_persist");
+        let max = max_leaf_keys();
+        {
+            let mut db = Db::create(&path).unwrap();
+            for k in 1..=((max * 3) as u32) {
+                db.put_u64(k, (k as u64) + 1).unwrap();
+            }
+            db.flush().unwrap();
+        }
+        let mut db = Db::open(&path).unwrap();
+        assert_ne!(db.pager_mut_for_tests().root_page_id(), ROOT_PAGE_ID);
+        assert_eq!(db.get_u64(5).unwrap(), Some(6));
+        assert_eq!(db.get_u64((max * 3) as u32).unwrap(), Some((max * 3 + 1) as u64));
+    }
+
+    #[test]
+    fn catalog_persists_table_definition() {
+        let path = unique_temp_path("catalog_persist");
+        let schema = Schema::new(vec![
+            Column {
+                name: "id".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "name".to_string(),
+                ty: ColType::String,
+                nullable: true,
+            },
+        ])
+        .unwrap();
+        {
+            let mut db = Db::create(&path).unwrap();
+            let id = db.create_table("users", &schema).unwrap();
+            assert_eq!(id, TableId(1));
+            db.flush().unwrap();
+        }
+
+        let mut db = Db::open(&path).unwrap();
+        let tbl = db.get_table("users").unwrap().expect("table exists");
+        assert_eq!(tbl.id, TableId(1));
+        assert_eq!(tbl.schema, schema);
+    }
+
+    #[test]
+    fn catalog_rejects_duplicate_table_name() {
+        let path = unique_temp_path("catalog_dupe");
+        let schema = Schema::new(vec![Column {
+            name: "id".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("users", &schema).unwrap();
+        let err = db.create_table("users", &schema).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::InvalidArgument {
+                name: "table.name",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn catalog_list_tables() {
+        let path = unique_temp_path("catalog_list");
+        let schema = Schema::new(vec![Column {
+            name: "id".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("a", &schema).unwrap();
+        db.create_table("b", &schema).unwrap();
+        let list = db.list_tables().unwrap();
+        let names: HashSet<_> = list.into_iter().map(|t| t.name).collect();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains("a"));
+        assert!(names.contains("b"));
+    }
+
+    #[test]
+    fn open_rejects_corrupt_catalog_magic_on_read() {
+        let path = unique_temp_path("catalog_corrupt_magic");
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.flush().unwrap();
+        }
+
+        // Corrupt catalog magic byte on disk at payload offset.
+        {
+            let mut f = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            let offset = (2 * PAGE_SIZE + 16) as u64;
+            f.seek(SeekFrom::Start(offset)).unwrap();
+            let mut b = [0u8; 1];
+            f.read_exact(&mut b).unwrap();
+            b[0] ^= 0xFF;
+            f.seek(SeekFrom::Start(offset)).unwrap();
+            f.write_all(&b).unwrap();
+        }
+
+        let err = Db::open(&path).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Corruption {
+                context: "catalog.magic",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn create_requires_catalog_page_kind() {
+        let path = unique_temp_path("catalog_kind");
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.flush().unwrap();
+        }
+        {
+            let mut f = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            let offset = (2 * PAGE_SIZE) as u64; // start of catalog page
+            f.seek(SeekFrom::Start(offset)).unwrap();
+            // Set page_kind byte 0 to 2 (btree kind)
+            f.write_all(&[2]).unwrap();
+        }
+
+        let err = Db::open(&path).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Corruption {
+                context: "catalog.page_kind",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn insert_and_get_row_roundtrip() {
+        let path = unique_temp_path("row_roundtrip");
+        let schema = Schema::new(vec![
+            Column {
+                name: "age".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "name".to_string(),
+                ty: ColType::String,
+                nullable: true,
+            },
+        ])
+        .unwrap();
+        let pk;
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("users", &schema).unwrap();
+            pk = db
+                .insert_row("users", &vec![Value::U32(20), Value::String("kazuha".to_string())])
+                .unwrap();
+            db.flush().unwrap();
+        }
+        let mut db = Db::open(&path).unwrap();
+        let row = db.get_row_by_pk("users", pk).unwrap().unwrap();
+        assert_eq!(row, vec![Value::U32(20), Value::String("kazuha".to_string())]);
+    }
+
+    #[test]
+    fn multiple_inserts_increment_pk() {
+        let path = unique_temp_path("row_pk_inc");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &schema).unwrap();
+        let pk1 = db.insert_row("t", &vec![Value::U32(1)]).unwrap();
+        let pk2 = db.insert_row("t", &vec![Value::U32(2)]).unwrap();
+        let pk3 = db.insert_row("t", &vec![Value::U32(3)]).unwrap();
+        assert_eq!(pk1, 1);
+        assert_eq!(pk2, 2);
+        assert_eq!(pk3, 3);
+        db.flush().unwrap();
+
+        let mut db = Db::open(&path).unwrap();
+        let rows = db.scan_table("t").unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], (1, vec![Value::U32(1)]));
+        assert_eq!(rows[1], (2, vec![Value::U32(2)]));
+        assert_eq!(rows[2], (3, vec![Value::U32(3)]));
+    }
+
+    #[test]
+    fn persistence_of_catalog_pk_state_rows() {
+        let path = unique_temp_path("row_pk_state");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("t", &schema).unwrap();
+            db.insert_row("t", &vec![Value::U32(1)]).unwrap();
+            db.insert_row("t", &vec![Value::U32(2)]).unwrap();
+            db.flush().unwrap();
+        }
+        let mut db = Db::open(&path).unwrap();
+        let pk3 = db.insert_row("t", &vec![Value::U32(3)]).unwrap();
+        assert_eq!(pk3, 3);
+        let row = db.get_row_by_pk("t", 3).unwrap().unwrap();
+        assert_eq!(row, vec![Value::U32(3)]);
+    }
+
+    #[test]
+    fn corruption_detect_pk_mismatch() {
+        let path = unique_temp_path("pk_mismatch");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("t", &schema).unwrap();
+            db.insert_row("t", &vec![Value::U32(10)]).unwrap();
+            db.insert_row("t", &vec![Value::U32(11)]).unwrap();
+            db.flush().unwrap();
+        }
+
+        // Corrupt pk prefix in stored row.
+        {
+            let mut db = Db::open(&path).unwrap();
+            let cat = db.pager.read_catalog().unwrap();
+            let table = cat.get_by_name("t").unwrap();
+            let composite = composite_for_tests(table.id.0, 1);
+            let root = db.pager.root_page_id();
+            let packed = crate::btree::search::search_u64(&mut db.pager, root, composite)
+                .unwrap()
+                .unwrap();
+            let ptr = RowPtr::unpack(packed);
+            let mut f = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            let byte_offset =
+                (ptr.page_id as u64) * (PAGE_SIZE as u64) + (ptr.offset as u64);
+            f.seek(SeekFrom::Start(byte_offset)).unwrap();
+            let mut b = [0u8; 1];
+            f.read_exact(&mut b).unwrap();
+            // pk 1 -> 2: still a live pk, so the prefix check must catch it.
+            b[0] ^= 0x03;
+            f.seek(SeekFrom::Start(byte_offset)).unwrap();
+            f.write_all(&b).unwrap();
+        }
+
+        let mut db = Db::open(&path).unwrap();
+        let err = db.get_row_by_pk("t", 1).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Corruption {
+                context: "table.pk_mismatch",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn rowpage_header_validation() {
+        let path = unique_temp_path("rowpage_magic");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let row_ptr_page;
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("t", &schema).unwrap();
+            db.insert_row("t", &vec![Value::U32(1)]).unwrap();
+
+            let cat = db.pager.read_catalog().unwrap();
+            let table = cat.get_by_name("t").unwrap();
+            let composite = composite_for_tests(table.id.0, 1);
+            let root = db.pager.root_page_id();
+            let packed = crate::btree::search::search_u64(
+                &mut db.pager,
+                root,
+                composite,
+            )
+            .unwrap()
+            .unwrap();
+            let ptr = RowPtr::unpack(packed);
+            row_ptr_page = ptr.page_id;
+            db.flush().unwrap();
+        }
+
+        // Corrupt row page magic
+        {
+            let mut f = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            let offset = (row_ptr_page as u64) * (PAGE_SIZE as u64) + 16;
+            f.seek(SeekFrom::Start(offset)).unwrap();
+            f.write_all(b"XOWP").unwrap();
+        }
+
+        let err = Db::open(&path).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Corruption {
+                context: "rowpage.magic",
+                ..
+            }
+        ));
+    }
+
+    fn pseudo_shuffle(n: u32) -> Vec<u32> {
+        let mut v: Vec<u32> = (1..=n).collect();
+        let mut seed: u64 = 0x1234_5678_9ABC_DEF0;
+        for i in (1..v.len()).rev() {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            let j = (seed >> 33) as usize % (i + 1);
+            v.swap(i, j);
+        }
+        v
+    }
+
+    #[test]
+    fn btree_stress_insert_and_lookup() {
+        let path = unique_temp_path("btree_stress");
+        let keys = pseudo_shuffle(5_000);
+        {
+            let mut db = Db::create(&path).unwrap();
+            for &k in &keys {
+                db.put_u64(k, (k as u64) * 10).unwrap();
+            }
+            db.flush().unwrap();
+        }
+        let mut db = Db::open(&path).unwrap();
+        for k in 1..=5_000u32 {
+            assert_eq!(db.get_u64(k).unwrap(), Some((k as u64) * 10));
+        }
+    }
+
+    #[test]
+    fn table_stress_insert_and_scan() {
+        let path = unique_temp_path("table_stress");
+        let schema = Schema::new(vec![
+            Column {
+                name: "x".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "name".to_string(),
+                ty: ColType::String,
+                nullable: true,
+            },
+        ])
+        .unwrap();
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("items", &schema).unwrap();
+            for i in 0..1000u32 {
+                db.insert_row(
+                    "items",
+                    &vec![Value::U32(i), Value::String(format!("item{}", i))],
+                )
+                .unwrap();
+            }
+            db.flush().unwrap();
+        }
+        let mut db = Db::open(&path).unwrap();
+        let rows = db.scan_table("items").unwrap();
+        assert_eq!(rows.len(), 1000);
+        for (idx, (pk, row)) in rows.iter().enumerate() {
+            assert_eq!(*pk, (idx as u32) + 1);
+            assert_eq!(
+                *row,
+                vec![
+                    Value::U32(idx as u32),
+                    Value::String(format!("item{}", idx))
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn random_access_after_reopen() {
+        let path = unique_temp_path("random_access");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("t", &schema).unwrap();
+            for i in 0..200u32 {
+                db.insert_row("t", &vec![Value::U32(i)]).unwrap();
+            }
+            db.flush().unwrap();
+        }
+        let mut db = Db::open(&path).unwrap();
+        let keys = pseudo_shuffle(200);
+        for pk in keys.into_iter().take(50) {
+            let row = db.get_row_by_pk("t", pk).unwrap().unwrap();
+            assert_eq!(row, vec![Value::U32(pk - 1)]);
+        }
+    }
+
+    #[test]
+    fn corruption_does_not_panic() {
+        let path = unique_temp_path("corruption_no_panic");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let leaf_page_id;
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("t", &schema).unwrap();
+            for i in 0..10u32 {
+                db.insert_row("t", &vec![Value::U32(i)]).unwrap();
+            }
+            let leaves = collect_leaf_chain(db.pager_mut_for_tests());
+            leaf_page_id = leaves[0].0;
+            db.flush().unwrap();
+        }
+        // Corrupt a byte in leaf payload.
+        {
+            let mut f = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            let leaf_payload_offset = (leaf_page_id as u64) * (PAGE_SIZE as u64) + 32;
+            f.seek(SeekFrom::Start(leaf_payload_offset)).unwrap();
+            f.write_all(&[0xFF]).unwrap();
+        }
+
+        let res = Db::open(&path);
+        if let Ok(mut db) = res {
+            let _ = db.get_row_by_pk("t", 1);
+        }
+    }
+
+    #[test]
+    fn btree_depth_growth_is_reasonable() {
+        let path = unique_temp_path("btree_depth");
+        {
+            let mut db = Db::create(&path).unwrap();
+            for k in 1..=5_000u32 {
+                db.put_u64(k, k as u64).unwrap();
+            }
+            db.flush().unwrap();
+        }
+        let mut db = Db::open(&path).unwrap();
+        let pager = db.pager_mut_for_tests();
+        let root_id = pager.root_page_id();
+        let page_count = pager.page_count();
+        let root_node = node::Node::decode(pager.get_page(root_id).unwrap(), page_count).unwrap();
+        match root_node {
+            Node::Internal(ref internal) => {
+                assert_eq!(internal.children.len(), (internal.num_keys as usize) + 1);
+                for child in &internal.children {
+                    assert!(child.0 > 0 && child.0 < page_count);
+                }
+            }
+            _ => panic!("expected internal root for deep tree"),
+        }
+    }
+
+    #[test]
+    fn catalog_and_row_invariants_hold() {
+        let path = unique_temp_path("catalog_invariants");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("a", &schema).unwrap();
+            db.create_table("b", &schema).unwrap();
+            db.insert_row("a", &vec![Value::U32(1)]).unwrap();
+            db.insert_row("a", &vec![Value::U32(2)]).unwrap();
+            db.insert_row("b", &vec![Value::U32(3)]).unwrap();
+            db.flush().unwrap();
+        }
+        let mut db = Db::open(&path).unwrap();
+        let cat = db.pager.read_catalog().unwrap();
+        for table in &cat.tables {
+            let rows = db.scan_table(&table.name).unwrap();
+            let max_pk = rows.iter().map(|(pk, _)| *pk).max().unwrap_or(0);
+            assert!(table.next_pk > max_pk);
+            assert_ne!(table.last_row_page, 0);
+            let page = db
+                .pager
+                .get_page(PageId(table.last_row_page))
+                .expect("row page readable");
+            crate::rowstore::validate_row_page_header(page.as_bytes()).unwrap();
+        }
+    }
+
+    #[test]
+    fn open_detects_corrupt_root_page() {
+        let path = unique_temp_path("corrupt_root_kind");
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.flush().unwrap();
+        }
+        {
+            let mut f = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            let offset = PAGE_SIZE as u64; // root page start
+            f.seek(SeekFrom::Start(offset)).unwrap();
+            f.write_all(&[config::META_PAGE_KIND]).unwrap();
+        }
+        let err = Db::open(&path).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Corruption {
+                context: "btree.page_kind",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn open_detects_catalog_duplicate_ids() {
+        let path = unique_temp_path("catalog_dup_ids");
+        {
+            let mut db = Db::create(&path).unwrap();
+            let schema = Schema::new(vec![Column {
+                name: "id".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            }])
+            .unwrap();
+            db.create_table("a", &schema).unwrap();
+            db.create_table("b", &schema).unwrap();
+            // Corrupt catalog to duplicate ids
+            let mut cat = db.pager.read_catalog().unwrap();
+            let first_id = cat.tables[0].id;
+            cat.tables[1].id = first_id;
+            db.pager.write_catalog(&cat).unwrap();
+            db.flush().unwrap();
+        }
+
+        let err = Db::open(&path).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Corruption {
+                context: "catalog.duplicate",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn open_detects_row_page_magic_corruption() {
+        let path = unique_temp_path("row_magic_open");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let row_page_id;
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("t", &schema).unwrap();
+            db.insert_row("t", &vec![Value::U32(7)]).unwrap();
+
+            let cat = db.pager.read_catalog().unwrap();
+            let table = cat.get_by_name("t").unwrap();
+            let composite = composite_for_tests(table.id.0, 1);
+            let root = db.pager.root_page_id();
+            let packed =
+                crate::btree::search::search_u64(&mut db.pager, root, composite)
+                    .unwrap()
+                    .unwrap();
+            let ptr = RowPtr::unpack(packed);
+            row_page_id = ptr.page_id;
+            db.flush().unwrap();
+        }
+
+        {
+            let mut f = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            let offset = (row_page_id as u64) * (PAGE_SIZE as u64) + 16;
+            f.seek(SeekFrom::Start(offset)).unwrap();
+            f.write_all(b"BAD!").unwrap();
+        }
+
+        let err = Db::open(&path).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Corruption {
+                context: "rowpage.magic",
+                ..
+            }
+        ));
+    }
+
+    fn collect_leaf_chain(pager: &mut Pager) -> Vec<PageId> {
+        let page_count = pager.page_count();
+        let mut ids = Vec::new();
+        let mut current = {
+            let root = pager.root_page_id();
+            // leftmost leaf
+            let mut cur = root;
+            loop {
+                let node = node::Node::decode(
+                    pager.get_page(cur).unwrap(),
+                    page_count,
+                )
+                .unwrap();
+                match node {
+                    Node::Leaf(_) => break cur,
+                    Node::Internal(int) => {
+                        cur = int.children[0];
+                    }
+                }
+            }
+        };
+        loop {
+            ids.push(current);
+            let node = node::Node::decode(
+                pager.get_page(current).unwrap(),
+                page_count,
+            )
+            .unwrap();
+            match node {
+                Node::Leaf(l) => {
+                    if l.next_leaf.0 == 0 {
+                        break;
+                    }
+                    current = PageId(l.next_leaf.0);
+                }
+                _ => break,
+            }
+        }
+        ids
+    }
+
+    #[test]
+    fn open_detects_leaf_cycle() {
+        let path = unique_temp_path("leaf_cycle");
+        {
+            let mut db = Db::create(&path).unwrap();
+            let schema = Schema::new(vec![Column {
+                name: "v".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            }])
+            .unwrap();
+            db.create_table("t", &schema).unwrap();
+            let inserts = (max_leaf_keys() as u32) + 10;
+            for i in 0..inserts {
+                db.insert_row("t", &vec![Value::U32(i)]).unwrap();
+            }
+            let leaves = collect_leaf_chain(db.pager_mut_for_tests());
+            assert!(leaves.len() >= 2, "expected multiple leaves");
+            let first = leaves[0];
+            let second = leaves[1];
+            db.flush().unwrap();
+
+            // Corrupt second leaf next_leaf to point back to first.
+            let mut f = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            let offset = (second.0 as u64) * (PAGE_SIZE as u64) + 16 + 8;
+            f.seek(SeekFrom::Start(offset)).unwrap();
+            f.write_all(&first.0.to_le_bytes()).unwrap();
+        }
+
+        let err = Db::open(&path).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Corruption {
+                context: "btree.leaf_cycle",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn open_valid_database_passes() {
+        let path = unique_temp_path("open_valid");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("t", &schema).unwrap();
+            db.insert_row("t", &vec![Value::U32(42)]).unwrap();
+            db.flush().unwrap();
+        }
+
+        let mut db = Db::open(&path).unwrap();
+        let row = db.get_row_by_pk("t", 1).unwrap().unwrap();
+        assert_eq!(row, vec![Value::U32(42)]);
+    }
+
+    #[test]
+    fn schema_validation_rejects_duplicates() {
+        let cols = vec![
+            Column {
+                name: "id".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "id".to_string(),
+                ty: ColType::U64,
+                nullable: false,
+            },
+        ];
+        let err = Schema::new(cols).unwrap_err();
+        assert!(matches!(err, InvError::InvalidArgument { name: "column.name", .. }));
+    }
+
+    #[test]
+    fn row_roundtrip_basic() {
+        let schema = Schema::new(vec![
+            Column {
+                name: "id".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "score".to_string(),
+                ty: ColType::U64,
+                nullable: false,
+            },
+            Column {
+                name: "ok".to_string(),
+                ty: ColType::Bool,
+                nullable: false,
+            },
+        ])
+        .unwrap();
+        let row = vec![Value::U32(7), Value::U64(9001), Value::Bool(true)];
+        let bytes = encode_row(&schema, &row).unwrap();
+        let decoded = decode_row(&schema, &bytes).unwrap();
+        assert_eq!(row, decoded);
+    }
+
+    #[test]
+    fn row_roundtrip_bytes_string() {
+        let schema = Schema::new(vec![
+            Column {
+                name: "payload".to_string(),
+                ty: ColType::Bytes,
+                nullable: false,
+            },
+            Column {
+                name: "name".to_string(),
+                ty: ColType::String,
+                nullable: true,
+            },
+        ])
+        .unwrap();
+        let row = vec![Value::Bytes(vec![1, 2, 3]), Value::String("abc".to_string())];
+        let bytes = encode_row(&schema, &row).unwrap();
+        let decoded = decode_row(&schema, &bytes).unwrap();
+        assert_eq!(row, decoded);
+    }
+
+    #[test]
+    fn row_rejects_null_for_nonnullable() {
+        let schema = Schema::new(vec![Column {
+            name: "x".to_string(),
+            ty: ColType::U64,
+            nullable: false,
+        }])
+        .unwrap();
+        let row = vec![Value::Null];
+        let err = encode_row(&schema, &row).unwrap_err();
+        assert!(matches!(err, InvError::InvalidArgument { name: "row.null", .. }));
+    }
+
+    #[test]
+    fn decode_rejects_bad_magic() {
+        let schema = Schema::new(vec![Column {
+            name: "x".to_string(),
+            ty: ColType::U64,
+            nullable: false,
+        }])
+        .unwrap();
+        let row = vec![Value::U64(1)];
+        let mut bytes = encode_row(&schema, &row).unwrap();
+        bytes[0] ^= 0xFF;
+        let err = decode_row(&schema, &bytes).unwrap_err();
+        assert!(matches!(err, InvError::Corruption { context: "row.magic", .. }));
+    }
+
+    #[test]
+    fn decode_rejects_trailing_bytes() {
+        let schema = Schema::new(vec![Column {
+            name: "x".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let row = vec![Value::U32(5)];
+        let mut bytes = encode_row(&schema, &row).unwrap();
+        bytes.push(0xAA);
+        let err = decode_row(&schema, &bytes).unwrap_err();
+        assert!(matches!(err, InvError::Corruption { context: "row.trailing", .. }));
+    }
+
+    #[test]
+    fn decode_rejects_bad_bool() {
+        let schema = Schema::new(vec![Column {
+            name: "b".to_string(),
+            ty: ColType::Bool,
+            nullable: false,
+        }])
+        .unwrap();
+        // Manually craft bytes: magic + count + tag + invalid bool byte
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"ROW1");
+        crate::encoding::write_var_u64(&mut bytes, 1);
+        bytes.push(0x04);
+        bytes.push(2);
+        let err = decode_row(&schema, &bytes).unwrap_err();
+        assert!(matches!(err, InvError::Corruption { context: "row.bool", .. }));
+    }
+
+    #[test]
+    fn display_formats_without_panic() {
+        let err = InvError::Overflow {
+            context: "test overflow",
+        };
+        let _ = format!("{}", err);
+    }
+
+    #[test]
+    fn schema_export_import_roundtrip() {
+        let src_path = unique_temp_path("schema_export_src");
+        let dst_path = unique_temp_path("schema_export_dst");
+        let schema = Schema::new(vec![
+            Column {
+                name: "id".to_string(),
+                ty: ColType::U64,
+                nullable: false,
+            },
+            Column {
+                name: "label".to_string(),
+                ty: ColType::String,
+                nullable: true,
+            },
+        ])
+        .unwrap();
+        let bytes = {
+            let mut src = Db::create(&src_path).unwrap();
+            src.create_table("events", &schema).unwrap();
+            src.export_table_schema("events").unwrap()
+        };
+
+        let mut dst = Db::create(&dst_path).unwrap();
+        dst.create_table_from_schema_bytes("events", &bytes).unwrap();
+        dst.flush().unwrap();
+
+        let mut reopened = Db::open(&dst_path).unwrap();
+        let tbl = reopened.get_table("events").unwrap().expect("table exists");
+        assert_eq!(tbl.schema, schema);
+        assert_eq!(reopened.export_table_schema("events").unwrap(), bytes);
+
+        let err = reopened.export_table_schema("missing").unwrap_err();
+        assert!(matches!(err, InvError::InvalidArgument { name: "table", .. }));
+        let err = reopened
+            .create_table_from_schema_bytes("broken", b"XXXX")
+            .unwrap_err();
+        assert!(matches!(err, InvError::Corruption { context: "schema.magic", .. }));
+    }
+
+
+    /// Store crafted row bytes under `pk`, bypassing `encode_row`.
+    fn insert_raw_row(db: &mut Db, table_name: &str, pk: u32, encoded: &[u8]) {
+        let mut cat = db.pager.read_catalog().unwrap();
+        let table = cat.tables.iter_mut().find(|t| t.name == table_name).unwrap();
+        let mut stored = pk.to_le_bytes().to_vec();
+        stored.extend_from_slice(encoded);
+        let ptr = crate::table::append_stored_row(&mut db.pager, table, &stored).unwrap();
+        table.next_pk = table.next_pk.max(pk + 1);
+        let composite = composite_for_tests(table.id.0, pk);
+        db.pager.write_catalog(&cat).unwrap();
+        let root = db.pager.root_page_id();
+        let new_root =
+            crate::btree::insert::insert_u64(&mut db.pager, root, composite, ptr.pack()).unwrap();
+        if new_root != root {
+            db.pager.set_root_page_id(new_root).unwrap();
+        }
+    }
+
+    #[test]
+    fn strict_reads_reject_non_canonical_row() {
+        let path = unique_temp_path("strict_reads");
+        let schema = Schema::new(vec![
+            Column {
+                name: "n".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "s".to_string(),
+                ty: ColType::String,
+                nullable: true,
+            },
+        ])
+        .unwrap();
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("t", &schema).unwrap();
+            db.insert_row("t", &vec![Value::U32(1), Value::Null]).unwrap();
+            // Valid values, but the string length uses an overlong varint.
+            let mut crafted = b"ROW1".to_vec();
+            crafted.push(2);
+            crafted.push(0x01);
+            crafted.extend_from_slice(&7u32.to_le_bytes());
+            crafted.push(0x06);
+            crafted.extend_from_slice(&[0x83, 0x00]);
+            crafted.extend_from_slice(b"abc");
+            insert_raw_row(&mut db, "t", 2, &crafted);
+            // Canonical and of the right types, but a string the column
+            // refuses: only re-encoding the row notices the NUL.
+            db.create_table("w", &schema.clone().reject_interior_nul("s").unwrap()).unwrap();
+            let mut refused = b"ROW1".to_vec();
+            refused.push(2);
+            refused.push(0x01);
+            refused.extend_from_slice(&7u32.to_le_bytes());
+            refused.push(0x06);
+            refused.push(3);
+            refused.extend_from_slice(b"a\0b");
+            insert_raw_row(&mut db, "w", 1, &refused);
+            db.flush().unwrap();
+        }
+
+        let mut lenient = Db::open(&path).unwrap();
+        assert!(!lenient.options().strict_reads);
+        assert_eq!(
+            lenient.get_row_by_pk("t", 2).unwrap().unwrap(),
+            vec![Value::U32(7), Value::String("abc".to_string())]
+        );
+        assert_eq!(lenient.scan_table("t").unwrap().len(), 2);
+        assert_eq!(
+            lenient.get_row_by_pk("w", 1).unwrap().unwrap(),
+            vec![Value::U32(7), Value::String("a\0b".to_string())]
+        );
+        assert_eq!(lenient.scan_table("w").unwrap().len(), 1);
+        drop(lenient);
+
+        let options = DbOptions { strict_reads: true, ..DbOptions::default() };
+        let mut strict = Db::open_with_options(&path, options).unwrap();
+        assert_eq!(
+            strict.get_row_by_pk("t", 1).unwrap().unwrap(),
+            vec![Value::U32(1), Value::Null]
+        );
+        let err = strict.get_row_by_pk("t", 2).unwrap_err();
+        assert!(matches!(err, InvError::Corruption { context: "row.strict", .. }));
+        let err = strict.scan_table("t").unwrap_err();
+        assert!(matches!(err, InvError::Corruption { context: "row.strict", .. }));
+        let err = strict.get_row_by_pk("w", 1).unwrap_err();
+        assert!(matches!(err, InvError::Corruption { context: "row.strict", .. }));
+        let err = strict.scan_table("w").unwrap_err();
+        assert!(matches!(err, InvError::Corruption { context: "row.strict", .. }));
+    }
+
+    #[test]
+    fn verify_row_strict_rejects_type_drift() {
+        let schema = Schema::new(vec![Column {
+            name: "n".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let bytes = encode_row(&schema, &vec![Value::U32(3)]).unwrap();
+        assert!(crate::row::verify_row_strict(&schema, &vec![Value::U32(3)], &bytes).is_ok());
+        let err = crate::row::verify_row_strict(&schema, &vec![Value::U64(3)], &bytes).unwrap_err();
+        assert!(matches!(err, InvError::Corruption { context: "row.strict", .. }));
+        let err = crate::row::verify_row_strict(&schema, &vec![Value::Null], &bytes).unwrap_err();
+        assert!(matches!(err, InvError::Corruption { context: "row.strict", .. }));
+    }
+
+
+    fn row_page_free_offset(db: &mut Db, page: PageId) -> u16 {
+        let buf = db.pager.get_page(page).unwrap().as_bytes();
+        u16::from_le_bytes([buf[22], buf[23]])
+    }
+
+    #[test]
+    fn defragment_row_page_drops_dead_slots() {
+        let path = unique_temp_path("defrag_page");
+        let schema = Schema::new(vec![
+            Column {
+                name: "n".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "s".to_string(),
+                ty: ColType::String,
+                nullable: false,
+            },
+        ])
+        .unwrap();
+        let page;
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("t", &schema).unwrap();
+            for i in 1..=20u32 {
+                db.insert_row("t", &vec![Value::U32(i), Value::String(format!("row-{}", i))])
+                    .unwrap();
+            }
+            page = PageId(db.get_table("t").unwrap().unwrap().last_row_page);
+            for pk in (2..=20u32).step_by(2) {
+                assert!(db.delete_row("t", pk).unwrap());
+            }
+            assert!(!db.delete_row("t", 2).unwrap());
+            let before = row_page_free_offset(&mut db, page);
+            db.defragment_row_page(page).unwrap();
+            let after = row_page_free_offset(&mut db, page);
+            assert!(after < before, "free_offset {} should shrink below {}", after, before);
+            db.flush().unwrap();
+        }
+
+        let mut db = Db::open(&path).unwrap();
+        for pk in 1..=20u32 {
+            let row = db.get_row_by_pk("t", pk).unwrap();
+            if pk % 2 == 1 {
+                assert_eq!(
+                    row,
+                    Some(vec![Value::U32(pk), Value::String(format!("row-{}", pk))])
+                );
+            } else {
+                assert!(row.is_none());
+            }
+        }
+        let pk = db.insert_row("t", &vec![Value::U32(99), Value::String("new".to_string())]).unwrap();
+        assert_eq!(
+            db.get_row_by_pk("t", pk).unwrap().unwrap(),
+            vec![Value::U32(99), Value::String("new".to_string())]
+        );
+        let err = db.defragment_row_page(ROOT_PAGE_ID).unwrap_err();
+        assert!(matches!(err, InvError::InvalidArgument { name: "page_id", .. }));
+    }
+
+
+    #[test]
+    fn decode_row_reuse_overwrites_buffer() {
+        let schema = Schema::new(vec![
+            Column {
+                name: "id".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "name".to_string(),
+                ty: ColType::String,
+                nullable: true,
+            },
+            Column {
+                name: "blob".to_string(),
+                ty: ColType::Bytes,
+                nullable: true,
+            },
+        ])
+        .unwrap();
+        let first = vec![
+            Value::U32(1),
+            Value::String("a much longer first name".to_string()),
+            Value::Bytes(vec![9; 32]),
+        ];
+        let second = vec![Value::U32(2), Value::String("b".to_string()), Value::Null];
+        let mut buf = Vec::new();
+        decode_row_reuse(&schema, &encode_row(&schema, &first).unwrap(), &mut buf).unwrap();
+        assert_eq!(buf, first);
+        let name_capacity = match &buf[1] {
+            Value::String(s) => s.capacity(),
+            _ => unreachable!(),
+        };
+        decode_row_reuse(&schema, &encode_row(&schema, &second).unwrap(), &mut buf).unwrap();
+        assert_eq!(buf, second);
+        match &buf[1] {
+            Value::String(s) => assert_eq!(s.capacity(), name_capacity),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn for_each_row_streams_in_pk_order() {
+        let path = unique_temp_path("for_each_row");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &schema).unwrap();
+        for i in 0..50u32 {
+            db.insert_row("t", &vec![Value::U32(i * 3)]).unwrap();
+        }
+        db.delete_row("t", 10).unwrap();
+        let mut seen = Vec::new();
+        db.for_each_row("t", |pk, row| {
+            seen.push((pk, row.clone()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(seen, db.scan_table("t").unwrap());
+
+        let mut calls = 0;
+        let err = db
+            .for_each_row("t", |_, _| {
+                calls += 1;
+                Err(InvError::invalid_arg("stop", "callback abort"))
+            })
+            .unwrap_err();
+        assert!(matches!(err, InvError::InvalidArgument { name: "stop", .. }));
+        assert_eq!(calls, 1);
+    }
+
+
+    #[test]
+    fn count_u64_range_matches_brute_force() {
+        let path = unique_temp_path("count_range");
+        let keys: Vec<u32> = pseudo_shuffle(5_000).into_iter().map(|k| k * 2).collect();
+        let mut db = Db::create(&path).unwrap();
+        for &k in &keys {
+            db.put_u64(k, k as u64).unwrap();
+        }
+        for (lo, hi) in [(1_001u32, 7_777u32), (0, 20_000), (4_000, 4_001), (9_999, 10_001)] {
+            let expected = keys.iter().filter(|&&k| k >= lo && k < hi).count() as u64;
+            assert_eq!(db.count_u64_range(lo, hi).unwrap(), expected, "range {}..{}", lo, hi);
+        }
+        assert_eq!(db.count_u64_range(500, 500).unwrap(), 0);
+        assert_eq!(db.count_u64_range(600, 100).unwrap(), 0);
+    }
+
+
+    #[test]
+    fn flush_coalesces_adjacent_dirty_pages() {
+        let path = unique_temp_path("flush_coalesce");
+        let mut db = Db::create(&path).unwrap();
+        let mut pages = Vec::new();
+        for _ in 0..100 {
+            pages.push(db.pager.allocate_row_page().unwrap());
+        }
+        db.flush().unwrap();
+        for &id in &pages {
+            let page = db.pager.get_page_mut(id).unwrap();
+            page.as_bytes_mut()[40] = 0xAB;
+        }
+        let before = db.io_stats();
+        db.flush().unwrap();
+        let after = db.io_stats();
+        assert_eq!(after.pages_written - before.pages_written, 101);
+        assert!(after.writes - before.writes <= 3, "issued {} writes", after.writes - before.writes);
+        drop(db);
+
+        let mut db = Db::open(&path).unwrap();
+        for &id in &pages {
+            assert_eq!(db.pager.get_page(id).unwrap().as_bytes()[40], 0xAB);
+        }
+    }
+
+
+    #[test]
+    fn decode_rejects_overlong_names_at_read() {
+        let long_name = vec![b'a'; 100];
+
+        let mut payload = Vec::new();
+        payload.extend_from_slice(b"CAT1");
+        payload.extend_from_slice(&1u16.to_le_bytes());
+        payload.extend_from_slice(&1u16.to_le_bytes());
+        payload.extend_from_slice(&2u32.to_le_bytes());
+        payload.extend_from_slice(&0u32.to_le_bytes());
+        payload.extend_from_slice(&1u32.to_le_bytes());
+        crate::encoding::write_bytes(&mut payload, &long_name);
+        let err = crate::catalog::decode_catalog(&payload).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Corruption {
+                context: "encoding.bytes.too_large",
+                ..
+            }
+        ));
+
+        let mut schema_bytes = b"SCH1".to_vec();
+        crate::encoding::write_var_u64(&mut schema_bytes, 1);
+        crate::encoding::write_bytes(&mut schema_bytes, &long_name);
+        schema_bytes.extend_from_slice(&[1, 0]);
+        let err = crate::catalog::decode_schema(&schema_bytes).unwrap_err();
+        assert!(matches!(
+            err,
+            InvError::Corruption {
+                context: "encoding.bytes.too_large",
+                ..
+            }
+        ));
+
+        let too_long = "t".repeat(config::MAX_NAME_BYTES + 1);
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(unique_temp_path("name_limit")).unwrap();
+        let err = db.create_table(&too_long, &schema).unwrap_err();
+        assert!(matches!(err, InvError::InvalidArgument { name: "table.name", .. }));
+        db.create_table(&too_long[1..], &schema).unwrap();
+    }
+
+
+    #[test]
+    fn composite_pk_inverts_composite_key() {
+        for table_id in [1u32, 2, 77, u32::MAX] {
+            for pk in [1u32, 2, 1000, 123_456_789, u32::MAX] {
+                let key = crate::table::composite_key(table_id, pk);
+                assert_eq!(crate::table::composite_pk(table_id, key), pk);
+            }
+        }
+    }
+
+    #[test]
+    fn list_pks_returns_surviving_pks_sorted() {
+        let path = unique_temp_path("list_pks");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("a", &schema).unwrap();
+        db.create_table("b", &schema).unwrap();
+        for i in 0..600u32 {
+            db.insert_row("a", &vec![Value::U32(i)]).unwrap();
+            db.insert_row("b", &vec![Value::U32(i)]).unwrap();
+        }
+        for k in 1..=200u32 {
+            db.put_u64(k, k as u64).unwrap();
+        }
+        let deleted: HashSet<u32> = [1u32, 7, 300, 599, 600].into_iter().collect();
+        for &pk in &deleted {
+            assert!(db.delete_row("a", pk).unwrap());
+        }
+        let expected: Vec<u32> = (1..=600u32).filter(|pk| !deleted.contains(pk)).collect();
+        assert_eq!(db.list_pks("a").unwrap(), expected);
+        assert_eq!(db.list_pks("b").unwrap(), (1..=600u32).collect::<Vec<_>>());
+
+        // A damaged row is reported, not skipped.
+        let table = db.get_table("a").unwrap().unwrap();
+        let root = db.pager.root_page_id();
+        let composite = composite_for_tests(table.id.0, 2);
+        let raw = crate::btree::search::search_u64(&mut db.pager, root, composite).unwrap().unwrap();
+        let ptr = crate::rowstore::RowPtr::unpack(raw);
+        db.pager.get_page_mut(PageId(ptr.page_id)).unwrap().as_bytes_mut()[ptr.offset as usize - 2] ^= 0x01;
+        let err = db.list_pks("a").unwrap_err();
+        assert!(matches!(err, InvError::Corruption { .. }));
+    }
+
+    #[test]
+    fn list_pks_reads_clustered_and_unchained_tables() {
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(unique_temp_path("list_pks_kinds")).unwrap();
+        db.create_clustered_table("c", &schema, "v").unwrap();
+        db.create_table("t", &schema).unwrap();
+        for i in 0..300u32 {
+            db.insert_row("c", &vec![Value::U32((i * 7) % 300)]).unwrap();
+            db.insert_row("t", &vec![Value::U32(i)]).unwrap();
         }
-        if !visited.insert(current.0) {
-            return Err(InvError::Corruption {
-                context: "btree.leaf_cycle",
-                details: format!("cycle detected at {}", current.0),
-            });
+        for k in 1..=200u32 {
+            db.put_u64(k, k as u64).unwrap();
         }
-        let page = pager.get_page(current)?;
-        let node = Node::decode(page, page_count)?;
-        let leaf = match node {
-            Node::Leaf(l) => l,
-            _ => {
-                return Err(InvError::Corruption {
-                    context: "btree.leaf_cycle",
-                    details: "expected leaf during traversal".to_string(),
-                })
+        let deleted: HashSet<u32> = [1u32, 2, 150, 299, 300].into_iter().collect();
+        for &pk in &deleted {
+            assert!(db.delete_row("c", pk).unwrap());
+            assert!(db.delete_row("t", pk).unwrap());
+        }
+        let expected: Vec<u32> = (1..=300u32).filter(|pk| !deleted.contains(pk)).collect();
+        assert_eq!(db.list_pks("c").unwrap(), expected);
+        let scanned: Vec<u32> = db.scan_table("c").unwrap().into_iter().map(|(pk, _)| pk).collect();
+        assert_eq!(scanned, expected);
+
+        // A table written before row pages were chained is read through the index.
+        let mut cat = db.pager.read_catalog().unwrap();
+        cat.tables.iter_mut().find(|t| t.name == "t").unwrap().first_row_page = None;
+        db.pager.write_catalog(&cat).unwrap();
+        assert_eq!(db.list_pks("t").unwrap(), expected);
+        assert_eq!(db.scan_table("t").unwrap().len(), expected.len());
+    }
+
+
+    #[test]
+    fn open_moves_shared_index_u64_rows_into_a_pk_tree() {
+        let path = unique_temp_path("u64_shared_index");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U64,
+            nullable: false,
+        }])
+        .unwrap();
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table_u64_pk("t", &schema).unwrap();
+            for v in 0..300u64 {
+                db.insert_row_u64("t", &vec![Value::U64(v)]).unwrap();
             }
-        };
-        let next = leaf.next_leaf.0;
-        if next == 0 {
-            break;
+            for k in 1..=100u32 {
+                db.put_u64(k, k as u64).unwrap();
+            }
+
+            // Rewrite the table the way files written before pk trees kept it.
+            let mut cat = db.pager.read_catalog().unwrap();
+            let table = cat.tables.iter_mut().find(|t| t.name == "t").unwrap();
+            let tree = table.pk_root.take().unwrap();
+            let mut root = db.pager.root_page_id();
+            for (pk, value) in crate::pktree::entries(&mut db.pager, tree).unwrap() {
+                let key = composite_for_tests(table.id.0, pk as u32);
+                root = crate::btree::insert_u64(&mut db.pager, root, key, value).unwrap();
+            }
+            for page in crate::pktree::pages(&mut db.pager, tree).unwrap() {
+                db.pager.free_page(page).unwrap();
+            }
+            db.pager.set_root_page_id(root).unwrap();
+            db.pager.write_catalog(&cat).unwrap();
+            db.flush().unwrap();
         }
-        if next >= page_count {
-            return Err(InvError::Corruption {
-                context: "btree.leaf_cycle",
-                details: format!("invalid next_leaf {} from {}", next, current.0),
-            });
+
+        let mut db = Db::open(&path).unwrap();
+        let table = db.get_table("t").unwrap().unwrap();
+        assert!(table.pk_root.is_some());
+        let rows = crate::table::scan_rows_u64(&mut db.pager, &table, true).unwrap();
+        assert_eq!(rows, (0..300u64).map(|v| (v + 1, vec![Value::U64(v)])).collect::<Vec<_>>());
+        assert_eq!(db.get_row_by_pk_u64("t", 300).unwrap(), Some(vec![Value::U64(299)]));
+        for k in 1..=100u32 {
+            assert_eq!(db.get_u64(k).unwrap(), Some(k as u64));
         }
-        current = PageId(next);
-        steps += 1;
+        assert_eq!(db.u64_key_count().unwrap(), 100);
+        assert_eq!(db.insert_row_u64("t", &vec![Value::U64(300)]).unwrap(), 301);
+        db.check_integrity().unwrap();
+        drop(db);
+        let mut db = Db::open(&path).unwrap();
+        let table = db.get_table("t").unwrap().unwrap();
+        assert_eq!(crate::table::scan_rows_u64(&mut db.pager, &table, false).unwrap().len(), 301);
     }
-    Ok(())
-}
 
-fn find_leftmost_leaf(pager: &mut Pager, mut current: PageId, page_count: u32) -> InvResult<PageId> {
-    let mut depth = 0usize;
-    loop {
-        if depth > 64 {
-            return Err(InvError::Corruption {
-                context: "btree.depth",
-                details: "exceeded max depth while finding leaf".to_string(),
-            });
+    #[test]
+    fn u64_pk_table_allocates_past_u32_max() {
+        let path = unique_temp_path("u64_pk");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U64,
+            nullable: false,
+        }])
+        .unwrap();
+        let start = u32::MAX as u64 - 2;
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table_u64_pk("events", &schema).unwrap();
+            db.create_table("narrow", &schema).unwrap();
+            let mut cat = db.pager.read_catalog().unwrap();
+            cat.tables[0].next_pk_u64 = Some(start);
+            db.pager.write_catalog(&cat).unwrap();
+
+            for i in 0..6u64 {
+                assert_eq!(db.insert_row_u64("events", &vec![Value::U64(i)]).unwrap(), start + i);
+            }
+            db.insert_row("narrow", &vec![Value::U64(9)]).unwrap();
+            assert!(matches!(
+                db.insert_row("events", &vec![Value::U64(0)]).unwrap_err(),
+                InvError::Unsupported { feature: "table.pk_u64" }
+            ));
+            assert!(matches!(
+                db.insert_row_u64("narrow", &vec![Value::U64(0)]).unwrap_err(),
+                InvError::InvalidArgument { name: "table", .. }
+            ));
         }
-        let page = pager.get_page(current)?;
-        let node = Node::decode(page, page_count)?;
-        match node {
-            Node::Leaf(_) => return Ok(current),
-            Node::Internal(internal) => {
-                if internal.children.is_empty() {
-                    return Err(InvError::Corruption {
-                        context: "btree.internal.child",
-                        details: "internal node has no children".to_string(),
-                    });
-                }
-                current = internal.children[0];
+
+        let mut db = Db::open(&path).unwrap();
+        let table = db.get_table("events").unwrap().unwrap();
+        assert_eq!(table.next_pk_u64, Some(start + 6));
+        for i in 0..6u64 {
+            assert_eq!(db.get_row_by_pk_u64("events", start + i).unwrap(), Some(vec![Value::U64(i)]));
+        }
+        // Same low 32 bits as a live pk, but a different pk.
+        assert_eq!(db.get_row_by_pk_u64("events", start + (1u64 << 32)).unwrap(), None);
+        assert_eq!(db.get_row_by_pk("narrow", 1).unwrap(), Some(vec![Value::U64(9)]));
+    }
+
+    #[test]
+    fn u64_pks_2_32_apart_are_both_live() {
+        let path = unique_temp_path("u64_pk_wide");
+        let copy_path = unique_temp_path("u64_pk_wide_copy");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let far = 1 + (1u64 << 32);
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table_u64_pk("events", &schema).unwrap();
+            db.create_table("narrow", &schema).unwrap();
+            assert_eq!(db.insert_row_u64("events", &vec![Value::U32(1)]).unwrap(), 1);
+            let mut cat = db.pager.read_catalog().unwrap();
+            cat.tables[0].next_pk_u64 = Some(far);
+            db.pager.write_catalog(&cat).unwrap();
+            assert_eq!(db.insert_row_u64("events", &vec![Value::U32(2)]).unwrap(), far);
+            for k in 0..500u32 {
+                db.put_u64(k, u64::MAX).unwrap();
+                db.insert_row("narrow", &vec![Value::U32(k)]).unwrap();
             }
+            db.flush().unwrap();
         }
-        depth += 1;
+
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.get_row_by_pk_u64("events", 1).unwrap(), Some(vec![Value::U32(1)]));
+        assert_eq!(db.get_row_by_pk_u64("events", far).unwrap(), Some(vec![Value::U32(2)]));
+        assert_eq!(db.get_row_by_pk_u64("events", 2).unwrap(), None);
+        assert_eq!(db.get_u64(1).unwrap(), Some(u64::MAX));
+        assert_eq!(db.scan_table("narrow").unwrap().len(), 500);
+        assert!(db.find_orphan_pages().unwrap().is_empty());
+
+        db.compact_to(&copy_path).unwrap();
+        let mut copy = Db::open(&copy_path).unwrap();
+        assert_eq!(copy.get_row_by_pk_u64("events", far).unwrap(), Some(vec![Value::U32(2)]));
+        assert_eq!(copy.get_row_by_pk_u64("events", 1).unwrap(), Some(vec![Value::U32(1)]));
+        assert!(copy.find_orphan_pages().unwrap().is_empty());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::btree::node::{self, Node};
-    use crate::btree::node::max_leaf_keys;
-    use crate::config::{MAX_SUPPORTED_VERSION, MIN_SUPPORTED_VERSION, PAGE_SIZE, ROOT_PAGE_ID};
-    use crate::rowstore::RowPtr;
-    use crate::table::composite_for_tests;
-    use crate::types::checked_page_index;
-    use std::collections::HashSet;
-    use std::fs::OpenOptions;
-    use std::io::{Read, Seek, SeekFrom, Write};
-    use std::path::PathBuf;
-    use std::time::{SystemTime, UNIX_EPOCH};
+    #[test]
+    fn defragment_keeps_u64_pk_rows() {
+        let schema = Schema::new(vec![Column {
+            name: "s".to_string(),
+            ty: ColType::String,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(unique_temp_path("u64_pk_defrag")).unwrap();
+        db.create_table_u64_pk("events", &schema).unwrap();
+        db.create_table("t", &schema).unwrap();
+        for i in 0..10u32 {
+            db.insert_row_u64("events", &vec![Value::String(format!("e{}", i))]).unwrap();
+            db.insert_row("t", &vec![Value::String(format!("t{}", i))]).unwrap();
+        }
+        for pk in [2u32, 5, 8] {
+            db.delete_row("t", pk).unwrap();
+        }
+        let page = PageId(db.get_table("t").unwrap().unwrap().last_row_page);
+        db.defragment_row_page(page).unwrap();
+        for i in 0..10u64 {
+            assert_eq!(
+                db.get_row_by_pk_u64("events", i + 1).unwrap(),
+                Some(vec![Value::String(format!("e{}", i))])
+            );
+        }
+        assert_eq!(db.get_row_by_pk("t", 5).unwrap(), None);
+        assert_eq!(db.get_row_by_pk("t", 6).unwrap(), Some(vec![Value::String("t5".to_string())]));
+    }
 
     #[test]
-    fn validate_version_accepts_current() {
-        assert!(config::validate_version(config::FILE_FORMAT_VERSION).is_ok());
+    fn defragment_keeps_blobs() {
+        let mut db = Db::create(unique_temp_path("blob_defrag")).unwrap();
+        db.put_blob(1, b"first").unwrap();
+        db.put_blob(2, b"second").unwrap();
+        // Replacing blob 1 leaves its old bytes dead ahead of blob 2's.
+        db.put_blob(1, b"replaced").unwrap();
+        let page = PageId(db.pager.read_catalog().unwrap().blob_last_row_page);
+        db.defragment_row_page(page).unwrap();
+        assert_eq!(db.get_blob(1).unwrap(), Some(b"replaced".to_vec()));
+        assert_eq!(db.get_blob(2).unwrap(), Some(b"second".to_vec()));
+        db.check_integrity().unwrap();
     }
 
+
     #[test]
-    fn validate_version_rejects_out_of_range() {
-        assert!(config::validate_version(0).is_err());
-        assert!(config::validate_version(MAX_SUPPORTED_VERSION.saturating_add(1)).is_err());
-        if MIN_SUPPORTED_VERSION > 0 {
-            assert!(config::validate_version(MIN_SUPPORTED_VERSION - 1).is_err());
+    fn validate_page_accepts_every_page_of_healthy_db() {
+        let path = unique_temp_path("validate_page_ok");
+        let schema = Schema::new(vec![Column {
+            name: "s".to_string(),
+            ty: ColType::String,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &schema).unwrap();
+        for i in 0..300u32 {
+            db.insert_row("t", &vec![Value::String(format!("row-{:04}", i))]).unwrap();
         }
+        for k in 0..2000u32 {
+            db.put_u64(k, k as u64).unwrap();
+        }
+        db.flush().unwrap();
+        let page_count = db.pager.page_count();
+        let mut kinds = HashSet::new();
+        for id in 0..page_count {
+            kinds.insert(db.pager.get_page(PageId(id)).unwrap().as_bytes()[0]);
+            db.validate_page(PageId(id)).unwrap();
+        }
+        assert!(kinds.contains(&2) && kinds.contains(&config::ROW_PAGE_KIND));
+        assert!(matches!(
+            db.validate_page(PageId(page_count)).unwrap_err(),
+            InvError::InvalidArgument { name: "page_id", .. }
+        ));
     }
 
     #[test]
-    fn checked_page_index_overflow() {
-        let result = checked_page_index(u64::MAX);
+    fn validate_page_flags_corrupted_row_page() {
+        let path = unique_temp_path("validate_page_bad");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &schema).unwrap();
+        db.insert_row("t", &vec![Value::U32(1)]).unwrap();
+        let row_page = db.get_table("t").unwrap().unwrap().last_row_page;
+        db.validate_page(PageId(row_page)).unwrap();
+
+        // Corrupt the cached row page magic behind the pager.
+        db.pager.get_page_mut(PageId(row_page)).unwrap().as_bytes_mut()[16..20]
+            .copy_from_slice(b"XXXX");
+        db.validate_page(config::CATALOG_PAGE_ID).unwrap();
+        let err = db.validate_page(PageId(row_page)).unwrap_err();
         assert!(matches!(
-            result,
-            Err(InvError::Overflow {
-                context: "page index exceeds u32::MAX"
-            })
+            err,
+            InvError::Corruption {
+                context: "rowpage.magic",
+                ..
+            }
+        ));
+    }
+
+
+    #[test]
+    fn merge_from_combines_shared_and_unique_tables() {
+        let string_schema = Schema::new(vec![Column {
+            name: "s".to_string(),
+            ty: ColType::String,
+            nullable: false,
+        }])
+        .unwrap();
+        let u32_schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let src_path = unique_temp_path("merge_src");
+        {
+            let mut src = Db::create(&src_path).unwrap();
+            src.create_table("users", &string_schema).unwrap();
+            src.create_table("orders", &u32_schema).unwrap();
+            src.create_table("clash", &u32_schema).unwrap();
+            src.create_table_with_row_crc("checked", &u32_schema).unwrap();
+            for name in ["carol", "dave"] {
+                src.insert_row("users", &vec![Value::String(name.to_string())]).unwrap();
+            }
+            for v in 0..3u32 {
+                src.insert_row("orders", &vec![Value::U32(v)]).unwrap();
+            }
+            src.insert_row("clash", &vec![Value::U32(7)]).unwrap();
+            src.insert_row("checked", &vec![Value::U32(8)]).unwrap();
+        }
+        let src_bytes = std::fs::read(&src_path).unwrap();
+
+        let mut db = Db::create(unique_temp_path("merge_dst")).unwrap();
+        db.create_table("users", &string_schema).unwrap();
+        db.create_table("clash", &string_schema).unwrap();
+        db.create_table("checked", &u32_schema).unwrap();
+        for name in ["alice", "bob"] {
+            db.insert_row("users", &vec![Value::String(name.to_string())]).unwrap();
+        }
+
+        let report = db.merge_from(&src_path, MergePolicy::AppendRows).unwrap();
+        assert_eq!(report.created, vec!["orders".to_string()]);
+        assert_eq!(report.appended, vec!["users".to_string()]);
+        assert_eq!(report.conflicts, vec!["clash".to_string(), "checked".to_string()]);
+        assert_eq!(report.rows_copied, 5);
+
+        let users: Vec<Row> = db.scan_table("users").unwrap().into_iter().map(|(_, r)| r).collect();
+        let expected: Vec<Row> = ["alice", "bob", "carol", "dave"]
+            .iter()
+            .map(|n| vec![Value::String(n.to_string())])
+            .collect();
+        assert_eq!(users, expected);
+        let orders = db.scan_table("orders").unwrap();
+        assert_eq!(orders.len(), 3);
+        assert!(db.scan_table("clash").unwrap().is_empty());
+        assert!(db.scan_table("checked").unwrap().is_empty());
+
+        let report = db.merge_from(&src_path, MergePolicy::SkipExisting).unwrap();
+        assert_eq!(report.skipped, vec!["users".to_string(), "orders".to_string()]);
+        assert_eq!(report.rows_copied, 0);
+        assert_eq!(db.scan_table("users").unwrap().len(), 4);
+
+        assert_eq!(std::fs::read(&src_path).unwrap(), src_bytes);
+    }
+
+    #[test]
+    fn merge_from_keeps_generated_keys() {
+        let src_path = unique_temp_path("merge_uuid_src");
+        let mut keys = Vec::new();
+        {
+            let mut src = Db::create(&src_path).unwrap();
+            src.create_table_with_uuid("u", &uuid_schema()).unwrap();
+            for n in 0..50u32 {
+                keys.push(src.insert_row_uuid("u", &vec![Value::Null, Value::U32(n)]).unwrap());
+            }
+        }
+        let mut db = Db::create(unique_temp_path("merge_uuid_dst")).unwrap();
+        db.merge_from(&src_path, MergePolicy::AppendRows).unwrap();
+        assert!(db.get_table("u").unwrap().unwrap().uuid_root.is_some());
+        for (n, key) in keys.iter().enumerate() {
+            assert_eq!(db.get_row_by_uuid("u", key).unwrap().unwrap()[1], Value::U32(n as u32));
+        }
+
+        // Appending the same rows again would give two rows one key.
+        assert!(matches!(
+            db.merge_from(&src_path, MergePolicy::AppendRows),
+            Err(InvError::InvalidArgument { name: "row", .. })
+        ));
+        let key = db.insert_row_uuid("u", &vec![Value::Null, Value::U32(99)]).unwrap();
+        assert_eq!(db.get_row_by_uuid("u", &key).unwrap().unwrap()[1], Value::U32(99));
+    }
+
+    #[test]
+    fn merge_from_checks_every_source_row_before_writing() {
+        let keyed_path = unique_temp_path("merge_stage_keyed");
+        {
+            let mut keyed = Db::create(&keyed_path).unwrap();
+            keyed.create_table_with_uuid("u", &uuid_schema()).unwrap();
+            for n in 0..10u32 {
+                keyed.insert_row_uuid("u", &vec![Value::Null, Value::U32(n)]).unwrap();
+            }
+        }
+        // "a" comes first and is new to the destination; "u" then clashes.
+        let src_path = unique_temp_path("merge_stage_src");
+        {
+            let mut src = Db::create(&src_path).unwrap();
+            src.create_table("a", &uuid_schema()).unwrap();
+            src.insert_row("a", &vec![Value::Bytes(vec![1; 16]), Value::U32(1)]).unwrap();
+            src.merge_from(&keyed_path, MergePolicy::AppendRows).unwrap();
+        }
+        let path = unique_temp_path("merge_stage_dst");
+        let mut db = Db::create(&path).unwrap();
+        db.merge_from(&keyed_path, MergePolicy::AppendRows).unwrap();
+        let before = db.content_checksum().unwrap();
+
+        assert!(matches!(
+            db.merge_from(&src_path, MergePolicy::AppendRows),
+            Err(InvError::InvalidArgument { name: "row", .. })
         ));
+        assert!(db.get_table("a").unwrap().is_none());
+        assert_eq!(db.content_checksum().unwrap(), before);
+        drop(db);
+        let mut db = Db::open(&path).unwrap();
+        db.check_integrity().unwrap();
+        assert_eq!(db.content_checksum().unwrap(), before);
+        // Nothing was left under the table id "a" would have taken.
+        db.create_table("b", &uuid_schema()).unwrap();
+        assert!(db.scan_table("b").unwrap().is_empty());
+        assert_eq!(db.get_row_by_pk("b", 1).unwrap(), None);
+    }
+
+
+    fn clustered_schema() -> Schema {
+        Schema::new(vec![
+            Column {
+                name: "ts".to_string(),
+                ty: ColType::I64,
+                nullable: false,
+            },
+            Column {
+                name: "payload".to_string(),
+                ty: ColType::String,
+                nullable: false,
+            },
+        ])
+        .unwrap()
     }
 
-    #[test]
-    fn create_rejects_empty_path() {
-        let err = Db::create("").unwrap_err();
-        assert!(matches!(
-            err,
-            InvError::InvalidArgument { name: "path", .. }
-        ));
-    }
+    #[test]
+    fn clustered_table_keeps_rows_ordered_across_splits() {
+        let path = unique_temp_path("clustered_order");
+        let schema = clustered_schema();
+        {
+            let mut db = Db::create(&path).unwrap();
+            db.create_clustered_table("events", &schema, "ts").unwrap();
+            for ts in pseudo_shuffle(400) {
+                let ts = ts as i64 - 200;
+                db.insert_row("events", &vec![Value::I64(ts), Value::String(format!("{:>40}", ts))])
+                    .unwrap();
+            }
+            for pk in [1u32, 50, 51, 399] {
+                assert!(db.delete_row("events", pk).unwrap());
+            }
+            db.insert_row("events", &vec![Value::I64(0), Value::String("dup".to_string())]).unwrap();
+        }
+
+        let mut db = Db::open(&path).unwrap();
+        let table = db.get_table("events").unwrap().unwrap();
+        let cluster = table.cluster.unwrap();
+        assert_eq!(cluster.column, 0);
+        db.validate_page(PageId(cluster.directory_page)).unwrap();
+
+        let all = db.scan_table("events").unwrap();
+        let mut expected: Vec<(u32, Row)> = all
+            .iter()
+            .filter(|(_, row)| matches!(row[0], Value::I64(ts) if (-20..=40).contains(&ts)))
+            .cloned()
+            .collect();
+        expected.sort_by_key(|(pk, row)| (crate::cluster::cluster_key(&row[0]), *pk));
+        let got = db.scan_column_range("events", "ts", &Value::I64(-20), &Value::I64(40)).unwrap();
+        assert_eq!(got, expected);
+        assert_eq!(all.len(), 397);
+
+        let full = db
+            .scan_column_range("events", "ts", &Value::I64(i64::MIN), &Value::I64(i64::MAX))
+            .unwrap();
+        assert_eq!(full.len(), 397);
+        let keys: Vec<u64> = full
+            .iter()
+            .map(|(_, row)| crate::cluster::cluster_key(&row[0]).unwrap())
+            .collect();
+        assert!(keys.windows(2).all(|w| w[0] <= w[1]));
 
-    #[test]
-    fn open_rejects_wal_path() {
-        let err = Db::open("test.wal").unwrap_err();
         assert!(matches!(
-            err,
-            InvError::Unsupported {
-                feature: "wal", ..
-            }
+            db.create_clustered_table("bad", &schema, "payload").unwrap_err(),
+            InvError::InvalidArgument { name: "cluster_column", .. }
         ));
     }
 
-    fn unique_temp_path(name: &str) -> PathBuf {
-        let nanos = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        std::env::temp_dir().join(format!("invdb_{}_{}", name, nanos))
-    }
-
     #[test]
-    fn create_creates_file_and_valid_header() {
-        let path = unique_temp_path("create_header");
+    fn clustered_range_filter_reads_fewer_pages() {
+        let path = unique_temp_path("clustered_reads");
+        let schema = clustered_schema();
         {
             let mut db = Db::create(&path).unwrap();
-            db.flush().unwrap();
+            db.create_clustered_table("clustered", &schema, "ts").unwrap();
+            db.create_table("plain", &schema).unwrap();
+            for ts in pseudo_shuffle(800) {
+                let row = vec![Value::I64(ts as i64), Value::String("x".repeat(120))];
+                db.insert_row("clustered", &row).unwrap();
+                db.insert_row("plain", &row).unwrap();
+            }
         }
-        let meta = std::fs::metadata(&path).unwrap();
-        assert_eq!(meta.len(), (3 * PAGE_SIZE) as u64);
 
-        let db_open = Db::open(&path);
-        assert!(db_open.is_ok());
+        let lo = Value::I64(400);
+        let hi = Value::I64(460);
+        let mut reads = Vec::new();
+        for table in ["clustered", "plain"] {
+            let mut db = Db::open(&path).unwrap();
+            let before = db.io_stats().reads;
+            let rows = db.scan_column_range(table, "ts", &lo, &hi).unwrap();
+            assert_eq!(rows.len(), 61);
+            reads.push(db.io_stats().reads - before);
+        }
+        assert!(reads[0] * 4 < reads[1], "clustered {} vs plain {}", reads[0], reads[1]);
     }
 
+
     #[test]
-    fn open_rejects_bad_magic() {
-        let path = unique_temp_path("bad_magic");
-        {
-            let mut db = Db::create(&path).unwrap();
-            db.flush().unwrap();
+    fn pk_range_tracks_surviving_bounds() {
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(unique_temp_path("pk_range")).unwrap();
+        db.create_table("t", &schema).unwrap();
+        assert_eq!(db.pk_range("t").unwrap(), None);
+        for i in 0..50u32 {
+            db.insert_row("t", &vec![Value::U32(i)]).unwrap();
         }
+        assert_eq!(db.pk_range("t").unwrap(), Some((1, 50)));
+        for pk in [1u32, 2, 3, 49, 50] {
+            db.delete_row("t", pk).unwrap();
+        }
+        assert_eq!(db.pk_range("t").unwrap(), Some((4, 48)));
+        for pk in 4..48u32 {
+            db.delete_row("t", pk).unwrap();
+        }
+        assert_eq!(db.pk_range("t").unwrap(), Some((48, 48)));
+        db.delete_row("t", 48).unwrap();
+        assert_eq!(db.pk_range("t").unwrap(), None);
+    }
 
-        {
-            let mut f = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&path)
-                .unwrap();
-            f.seek(SeekFrom::Start(0)).unwrap();
-            f.write_all(&[0xFF]).unwrap();
+
+    #[test]
+    fn testing_helpers_accept_canonical_encodings() {
+        use crate::testing::{
+            assert_canonical_roundtrip_catalog, assert_canonical_roundtrip_row, assert_canonical_roundtrip_schema,
+        };
+        let schema = Schema::new(vec![
+            Column {
+                name: "id".to_string(),
+                ty: ColType::U64,
+                nullable: false,
+            },
+            Column {
+                name: "delta".to_string(),
+                ty: ColType::I64,
+                nullable: false,
+            },
+            Column {
+                name: "flag".to_string(),
+                ty: ColType::Bool,
+                nullable: true,
+            },
+            Column {
+                name: "blob".to_string(),
+                ty: ColType::Bytes,
+                nullable: true,
+            },
+            Column {
+                name: "name".to_string(),
+                ty: ColType::String,
+                nullable: true,
+            },
+        ])
+        .unwrap();
+        let single = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        assert_eq!(
+            assert_canonical_roundtrip_schema(&schema),
+            crate::catalog::encode_schema(&schema).unwrap()
+        );
+        assert_canonical_roundtrip_schema(&single);
+
+        for row in [
+            vec![Value::U64(1), Value::I64(-5), Value::Bool(true), Value::Bytes(vec![0, 1, 2]), Value::String("a".into())],
+            vec![Value::U64(u64::MAX), Value::I64(i64::MIN), Value::Null, Value::Null, Value::Null],
+            vec![Value::U64(0), Value::I64(0), Value::Bool(false), Value::Bytes(Vec::new()), Value::String(String::new())],
+        ] {
+            assert_canonical_roundtrip_row(&schema, &row);
         }
+        assert_canonical_roundtrip_row(&single, &vec![Value::U32(7)]);
 
-        let err = Db::open(&path).unwrap_err();
-        assert!(matches!(err, InvError::InvalidMagic { .. }));
+        let mut cat = crate::catalog::Catalog::empty();
+        assert_canonical_roundtrip_catalog(&cat);
+        cat.create_table("wide", &schema).unwrap();
+        cat.create_table_u64_pk("narrow", &single).unwrap();
+        assert_canonical_roundtrip_catalog(&cat);
     }
 
+
     #[test]
-    fn open_rejects_bad_page_size() {
-        let path = unique_temp_path("bad_page_size");
-        {
-            let mut db = Db::create(&path).unwrap();
-            db.flush().unwrap();
+    fn rewrite_rows_drops_trailing_values() {
+        let schema = Schema::new(vec![
+            Column {
+                name: "id".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "notes".to_string(),
+                ty: ColType::String,
+                nullable: false,
+            },
+        ])
+        .unwrap();
+        let mut db = Db::create(unique_temp_path("rewrite_rows")).unwrap();
+        db.create_table("t", &schema).unwrap();
+        for i in 0..20u32 {
+            db.insert_row("t", &vec![Value::U32(i), Value::String("n".repeat(50))]).unwrap();
         }
+        assert_eq!(db.rewrite_rows("t").unwrap(), 0);
 
-        {
-            let mut f = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&path)
+        // Narrow the stored schema to the first column.
+        let mut cat = db.pager.read_catalog().unwrap();
+        let table = cat.tables.iter_mut().find(|t| t.name == "t").unwrap();
+        table.schema = Schema::new(vec![table.schema.columns[0].clone()]).unwrap();
+        let table_id = table.id.0;
+        db.pager.write_catalog(&cat).unwrap();
+
+        let stored_len = |db: &mut Db, pk: u32| {
+            let root = db.pager.root_page_id();
+            let raw = crate::btree::search::search_u64(&mut db.pager, root, composite_for_tests(table_id, pk))
+                .unwrap()
                 .unwrap();
-            f.seek(SeekFrom::Start(10)).unwrap();
-            f.write_all(&[0x01, 0x00]).unwrap(); // set page_size to 1
+            RowPtr::unpack(raw).len
+        };
+        let before = stored_len(&mut db, 5);
+        assert_eq!(db.rewrite_rows("t").unwrap(), 20);
+        assert!(stored_len(&mut db, 5) + 50 < before);
+        for i in 0..20u32 {
+            let stored = crate::table::get_row_by_pk(&mut db.pager, &cat, "t", i + 1).unwrap();
+            assert_eq!(stored, Some(vec![Value::U32(i)]));
         }
+        assert_eq!(db.rewrite_rows("t").unwrap(), 0);
+    }
 
-        let err = Db::open(&path).unwrap_err();
+    #[test]
+    fn decode_row_lenient_skips_trailing_values() {
+        let full = Schema::new(vec![
+            Column {
+                name: "a".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "b".to_string(),
+                ty: ColType::Bytes,
+                nullable: true,
+            },
+        ])
+        .unwrap();
+        let prefix = Schema::new(vec![full.columns[0].clone()]).unwrap();
+        let bytes = encode_row(&full, &vec![Value::U32(3), Value::Bytes(vec![9; 10])]).unwrap();
+        assert_eq!(decode_row_lenient(&prefix, &bytes).unwrap(), vec![Value::U32(3)]);
         assert!(matches!(
-            err,
-            InvError::Corruption {
-                context: "header.page_size",
-                ..
-            }
+            decode_row(&prefix, &bytes).unwrap_err(),
+            InvError::Corruption { context: "row.column_count", .. }
         ));
+        let mut truncated = bytes.clone();
+        truncated.pop();
+        assert!(decode_row_lenient(&prefix, &truncated).is_err());
     }
 
+
     #[test]
-    fn pager_get_page_validates_page_header() {
-        let path = unique_temp_path("page_header");
+    fn drop_column_reads_old_rows_lazily() {
+        let path = unique_temp_path("drop_column");
+        let schema = Schema::new(vec![
+            Column {
+                name: "id".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "name".to_string(),
+                ty: ColType::String,
+                nullable: false,
+            },
+            Column {
+                name: "legacy".to_string(),
+                ty: ColType::Bytes,
+                nullable: true,
+            },
+        ])
+        .unwrap();
         {
             let mut db = Db::create(&path).unwrap();
-            {
-                let pager = db.pager_mut_for_tests();
-                let root_page = pager.get_page(ROOT_PAGE_ID).unwrap();
-                assert_eq!(root_page.as_bytes()[0], 2);
-
-                let root_page_mut = pager.get_page_mut(ROOT_PAGE_ID).unwrap();
-                root_page_mut.as_bytes_mut()[1] = 1; // set flags non-zero
+            db.create_table("t", &schema).unwrap();
+            for i in 0..5u32 {
+                db.insert_row("t", &vec![Value::U32(i), Value::String(format!("n{}", i)), Value::Bytes(vec![1; 30])])
+                    .unwrap();
             }
-            db.flush().unwrap();
+            db.drop_column("t", "legacy").unwrap();
+            db.insert_row("t", &vec![Value::U32(5), Value::String("n5".to_string())]).unwrap();
         }
 
-        let err = Db::open(&path).unwrap_err();
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.get_table("t").unwrap().unwrap().schema.len(), 2);
+        let rows = db.scan_table("t").unwrap();
+        assert_eq!(rows.len(), 6);
+        for (pk, row) in &rows {
+            assert_eq!(row, &vec![Value::U32(pk - 1), Value::String(format!("n{}", pk - 1))]);
+        }
+        drop(db);
+
+        let mut strict = Db::open_with_options(&path, DbOptions { strict_reads: true, ..DbOptions::default() }).unwrap();
         assert!(matches!(
-            err,
-            InvError::Unsupported {
-                feature: "page.flags"
-            }
+            strict.get_row_by_pk("t", 1).unwrap_err(),
+            InvError::Corruption { context: "row.strict", .. }
         ));
+        assert_eq!(strict.rewrite_rows("t").unwrap(), 5);
+        assert_eq!(strict.scan_table("t").unwrap(), rows);
     }
 
     #[test]
-    fn search_empty_leaf_returns_none() {
-        let path = unique_temp_path("search_empty");
-        let mut db = Db::create(&path).unwrap();
-        let result = db.get_u64(10).unwrap();
-        assert!(result.is_none());
+    fn drop_column_rejects_non_trailing_columns() {
+        let schema = Schema::new(vec![
+            Column {
+                name: "a".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "b".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+        ])
+        .unwrap();
+        let mut db = Db::create(unique_temp_path("drop_column_reject")).unwrap();
+        db.create_table("t", &schema).unwrap();
+        assert!(matches!(
+            db.drop_column("t", "a").unwrap_err(),
+            InvError::Unsupported { feature: "column.drop_non_trailing" }
+        ));
+        assert!(matches!(
+            db.drop_column("t", "zzz").unwrap_err(),
+            InvError::InvalidArgument { name: "column", .. }
+        ));
+        db.drop_column("t", "b").unwrap();
+        assert!(matches!(
+            db.drop_column("t", "a").unwrap_err(),
+            InvError::InvalidArgument { name: "column", .. }
+        ));
+        db.create_clustered_table("c", &schema, "b").unwrap();
+        assert!(matches!(
+            db.drop_column("c", "b").unwrap_err(),
+            InvError::InvalidArgument { name: "column", .. }
+        ));
+        assert_eq!(db.get_table("t").unwrap().unwrap().schema.columns[0].name, "a");
     }
 
+
     #[test]
-    fn search_leaf_finds_value() {
-        let path = unique_temp_path("search_leaf");
-        {
-            let mut db = Db::create(&path).unwrap();
-            {
-                let pager = db.pager_mut_for_tests();
-                let root_id = pager.root_page_id();
-                let page = pager.get_page_mut(root_id).unwrap();
-                let buf = page.as_bytes_mut();
-                let base = 16;
-                buf[base] = 1; // leaf
-                buf[base + 1] = 0;
-                buf[base + 2..base + 4].copy_from_slice(&(3u16).to_le_bytes());
-                buf[base + 4..base + 8].copy_from_slice(&0u32.to_le_bytes());
-                buf[base + 8..base + 12].copy_from_slice(&0u32.to_le_bytes());
-                buf[base + 12..base + 16].copy_from_slice(&0u32.to_le_bytes());
+    fn repair_leaf_chain_fixes_cycle() {
+        let mut db = Db::create(unique_temp_path("repair_chain")).unwrap();
+        for k in pseudo_shuffle(3000) {
+            db.put_u64(k, k as u64 * 10).unwrap();
+        }
+        let leaves = collect_leaf_chain(&mut db.pager);
+        assert!(leaves.len() > 3);
+        assert_eq!(db.repair_leaf_chain().unwrap(), RepairReport { leaves: leaves.len() as u32, relinked: 0 });
 
-                let keys = [10u32, 20, 30];
-                let values = [1000u64, 2000, 3000];
-                let keys_offset = base + 16;
-                for (i, k) in keys.iter().enumerate() {
-                    let offset = keys_offset + 4 * i;
-                    buf[offset..offset + 4].copy_from_slice(&k.to_le_bytes());
+        // Point the second leaf back at the first.
+        let page_count = db.pager.page_count();
+        let second = leaves[1];
+        let mut leaf = match Node::decode(db.pager.get_page(second).unwrap(), page_count).unwrap() {
+            Node::Leaf(l) => l,
+            _ => unreachable!(),
+        };
+        leaf.next_leaf = leaves[0];
+        db.pager.encode_leaf_into_page(second, &leaf).unwrap();
+        let root = db.pager.root_page_id();
+        assert!(matches!(
+            validate_leaf_chain(&mut db.pager, root, page_count, &mut PageBudget::new(None)).unwrap_err(),
+            InvError::Corruption { context: "btree.leaf_cycle", .. }
+        ));
+
+        let report = db.repair_leaf_chain().unwrap();
+        assert_eq!(report, RepairReport { leaves: leaves.len() as u32, relinked: 1 });
+        validate_leaf_chain(&mut db.pager, root, page_count, &mut PageBudget::new(None)).unwrap();
+        assert_eq!(collect_leaf_chain(&mut db.pager), leaves);
+
+        let mut seen = Vec::new();
+        crate::btree::search::walk_from(&mut db.pager, root, 0, |k, v| {
+            assert_eq!(v, k as u64 * 10);
+            seen.push(k);
+            Ok(true)
+        })
+        .unwrap();
+        assert_eq!(seen, (1..=3000u32).collect::<Vec<_>>());
+    }
+
+
+    #[test]
+    fn large_row_policy_controls_oversized_inserts() {
+        let schema = Schema::new(vec![
+            Column {
+                name: "id".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "body".to_string(),
+                ty: ColType::Bytes,
+                nullable: false,
+            },
+            Column {
+                name: "tail".to_string(),
+                ty: ColType::U64,
+                nullable: false,
+            },
+        ])
+        .unwrap();
+        // 4 pk + 4 magic + 1 count + 5 id + (1 tag + 2 varint + n) + 9 tail.
+        let fits = crate::rowstore::MAX_INLINE_ROW - 26;
+        let row = |n: usize| vec![Value::U32(1), Value::Bytes(vec![7; n]), Value::U64(2)];
+
+        for policy in [LargeRowPolicy::Error, LargeRowPolicy::Overflow, LargeRowPolicy::RejectWithDetails] {
+            let options = DbOptions {
+                large_row_policy: policy,
+                ..DbOptions::default()
+            };
+            let mut db = Db::create_with_options(unique_temp_path("large_row_policy"), options).unwrap();
+            db.create_table("t", &schema).unwrap();
+            let pk = db.insert_row("t", &row(fits)).unwrap();
+            assert_eq!(db.get_row_by_pk("t", pk).unwrap(), Some(row(fits)));
+
+            let err = db.insert_row("t", &row(fits + 1)).unwrap_err();
+            match policy {
+                LargeRowPolicy::Error => {
+                    assert!(matches!(err, InvError::Unsupported { feature: "row.too_large" }))
                 }
-                let values_offset = keys_offset + 4 * keys.len();
-                for (i, v) in values.iter().enumerate() {
-                    let offset = values_offset + 8 * i;
-                    buf[offset..offset + 8].copy_from_slice(&v.to_le_bytes());
+                LargeRowPolicy::Overflow => {
+                    assert!(matches!(err, InvError::Unsupported { feature: "row.overflow" }))
                 }
+                LargeRowPolicy::RejectWithDetails => match err {
+                    InvError::InvalidArgument { name: "row.too_large", details } => {
+                        assert!(details.contains("'tail'"), "{}", details);
+                        assert!(details.contains("3501"), "{}", details);
+                    }
+                    other => panic!("unexpected error {:?}", other),
+                },
             }
-            db.flush().unwrap();
         }
+    }
 
-        let mut db = Db::open(&path).unwrap();
-        assert_eq!(db.get_u64(20).unwrap(), Some(2000));
-        assert_eq!(db.get_u64(25).unwrap(), None);
-        assert_eq!(db.get_u64(10).unwrap(), Some(1000));
+
+    #[test]
+    fn dump_all_and_load_all_round_trip() {
+        let schema = Schema::new(vec![
+            Column {
+                name: "k".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "s".to_string(),
+                ty: ColType::String,
+                nullable: true,
+            },
+        ])
+        .unwrap();
+        let mut src = Db::create(unique_temp_path("dump_src")).unwrap();
+        src.create_table("plain", &schema).unwrap();
+        src.create_table("empty", &schema).unwrap();
+        src.create_clustered_table("sorted", &schema, "k").unwrap();
+        src.create_table_u64_pk("log", &schema).unwrap();
+        for i in 0..40u32 {
+            let row = vec![Value::U32(i * 7 % 40), if i % 3 == 0 { Value::Null } else { Value::String(format!("v{}", i)) }];
+            src.insert_row("plain", &row).unwrap();
+            src.insert_row("sorted", &row).unwrap();
+            src.insert_row_u64("log", &row).unwrap();
+        }
+        for pk in [1u32, 17, 40] {
+            src.delete_row("plain", pk).unwrap();
+        }
+
+        let mut stream = Vec::new();
+        src.dump_all(&mut stream).unwrap();
+        let path = unique_temp_path("dump_dst");
+        {
+            let mut dst = Db::create(&path).unwrap();
+            dst.load_all(&mut stream.as_slice()).unwrap();
+        }
+
+        let mut dst = Db::open(&path).unwrap();
+        for name in ["plain", "empty", "sorted"] {
+            assert_eq!(dst.scan_table(name).unwrap(), src.scan_table(name).unwrap(), "table {}", name);
+            let (a, b) = (dst.get_table(name).unwrap().unwrap(), src.get_table(name).unwrap().unwrap());
+            assert_eq!((a.schema, a.next_pk, a.cluster.is_some()), (b.schema, b.next_pk, b.cluster.is_some()));
+        }
+        for pk in 1..=40u64 {
+            assert_eq!(dst.get_row_by_pk_u64("log", pk).unwrap(), src.get_row_by_pk_u64("log", pk).unwrap());
+        }
+        assert_eq!(
+            dst.scan_column_range("sorted", "k", &Value::U32(5), &Value::U32(9)).unwrap(),
+            src.scan_column_range("sorted", "k", &Value::U32(5), &Value::U32(9)).unwrap()
+        );
+        assert_eq!(dst.insert_row("plain", &vec![Value::U32(0), Value::Null]).unwrap(), 41);
+
+        let mut fresh = Db::create(unique_temp_path("dump_truncated")).unwrap();
+        let err = fresh.load_all(&mut &stream[..stream.len() - 3]).unwrap_err();
+        assert!(matches!(err, InvError::Corruption { context: "dump.eof", .. }));
     }
 
     #[test]
-    fn search_internal_traversal_basic() {
-        let path = unique_temp_path("search_internal");
+    fn dump_all_and_load_all_keep_generated_keys() {
+        let mut src = Db::create(unique_temp_path("dump_uuid_src")).unwrap();
+        src.create_table_with_uuid("u", &uuid_schema()).unwrap();
+        let keys: Vec<[u8; 16]> = (0..50u32)
+            .map(|n| src.insert_row_uuid("u", &vec![Value::Null, Value::U32(n)]).unwrap())
+            .collect();
+        src.delete_row("u", 3).unwrap();
+
+        let mut stream = Vec::new();
+        src.dump_all(&mut stream).unwrap();
+        let path = unique_temp_path("dump_uuid_dst");
         {
-            let mut db = Db::create(&path).unwrap();
-            {
-                let pager = db.pager_mut_for_tests();
-                let child_a = pager.allocate_btree_page().unwrap();
-                let child_b = pager.allocate_btree_page().unwrap();
+            let mut dst = Db::create(&path).unwrap();
+            dst.load_all(&mut stream.as_slice()).unwrap();
+        }
+        let mut dst = Db::open(&path).unwrap();
+        assert!(dst.get_table("u").unwrap().unwrap().uuid_root.is_some());
+        assert_eq!(dst.get_row_by_uuid("u", &keys[2]).unwrap(), None);
+        for (n, key) in keys.iter().enumerate().filter(|&(n, _)| n != 2) {
+            assert_eq!(dst.get_row_by_uuid("u", key).unwrap().unwrap()[1], Value::U32(n as u32));
+        }
+        assert!(src.logically_equals(&mut dst).unwrap());
+        dst.check_integrity().unwrap();
+    }
 
-                // root internal node with key 50 separating child_a and child_b
-                let root_id = pager.root_page_id();
-                let root_page = pager.get_page_mut(root_id).unwrap();
-                let buf = root_page.as_bytes_mut();
-                let base = 16;
-                buf[base] = 2; // internal
-                buf[base + 1] = 0;
-                buf[base + 2..base + 4].copy_from_slice(&(1u16).to_le_bytes());
-                buf[base + 4..base + 8].copy_from_slice(&0u32.to_le_bytes());
-                buf[base + 8..base + 12].copy_from_slice(&0u32.to_le_bytes()); // reserved2
-                buf[base + 12..base + 16].copy_from_slice(&0u32.to_le_bytes()); // reserved3
-                let children_offset = base + 16;
-                buf[children_offset..children_offset + 4]
-                    .copy_from_slice(&child_a.0.to_le_bytes());
-                buf[children_offset + 4..children_offset + 8]
-                    .copy_from_slice(&child_b.0.to_le_bytes());
-                let keys_offset = children_offset + 8;
-                buf[keys_offset..keys_offset + 4].copy_from_slice(&50u32.to_le_bytes());
+    #[test]
+    fn merge_and_dump_keep_row_checksums() {
+        let src_path = unique_temp_path("crc_keep_src");
+        let mut src = Db::create(&src_path).unwrap();
+        src.create_table_with_row_crc("c", &uuid_schema()).unwrap();
+        for n in 0..20u32 {
+            src.insert_row("c", &vec![Value::Bytes(vec![n as u8; 16]), Value::U32(n)]).unwrap();
+        }
+        let mut stream = Vec::new();
+        src.dump_all(&mut stream).unwrap();
+        drop(src);
 
-                // child_a leaf with key 10 -> 111
-                let child_a_page = pager.get_page_mut(child_a).unwrap();
-                let buf_a = child_a_page.as_bytes_mut();
-                buf_a[base] = 1;
-                buf_a[base + 1] = 0;
-                buf_a[base + 2..base + 4].copy_from_slice(&(1u16).to_le_bytes());
-                buf_a[base + 4..base + 8].copy_from_slice(&0u32.to_le_bytes());
-                buf_a[base + 8..base + 12].copy_from_slice(&0u32.to_le_bytes());
-                buf_a[base + 12..base + 16].copy_from_slice(&0u32.to_le_bytes());
-                let keys_offset_a = base + 16;
-                buf_a[keys_offset_a..keys_offset_a + 4].copy_from_slice(&10u32.to_le_bytes());
-                let values_offset_a = keys_offset_a + 4;
-                buf_a[values_offset_a..values_offset_a + 8]
-                    .copy_from_slice(&111u64.to_le_bytes());
+        let mut merged = Db::create(unique_temp_path("crc_keep_merged")).unwrap();
+        merged.merge_from(&src_path, MergePolicy::AppendRows).unwrap();
+        let mut loaded = Db::create(unique_temp_path("crc_keep_loaded")).unwrap();
+        loaded.load_all(&mut stream.as_slice()).unwrap();
+        let mut src = Db::open(&src_path).unwrap();
+        for db in [&mut merged, &mut loaded] {
+            assert!(db.get_table("c").unwrap().unwrap().row_crc);
+            assert_eq!(db.scan_table("c").unwrap(), src.scan_table("c").unwrap());
+            db.check_integrity().unwrap();
+        }
+    }
 
-                // child_b leaf with key 60 -> 222
-                let child_b_page = pager.get_page_mut(child_b).unwrap();
-                let buf_b = child_b_page.as_bytes_mut();
-                buf_b[base] = 1;
-                buf_b[base + 1] = 0;
-                buf_b[base + 2..base + 4].copy_from_slice(&(1u16).to_le_bytes());
-                buf_b[base + 4..base + 8].copy_from_slice(&0u32.to_le_bytes());
-                buf_b[base + 8..base + 12].copy_from_slice(&0u32.to_le_bytes());
-                buf_b[base + 12..base + 16].copy_from_slice(&0u32.to_le_bytes());
-                let keys_offset_b = base + 16;
-                buf_b[keys_offset_b..keys_offset_b + 4].copy_from_slice(&60u32.to_le_bytes());
-                let values_offset_b = keys_offset_b + 4;
-                buf_b[values_offset_b..values_offset_b + 8]
-                    .copy_from_slice(&222u64.to_le_bytes());
+
+    #[test]
+    fn decode_errors_report_byte_offset() {
+        let schema = Schema::new(vec![
+            Column {
+                name: "x".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "b".to_string(),
+                ty: ColType::Bool,
+                nullable: false,
+            },
+        ])
+        .unwrap();
+        let row = vec![Value::U32(7), Value::Bool(true)];
+        let bytes = encode_row(&schema, &row).unwrap();
+        // magic (0..4), count (4), tag (5), u32 (6..10), tag (10), bool (11)
+        let mut bad_bool = bytes.clone();
+        bad_bool[11] = 7;
+        match decode_row(&schema, &bad_bool).unwrap_err() {
+            InvError::Corruption { context: "row.bool", details } => {
+                assert!(details.contains("offset 11"), "{}", details)
             }
-            db.flush().unwrap();
+            other => panic!("unexpected error {:?}", other),
+        }
+        let mut bad_tag = bytes.clone();
+        bad_tag[10] = 0x7F;
+        match decode_row(&schema, &bad_tag).unwrap_err() {
+            InvError::Corruption { context: "row.tag", details } => {
+                assert!(details.contains("offset 10"), "{}", details)
+            }
+            other => panic!("unexpected error {:?}", other),
         }
+        match decode_row(&schema, &bytes[..8]).unwrap_err() {
+            InvError::Corruption { details, .. } => assert!(details.contains("offset 6"), "{}", details),
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
 
-        let mut db = Db::open(&path).unwrap();
-        assert_eq!(db.get_u64(10).unwrap(), Some(111));
-        assert_eq!(db.get_u64(60).unwrap(), Some(222));
-        assert_eq!(db.get_u64(55).unwrap(), None);
+
+    #[test]
+    fn with_table_batches_inserts_under_one_catalog_write() {
+        let mut db = Db::create(unique_temp_path("with_table")).unwrap();
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        db.create_table("t", &schema).unwrap();
+
+        let writes = db.pager.catalog_writes();
+        let pks = db
+            .with_table("t", |pager, _cat, table| {
+                let a = crate::table::insert_into_table(pager, table, &vec![Value::U32(1)])?;
+                let b = crate::table::insert_into_table(pager, table, &vec![Value::U32(2)])?;
+                Ok((a, b))
+            })
+            .unwrap();
+        assert_eq!(db.pager.catalog_writes(), writes + 1);
+        assert_eq!(pks, (1, 2));
+        assert_eq!(db.get_row_by_pk("t", 2).unwrap(), Some(vec![Value::U32(2)]));
+        assert_eq!(db.get_table("t").unwrap().unwrap().next_pk, 3);
+
+        // A read-only closure leaves the catalog untouched.
+        let writes = db.pager.catalog_writes();
+        let next = db.with_table("t", |_, _, table| Ok(table.next_pk)).unwrap();
+        assert_eq!(next, 3);
+        assert_eq!(db.pager.catalog_writes(), writes);
+        assert!(matches!(
+            db.with_table("missing", |_, _, _| Ok(())),
+            Err(InvError::InvalidArgument { name: "table", .. })
+        ));
     }
 
+
     #[test]
-    fn node_validation_rejects_unsorted_keys() {
-        let path = unique_temp_path("unsorted_keys");
-        {
-            let mut db = Db::create(&path).unwrap();
-            {
-                let pager = db.pager_mut_for_tests();
-                let root_id = pager.root_page_id();
-                let page = pager.get_page_mut(root_id).unwrap();
-                let buf = page.as_bytes_mut();
-                let base = 16;
-                buf[base] = 1; // leaf
-                buf[base + 1] = 0;
-                buf[base + 2..base + 4].copy_from_slice(&(2u16).to_le_bytes());
-                buf[base + 4..base + 8].copy_from_slice(&0u32.to_le_bytes());
-                buf[base + 8..base + 12].copy_from_slice(&0u32.to_le_bytes());
-                buf[base + 12..base + 16].copy_from_slice(&0u32.to_le_bytes());
-                let keys_offset = base + 16;
-                let keys = [20u32, 10u32];
-                for (i, k) in keys.iter().enumerate() {
-                    let offset = keys_offset + 4 * i;
-                    buf[offset..offset + 4].copy_from_slice(&k.to_le_bytes());
-                }
-                let values_offset = keys_offset + 4 * keys.len();
-                let values = [1u64, 2u64];
-                for (i, v) in values.iter().enumerate() {
-                    let offset = values_offset + 8 * i;
-                    buf[offset..offset + 8].copy_from_slice(&v.to_le_bytes());
-                }
+    fn order_preserving_encoding_sorts_like_raw_bytes() {
+        let cases: &[&[u8]] = &[b"", b"\0", b"\0\0", b"\0\x01", b"a", b"a\0", b"a\0b", b"a\x01", b"ab", b"abc", b"b", b"\xFF"];
+        let encoded: Vec<Vec<u8>> = cases
+            .iter()
+            .map(|c| {
+                let mut out = Vec::new();
+                crate::encoding::write_order_preserving(&mut out, c);
+                out
+            })
+            .collect();
+        for (i, a) in cases.iter().enumerate() {
+            for (j, b) in cases.iter().enumerate() {
+                assert_eq!(a.cmp(b), encoded[i].cmp(&encoded[j]), "{:?} vs {:?}", a, b);
             }
-            db.flush().unwrap();
+            let mut pos = 0;
+            let decoded = crate::encoding::read_order_preserving(&encoded[i], &mut pos, 16).unwrap();
+            assert_eq!(&decoded[..], *a);
+            assert_eq!(pos, encoded[i].len());
         }
 
-        let err = Db::open(&path).unwrap_err();
+        let mut pos = 0;
         assert!(matches!(
-            err,
-            InvError::Corruption {
-                context: "btree.leaf.keys_order",
-                ..
-            }
+            crate::encoding::read_order_preserving(&[b'a', 0, 7], &mut pos, 16),
+            Err(InvError::Corruption { context: "encoding.ordered.escape", .. })
         ));
+
+        // Length prefixes would put "b" before "ab"; index keys must not.
+        let key = |s: &str, n: i64| encode_index_key(&[Value::String(s.to_string()), Value::I64(n)]);
+        assert!(key("a", 5) < key("ab", -5));
+        assert!(key("ab", 9) < key("b", -9));
+        assert!(key("a\0", 0) < key("a\0b", 0));
+        assert!(key("b", -1) < key("b", 0));
+        assert!(encode_index_key(&[Value::Null]) < encode_index_key(&[Value::String(String::new())]));
     }
 
+
     #[test]
-    fn put_then_get_single() {
-        let path = unique_temp_path("put_single");
-        {
-            let mut db = Db::create(&path).unwrap();
-            db.put_u64(10, 111).unwrap();
-            db.flush().unwrap();
+    fn sample_rows_is_reproducible_for_a_seed() {
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(unique_temp_path("sample_rows")).unwrap();
+        db.create_table("t", &schema).unwrap();
+        assert!(db.sample_rows("t", 5, 1).unwrap().is_empty());
+        for i in 0..200u32 {
+            db.insert_row("t", &vec![Value::U32(i)]).unwrap();
         }
-        let mut db = Db::open(&path).unwrap();
-        assert_eq!(db.get_u64(10).unwrap(), Some(111));
+
+        let first = db.sample_rows("t", 10, 42).unwrap();
+        assert_eq!(first.len(), 10);
+        assert_eq!(db.sample_rows("t", 10, 42).unwrap(), first);
+        assert_ne!(db.sample_rows("t", 10, 43).unwrap(), first);
+        assert!(first.windows(2).all(|w| w[0].0 < w[1].0));
+        for (pk, row) in &first {
+            assert_eq!(row, &vec![Value::U32(pk - 1)]);
+        }
+        // Asking for more than the table holds returns every row once.
+        assert_eq!(db.sample_rows("t", 500, 7).unwrap().len(), 200);
     }
 
+
     #[test]
-    fn overwrite_value_same_key() {
-        let path = unique_temp_path("overwrite_key");
+    fn flush_table_persists_only_that_table() {
+        let path = unique_temp_path("flush_table");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
         {
             let mut db = Db::create(&path).unwrap();
-            db.put_u64(10, 111).unwrap();
-            db.put_u64(10, 222).unwrap();
+            db.create_table("a", &schema).unwrap();
+            db.create_table("b", &schema).unwrap();
+            db.insert_row("b", &vec![Value::U32(100)]).unwrap();
             db.flush().unwrap();
+
+            for i in 0..20u32 {
+                db.insert_row("a", &vec![Value::U32(i)]).unwrap();
+                db.insert_row("b", &vec![Value::U32(i)]).unwrap();
+            }
+            db.delete_row("b", 1).unwrap();
+            db.flush_table("a").unwrap();
+            // Simulate a crash: skip the flush on drop.
+            std::mem::forget(db);
         }
         let mut db = Db::open(&path).unwrap();
-        assert_eq!(db.get_u64(10).unwrap(), Some(222));
+        let a = db.scan_table("a").unwrap();
+        assert_eq!(a.len(), 20);
+        assert_eq!(a[19], (20, vec![Value::U32(19)]));
+        assert_eq!(db.get_table("a").unwrap().unwrap().next_pk, 21);
+        // b is back at its last full flush: one row, nothing newer.
+        assert_eq!(db.scan_table("b").unwrap(), vec![(1, vec![Value::U32(100)])]);
+        assert_eq!(db.get_table("b").unwrap().unwrap().next_pk, 2);
     }
 
     #[test]
-    fn leaf_split_creates_multiple_pages() {
-        let path = unique_temp_path("leaf_split");
-        let max = max_leaf_keys();
+    fn flush_table_leaves_raw_and_other_tables_entries_unflushed() {
+        let path = unique_temp_path("flush_table_exact");
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let (a_deleted, a_other);
         {
             let mut db = Db::create(&path).unwrap();
-            for k in 1..=(max as u32 + 5) {
-                db.put_u64(k, (k as u64) * 10).unwrap();
-            }
+            let a = db.create_table("a", &schema).unwrap();
+            db.create_table("b", &schema).unwrap();
+            db.put_u64(7, 70).unwrap();
             db.flush().unwrap();
+
+            for i in 0..20u32 {
+                db.insert_row("a", &vec![Value::U32(i)]).unwrap();
+                db.insert_row("b", &vec![Value::U32(i)]).unwrap();
+            }
+            db.delete_row("a", 2).unwrap();
+            // Raw entries in a's key space pointing at a's pk 3: one under
+            // the key of a deleted pk, one under a pk past next_pk.
+            a_deleted = crate::table::composite_key(a.0, 2);
+            a_other = crate::table::composite_key(a.0, 25);
+            let ptr = db.get_u64(crate::table::composite_key(a.0, 3)).unwrap().unwrap();
+            db.put_u64(a_deleted, ptr).unwrap();
+            db.put_u64(a_other, ptr).unwrap();
+            db.put_u64(7, 71).unwrap();
+            db.flush_table("a").unwrap();
+            // Simulate a crash: skip the flush on drop.
+            std::mem::forget(db);
         }
         let mut db = Db::open(&path).unwrap();
-        assert!(db.pager_mut_for_tests().page_count() > 2);
-        assert_eq!(db.get_u64(1).unwrap(), Some(10));
-        assert_eq!(db.get_u64((max as u32) / 2).unwrap(), Some(((max as u32) / 2) as u64 * 10));
-        assert_eq!(db.get_u64(max as u32 + 5).unwrap(), Some((max as u32 + 5) as u64 * 10));
+        assert_eq!(db.scan_table("a").unwrap().len(), 19);
+        assert_eq!(db.get_row_by_pk("a", 2).unwrap(), None);
+        assert_eq!(db.get_u64(a_deleted).unwrap(), None);
+        assert_eq!(db.get_u64(a_other).unwrap(), None);
+        assert_eq!(db.get_u64(7).unwrap(), Some(70));
+        assert!(db.scan_table("b").unwrap().is_empty());
+        assert_eq!(db.get_row_by_pk("b", 1).unwrap(), None);
+        db.check_integrity().unwrap();
+    }
+
+
+    #[test]
+    fn value_tags_match_the_codec() {
+        use crate::row::{TAG_BOOL, TAG_BYTES, TAG_I64, TAG_NULL, TAG_STRING, TAG_U32, TAG_U64};
+        let cases = vec![
+            (ColType::U32, Value::U32(9), TAG_U32),
+            (ColType::U64, Value::U64(9), TAG_U64),
+            (ColType::I64, Value::I64(-9), TAG_I64),
+            (ColType::Bool, Value::Bool(true), TAG_BOOL),
+            (ColType::Bytes, Value::Bytes(vec![1, 2]), TAG_BYTES),
+            (ColType::String, Value::String("s".to_string()), TAG_STRING),
+            (ColType::U32, Value::Null, TAG_NULL),
+        ];
+        assert_eq!(
+            cases.iter().map(|(_, _, tag)| *tag).collect::<Vec<_>>(),
+            vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x00]
+        );
+        for (ty, value, tag) in cases {
+            assert_eq!(value_tag(&value), tag);
+            assert_eq!(value.encoded_tag(), tag);
+            let schema = Schema::new(vec![Column {
+                name: "c".to_string(),
+                ty,
+                nullable: true,
+            }])
+            .unwrap();
+            let row = vec![value];
+            let bytes = encode_row(&schema, &row).unwrap();
+            // magic (4) + count (1), then the tag
+            assert_eq!(bytes[5], tag);
+            assert_eq!(decode_row(&schema, &bytes).unwrap(), row);
+        }
+
+        // The decoder accepts bytes built from the constants alone.
+        let schema = Schema::new(vec![Column {
+            name: "b".to_string(),
+            ty: ColType::Bool,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut bytes = b"ROW1".to_vec();
+        crate::encoding::write_var_u64(&mut bytes, 1);
+        bytes.extend_from_slice(&[TAG_BOOL, 0]);
+        assert_eq!(decode_row(&schema, &bytes).unwrap(), vec![Value::Bool(false)]);
     }
 
-    #[test]
-    fn root_split_creates_internal_root() {
-        let path = unique_temp_path("root_split");
-        let max = max_leaf_keys();
-        {
-            let mut db = Db::create(&path).unwrap();
-            let upper = (max * 3) as u32;
-            for k in 1..=upper {
-                db.put_u64(k, (k as u64) * 2).unwrap();
+
+    #[test]
+    fn stored_pk_outside_next_pk_is_rejected() {
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(unique_temp_path("pk_out_of_range")).unwrap();
+        db.create_table("t", &schema).unwrap();
+        for i in 0..3u32 {
+            db.insert_row("t", &vec![Value::U32(i)]).unwrap();
+        }
+        let table = db.get_table("t").unwrap().unwrap();
+        let root = db.pager.root_page_id();
+        let packed = crate::btree::search::search_u64(&mut db.pager, root, composite_for_tests(table.id.0, 2))
+            .unwrap()
+            .unwrap();
+        let ptr = RowPtr::unpack(packed);
+        let page = db.pager.get_page_mut(PageId(ptr.page_id)).unwrap();
+        let at = ptr.offset as usize;
+        page.as_bytes_mut()[at..at + 4].copy_from_slice(&0xFFFF_FF00u32.to_le_bytes());
+
+        for err in [db.get_row_by_pk("t", 2).unwrap_err(), db.scan_table("t").unwrap_err()] {
+            match err {
+                InvError::Corruption { context: "table.pk_out_of_range", details } => {
+                    assert!(details.contains(&0xFFFF_FF00u32.to_string()), "{}", details)
+                }
+                other => panic!("unexpected error {:?}", other),
             }
-            db.flush().unwrap();
-        }
-        let mut db = Db::open(&path).unwrap();
-        let pager = db.pager_mut_for_tests();
-        let root = pager.root_page_id();
-        let page_count = pager.page_count();
-        let node = node::Node::decode(pager.get_page(root).unwrap(), page_count).unwrap();
-        match node {
-            Node::Internal(_) => {}
-            _ => panic!("expected internal root after splits"),
         }
-        assert_eq!(db.get_u64(1).unwrap(), Some(2));
-        assert_eq!(db.get_u64((max * 2) as u32).unwrap(), Some((max * 2) as u64 * 2));
-        assert_eq!(db.get_u64((max * 3) as u32).unwrap(), Some((max * 3) as u64 * 2));
+        assert_eq!(db.get_row_by_pk("t", 1).unwrap(), Some(vec![Value::U32(0)]));
     }
 
+
     #[test]
-    fn persistence_of_root_page_id() {
-        let path = unique_temp_path("root_persist");
-        let max = max_leaf_keys();
-        {
-            let mut db = Db::create(&path).unwrap();
-            for k in 1..=((max * 3) as u32) {
-                db.put_u64(k, (k as u64) + 1).unwrap();
+    fn compact_to_writes_a_smaller_copy_with_the_same_rows() {
+        let schema = Schema::new(vec![
+            Column {
+                name: "id".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "body".to_string(),
+                ty: ColType::Bytes,
+                nullable: false,
+            },
+        ])
+        .unwrap();
+        let src_path = unique_temp_path("compact_src");
+        let dest_path = unique_temp_path("compact_dest");
+        let mut db = Db::create(&src_path).unwrap();
+        db.create_table("t", &schema).unwrap();
+        db.create_table_u64_pk("wide", &schema).unwrap();
+        for i in 0..1500u32 {
+            db.insert_row("t", &vec![Value::U32(i), Value::Bytes(vec![i as u8; 100])]).unwrap();
+        }
+        for i in 0..40u32 {
+            db.insert_row_u64("wide", &vec![Value::U32(i), Value::Bytes(vec![1; 10])]).unwrap();
+        }
+        for pk in 1..=1500u32 {
+            if pk % 4 != 0 {
+                db.delete_row("t", pk).unwrap();
             }
-            db.flush().unwrap();
         }
-        let mut db = Db::open(&path).unwrap();
-        assert_ne!(db.pager_mut_for_tests().root_page_id(), ROOT_PAGE_ID);
-        assert_eq!(db.get_u64(5).unwrap(), Some(6));
-        assert_eq!(db.get_u64((max * 3) as u32).unwrap(), Some((max * 3 + 1) as u64));
+        db.flush().unwrap();
+
+        db.compact_to(&dest_path).unwrap();
+        assert!(matches!(
+            db.compact_to(&dest_path),
+            Err(InvError::InvalidArgument { name: "dest", .. })
+        ));
+        let src_len = std::fs::metadata(&src_path).unwrap().len();
+        let dest_len = std::fs::metadata(&dest_path).unwrap().len();
+        assert!(dest_len * 2 < src_len, "{} vs {}", dest_len, src_len);
+
+        let mut copy = Db::open(&dest_path).unwrap();
+        assert_eq!(copy.scan_table("t").unwrap(), db.scan_table("t").unwrap());
+        assert_eq!(copy.scan_table("t").unwrap().len(), 375);
+        assert_eq!(copy.get_row_by_pk_u64("wide", 40).unwrap(), db.get_row_by_pk_u64("wide", 40).unwrap());
+        assert_eq!(copy.get_table("t").unwrap().unwrap().next_pk, 1501);
+        // The copy keeps accepting writes after its bulk-loaded index.
+        let pk = copy.insert_row("t", &vec![Value::U32(9), Value::Bytes(vec![])]).unwrap();
+        assert_eq!(pk, 1501);
+        assert_eq!(copy.get_row_by_pk("t", 1500).unwrap(), db.get_row_by_pk("t", 1500).unwrap());
     }
 
+
     #[test]
-    fn catalog_persists_table_definition() {
-        let path = unique_temp_path("catalog_persist");
+    fn scan_table_named_reads_columns_by_name() {
         let schema = Schema::new(vec![
             Column {
-                name: "id".to_string(),
+                name: "age".to_string(),
                 ty: ColType::U32,
                 nullable: false,
             },
@@ -750,851 +4671,2412 @@ mod tests {
             },
         ])
         .unwrap();
-        {
-            let mut db = Db::create(&path).unwrap();
-            let id = db.create_table("users", &schema).unwrap();
-            assert_eq!(id, TableId(1));
-            db.flush().unwrap();
+        let mut db = Db::create(unique_temp_path("scan_named")).unwrap();
+        db.create_table("users", &schema).unwrap();
+        db.insert_row("users", &vec![Value::U32(31), Value::String("ada".to_string())]).unwrap();
+        db.insert_row("users", &vec![Value::U32(42), Value::Null]).unwrap();
+
+        let positional = db.scan_table("users").unwrap();
+        let named = db.scan_table_named("users").unwrap();
+        assert_eq!(named.len(), positional.len());
+        for (row, (pk, values)) in named.iter().zip(&positional) {
+            assert_eq!(row.pk(), *pk);
+            assert_eq!(row.get("age"), Some(&values[0]));
+            assert_eq!(row.get("name"), Some(&values[1]));
+            assert_eq!(row.get("missing"), None);
+            assert_eq!(row.values(), &values[..]);
         }
+        assert_eq!(named[0].get("name"), Some(&Value::String("ada".to_string())));
+        assert_eq!(named[1].clone().into_values(), positional[1].1);
+    }
 
-        let mut db = Db::open(&path).unwrap();
-        let tbl = db.get_table("users").unwrap().expect("table exists");
-        assert_eq!(tbl.id, TableId(1));
-        assert_eq!(tbl.schema, schema);
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn colliding_composite_keys_are_rejected_in_debug_builds() {
+        let schema = Schema::new(vec![Column {
+            name: "v".to_string(),
+            ty: ColType::U32,
+            nullable: false,
+        }])
+        .unwrap();
+        let mut db = Db::create(unique_temp_path("key_collision")).unwrap();
+        let a = db.create_table("a", &schema).unwrap();
+        let b = db.create_table("b", &schema).unwrap();
+        db.insert_row("a", &vec![Value::U32(1)]).unwrap();
+
+        // Move b's next pk onto the pk whose key collides with a's pk 1.
+        let key = composite_for_tests(a.0, 1);
+        let colliding = crate::table::composite_pk(b.0, key);
+        assert_eq!(composite_for_tests(b.0, colliding), key);
+        let mut cat = db.pager.read_catalog().unwrap();
+        cat.tables.iter_mut().find(|t| t.id == b).unwrap().next_pk = colliding;
+        db.pager.write_catalog(&cat).unwrap();
+
+        match db.insert_row("b", &vec![Value::U32(2)]) {
+            Err(InvError::InvalidArgument { name: "pk", details }) => {
+                assert!(details.contains("composite key collision"), "{}", details)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(db.get_row_by_pk("a", 1).unwrap(), Some(vec![Value::U32(1)]));
+        assert_eq!(db.get_table("b").unwrap().unwrap().next_pk, colliding);
     }
 
+
     #[test]
-    fn catalog_rejects_duplicate_table_name() {
-        let path = unique_temp_path("catalog_dupe");
+    fn infer_schema_from_sample_row() {
+        let row = vec![
+            Value::U32(1),
+            Value::U64(2),
+            Value::I64(-3),
+            Value::Bool(true),
+            Value::Bytes(vec![4]),
+            Value::String("five".to_string()),
+        ];
+        let names = ["a", "b", "c", "d", "e", "f"];
+        let schema = infer_schema(&names, &row).unwrap();
+        let types: Vec<ColType> = schema.columns.iter().map(|c| c.ty.clone()).collect();
+        assert_eq!(
+            types,
+            vec![ColType::U32, ColType::U64, ColType::I64, ColType::Bool, ColType::Bytes, ColType::String]
+        );
+        assert!(schema.columns.iter().all(|c| !c.nullable));
+        assert_eq!(schema.columns[5].name, "f");
+        assert_eq!(decode_row(&schema, &encode_row(&schema, &row).unwrap()).unwrap(), row);
+
+        assert!(matches!(
+            infer_schema(&["a", "b"], &vec![Value::U32(1), Value::Null]),
+            Err(InvError::InvalidArgument { name: "row.null", .. })
+        ));
+        assert!(matches!(
+            infer_schema(&["a"], &vec![Value::U32(1), Value::U32(2)]),
+            Err(InvError::InvalidArgument { name: "row", .. })
+        ));
+    }
+
+
+    #[test]
+    fn transient_io_errors_are_retried_per_policy() {
+        use crate::file::IoOp;
+        use crate::testing::{clear_io_faults, inject_io_faults};
+        use std::io::ErrorKind;
+
+        let path = unique_temp_path("io_retry");
         let schema = Schema::new(vec![Column {
-            name: "id".to_string(),
+            name: "v".to_string(),
             ty: ColType::U32,
             nullable: false,
         }])
         .unwrap();
         let mut db = Db::create(&path).unwrap();
-        db.create_table("users", &schema).unwrap();
-        let err = db.create_table("users", &schema).unwrap_err();
+        db.create_table("t", &schema).unwrap();
+        db.insert_row("t", &vec![Value::U32(7)]).unwrap();
+
+        // Two interrupted writes are absorbed by the default three retries.
+        inject_io_faults(&path, IoOp::Write, ErrorKind::Interrupted, 2);
+        db.flush().unwrap();
+        drop(db);
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.get_row_by_pk("t", 1).unwrap(), Some(vec![Value::U32(7)]));
+        drop(db);
+
+        // Non-retryable kinds and exhausted budgets surface as Io.
+        let options = DbOptions {
+            retry_policy: RetryPolicy {
+                max_retries: 1,
+                ..RetryPolicy::default()
+            },
+            ..DbOptions::default()
+        };
+        let mut db = Db::open_with_options(&path, options).unwrap();
+        db.insert_row("t", &vec![Value::U32(8)]).unwrap();
+        inject_io_faults(&path, IoOp::Write, ErrorKind::Interrupted, 2);
+        assert!(matches!(db.flush(), Err(InvError::Io { .. })));
+        inject_io_faults(&path, IoOp::Write, ErrorKind::PermissionDenied, 1);
+        assert!(matches!(db.flush(), Err(InvError::Io { .. })));
+        clear_io_faults(&path);
+        db.flush().unwrap();
+    }
+
+
+    #[test]
+    fn iter_u64_from_resumes_mid_tree() {
+        let mut db = Db::create(unique_temp_path("iter_u64_from")).unwrap();
+        for key in pseudo_shuffle(3_000) {
+            db.put_u64(key * 3, key as u64 + 10).unwrap();
+        }
+        let all: Vec<(u32, u64)> = db.iter_u64().unwrap().collect::<InvResult<_>>().unwrap();
+        assert_eq!(all.len(), 3_000);
+        assert!(all.windows(2).all(|w| w[0].0 < w[1].0));
+
+        for start in [0, 1, 3, 4_000, 4_001, 8_999, 9_000] {
+            let resumed: Vec<(u32, u64)> = db.iter_u64_from(start).unwrap().collect::<InvResult<_>>().unwrap();
+            let expected: Vec<(u32, u64)> = all.iter().copied().filter(|&(k, _)| k >= start).collect();
+            assert_eq!(resumed, expected, "start {}", start);
+        }
+        assert_eq!(db.iter_u64_from(9_001).unwrap().count(), 0);
+        assert_eq!(db.iter_u64_from(u32::MAX).unwrap().count(), 0);
+        let first_two: Vec<_> = db.iter_u64_from(4_000).unwrap().take(2).collect::<InvResult<_>>().unwrap();
+        assert_eq!(first_two, vec![(4_002, 1_344), (4_005, 1_345)]);
+    }
+
+
+    #[test]
+    fn read_page_bytes_returns_validated_copies() {
+        let mut db = Db::create(unique_temp_path("read_page_bytes")).unwrap();
+        let catalog = db.read_page_bytes(config::CATALOG_PAGE_ID).unwrap();
+        assert_eq!(catalog[0], config::META_PAGE_KIND);
+        assert_eq!(&catalog[16..20], b"CAT1");
+        let header = db.read_page_bytes(config::HEADER_PAGE_ID).unwrap();
+        assert_eq!(header.len(), PAGE_SIZE);
+        assert_eq!(&header[..], &std::fs::read(db.path()).unwrap()[..PAGE_SIZE]);
+        let count = db.pager.page_count();
         assert!(matches!(
-            err,
-            InvError::InvalidArgument {
-                name: "table.name",
-                ..
-            }
+            db.read_page_bytes(PageId(count)),
+            Err(InvError::InvalidArgument { name: "page_id", .. })
         ));
     }
 
+
     #[test]
-    fn catalog_list_tables() {
-        let path = unique_temp_path("catalog_list");
+    fn table_storage_grows_with_inserted_rows() {
         let schema = Schema::new(vec![Column {
-            name: "id".to_string(),
-            ty: ColType::U32,
+            name: "body".to_string(),
+            ty: ColType::Bytes,
             nullable: false,
         }])
         .unwrap();
+        let mut db = Db::create(unique_temp_path("table_storage")).unwrap();
+        db.create_table("t", &schema).unwrap();
+        db.create_table("other", &schema).unwrap();
+        assert_eq!(db.table_storage("t").unwrap(), TableStorage::default());
+
+        let row = vec![Value::Bytes(vec![3; 200])];
+        // 2 slot len + 4 pk + 4 magic + 1 count + 1 tag + 2 varint + 200, plus a 12-byte entry.
+        let per_row = 2 + 4 + 4 + 1 + 1 + 2 + 200 + 12;
+        db.insert_row("t", &row).unwrap();
+        let before = db.table_storage_bytes("t").unwrap();
+        for _ in 0..500 {
+            db.insert_row("t", &row).unwrap();
+            db.insert_row("other", &vec![Value::Bytes(vec![1; 50])]).unwrap();
+        }
+        let after = db.table_storage("t").unwrap();
+        assert_eq!(after.used_bytes - before, 500 * per_row);
+        assert_eq!(after.live_bytes, 501 * per_row);
+        assert!(after.allocated_bytes >= after.used_bytes);
+        assert!(after.allocated_bytes < after.used_bytes + 4 * PAGE_SIZE as u64);
+
+        // Deleted rows stop counting as live but keep using their slots.
+        for pk in (2..=200).step_by(2) {
+            db.delete_row("t", pk).unwrap();
+        }
+        let deleted = db.table_storage("t").unwrap();
+        assert_eq!(deleted.live_bytes, 401 * per_row);
+        assert_eq!(deleted.used_bytes, after.used_bytes - 100 * 12);
+    }
+
+
+    #[test]
+    fn layout_offsets_match_encoded_bytes() {
+        use crate::layout::*;
+
+        let path = unique_temp_path("layout_offsets");
         let mut db = Db::create(&path).unwrap();
-        db.create_table("a", &schema).unwrap();
-        db.create_table("b", &schema).unwrap();
-        let list = db.list_tables().unwrap();
-        let names: HashSet<_> = list.into_iter().map(|t| t.name).collect();
-        assert_eq!(names.len(), 2);
-        assert!(names.contains("a"));
-        assert!(names.contains("b"));
+        let schema = clustered_schema();
+        db.create_table("plain", &schema).unwrap();
+        db.create_clustered_table("events", &schema, "ts").unwrap();
+        db.insert_row("plain", &vec![Value::I64(-1), Value::String("abc".to_string())]).unwrap();
+        db.insert_row("events", &vec![Value::I64(7), Value::String("x".to_string())]).unwrap();
+        db.flush().unwrap();
+
+        // Header page: explicit little-endian byte patterns, not native order.
+        let header = db.read_page_bytes(config::HEADER_PAGE_ID).unwrap();
+        assert_eq!(&header[HEADER_MAGIC..HEADER_MAGIC + 8], &config::FILE_MAGIC);
+        assert_eq!(&header[HEADER_VERSION..HEADER_VERSION + 2], &[1, 0]);
+        assert_eq!(&header[HEADER_PAGE_SIZE..HEADER_PAGE_SIZE + 2], &[0x00, 0x10]);
+        assert_eq!(&header[HEADER_ROOT_PAGE_ID..HEADER_ROOT_PAGE_ID + 4], &[1, 0, 0, 0]);
+        let page_count = db.pager.page_count();
+        assert_eq!(read_u32_le(&header, HEADER_PAGE_COUNT), page_count);
+        assert_eq!(header[HEADER_PAGE_COUNT + 1..HEADER_PAGE_COUNT + 4], [0, 0, 0]);
+        assert_eq!(&header[HEADER_RESERVED..HEADER_RESERVED + 4], &[0, 0, 0, 0]);
+
+        // Catalog page: per-page header, then the catalog payload.
+        let catalog = db.read_page_bytes(config::CATALOG_PAGE_ID).unwrap();
+        assert_eq!(catalog[PAGE_KIND], config::META_PAGE_KIND);
+        assert_eq!(catalog[PAGE_FLAGS], 0);
+        assert_eq!(&catalog[PAGE_RESERVED..PAGE_RESERVED + 2], &[0, 0]);
+        assert_eq!(&catalog[PAGE_CRC32..PAGE_CRC32 + 4], &[0, 0, 0, 0]);
+        assert_eq!(&catalog[PAGE_ID..PAGE_ID + 4], &[2, 0, 0, 0]);
+        assert_eq!(&catalog[PAGE_RESERVED2..PAGE_RESERVED2 + 4], &[0, 0, 0, 0]);
+        let payload = &catalog[PAGE_PAYLOAD..];
+        assert_eq!(&payload[CATALOG_MAGIC..CATALOG_MAGIC + 4], b"CAT1");
+        assert_eq!(read_u16_le(payload, CATALOG_VERSION), crate::catalog::CATALOG_VERSION);
+        assert_eq!(&payload[CATALOG_ENTRY_COUNT..CATALOG_ENTRY_COUNT + 2], &[2, 0]);
+        assert_eq!(&payload[CATALOG_NEXT_TABLE_ID..CATALOG_NEXT_TABLE_ID + 4], &[3, 0, 0, 0]);
+        assert_eq!(&payload[CATALOG_RESERVED..CATALOG_RESERVED + 4], &[0, 0, 0, 0]);
+        assert_eq!(&payload[CATALOG_ENTRIES..CATALOG_ENTRIES + 4], &[1, 0, 0, 0]); // first table id
+
+        // Row page: header fields and the first slot (u16 length, pk, row).
+        let plain = db.get_table("plain").unwrap().unwrap();
+        let rows = db.read_page_bytes(PageId(plain.last_row_page)).unwrap();
+        assert_eq!(rows[PAGE_KIND], config::ROW_PAGE_KIND);
+        assert_eq!(&rows[ROW_PAGE_MAGIC..ROW_PAGE_MAGIC + 4], b"ROWP");
+        assert_eq!(&rows[ROW_PAGE_VERSION..ROW_PAGE_VERSION + 2], &[2, 0]);
+        assert_eq!(&rows[ROW_PAGE_NEXT..ROW_PAGE_NEXT + 4], &[0, 0, 0, 0]);
+        assert_eq!(&rows[ROW_PAGE_RESERVED2..ROW_PAGE_RESERVED2 + 4], &[0, 0, 0, 0]);
+        let encoded = encode_row(&schema, &vec![Value::I64(-1), Value::String("abc".to_string())]).unwrap();
+        let stored_len = 4 + encoded.len();
+        assert_eq!(read_u16_le(&rows, ROW_PAGE_DATA) as usize, stored_len);
+        assert_eq!(&rows[ROW_PAGE_DATA + 2..ROW_PAGE_DATA + 6], &[1, 0, 0, 0]);
+        assert_eq!(&rows[ROW_PAGE_DATA + 6..ROW_PAGE_DATA + 2 + stored_len], &encoded[..]);
+        assert_eq!(read_u16_le(&rows, ROW_PAGE_FREE_OFFSET) as usize, ROW_PAGE_DATA + 2 + stored_len);
+
+        // Cluster directory: one entry holding the page and its fence key.
+        let events = db.get_table("events").unwrap().unwrap();
+        let directory = db.read_page_bytes(PageId(events.cluster.unwrap().directory_page)).unwrap();
+        assert_eq!(directory[PAGE_KIND], config::META_PAGE_KIND);
+        assert_eq!(&directory[DIRECTORY_MAGIC..DIRECTORY_MAGIC + 4], b"CLD1");
+        assert_eq!(&directory[DIRECTORY_VERSION..DIRECTORY_VERSION + 2], &[1, 0]);
+        assert_eq!(&directory[DIRECTORY_COUNT..DIRECTORY_COUNT + 2], &[1, 0]);
+        let cluster_page = read_u32_le(&directory, DIRECTORY_ENTRIES);
+        assert_eq!(db.read_page_bytes(PageId(cluster_page)).unwrap()[PAGE_KIND], config::ROW_PAGE_KIND);
+        let fence = crate::cluster::cluster_key(&Value::I64(7)).unwrap();
+        assert_eq!(&directory[DIRECTORY_ENTRIES + 4..DIRECTORY_ENTRIES + 12], &fence.to_le_bytes());
+
+        // B-tree: keys are far above the table keys so the entries stay apart.
+        let base = 0x8000_0000u32;
+        db.put_u64(base, 0x0102_0304_0506_0708).unwrap();
+        let root = db.read_page_bytes(db.pager.root_page_id()).unwrap();
+        assert_eq!(root[PAGE_KIND], 2);
+        assert_eq!(root[NODE_KIND], NODE_KIND_LEAF);
+        assert_eq!(root[NODE_FLAGS], 0);
+        let num_keys = read_u16_le(&root, NODE_NUM_KEYS) as usize;
+        assert_eq!(num_keys, 3);
+        assert_eq!(&root[NODE_RESERVED..NODE_RESERVED + 4], &[0, 0, 0, 0]);
+        assert_eq!(&root[NODE_NEXT_LEAF..NODE_NEXT_LEAF + 4], &[0, 0, 0, 0]);
+        assert_eq!(&root[NODE_RESERVED2..NODE_RESERVED2 + 4], &[0, 0, 0, 0]);
+        let last_key = NODE_ENTRIES + 4 * (num_keys - 1);
+        assert_eq!(&root[last_key..last_key + 4], &[0, 0, 0, 0x80]);
+        let last_value = NODE_ENTRIES + 4 * num_keys + 8 * (num_keys - 1);
+        assert_eq!(&root[last_value..last_value + 8], &[8, 7, 6, 5, 4, 3, 2, 1]);
+
+        for i in 1..=crate::btree::node::max_leaf_keys() as u32 {
+            db.put_u64(base + i, i as u64).unwrap();
+        }
+        let root = db.read_page_bytes(db.pager.root_page_id()).unwrap();
+        assert_eq!(root[NODE_KIND], NODE_KIND_INTERNAL);
+        assert_eq!(&root[NODE_NUM_KEYS..NODE_NUM_KEYS + 2], &[1, 0]);
+        let left = read_u32_le(&root, NODE_ENTRIES);
+        let right = read_u32_le(&root, NODE_ENTRIES + 4);
+        let separator = read_u32_le(&root, NODE_ENTRIES + 8);
+        let left_page = db.read_page_bytes(PageId(left)).unwrap();
+        assert_eq!(left_page[NODE_KIND], NODE_KIND_LEAF);
+        assert_eq!(read_u32_le(&left_page, NODE_NEXT_LEAF), right);
+        let right_page = db.read_page_bytes(PageId(right)).unwrap();
+        assert_eq!(read_u32_le(&right_page, NODE_ENTRIES), separator);
     }
 
+
     #[test]
-    fn open_rejects_corrupt_catalog_magic_on_read() {
-        let path = unique_temp_path("catalog_corrupt_magic");
-        {
+    fn get_many_u64_matches_between_sorted_and_unsorted_batches() {
+        let path = unique_temp_path("get_many_u64");
+        let mut db = Db::create(&path).unwrap();
+        for key in pseudo_shuffle(1500) {
+            db.put_u64(key * 2, key as u64 * 10).unwrap();
+        }
+
+        let sorted: Vec<u32> = (0..3000).step_by(7).collect();
+        let mut unsorted = sorted.clone();
+        unsorted.reverse();
+        let from_sorted = db.get_many_u64(&sorted).unwrap();
+        let mut from_unsorted = db.get_many_u64(&unsorted).unwrap();
+        from_unsorted.reverse();
+        assert_eq!(from_sorted, from_unsorted);
+        for (key, got) in sorted.iter().zip(&from_sorted) {
+            assert_eq!(*got, db.get_u64(*key).unwrap());
+        }
+
+        // Absent keys, duplicates and keys past the last entry.
+        let batch = [1u32, 2, 2, 2999, 3000, 3001, 9000];
+        assert_eq!(
+            db.get_many_u64(&batch).unwrap(),
+            vec![None, Some(10), Some(10), None, Some(15000), None, None]
+        );
+        assert_eq!(db.get_many_u64(&[9000, 4, 3]).unwrap(), vec![None, Some(20), None]);
+        assert!(db.get_many_u64(&[]).unwrap().is_empty());
+    }
+
+
+    #[test]
+    fn allocators_produce_pages_passing_their_validation() {
+        let path = unique_temp_path("allocator_validation");
+        let mut db = Db::create(&path).unwrap();
+        let pager = db.pager_mut_for_tests();
+        let btree_page = pager.allocate_btree_page().unwrap();
+        let row_page = pager.allocate_row_page().unwrap();
+        let page_count = pager.page_count();
+
+        let page = pager.get_page(btree_page).unwrap();
+        page.validate_header().unwrap();
+        match crate::btree::node::Node::decode(page, page_count).unwrap() {
+            crate::btree::node::Node::Leaf(leaf) => assert_eq!(leaf.num_keys, 0),
+            other => panic!("expected empty leaf, got {:?}", other),
+        }
+        assert!(crate::rowstore::validate_row_page_header(page.as_bytes()).is_err());
+
+        let page = pager.get_page(row_page).unwrap();
+        page.validate_header().unwrap();
+        crate::rowstore::validate_row_page_header(page.as_bytes()).unwrap();
+        assert!(crate::btree::node::Node::decode(page, page_count).is_err());
+    }
+
+
+    #[test]
+    fn u64_key_count_counts_distinct_keys() {
+        let path = unique_temp_path("u64_key_count");
+        let mut db = Db::create(&path).unwrap();
+        assert_eq!(db.u64_key_count().unwrap(), 0);
+        for key in pseudo_shuffle(1000) {
+            db.put_u64(key, key as u64).unwrap();
+        }
+        db.put_u64(u32::MAX, 1).unwrap();
+        assert_eq!(db.u64_key_count().unwrap(), 1001);
+
+        db.put_u64(500, 0).unwrap();
+        db.put_u64(u32::MAX, 2).unwrap();
+        assert_eq!(db.u64_key_count().unwrap(), 1001);
+        assert_eq!(db.iter_u64().unwrap().count(), 1001);
+    }
+
+
+    #[test]
+    fn header_validation_level_skips_deep_checks_on_open() {
+        let path = unique_temp_path("validation_level");
+        let first_leaf = {
             let mut db = Db::create(&path).unwrap();
+            for key in 0..20_000u32 {
+                db.put_u64(key, key as u64).unwrap();
+            }
             db.flush().unwrap();
-        }
+            let root = db.pager.root_page_id();
+            crate::btree::search::find_leaf(&mut db.pager, root, 0).unwrap()
+        };
+        let header_only = DbOptions {
+            validate_on_open: ValidationLevel::Header,
+            ..DbOptions::default()
+        };
 
-        // Corrupt catalog magic byte on disk at payload offset.
+        let full_reads = Db::open(&path).unwrap().io_stats().reads;
+        let mut db = Db::open_with_options(&path, header_only.clone()).unwrap();
+        let header_reads = db.io_stats().reads;
+        assert!(header_reads <= 3, "header level read {} pages", header_reads);
+        assert!(full_reads > 50 * header_reads, "full {} vs header {}", full_reads, header_reads);
+        assert_eq!(db.get_u64(12_345).unwrap(), Some(12_345));
+        db.check_integrity().unwrap();
+        drop(db);
+
+        // A damaged leaf is only found by the deferred check.
         {
-            let mut f = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&path)
+            let mut f = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+            f.seek(SeekFrom::Start((first_leaf.0 as usize * PAGE_SIZE + crate::layout::NODE_FLAGS) as u64))
                 .unwrap();
-            let offset = (2 * PAGE_SIZE + 16) as u64;
-            f.seek(SeekFrom::Start(offset)).unwrap();
-            let mut b = [0u8; 1];
-            f.read_exact(&mut b).unwrap();
-            b[0] ^= 0xFF;
-            f.seek(SeekFrom::Start(offset)).unwrap();
-            f.write_all(&b).unwrap();
+            f.write_all(&[1]).unwrap();
         }
-
-        let err = Db::open(&path).unwrap_err();
+        assert!(Db::open(&path).is_err());
+        let mut db = Db::open_with_options(&path, header_only.clone()).unwrap();
         assert!(matches!(
-            err,
-            InvError::Corruption {
-                context: "catalog.magic",
-                ..
-            }
+            db.check_integrity().unwrap_err(),
+            InvError::Unsupported { feature: "btree.node_flags" }
         ));
+        drop(db);
+
+        // A root pointing at the catalog page still fails at the header level.
+        {
+            let mut f = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+            f.seek(SeekFrom::Start(crate::layout::HEADER_ROOT_PAGE_ID as u64)).unwrap();
+            f.write_all(&2u32.to_le_bytes()).unwrap();
+        }
+        let err = Db::open_with_options(&path, header_only).unwrap_err();
+        assert!(matches!(err, InvError::Corruption { context: "btree.page_kind", .. }), "{:?}", err);
+        let none = DbOptions {
+            validate_on_open: ValidationLevel::None,
+            ..DbOptions::default()
+        };
+        assert!(Db::open_with_options(&path, none).is_ok());
     }
 
+
     #[test]
-    fn create_requires_catalog_page_kind() {
-        let path = unique_temp_path("catalog_kind");
+    fn put_u64_batch_appends_to_populated_tree() {
+        let path = unique_temp_path("put_u64_batch");
+        {
+            let mut db = Db::create(&path).unwrap();
+            for key in pseudo_shuffle(2000) {
+                db.put_u64(key, key as u64).unwrap();
+            }
+            let pages_before = db.pager.page_count();
+            // Enough to add leaves and force a root split of the internal level.
+            let batch: Vec<(u32, u64)> = (2001..=200_000u32).map(|k| (k, k as u64 * 3)).collect();
+            db.put_u64_batch(&batch).unwrap();
+            let added = db.pager.page_count() - pages_before;
+            assert!(added as usize <= batch.len() / max_leaf_keys() + 4, "added {} pages", added);
+
+            // Not an append: falls back to per-key inserts.
+            db.put_u64_batch(&[(5, 50), (3, 30), (200_001, 1)]).unwrap();
+            db.flush().unwrap();
+        }
+
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.u64_key_count().unwrap(), 200_001);
+        for key in [1u32, 2000, 2001, 2002, 70_000, 199_999, 200_000] {
+            let expected = if key <= 2000 { key as u64 } else { key as u64 * 3 };
+            assert_eq!(db.get_u64(key).unwrap(), Some(expected), "key {}", key);
+        }
+        assert_eq!(db.get_u64(5).unwrap(), Some(50));
+        assert_eq!(db.get_u64(3).unwrap(), Some(30));
+        assert_eq!(db.get_u64(200_001).unwrap(), Some(1));
+        let keys: Vec<u32> = db.iter_u64().unwrap().map(|e| e.unwrap().0).collect();
+        assert_eq!(keys, (1..=200_001).collect::<Vec<_>>());
+    }
+
+
+    #[test]
+    fn row_present_treats_missing_table_as_absent() {
+        let path = unique_temp_path("row_present");
+        let mut db = Db::create(&path).unwrap();
+        assert!(!db.row_present("t", 1).unwrap());
+        db.create_table("t", &clustered_schema()).unwrap();
+        assert!(!db.row_present("t", 1).unwrap());
+        let pk = db.insert_row("t", &vec![Value::I64(1), Value::String("a".to_string())]).unwrap();
+        assert!(db.row_present("t", pk).unwrap());
+        assert!(!db.row_present("t", pk + 1).unwrap());
+        assert!(db.delete_row("t", pk).unwrap());
+        assert!(!db.row_present("t", pk).unwrap());
+        assert!(db.get_row_by_pk("missing", 1).is_err());
+    }
+
+
+    #[test]
+    fn blobs_round_trip_apart_from_tables_and_raw_keys() {
+        let path = unique_temp_path("blobs");
+        let large: Vec<u8> = (0..crate::blob::MAX_BLOB_BYTES).map(|i| (i % 251) as u8).collect();
+        let table_key = {
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("t", &clustered_schema()).unwrap();
+            let pk = db.insert_row("t", &vec![Value::I64(1), Value::String("row".to_string())]).unwrap();
+            let table_key = composite_for_tests(1, pk);
+            db.put_u64(7, 70).unwrap();
+
+            assert_eq!(db.get_blob(1).unwrap(), None);
+            db.put_blob(1, b"first").unwrap();
+            db.put_blob(7, b"").unwrap();
+            db.put_blob(table_key, &large).unwrap();
+            db.put_blob(1, b"replaced").unwrap();
+            assert!(matches!(
+                db.put_blob(2, &vec![0; crate::blob::MAX_BLOB_BYTES + 1]).unwrap_err(),
+                InvError::Unsupported { feature: "blob.too_large" }
+            ));
+            db.flush().unwrap();
+            table_key
+        };
+
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.get_blob(1).unwrap(), Some(b"replaced".to_vec()));
+        assert_eq!(db.get_blob(7).unwrap(), Some(Vec::new()));
+        assert_eq!(db.get_blob(table_key).unwrap(), Some(large.clone()));
+        assert_eq!(db.get_blob(2).unwrap(), None);
+        assert_eq!(db.get_u64(7).unwrap(), Some(70));
+        assert_eq!(db.scan_table("t").unwrap().len(), 1);
+
+        let compacted = unique_temp_path("blobs_compacted");
+        db.compact_to(&compacted).unwrap();
+        let mut copy = Db::open(&compacted).unwrap();
+        assert_eq!(copy.get_blob(1).unwrap(), Some(b"replaced".to_vec()));
+        assert_eq!(copy.get_blob(table_key).unwrap(), Some(large));
+    }
+
+
+    #[test]
+    fn compact_btree_frees_empty_leaves() {
+        let path = unique_temp_path("compact_btree");
         {
             let mut db = Db::create(&path).unwrap();
+            for key in 1..=3000u32 {
+                db.put_u64(key, key as u64).unwrap();
+            }
+            let root = db.pager.root_page_id();
+            for key in 200..=2500u32 {
+                assert!(crate::btree::delete_u64(&mut db.pager, root, key).unwrap().1);
+            }
+            let (pages_before, _) = crate::btree::search::tree_counts(&mut db.pager, root).unwrap();
+
+            let freed = db.compact_btree().unwrap();
+            assert!(freed >= 5, "freed {}", freed);
+            assert_eq!(db.pager.free_pages().unwrap().len() as u64, freed);
+            let root = db.pager.root_page_id();
+            let (pages_after, keys) = crate::btree::search::tree_counts(&mut db.pager, root).unwrap();
+            assert_eq!(pages_after as u64, pages_before as u64 - freed);
+            assert_eq!(keys, 699);
+            assert_eq!(db.compact_btree().unwrap(), 0);
+
+            // Freed pages are handed out again before the file grows.
+            let page_count = db.pager.page_count();
+            let reused = db.pager.allocate_row_page().unwrap();
+            assert!(reused.0 < page_count);
+            assert_eq!(db.pager.page_count(), page_count);
             db.flush().unwrap();
         }
-        {
-            let mut f = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&path)
-                .unwrap();
-            let offset = (2 * PAGE_SIZE) as u64; // start of catalog page
-            f.seek(SeekFrom::Start(offset)).unwrap();
-            // Set page_kind byte 0 to 2 (btree kind)
-            f.write_all(&[2]).unwrap();
+
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.get_u64(199).unwrap(), Some(199));
+        assert_eq!(db.get_u64(200).unwrap(), None);
+        assert_eq!(db.get_u64(2501).unwrap(), Some(2501));
+        let keys: Vec<u32> = db.iter_u64().unwrap().map(|e| e.unwrap().0).collect();
+        assert_eq!(keys, (1..200).chain(2501..=3000).collect::<Vec<_>>());
+
+        // Emptying the whole tree leaves a single empty leaf as the root.
+        let root = db.pager.root_page_id();
+        for key in keys {
+            crate::btree::delete_u64(&mut db.pager, root, key).unwrap();
+        }
+        assert!(db.compact_btree().unwrap() > 0);
+        let root = db.pager.root_page_id();
+        assert_eq!(crate::btree::search::tree_counts(&mut db.pager, root).unwrap(), (1, 0));
+        db.put_u64(5, 50).unwrap();
+        drop(db);
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.get_u64(5).unwrap(), Some(50));
+        for page in db.pager.free_pages().unwrap() {
+            db.validate_page(page).unwrap();
         }
+    }
 
-        let err = Db::open(&path).unwrap_err();
+
+    #[test]
+    fn validate_table_rows_catches_schema_mismatch() {
+        let path = unique_temp_path("validate_table_rows");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("events", &clustered_schema()).unwrap();
+        for i in 0..200i64 {
+            db.insert_row("events", &vec![Value::I64(i), Value::String(format!("e{}", i))]).unwrap();
+        }
+        db.validate_table_rows("events", 50).unwrap();
+        db.validate_table_rows("events", 0).unwrap();
         assert!(matches!(
-            err,
-            InvError::Corruption {
-                context: "catalog.page_kind",
-                ..
-            }
+            db.validate_table_rows("missing", 10).unwrap_err(),
+            InvError::InvalidArgument { name: "table", .. }
         ));
-    }
 
-    #[test]
-    fn insert_and_get_row_roundtrip() {
-        let path = unique_temp_path("row_roundtrip");
-        let schema = Schema::new(vec![
+        // Claim the first column holds strings: every row now disagrees.
+        let mut cat = db.pager.read_catalog().unwrap();
+        cat.tables[0].schema = Schema::new(vec![
             Column {
-                name: "age".to_string(),
-                ty: ColType::U32,
+                name: "ts".to_string(),
+                ty: ColType::String,
                 nullable: false,
             },
             Column {
-                name: "name".to_string(),
+                name: "payload".to_string(),
                 ty: ColType::String,
-                nullable: true,
+                nullable: false,
             },
         ])
         .unwrap();
-        let pk;
-        {
-            let mut db = Db::create(&path).unwrap();
-            db.create_table("users", &schema).unwrap();
-            pk = db
-                .insert_row("users", &vec![Value::U32(20), Value::String("kazuha".to_string())])
-                .unwrap();
-            db.flush().unwrap();
+        db.pager.write_catalog(&cat).unwrap();
+        assert!(matches!(
+            db.validate_table_rows("events", 5).unwrap_err(),
+            InvError::Corruption { .. }
+        ));
+    }
+
+
+    #[test]
+    fn missing_pks_reports_deleted_pks() {
+        let path = unique_temp_path("missing_pks");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        assert!(db.missing_pks("t").unwrap().is_empty());
+        for i in 0..50i64 {
+            db.insert_row("t", &vec![Value::I64(i), Value::String(String::new())]).unwrap();
         }
+        assert!(db.missing_pks("t").unwrap().is_empty());
+        for pk in [1u32, 17, 18, 50] {
+            assert!(db.delete_row("t", pk).unwrap());
+        }
+        assert_eq!(db.missing_pks("t").unwrap(), vec![1, 17, 18, 50]);
+        assert_eq!(db.list_pks("t").unwrap().len(), 46);
+    }
+
+
+    #[test]
+    fn reserve_pk_block_skips_auto_allocation_past_the_block() {
+        let path = unique_temp_path("reserve_pk_block");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        let row = |i: i64| vec![Value::I64(i), Value::String(format!("r{}", i))];
+        assert_eq!(db.insert_row("t", &row(0)).unwrap(), 1);
+        let first = db.reserve_pk_block("t", 10).unwrap();
+        assert_eq!(first, 2);
+        db.insert_row_with_pk("t", 5, &row(5)).unwrap();
+        assert!(db.insert_row_with_pk("t", 5, &row(5)).is_err());
+        assert_eq!(db.insert_row("t", &row(12)).unwrap(), 12);
+        drop(db);
+
         let mut db = Db::open(&path).unwrap();
-        let row = db.get_row_by_pk("users", pk).unwrap().unwrap();
-        assert_eq!(row, vec![Value::U32(20), Value::String("kazuha".to_string())]);
+        assert_eq!(db.get_row_by_pk("t", 5).unwrap(), Some(row(5)));
+        assert_eq!(db.get_row_by_pk("t", 3).unwrap(), None);
+        assert_eq!(db.insert_row("t", &row(13)).unwrap(), 13);
+        assert!(matches!(
+            db.reserve_pk_block("t", u32::MAX),
+            Err(InvError::Overflow { .. })
+        ));
     }
 
+
     #[test]
-    fn multiple_inserts_increment_pk() {
-        let path = unique_temp_path("row_pk_inc");
-        let schema = Schema::new(vec![Column {
-            name: "v".to_string(),
-            ty: ColType::U32,
-            nullable: false,
-        }])
-        .unwrap();
+    fn next_allocation_id_follows_the_free_list() {
+        let path = unique_temp_path("next_allocation_id");
         let mut db = Db::create(&path).unwrap();
-        db.create_table("t", &schema).unwrap();
-        let pk1 = db.insert_row("t", &vec![Value::U32(1)]).unwrap();
-        let pk2 = db.insert_row("t", &vec![Value::U32(2)]).unwrap();
-        let pk3 = db.insert_row("t", &vec![Value::U32(3)]).unwrap();
-        assert_eq!(pk1, 1);
-        assert_eq!(pk2, 2);
-        assert_eq!(pk3, 3);
+        let a = db.pager.allocate_row_page().unwrap();
+        let b = db.pager.allocate_row_page().unwrap();
+        let end = PageId(db.pager.page_count());
+        assert_eq!(db.next_allocation_id(), end);
+
+        db.pager.free_page(a).unwrap();
+        assert_eq!(db.next_allocation_id(), a);
+        db.pager.free_page(b).unwrap();
+        assert_eq!(db.next_allocation_id(), b);
+
+        assert_eq!(db.pager.allocate_row_page().unwrap(), b);
+        assert_eq!(db.next_allocation_id(), a);
+        assert_eq!(db.pager.allocate_btree_page().unwrap(), a);
+        assert_eq!(db.next_allocation_id(), end);
+    }
+
+
+    #[test]
+    fn logically_equals_ignores_layout_but_not_rows() {
+        let src_path = unique_temp_path("logical_eq_src");
+        let dest_path = unique_temp_path("logical_eq_dest");
+        let mut db = Db::create(&src_path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        db.create_table_u64_pk("wide", &clustered_schema()).unwrap();
+        for i in 0..300i64 {
+            db.insert_row("t", &vec![Value::I64(i), Value::String(format!("r{}", i))]).unwrap();
+        }
+        for i in 0..20i64 {
+            db.insert_row_u64("wide", &vec![Value::I64(i), Value::String(String::new())]).unwrap();
+        }
+        for pk in (1..=300u32).step_by(3) {
+            db.delete_row("t", pk).unwrap();
+        }
         db.flush().unwrap();
+        db.compact_to(&dest_path).unwrap();
 
-        let mut db = Db::open(&path).unwrap();
-        let rows = db.scan_table("t").unwrap();
-        assert_eq!(rows.len(), 3);
-        assert_eq!(rows[0], (1, vec![Value::U32(1)]));
-        assert_eq!(rows[1], (2, vec![Value::U32(2)]));
-        assert_eq!(rows[2], (3, vec![Value::U32(3)]));
+        let mut copy = Db::open(&dest_path).unwrap();
+        assert!(db.logically_equals(&mut copy).unwrap());
+        assert!(copy.logically_equals(&mut db).unwrap());
+
+        copy.delete_row("t", 2).unwrap();
+        assert!(!db.logically_equals(&mut copy).unwrap());
+        db.delete_row("t", 2).unwrap();
+        assert!(db.logically_equals(&mut copy).unwrap());
+
+        copy.create_table("extra", &clustered_schema()).unwrap();
+        assert!(!db.logically_equals(&mut copy).unwrap());
     }
 
     #[test]
-    fn persistence_of_catalog_pk_state_rows() {
-        let path = unique_temp_path("row_pk_state");
-        let schema = Schema::new(vec![Column {
-            name: "v".to_string(),
-            ty: ColType::U32,
-            nullable: false,
-        }])
-        .unwrap();
-        {
-            let mut db = Db::create(&path).unwrap();
-            db.create_table("t", &schema).unwrap();
-            db.insert_row("t", &vec![Value::U32(1)]).unwrap();
-            db.insert_row("t", &vec![Value::U32(2)]).unwrap();
-            db.flush().unwrap();
+    fn logical_comparisons_tell_table_kinds_apart() {
+        let mut keyed = Db::create(unique_temp_path("kind_keyed")).unwrap();
+        let mut plain = Db::create(unique_temp_path("kind_plain")).unwrap();
+        keyed.create_table_with_uuid("u", &uuid_schema()).unwrap();
+        plain.create_table("u", &uuid_schema()).unwrap();
+        for n in 0..5u32 {
+            let key = keyed.insert_row_uuid("u", &vec![Value::Null, Value::U32(n)]).unwrap();
+            plain.insert_row("u", &vec![Value::Bytes(key.to_vec()), Value::U32(n)]).unwrap();
         }
-        let mut db = Db::open(&path).unwrap();
-        let pk3 = db.insert_row("t", &vec![Value::U32(3)]).unwrap();
-        assert_eq!(pk3, 3);
-        let row = db.get_row_by_pk("t", 3).unwrap().unwrap();
-        assert_eq!(row, vec![Value::U32(3)]);
+        assert_eq!(keyed.scan_table("u").unwrap(), plain.scan_table("u").unwrap());
+        assert!(!keyed.logically_equals(&mut plain).unwrap());
+        assert_ne!(keyed.content_checksum().unwrap(), plain.content_checksum().unwrap());
     }
 
+
     #[test]
-    fn corruption_detect_pk_mismatch() {
-        let path = unique_temp_path("pk_mismatch");
-        let schema = Schema::new(vec![Column {
-            name: "v".to_string(),
-            ty: ColType::U32,
-            nullable: false,
-        }])
-        .unwrap();
+    fn row_crc_pinpoints_a_corrupted_row() {
+        let path = unique_temp_path("row_crc");
+        let row = |i: i64| vec![Value::I64(i), Value::String(format!("payload {}", i))];
         {
             let mut db = Db::create(&path).unwrap();
-            db.create_table("t", &schema).unwrap();
-            db.insert_row("t", &vec![Value::U32(10)]).unwrap();
+            db.create_table_with_row_crc("t", &clustered_schema()).unwrap();
+            for i in 1..=3i64 {
+                db.insert_row("t", &row(i)).unwrap();
+            }
             db.flush().unwrap();
         }
 
-        // Corrupt pk prefix in stored row.
-        {
-            let mut db = Db::open(&path).unwrap();
-            let cat = db.pager.read_catalog().unwrap();
-            let table = cat.get_by_name("t").unwrap();
-            let composite = composite_for_tests(table.id.0, 1);
-            let root = db.pager.root_page_id();
-            let packed = crate::btree::search::search_u64(&mut db.pager, root, composite)
-                .unwrap()
-                .unwrap();
-            let ptr = RowPtr::unpack(packed);
-            let mut f = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&path)
-                .unwrap();
-            let byte_offset =
-                (ptr.page_id as u64) * (PAGE_SIZE as u64) + (ptr.offset as u64);
-            f.seek(SeekFrom::Start(byte_offset)).unwrap();
-            let mut b = [0u8; 1];
-            f.read_exact(&mut b).unwrap();
-            b[0] ^= 0xFF;
-            f.seek(SeekFrom::Start(byte_offset)).unwrap();
-            f.write_all(&b).unwrap();
-        }
+        let mut db = Db::open(&path).unwrap();
+        assert!(db.get_table("t").unwrap().unwrap().row_crc);
+        assert_eq!(db.get_row_by_pk("t", 2).unwrap(), Some(row(2)));
+        let cat = db.pager.read_catalog().unwrap();
+        let composite = composite_for_tests(cat.get_by_name("t").unwrap().id.0, 2);
+        let root = db.pager.root_page_id();
+        let ptr = RowPtr::unpack(crate::btree::search::search_u64(&mut db.pager, root, composite).unwrap().unwrap());
+        drop(db);
+
+        // Flip the last byte of the row, inside the string value past the pk prefix.
+        let mut f = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+        let at = ptr.page_id as u64 * PAGE_SIZE as u64 + ptr.offset as u64 + ptr.len as u64 - 1;
+        f.seek(SeekFrom::Start(at)).unwrap();
+        let mut b = [0u8; 1];
+        f.read_exact(&mut b).unwrap();
+        b[0] ^= 0x20;
+        f.seek(SeekFrom::Start(at)).unwrap();
+        f.write_all(&b).unwrap();
+        drop(f);
 
         let mut db = Db::open(&path).unwrap();
-        let err = db.get_row_by_pk("t", 1).unwrap_err();
         assert!(matches!(
-            err,
-            InvError::Corruption {
-                context: "table.pk_mismatch",
-                ..
-            }
+            db.get_row_by_pk("t", 2),
+            Err(InvError::Corruption { context: "row.crc", .. })
         ));
+        assert_eq!(db.get_row_by_pk("t", 1).unwrap(), Some(row(1)));
+        assert_eq!(db.get_row_by_pk("t", 3).unwrap(), Some(row(3)));
     }
 
+
     #[test]
-    fn rowpage_header_validation() {
-        let path = unique_temp_path("rowpage_magic");
-        let schema = Schema::new(vec![Column {
-            name: "v".to_string(),
-            ty: ColType::U32,
-            nullable: false,
-        }])
-        .unwrap();
-        let row_ptr_page;
-        {
-            let mut db = Db::create(&path).unwrap();
-            db.create_table("t", &schema).unwrap();
-            db.insert_row("t", &vec![Value::U32(1)]).unwrap();
+    fn rebuild_table_index_restores_cleared_entries() {
+        let path = unique_temp_path("rebuild_index");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        db.create_table("other", &clustered_schema()).unwrap();
+        let row = |i: i64| vec![Value::I64(i), Value::String(format!("row {}", i))];
+        for i in 1..=400i64 {
+            db.insert_row("t", &row(i)).unwrap();
+            if i % 10 == 0 {
+                db.insert_row("other", &row(-i)).unwrap();
+            }
+        }
+        db.put_blob(7, b"not a row").unwrap();
 
-            let cat = db.pager.read_catalog().unwrap();
-            let table = cat.get_by_name("t").unwrap();
-            let composite = composite_for_tests(table.id.0, 1);
+        let cat = db.pager.read_catalog().unwrap();
+        let id = cat.get_by_name("t").unwrap().id.0;
+        for pk in 1..=400u32 {
             let root = db.pager.root_page_id();
-            let packed = crate::btree::search::search_u64(
-                &mut db.pager,
-                root,
-                composite,
-            )
-            .unwrap()
-            .unwrap();
-            let ptr = RowPtr::unpack(packed);
-            row_ptr_page = ptr.page_id;
-            db.flush().unwrap();
+            let key = composite_for_tests(id, pk);
+            assert!(crate::btree::delete_u64(&mut db.pager, root, key).unwrap().1);
         }
+        assert!(db.scan_table("t").unwrap().is_empty());
 
-        // Corrupt row page magic
-        {
-            let mut f = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&path)
+        assert_eq!(db.rebuild_table_index("t").unwrap(), 400);
+        let rows = db.scan_table("t").unwrap();
+        assert_eq!(rows.len(), 400);
+        assert!(rows.iter().all(|(pk, r)| *r == row(*pk as i64)));
+        assert_eq!(db.scan_table("other").unwrap().len(), 40);
+        assert_eq!(db.get_blob(7).unwrap().as_deref(), Some(&b"not a row"[..]));
+        assert_eq!(db.rebuild_table_index("t").unwrap(), 0);
+        drop(db);
+        Db::open(&path).unwrap().check_integrity().unwrap();
+    }
+
+
+    #[test]
+    fn create_table_rejects_catalog_overflow_without_side_effects() {
+        let path = unique_temp_path("catalog_full");
+        let mut db = Db::create(&path).unwrap();
+        let columns: Vec<Column> = (0..40)
+            .map(|i| Column {
+                name: format!("column_with_a_long_name_{:02}", i),
+                ty: ColType::U64,
+                nullable: true,
+            })
+            .collect();
+        let wide = Schema::new(columns).unwrap();
+        let mut created = 0;
+        let err = loop {
+            match db.create_table(&format!("t{}", created), &wide) {
+                Ok(_) => created += 1,
+                Err(e) => break e,
+            }
+        };
+        assert!(created > 0);
+        assert!(matches!(err, InvError::Unsupported { feature: "catalog.page_overflow" }));
+
+        let before = crate::catalog::encode_catalog(&db.pager.read_catalog().unwrap()).unwrap();
+        assert!(db.create_table_u64_pk("again", &wide).is_err());
+        let after = crate::catalog::encode_catalog(&db.pager.read_catalog().unwrap()).unwrap();
+        assert_eq!(before, after);
+        assert_eq!(db.list_tables().unwrap().len(), created);
+        db.flush().unwrap();
+        drop(db);
+        assert_eq!(Db::open(&path).unwrap().list_tables().unwrap().len(), created);
+    }
+
+
+    #[test]
+    fn column_values_extracts_one_column_in_pk_order() {
+        let path = unique_temp_path("column_values");
+        let mut db = Db::create(&path).unwrap();
+        let schema = Schema::new(vec![
+            Column {
+                name: "name".to_string(),
+                ty: ColType::String,
+                nullable: false,
+            },
+            Column {
+                name: "count".to_string(),
+                ty: ColType::U32,
+                nullable: false,
+            },
+            Column {
+                name: "flag".to_string(),
+                ty: ColType::Bool,
+                nullable: false,
+            },
+        ])
+        .unwrap();
+        db.create_table("t", &schema).unwrap();
+        for i in 0..20u32 {
+            db.insert_row("t", &vec![Value::String(format!("n{}", i)), Value::U32(i * 3), Value::Bool(i % 2 == 0)])
                 .unwrap();
-            let offset = (row_ptr_page as u64) * (PAGE_SIZE as u64) + 16;
-            f.seek(SeekFrom::Start(offset)).unwrap();
-            f.write_all(b"XOWP").unwrap();
         }
+        db.delete_row("t", 5).unwrap();
 
-        let err = Db::open(&path).unwrap_err();
+        let expected: Vec<Value> = (0..20u32).filter(|&i| i != 4).map(|i| Value::U32(i * 3)).collect();
+        assert_eq!(db.column_values("t", "count").unwrap(), expected);
+        assert_eq!(db.column_values("t", "flag").unwrap().len(), 19);
         assert!(matches!(
-            err,
-            InvError::Corruption {
-                context: "rowpage.magic",
-                ..
-            }
+            db.column_values("t", "missing"),
+            Err(InvError::InvalidArgument { name: "column", .. })
         ));
     }
 
-    fn pseudo_shuffle(n: u32) -> Vec<u32> {
-        let mut v: Vec<u32> = (1..=n).collect();
-        let mut seed: u64 = 0x1234_5678_9ABC_DEF0;
-        for i in (1..v.len()).rev() {
-            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
-            let j = (seed >> 33) as usize % (i + 1);
-            v.swap(i, j);
+
+    #[test]
+    fn compact_to_builds_the_copy_in_the_scratch_dir() {
+        let src_path = unique_temp_path("scratch_src");
+        let scratch = unique_temp_path("scratch_dir");
+        let dest_dir = unique_temp_path("scratch_dest_dir");
+        std::fs::create_dir(&scratch).unwrap();
+        std::fs::create_dir(&dest_dir).unwrap();
+        let dest_path = dest_dir.join("copy.inv");
+
+        let options = DbOptions {
+            scratch_dir: Some(scratch.join("missing")),
+            ..DbOptions::default()
+        };
+        let mut db = Db::create_with_options(&src_path, options).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        for i in 0..200i64 {
+            db.insert_row("t", &vec![Value::I64(i), Value::String(format!("r{}", i))]).unwrap();
         }
-        v
+        db.flush().unwrap();
+        // A scratch dir that does not exist makes the copy fail before `dest` appears.
+        assert!(matches!(db.compact_to(&dest_path), Err(InvError::Io { .. })));
+        assert!(!dest_path.exists());
+        drop(db);
+
+        let options = DbOptions {
+            scratch_dir: Some(scratch.clone()),
+            ..DbOptions::default()
+        };
+        let mut db = Db::open_with_options(&src_path, options).unwrap();
+        db.compact_to(&dest_path).unwrap();
+        assert_eq!(std::fs::read_dir(&scratch).unwrap().count(), 0);
+        assert_eq!(std::fs::read_dir(&dest_dir).unwrap().count(), 1);
+        let mut copy = Db::open(&dest_path).unwrap();
+        assert!(db.logically_equals(&mut copy).unwrap());
     }
 
+
     #[test]
-    fn btree_stress_insert_and_lookup() {
-        let path = unique_temp_path("btree_stress");
-        let keys = pseudo_shuffle(5_000);
+    fn create_failing_midway_leaves_the_target_untouched() {
+        use crate::file::IoOp;
+        use crate::testing::{clear_io_faults, inject_io_faults};
+        use std::io::ErrorKind;
+
+        let path = unique_temp_path("atomic_create");
+        let staging = crate::pager::staging_path(&path);
+
+        // Nothing at the target: a failed create leaves nothing behind.
+        inject_io_faults(&staging, IoOp::Write, ErrorKind::Other, 1);
+        assert!(matches!(Db::create(&path), Err(InvError::Io { .. })));
+        assert!(!path.exists());
+        assert!(!staging.exists());
+
         {
             let mut db = Db::create(&path).unwrap();
-            for &k in &keys {
-                db.put_u64(k, (k as u64) * 10).unwrap();
-            }
+            db.create_table("t", &clustered_schema()).unwrap();
+            db.insert_row("t", &vec![Value::I64(1), Value::String("kept".to_string())]).unwrap();
             db.flush().unwrap();
         }
+        let before = std::fs::read(&path).unwrap();
+
+        // Recreating over an existing database fails before touching it.
+        inject_io_faults(&staging, IoOp::Write, ErrorKind::Other, 1);
+        assert!(Db::create(&path).is_err());
+        clear_io_faults(&staging);
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+        assert!(!staging.exists());
         let mut db = Db::open(&path).unwrap();
-        for k in 1..=5_000u32 {
-            assert_eq!(db.get_u64(k).unwrap(), Some((k as u64) * 10));
+        assert_eq!(db.scan_table("t").unwrap().len(), 1);
+        drop(db);
+
+        let mut db = Db::create(&path).unwrap();
+        assert!(db.list_tables().unwrap().is_empty());
+    }
+
+
+    #[test]
+    fn put_u64_batch_unsorted_writes_the_header_once() {
+        let pairs: Vec<(u32, u64)> = pseudo_shuffle(20_000).into_iter().map(|k| (k, k as u64 * 2)).collect();
+
+        let single_path = unique_temp_path("unsorted_single");
+        let mut single = Db::create(&single_path).unwrap();
+        let base = single.io_stats().writes;
+        for &(key, value) in &pairs {
+            single.put_u64(key, value).unwrap();
         }
+        let single_writes = single.io_stats().writes - base;
+        let single_allocated = single.pager.page_count() as u64 - 3;
+
+        let batch_path = unique_temp_path("unsorted_batch");
+        let mut batch = Db::create(&batch_path).unwrap();
+        let base = batch.io_stats().writes;
+        batch.put_u64_batch_unsorted(&pairs).unwrap();
+        let batch_writes = batch.io_stats().writes - base;
+        let batch_allocated = batch.pager.page_count() as u64 - 3;
+
+        assert!(batch_allocated > 50);
+        // One write per new page, plus a single header write.
+        assert_eq!(batch_writes, batch_allocated + 1);
+        // Unbatched, every root split also writes the header.
+        assert!(single_writes > single_allocated, "{} writes", single_writes);
+        assert_eq!(batch.get_many_u64(&[1, 777, 20_000]).unwrap(), vec![Some(2), Some(1554), Some(40_000)]);
+        batch.flush().unwrap();
+        drop(batch);
+        assert_eq!(Db::open(&batch_path).unwrap().get_u64(19_999).unwrap(), Some(39_998));
     }
 
+
     #[test]
-    fn table_stress_insert_and_scan() {
-        let path = unique_temp_path("table_stress");
-        let schema = Schema::new(vec![
-            Column {
-                name: "x".to_string(),
-                ty: ColType::U32,
-                nullable: false,
-            },
-            Column {
-                name: "name".to_string(),
-                ty: ColType::String,
-                nullable: true,
-            },
-        ])
-        .unwrap();
-        {
-            let mut db = Db::create(&path).unwrap();
-            db.create_table("items", &schema).unwrap();
-            for i in 0..1000u32 {
-                db.insert_row(
-                    "items",
-                    &vec![Value::U32(i), Value::String(format!("item{}", i))],
-                )
-                .unwrap();
-            }
-            db.flush().unwrap();
+    fn allocations_defer_the_header_to_flush() {
+        let path = unique_temp_path("header_per_flush");
+        let mut db = Db::create(&path).unwrap();
+        db.flush().unwrap();
+        let base = db.io_stats().writes;
+        for _ in 0..100 {
+            db.pager.allocate_row_page().unwrap();
         }
+        // Each new page is written once; the header is not touched.
+        assert_eq!(db.io_stats().writes - base, 100);
+        db.flush().unwrap();
+        assert_eq!(db.io_stats().writes - base, 101);
+        drop(db);
+        assert_eq!(Db::open(&path).unwrap().pager.page_count(), 103);
+
+        // Pages allocated after the last header write are trimmed on open.
         let mut db = Db::open(&path).unwrap();
-        let rows = db.scan_table("items").unwrap();
-        assert_eq!(rows.len(), 1000);
-        for (idx, (pk, row)) in rows.iter().enumerate() {
-            assert_eq!(*pk, (idx as u32) + 1);
-            assert_eq!(
-                *row,
-                vec![
-                    Value::U32(idx as u32),
-                    Value::String(format!("item{}", idx))
-                ]
-            );
+        for _ in 0..5 {
+            db.pager.allocate_row_page().unwrap();
         }
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 108 * PAGE_SIZE as u64);
+        std::mem::forget(db);
+        let db = Db::open(&path).unwrap();
+        assert_eq!(db.pager.page_count(), 103);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 103 * PAGE_SIZE as u64);
     }
 
+
     #[test]
-    fn random_access_after_reopen() {
-        let path = unique_temp_path("random_access");
-        let schema = Schema::new(vec![Column {
-            name: "v".to_string(),
-            ty: ColType::U32,
-            nullable: false,
-        }])
+    fn for_each_u64_streams_in_order_and_stops_on_error() {
+        let path = unique_temp_path("for_each_u64");
+        let mut db = Db::create(&path).unwrap();
+        let pairs: Vec<(u32, u64)> = (1..=30_000u32).map(|k| (k, k as u64 * 3)).collect();
+        db.put_u64_batch(&pairs).unwrap();
+
+        let mut sum = 0u64;
+        let mut last = 0u32;
+        db.for_each_u64(|key, value| {
+            assert!(key > last);
+            last = key;
+            sum += value;
+            Ok(())
+        })
         .unwrap();
-        {
-            let mut db = Db::create(&path).unwrap();
-            db.create_table("t", &schema).unwrap();
-            for i in 0..200u32 {
-                db.insert_row("t", &vec![Value::U32(i)]).unwrap();
+        assert_eq!(last, 30_000);
+        assert_eq!(sum, 3 * 30_000 * 30_001 / 2);
+
+        let mut calls = 0;
+        let err = db
+            .for_each_u64(|key, _| {
+                calls += 1;
+                if key == 100 {
+                    return Err(InvError::InvalidArgument {
+                        name: "stop",
+                        details: String::new(),
+                    });
+                }
+                Ok(())
+            })
+            .unwrap_err();
+        assert!(matches!(err, InvError::InvalidArgument { name: "stop", .. }));
+        assert_eq!(calls, 100);
+    }
+
+
+    #[test]
+    fn explain_search_follows_the_lookup_path() {
+        use crate::btree::node::NodeKind;
+
+        let path = unique_temp_path("explain_search");
+        let mut db = Db::create(&path).unwrap();
+        for key in 1..=1000u32 {
+            db.put_u64(key * 2, key as u64).unwrap();
+        }
+        let root = db.pager.root_page_id();
+        let steps = db.explain_search(1234).unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!((steps[0].0, steps[0].1), (root, NodeKind::Internal));
+        let (leaf_id, kind, idx) = steps[1];
+        assert_eq!(kind, NodeKind::Leaf);
+        assert_eq!(leaf_id, crate::btree::search::find_leaf(&mut db.pager, root, 1234).unwrap());
+        let page_count = db.pager.page_count();
+        let Node::Internal(internal) = Node::decode(db.pager.get_page(root).unwrap(), page_count).unwrap() else {
+            panic!("root must be internal");
+        };
+        assert_eq!(internal.children[steps[0].2], leaf_id);
+        let Node::Leaf(leaf) = Node::decode(db.pager.get_page(leaf_id).unwrap(), page_count).unwrap() else {
+            panic!("expected a leaf");
+        };
+        assert_eq!(leaf.keys[idx], 1234);
+
+        // An absent key lands where it would be inserted.
+        let (_, _, idx) = *db.explain_search(1235).unwrap().last().unwrap();
+        assert_eq!(leaf.keys[idx], 1236);
+    }
+
+
+    #[test]
+    fn insert_row_returning_echoes_the_stored_row() {
+        let path = unique_temp_path("insert_returning");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        db.insert_row("t", &vec![Value::I64(0), Value::String("first".to_string())]).unwrap();
+        let row = vec![Value::I64(-7), Value::String("echo".to_string())];
+        let (pk, stored) = db.insert_row_returning("t", &row).unwrap();
+        assert_eq!(pk, 2);
+        assert_eq!(stored, row);
+        assert_eq!(db.get_row_by_pk("t", pk).unwrap(), Some(stored));
+        assert!(db.insert_row_returning("t", &vec![Value::I64(1)]).is_err());
+        assert_eq!(db.insert_row("t", &row).unwrap(), 3);
+    }
+
+
+    #[test]
+    fn lower_min_fill_ratio_merges_less() {
+        let mut merges = Vec::new();
+        for ratio in [0.0, 0.1, 0.5] {
+            let path = unique_temp_path("min_fill_ratio");
+            let options = DbOptions { min_fill_ratio: ratio, ..DbOptions::default() };
+            let mut db = Db::create_with_options(&path, options).unwrap();
+            db.create_table("t", &clustered_schema()).unwrap();
+            for i in 0..2000 {
+                db.insert_row("t", &vec![Value::I64(i), Value::String("x".to_string())]).unwrap();
+            }
+            let splits = db.btree_stats().splits;
+            assert!(splits > 0);
+            let order = pseudo_shuffle(2000);
+            for &pk in &order[..1800] {
+                assert!(db.delete_row("t", pk).unwrap());
             }
+            assert_eq!(db.btree_stats().splits, splits);
+            merges.push(db.btree_stats().merges);
             db.flush().unwrap();
+            db.check_integrity().unwrap();
+            for &pk in &order[1800..] {
+                assert!(db.get_row_by_pk("t", pk).unwrap().is_some());
+            }
+            drop(db);
+            let mut reopened = Db::open(&path).unwrap();
+            assert_eq!(reopened.scan_table("t").unwrap().len(), 200);
         }
-        let mut db = Db::open(&path).unwrap();
-        let keys = pseudo_shuffle(200);
-        for pk in keys.into_iter().take(50) {
-            let row = db.get_row_by_pk("t", pk).unwrap().unwrap();
-            assert_eq!(row, vec![Value::U32(pk - 1)]);
-        }
+        assert_eq!(merges[0], 0);
+        assert!(merges[1] < merges[2], "merges {:?}", merges);
     }
 
+
     #[test]
-    fn corruption_does_not_panic() {
-        let path = unique_temp_path("corruption_no_panic");
-        let schema = Schema::new(vec![Column {
+    fn catalog_fingerprint_ignores_creation_order() {
+        let other = Schema::new(vec![Column {
             name: "v".to_string(),
-            ty: ColType::U32,
+            ty: ColType::U64,
             nullable: false,
         }])
         .unwrap();
-        let leaf_page_id;
-        {
+        let mut fingerprints = Vec::new();
+        for (first, second) in [(("a", &clustered_schema()), ("b", &other)), (("b", &other), ("a", &clustered_schema()))] {
+            let path = unique_temp_path("catalog_fingerprint");
             let mut db = Db::create(&path).unwrap();
-            db.create_table("t", &schema).unwrap();
-            for i in 0..10u32 {
-                db.insert_row("t", &vec![Value::U32(i)]).unwrap();
+            db.create_table(first.0, first.1).unwrap();
+            db.create_table(second.0, second.1).unwrap();
+            fingerprints.push(db.catalog_fingerprint().unwrap());
+            drop(db);
+            fingerprints.push(Db::open(&path).unwrap().catalog_fingerprint().unwrap());
+        }
+        assert!(fingerprints.iter().all(|&f| f == fingerprints[0]));
+
+        let mut altered = other.clone();
+        altered.columns[0].nullable = true;
+        let path = unique_temp_path("catalog_fingerprint_altered");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("a", &clustered_schema()).unwrap();
+        db.create_table("b", &altered).unwrap();
+        assert_ne!(db.catalog_fingerprint().unwrap(), fingerprints[0]);
+    }
+
+
+    #[test]
+    fn page_kind_classifies_a_fresh_database() {
+        let path = unique_temp_path("page_kind");
+        let mut db = Db::create(&path).unwrap();
+        assert_eq!(db.page_kind(PageId(0)).unwrap(), PageKind::Header);
+        assert_eq!(db.page_kind(PageId(1)).unwrap(), PageKind::Btree);
+        assert_eq!(db.page_kind(PageId(2)).unwrap(), PageKind::Meta);
+        assert!(matches!(db.page_kind(PageId(3)), Err(InvError::InvalidArgument { .. })));
+        // The header's leading magic byte is not a page kind.
+        assert_eq!(PageKind::from_byte(config::FILE_MAGIC[0]), None);
+
+        db.pager.get_page_mut(PageId(1)).unwrap().as_bytes_mut()[0] = 9;
+        assert!(matches!(db.page_kind(PageId(1)), Err(InvError::Corruption { context: "page.kind", .. })));
+    }
+
+
+    #[test]
+    fn table_row_pages_follow_allocation_order() {
+        let path = unique_temp_path("table_row_pages");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("a", &clustered_schema()).unwrap();
+        db.create_table("b", &clustered_schema()).unwrap();
+        assert!(db.table_row_pages("a").unwrap().is_empty());
+        let payload = "x".repeat(500);
+        let mut used_a = Vec::new();
+        for i in 0..60 {
+            let name = if i % 3 == 0 { "b" } else { "a" };
+            db.insert_row(name, &vec![Value::I64(i), Value::String(payload.clone())]).unwrap();
+            let last = PageId(db.get_table(name).unwrap().unwrap().last_row_page);
+            if name == "a" && used_a.last() != Some(&last) {
+                used_a.push(last);
             }
-            let leaves = collect_leaf_chain(db.pager_mut_for_tests());
-            leaf_page_id = leaves[0].0;
-            db.flush().unwrap();
         }
-        // Corrupt a byte in leaf payload.
-        {
-            let mut f = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&path)
-                .unwrap();
-            let leaf_payload_offset = (leaf_page_id as u64) * (PAGE_SIZE as u64) + 32;
-            f.seek(SeekFrom::Start(leaf_payload_offset)).unwrap();
-            f.write_all(&[0xFF]).unwrap();
+        assert!(used_a.len() > 3);
+        assert_eq!(db.table_row_pages("a").unwrap(), used_a);
+        let pages_b = db.table_row_pages("b").unwrap();
+        assert!(pages_b.iter().all(|p| !used_a.contains(p)));
+        assert_eq!(pages_b.last().unwrap().0, db.get_table("b").unwrap().unwrap().last_row_page);
+        assert!(matches!(db.table_row_pages("missing"), Err(InvError::InvalidArgument { .. })));
+
+        db.flush().unwrap();
+        drop(db);
+        let mut reopened = Db::open(&path).unwrap();
+        assert_eq!(reopened.table_row_pages("a").unwrap(), used_a);
+    }
+
+
+    #[test]
+    fn row_page_chain_links_pages_and_survives_reopen() {
+        let path = unique_temp_path("row_page_chain");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        let payload = "y".repeat(700);
+        for i in 0..40 {
+            db.insert_row("t", &vec![Value::I64(i), Value::String(payload.clone())]).unwrap();
         }
+        for pk in (1..=40).step_by(4) {
+            db.delete_row("t", pk).unwrap();
+        }
+        let expected = db.scan_table("t").unwrap();
+        assert_eq!(expected.len(), 30);
+        db.flush().unwrap();
+        drop(db);
 
-        let res = Db::open(&path);
-        if let Ok(mut db) = res {
-            let _ = db.get_row_by_pk("t", 1);
+        let mut db = Db::open(&path).unwrap();
+        let table = db.get_table("t").unwrap().unwrap();
+        let pages = db.table_row_pages("t").unwrap();
+        assert!(pages.len() > 4);
+        assert_eq!(table.first_row_page, Some(pages[0].0));
+        for (i, page) in pages.iter().enumerate() {
+            let bytes = db.read_page_bytes(*page).unwrap();
+            assert_eq!(crate::layout::read_u16_le(&bytes, crate::layout::ROW_PAGE_VERSION), config::ROW_PAGE_FORMAT_VERSION);
+            let next = pages.get(i + 1).map_or(0, |p| p.0);
+            assert_eq!(crate::layout::read_u32_le(&bytes, crate::layout::ROW_PAGE_NEXT), next);
         }
+        assert_eq!(db.scan_table("t").unwrap(), expected);
+
+        // Version 1 pages read as long as their next pointer is still zero.
+        let last = *pages.last().unwrap();
+        db.pager.get_page_mut(last).unwrap().as_bytes_mut()[crate::layout::ROW_PAGE_VERSION] = 1;
+        assert_eq!(db.table_row_pages("t").unwrap(), pages);
+        db.pager.get_page_mut(pages[0]).unwrap().as_bytes_mut()[crate::layout::ROW_PAGE_VERSION] = 1;
+        assert!(matches!(db.table_row_pages("t"), Err(InvError::Unsupported { feature: "rowpage.reserved" })));
     }
 
+
     #[test]
-    fn btree_depth_growth_is_reasonable() {
-        let path = unique_temp_path("btree_depth");
-        {
-            let mut db = Db::create(&path).unwrap();
-            for k in 1..=5_000u32 {
-                db.put_u64(k, k as u64).unwrap();
-            }
-            db.flush().unwrap();
+    fn approx_row_count_tracks_uniform_rows() {
+        let path = unique_temp_path("approx_row_count");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        assert_eq!(db.approx_row_count("t").unwrap(), 0);
+        db.insert_row("t", &vec![Value::I64(0), Value::String("row".to_string())]).unwrap();
+        assert_eq!(db.approx_row_count("t").unwrap(), 1);
+        for i in 1..5000 {
+            db.insert_row("t", &vec![Value::I64(i * 7919), Value::String(format!("row {:06}", i))]).unwrap();
         }
-        let mut db = Db::open(&path).unwrap();
-        let pager = db.pager_mut_for_tests();
-        let root_id = pager.root_page_id();
-        let page_count = pager.page_count();
-        let root_node = node::Node::decode(pager.get_page(root_id).unwrap(), page_count).unwrap();
-        match root_node {
-            Node::Internal(ref internal) => {
-                assert_eq!(internal.children.len(), (internal.num_keys as usize) + 1);
-                for child in &internal.children {
-                    assert!(child.0 > 0 && child.0 < page_count);
-                }
+        let exact = db.scan_table("t").unwrap().len() as u64;
+        let estimate = db.approx_row_count("t").unwrap();
+        assert!(estimate.abs_diff(exact) * 100 <= exact * 2, "estimate {} for {} rows", estimate, exact);
+        assert!(matches!(db.approx_row_count("missing"), Err(InvError::InvalidArgument { .. })));
+    }
+
+
+    #[test]
+    fn watched_catalog_page_logs_create_table() {
+        let path = unique_temp_path("watch_page");
+        let mut db = Db::create(&path).unwrap();
+        db.watch_page(config::CATALOG_PAGE_ID);
+        db.create_table("t", &clustered_schema()).unwrap();
+        db.flush().unwrap();
+        let log = db.take_page_log();
+        assert!(log.iter().all(|a| a.page == config::CATALOG_PAGE_ID));
+        for kind in [PageAccessKind::Read, PageAccessKind::Modify, PageAccessKind::DiskWrite] {
+            assert!(log.iter().any(|a| a.kind == kind), "no {:?} in {:?}", kind, log);
+        }
+        assert!(log.iter().any(|a| a.caller.file().ends_with("lib.rs")));
+        assert!(db.take_page_log().is_empty());
+
+        // Other pages stay unlogged.
+        db.insert_row("t", &vec![Value::I64(1), Value::String("a".to_string())]).unwrap();
+        assert!(db.take_page_log().iter().all(|a| a.page == config::CATALOG_PAGE_ID));
+    }
+
+
+    #[test]
+    fn deleting_every_row_of_a_page_frees_it() {
+        let path = unique_temp_path("dead_row_page");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        let payload = "z".repeat(900);
+        let mut page_of = Vec::new();
+        for i in 0..20 {
+            let pk = db.insert_row("t", &vec![Value::I64(i), Value::String(payload.clone())]).unwrap();
+            page_of.push((pk, db.get_table("t").unwrap().unwrap().last_row_page));
+        }
+        let pages = db.table_row_pages("t").unwrap();
+        assert!(pages.len() >= 4);
+        let on = |page: PageId| page_of.iter().filter(move |(_, p)| *p == page.0).map(|(pk, _)| *pk);
+
+        let middle = pages[1];
+        let mut pks = on(middle).peekable();
+        while let Some(pk) = pks.next() {
+            db.delete_row("t", pk).unwrap();
+            let freed = db.pager.free_pages().unwrap().contains(&middle);
+            assert_eq!(freed, pks.peek().is_none());
+        }
+        let mut expected: Vec<PageId> = pages.iter().copied().filter(|&p| p != middle).collect();
+        assert_eq!(db.table_row_pages("t").unwrap(), expected);
+
+        // The head moves on, and the append target is kept even when empty.
+        for pk in on(pages[0]).chain(on(*pages.last().unwrap())) {
+            db.delete_row("t", pk).unwrap();
+        }
+        expected.remove(0);
+        assert_eq!(db.table_row_pages("t").unwrap(), expected);
+        assert_eq!(db.get_table("t").unwrap().unwrap().first_row_page, Some(expected[0].0));
+
+        let live = db.scan_table("t").unwrap();
+        db.flush().unwrap();
+        drop(db);
+        let mut reopened = Db::open(&path).unwrap();
+        reopened.check_integrity().unwrap();
+        assert_eq!(reopened.table_row_pages("t").unwrap(), expected);
+        assert_eq!(reopened.scan_table("t").unwrap(), live);
+    }
+
+
+    #[test]
+    fn payload_constants_match_the_encoders() {
+        use crate::config::{usable_payload, PAGE_HEADER_SIZE, ROW_PAGE_PAYLOAD_START};
+        assert_eq!(PAGE_HEADER_SIZE, crate::layout::PAGE_PAYLOAD);
+        assert_eq!(ROW_PAGE_PAYLOAD_START, crate::layout::ROW_PAGE_DATA);
+
+        let path = unique_temp_path("payload_constants");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        db.insert_row("t", &vec![Value::I64(1), Value::String("a".to_string())]).unwrap();
+        let row_page = db.table_row_pages("t").unwrap()[0];
+        let ptr = crate::rowstore::RowPtr::unpack(
+            crate::btree::search_u64(&mut db.pager, PageId(1), composite_for_tests(1, 1)).unwrap().unwrap(),
+        );
+        assert_eq!(ptr.page_id, row_page.0);
+        assert_eq!(ptr.offset as usize, ROW_PAGE_PAYLOAD_START + 2);
+
+        let catalog = db.read_page_bytes(config::CATALOG_PAGE_ID).unwrap();
+        assert_eq!(&catalog[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + 4], b"CAT1");
+        crate::catalog::decode_catalog(&catalog[PAGE_HEADER_SIZE..]).unwrap();
+
+        assert_eq!(usable_payload(PageKind::Row), config::PAGE_SIZE - ROW_PAGE_PAYLOAD_START);
+        assert_eq!(usable_payload(PageKind::Meta), config::PAGE_SIZE - PAGE_HEADER_SIZE);
+        // A full leaf uses every usable byte it can: one more key would not fit.
+        let node_bytes = crate::btree::node::max_leaf_keys() * 12;
+        assert!(node_bytes <= usable_payload(PageKind::Btree));
+        assert!(node_bytes + 12 > usable_payload(PageKind::Btree));
+        let internal_bytes = crate::btree::node::max_internal_keys() * 8 + 4;
+        assert!(internal_bytes <= usable_payload(PageKind::Btree));
+        assert!(internal_bytes + 8 > usable_payload(PageKind::Btree));
+        assert_eq!(usable_payload(PageKind::Header), 0);
+    }
+
+
+    #[test]
+    fn for_each_table_borrows_every_def() {
+        let path = unique_temp_path("for_each_table");
+        let mut db = Db::create(&path).unwrap();
+        for (name, rows) in [("a", 3), ("b", 0), ("c", 5)] {
+            db.create_table(name, &clustered_schema()).unwrap();
+            for i in 0..rows {
+                db.insert_row(name, &vec![Value::I64(i), Value::String(String::new())]).unwrap();
             }
-            _ => panic!("expected internal root for deep tree"),
         }
+        let mut names = Vec::new();
+        let mut next_pks = 0;
+        db.for_each_table(|table| {
+            names.push(table.name.clone());
+            next_pks += table.next_pk;
+        })
+        .unwrap();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert_eq!(next_pks, 4 + 1 + 6);
     }
 
+
     #[test]
-    fn catalog_and_row_invariants_hold() {
-        let path = unique_temp_path("catalog_invariants");
-        let schema = Schema::new(vec![Column {
-            name: "v".to_string(),
-            ty: ColType::U32,
-            nullable: false,
-        }])
+    fn empty_values_and_null_stay_distinct() {
+        let schema = Schema::new(vec![
+            Column { name: "b".to_string(), ty: ColType::Bytes, nullable: true },
+            Column { name: "s".to_string(), ty: ColType::String, nullable: true },
+        ])
         .unwrap();
-        {
-            let mut db = Db::create(&path).unwrap();
-            db.create_table("a", &schema).unwrap();
-            db.create_table("b", &schema).unwrap();
-            db.insert_row("a", &vec![Value::U32(1)]).unwrap();
-            db.insert_row("a", &vec![Value::U32(2)]).unwrap();
-            db.insert_row("b", &vec![Value::U32(3)]).unwrap();
-            db.flush().unwrap();
+        let rows = [
+            vec![Value::Bytes(Vec::new()), Value::String(String::new())],
+            vec![Value::Null, Value::Null],
+            vec![Value::Bytes(Vec::new()), Value::Null],
+            vec![Value::Null, Value::String(String::new())],
+        ];
+        let encoded: Vec<Vec<u8>> = rows.iter().map(|r| encode_row(&schema, r).unwrap()).collect();
+        for (row, bytes) in rows.iter().zip(&encoded) {
+            assert_eq!(&decode_row(&schema, bytes).unwrap(), row);
         }
-        let mut db = Db::open(&path).unwrap();
-        let cat = db.pager.read_catalog().unwrap();
-        for table in &cat.tables {
-            let rows = db.scan_table(&table.name).unwrap();
-            let max_pk = rows.iter().map(|(pk, _)| *pk).max().unwrap_or(0);
-            assert!(table.next_pk > max_pk);
-            assert_ne!(table.last_row_page, 0);
-            let page = db
-                .pager
-                .get_page(PageId(table.last_row_page))
-                .expect("row page readable");
-            crate::rowstore::validate_row_page_header(page.as_bytes()).unwrap();
+        assert_ne!(encoded[0], encoded[1]);
+        assert!(encode_index_key(&[Value::Null]) < encode_index_key(&[Value::Bytes(Vec::new())]));
+        assert!(encode_index_key(&[Value::Null]) < encode_index_key(&[Value::String(String::new())]));
+
+        let path = unique_temp_path("empty_values");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &schema).unwrap();
+        let pks: Vec<u32> = rows.iter().map(|r| db.insert_row("t", r).unwrap()).collect();
+        db.flush().unwrap();
+        drop(db);
+        let mut reopened = Db::open(&path).unwrap();
+        for (pk, row) in pks.iter().zip(&rows) {
+            assert_eq!(reopened.get_row_by_pk("t", *pk).unwrap().as_ref(), Some(row));
         }
     }
 
     #[test]
-    fn open_detects_corrupt_root_page() {
-        let path = unique_temp_path("corrupt_root_kind");
+    fn validate_page_budget_truncates_open_validation() {
+        let path = unique_temp_path("validate_budget");
         {
             let mut db = Db::create(&path).unwrap();
+            for key in 0..5_000u32 {
+                db.put_u64(key, key as u64).unwrap();
+            }
             db.flush().unwrap();
+            assert!(db.was_validation_complete());
         }
-        {
-            let mut f = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&path)
-                .unwrap();
-            let offset = PAGE_SIZE as u64; // root page start
-            f.seek(SeekFrom::Start(offset)).unwrap();
-            f.write_all(&[config::META_PAGE_KIND]).unwrap();
-        }
-        let err = Db::open(&path).unwrap_err();
-        assert!(matches!(
-            err,
-            InvError::Corruption {
-                context: "btree.page_kind",
-                ..
-            }
-        ));
+        let tiny = DbOptions {
+            validate_page_budget: Some(1),
+            ..DbOptions::default()
+        };
+
+        let tiny_reads = {
+            let mut db = Db::open_with_options(&path, tiny).unwrap();
+            assert!(!db.was_validation_complete());
+            assert_eq!(db.get_u64(4_321).unwrap(), Some(4_321));
+            let reads = db.io_stats().reads;
+            db.check_integrity().unwrap();
+            assert!(db.was_validation_complete());
+            reads
+        };
+        let db = Db::open(&path).unwrap();
+        assert!(db.was_validation_complete());
+        assert!(tiny_reads < db.io_stats().reads);
+
+        let enough = DbOptions {
+            validate_page_budget: Some(u64::MAX),
+            ..DbOptions::default()
+        };
+        drop(db);
+        assert!(Db::open_with_options(&path, enough).unwrap().was_validation_complete());
     }
 
     #[test]
-    fn open_detects_catalog_duplicate_ids() {
-        let path = unique_temp_path("catalog_dup_ids");
-        {
-            let mut db = Db::create(&path).unwrap();
-            let schema = Schema::new(vec![Column {
-                name: "id".to_string(),
+    fn set_value_replaces_one_column() {
+        let path = unique_temp_path("set_value");
+        let schema = Schema::new(vec![
+            Column {
+                name: "count".to_string(),
                 ty: ColType::U32,
                 nullable: false,
-            }])
+            },
+            Column {
+                name: "label".to_string(),
+                ty: ColType::String,
+                nullable: true,
+            },
+        ])
+        .unwrap();
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("items", &schema).unwrap();
+        let pk = db
+            .insert_row("items", &vec![Value::U32(1), Value::String("first".into())])
             .unwrap();
-            db.create_table("a", &schema).unwrap();
-            db.create_table("b", &schema).unwrap();
-            // Corrupt catalog to duplicate ids
-            let mut cat = db.pager.read_catalog().unwrap();
-            let first_id = cat.tables[0].id;
-            cat.tables[1].id = first_id;
-            db.pager.write_catalog(&cat).unwrap();
-            db.flush().unwrap();
+        let other = db
+            .insert_row("items", &vec![Value::U32(2), Value::String("second".into())])
+            .unwrap();
+
+        db.set_value("items", pk, "count", Value::U32(41)).unwrap();
+        assert_eq!(
+            db.get_row_by_pk("items", pk).unwrap(),
+            Some(vec![Value::U32(41), Value::String("first".into())])
+        );
+        db.set_value("items", pk, "label", Value::Null).unwrap();
+        assert_eq!(db.get_row_by_pk("items", pk).unwrap(), Some(vec![Value::U32(41), Value::Null]));
+        assert_eq!(
+            db.get_row_by_pk("items", other).unwrap(),
+            Some(vec![Value::U32(2), Value::String("second".into())])
+        );
+
+        assert!(matches!(
+            db.set_value("items", pk, "count", Value::String("many".into())),
+            Err(InvError::InvalidArgument { name: "row.type", .. })
+        ));
+        assert!(matches!(
+            db.set_value("items", pk, "count", Value::Null),
+            Err(InvError::InvalidArgument { name: "row.null", .. })
+        ));
+        assert!(matches!(
+            db.set_value("items", pk, "missing", Value::U32(0)),
+            Err(InvError::InvalidArgument { name: "column", .. })
+        ));
+        assert!(matches!(
+            db.set_value("items", 99, "count", Value::U32(0)),
+            Err(InvError::InvalidArgument { name: "pk", .. })
+        ));
+        assert_eq!(db.get_row_by_pk("items", pk).unwrap(), Some(vec![Value::U32(41), Value::Null]));
+        db.check_integrity().unwrap();
+    }
+
+    #[test]
+    fn pks_where_matches_filtered_scan() {
+        let path = unique_temp_path("pks_where");
+        let schema = Schema::new(vec![
+            Column {
+                name: "score".to_string(),
+                ty: ColType::I64,
+                nullable: true,
+            },
+            Column {
+                name: "note".to_string(),
+                ty: ColType::String,
+                nullable: false,
+            },
+        ])
+        .unwrap();
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("scores", &schema).unwrap();
+        for i in pseudo_shuffle(200) {
+            let score = if i % 17 == 0 { Value::Null } else { Value::I64(i as i64 - 100) };
+            db.insert_row("scores", &vec![score, Value::String(format!("n{}", i))]).unwrap();
         }
+        db.delete_row("scores", 7).unwrap();
+
+        let rows = db.scan_table("scores").unwrap();
+        let pivot = Value::I64(3);
+        for op in [CmpOp::Eq, CmpOp::Ne, CmpOp::Lt, CmpOp::Le, CmpOp::Gt, CmpOp::Ge] {
+            let expected: Vec<u32> =
+                rows.iter().filter(|(_, row)| op.eval(&row[0], &pivot)).map(|(pk, _)| *pk).collect();
+            assert_eq!(db.pks_where("scores", "score", op, &pivot).unwrap(), expected, "{:?}", op);
+        }
+        assert_eq!(db.pks_where("scores", "score", CmpOp::Eq, &pivot).unwrap().len(), 1);
+        let by_note = db.pks_where("scores", "note", CmpOp::Eq, &Value::String("n42".into())).unwrap();
+        assert_eq!(by_note.len(), 1);
+        assert_eq!(db.get_row_by_pk("scores", by_note[0]).unwrap().unwrap()[1], Value::String("n42".into()));
 
-        let err = Db::open(&path).unwrap_err();
         assert!(matches!(
-            err,
-            InvError::Corruption {
-                context: "catalog.duplicate",
-                ..
-            }
+            db.pks_where("scores", "score", CmpOp::Eq, &Value::U32(3)),
+            Err(InvError::InvalidArgument { name: "value", .. })
+        ));
+        assert!(matches!(
+            db.pks_where("scores", "score", CmpOp::Eq, &Value::Null),
+            Err(InvError::InvalidArgument { name: "value", .. })
+        ));
+        assert!(matches!(
+            db.pks_where("scores", "missing", CmpOp::Eq, &pivot),
+            Err(InvError::InvalidArgument { name: "column", .. })
         ));
     }
 
     #[test]
-    fn open_detects_row_page_magic_corruption() {
-        let path = unique_temp_path("row_magic_open");
+    fn table_info_bundles_definition_and_stats() {
+        let path = unique_temp_path("table_info");
+        let mut db = Db::create(&path).unwrap();
         let schema = Schema::new(vec![Column {
-            name: "v".to_string(),
-            ty: ColType::U32,
+            name: "payload".to_string(),
+            ty: ColType::Bytes,
             nullable: false,
         }])
         .unwrap();
-        let row_page_id;
-        {
-            let mut db = Db::create(&path).unwrap();
-            db.create_table("t", &schema).unwrap();
-            db.insert_row("t", &vec![Value::U32(7)]).unwrap();
+        db.create_table("blobs", &schema).unwrap();
+        db.create_table("idle", &schema).unwrap();
+        for i in 0..300u32 {
+            db.insert_row("blobs", &vec![Value::Bytes(vec![i as u8; 100])]).unwrap();
+        }
+        for pk in (1..300).step_by(3) {
+            db.delete_row("blobs", pk).unwrap();
+        }
 
-            let cat = db.pager.read_catalog().unwrap();
-            let table = cat.get_by_name("t").unwrap();
-            let composite = composite_for_tests(table.id.0, 1);
-            let root = db.pager.root_page_id();
-            let packed =
-                crate::btree::search::search_u64(&mut db.pager, root, composite)
-                    .unwrap()
-                    .unwrap();
-            let ptr = RowPtr::unpack(packed);
-            row_page_id = ptr.page_id;
-            db.flush().unwrap();
+        let info = db.table_info("blobs").unwrap().expect("table exists");
+        assert_eq!(Some(&info.def), db.get_table("blobs").unwrap().as_ref());
+        assert_eq!(info.live_rows, db.list_pks("blobs").unwrap().len() as u64);
+        assert_eq!(info.live_rows, 200);
+        assert_eq!(info.owned_pages, db.table_row_pages("blobs").unwrap().len() as u64);
+        assert_eq!(info.storage, db.table_storage("blobs").unwrap());
+
+        let idle = db.table_info("idle").unwrap().unwrap();
+        assert_eq!((idle.owned_pages, idle.live_rows), (0, 0));
+        assert_eq!(idle.storage, db.table_storage("idle").unwrap());
+        assert_eq!(db.table_info("absent").unwrap(), None);
+    }
+
+    #[test]
+    fn flush_reporting_lists_pages_modified_since_last_flush() {
+        let path = unique_temp_path("flush_report");
+        let mut db = Db::create(&path).unwrap();
+        for key in 0..2_000u32 {
+            db.put_u64(key * 2, key as u64).unwrap();
+        }
+        let first = db.flush_reporting().unwrap();
+        assert!(first.pages.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(first.pages.len(), first.checksums.len());
+        assert!(first.pages.len() > 2);
+
+        let root = db.pager.root_page_id();
+        let low_leaf = crate::btree::search::find_leaf(&mut db.pager, root, 1).unwrap();
+        let high_leaf = crate::btree::search::find_leaf(&mut db.pager, root, 3_999).unwrap();
+        assert_ne!(low_leaf, high_leaf);
+        db.put_u64(1, 1).unwrap();
+        db.put_u64(3_999, 3_999).unwrap();
+        let report = db.flush_reporting().unwrap();
+        let mut expected = vec![low_leaf, high_leaf];
+        expected.sort();
+        assert_eq!(report.pages, expected);
+        for (&id, &crc) in report.pages.iter().zip(&report.checksums) {
+            assert_eq!(crc, crate::encoding::crc32(&db.read_page_bytes(id).unwrap()));
         }
 
+        assert_eq!(db.flush_reporting().unwrap(), FlushReport::default());
+    }
+
+    #[test]
+    fn reject_interior_nul_is_enforced_per_column() {
+        let path = unique_temp_path("string_nul");
+        let schema = Schema::new(vec![
+            Column {
+                name: "loose".to_string(),
+                ty: ColType::String,
+                nullable: false,
+            },
+            Column {
+                name: "strict".to_string(),
+                ty: ColType::String,
+                nullable: true,
+            },
+        ])
+        .unwrap()
+        .reject_interior_nul("strict")
+        .unwrap();
+        assert!(!schema.rejects_interior_nul(0));
+        assert!(schema.rejects_interior_nul(1));
+        let with_nul = Value::String("a\0b".into());
         {
-            let mut f = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&path)
-                .unwrap();
-            let offset = (row_page_id as u64) * (PAGE_SIZE as u64) + 16;
-            f.seek(SeekFrom::Start(offset)).unwrap();
-            f.write_all(b"BAD!").unwrap();
+            let mut db = Db::create(&path).unwrap();
+            db.create_table("names", &schema).unwrap();
+            let pk = db.insert_row("names", &vec![with_nul.clone(), Value::String("ok".into())]).unwrap();
+            assert_eq!(db.get_row_by_pk("names", pk).unwrap().unwrap()[0], with_nul);
+            db.flush().unwrap();
         }
 
-        let err = Db::open(&path).unwrap_err();
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.get_table("names").unwrap().unwrap().schema, schema);
         assert!(matches!(
-            err,
-            InvError::Corruption {
-                context: "rowpage.magic",
-                ..
-            }
+            db.insert_row("names", &vec![Value::String("x".into()), with_nul.clone()]),
+            Err(InvError::InvalidArgument { name: "row.string_nul", .. })
+        ));
+        assert!(matches!(
+            db.set_value("names", 1, "strict", Value::String("\0".into())),
+            Err(InvError::InvalidArgument { name: "row.string_nul", .. })
         ));
+        db.insert_row("names", &vec![Value::String("x".into()), Value::Null]).unwrap();
+        assert_eq!(db.list_pks("names").unwrap(), vec![1, 2]);
     }
 
-    fn collect_leaf_chain(pager: &mut Pager) -> Vec<PageId> {
-        let page_count = pager.page_count();
-        let mut ids = Vec::new();
-        let mut current = {
-            let root = pager.root_page_id();
-            // leftmost leaf
-            let mut cur = root;
-            loop {
-                let node = node::Node::decode(
-                    pager.get_page(cur).unwrap(),
-                    page_count,
-                )
-                .unwrap();
-                match node {
-                    Node::Leaf(_) => break cur,
-                    Node::Internal(int) => {
-                        cur = int.children[0];
-                    }
-                }
-            }
-        };
-        loop {
-            ids.push(current);
-            let node = node::Node::decode(
-                pager.get_page(current).unwrap(),
-                page_count,
-            )
-            .unwrap();
-            match node {
-                Node::Leaf(l) => {
-                    if l.next_leaf.0 == 0 {
-                        break;
-                    }
-                    current = PageId(l.next_leaf.0);
-                }
-                _ => break,
-            }
+    #[test]
+    fn count_tables_matches_list_tables() {
+        let path = unique_temp_path("count_tables");
+        let mut db = Db::create(&path).unwrap();
+        assert_eq!(db.count_tables().unwrap(), 0);
+        for name in ["a", "b", "c", "d"] {
+            db.create_table(name, &clustered_schema()).unwrap();
         }
-        ids
+        assert_eq!(db.count_tables().unwrap(), 4);
+        assert_eq!(db.count_tables().unwrap(), db.list_tables().unwrap().len());
     }
 
     #[test]
-    fn open_detects_leaf_cycle() {
-        let path = unique_temp_path("leaf_cycle");
-        {
-            let mut db = Db::create(&path).unwrap();
-            let schema = Schema::new(vec![Column {
-                name: "v".to_string(),
+    fn row_schema_version_tracks_rows_predating_a_drop_column() {
+        let path = unique_temp_path("row_schema_version");
+        let schema = Schema::new(vec![
+            Column {
+                name: "id".to_string(),
                 ty: ColType::U32,
                 nullable: false,
-            }])
-            .unwrap();
-            db.create_table("t", &schema).unwrap();
-            let inserts = (max_leaf_keys() as u32) + 10;
-            for i in 0..inserts {
-                db.insert_row("t", &vec![Value::U32(i)]).unwrap();
+            },
+            Column {
+                name: "extra".to_string(),
+                ty: ColType::String,
+                nullable: true,
+            },
+        ])
+        .unwrap();
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("evolving", &schema).unwrap();
+        let old = db.insert_row("evolving", &vec![Value::U32(1), Value::String("x".into())]).unwrap();
+        assert_eq!(db.row_schema_version("evolving", old).unwrap(), Some(2));
+
+        db.drop_column("evolving", "extra").unwrap();
+        let new = db.insert_row("evolving", &vec![Value::U32(2)]).unwrap();
+        assert_eq!(db.row_schema_version("evolving", old).unwrap(), Some(2));
+        assert_eq!(db.row_schema_version("evolving", new).unwrap(), Some(1));
+        assert_eq!(db.row_schema_version("evolving", 99).unwrap(), None);
+
+        db.rewrite_rows("evolving").unwrap();
+        assert_eq!(db.row_schema_version("evolving", old).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn coalesce_row_pages_packs_fragmented_rows() {
+        let path = unique_temp_path("coalesce_rows");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("sparse", &clustered_schema()).unwrap();
+        db.create_table("other", &clustered_schema()).unwrap();
+        let payload = |i: u32| Value::String(format!("{:0>200}", i));
+        for i in 0..400u32 {
+            db.insert_row("sparse", &vec![Value::I64(i as i64), payload(i)]).unwrap();
+            db.insert_row("other", &vec![Value::I64(i as i64), payload(i)]).unwrap();
+        }
+        for pk in 1..=400u32 {
+            if pk % 4 != 0 {
+                db.delete_row("sparse", pk).unwrap();
             }
-            let leaves = collect_leaf_chain(db.pager_mut_for_tests());
-            assert!(leaves.len() >= 2, "expected multiple leaves");
-            let first = leaves[0];
-            let second = leaves[1];
-            db.flush().unwrap();
+        }
+        let before_rows = db.scan_table("sparse").unwrap();
+        let other_rows = db.scan_table("other").unwrap();
+        let before_pages = db.table_row_pages("sparse").unwrap().len() as u64;
 
-            // Corrupt second leaf next_leaf to point back to first.
-            let mut f = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&path)
-                .unwrap();
-            let offset = (second.0 as u64) * (PAGE_SIZE as u64) + 16 + 8;
-            f.seek(SeekFrom::Start(offset)).unwrap();
-            f.write_all(&first.0.to_le_bytes()).unwrap();
+        let freed = db.coalesce_row_pages("sparse").unwrap();
+        let after_pages = db.table_row_pages("sparse").unwrap().len() as u64;
+        assert!(freed > 0);
+        assert_eq!(after_pages, before_pages - freed);
+        assert!(after_pages * 3 < before_pages, "{} pages left of {}", after_pages, before_pages);
+        assert_eq!(db.scan_table("sparse").unwrap(), before_rows);
+        assert_eq!(db.scan_table("other").unwrap(), other_rows);
+        db.check_integrity().unwrap();
+
+        let pk = db.insert_row("sparse", &vec![Value::I64(-1), payload(0)]).unwrap();
+        db.flush().unwrap();
+        drop(db);
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.scan_table("sparse").unwrap().len(), before_rows.len() + 1);
+        assert_eq!(db.get_row_by_pk("sparse", pk).unwrap(), Some(vec![Value::I64(-1), payload(0)]));
+        assert_eq!(db.coalesce_row_pages("sparse").unwrap(), 0);
+    }
+
+    #[test]
+    fn coalesce_row_pages_failing_partway_keeps_every_row() {
+        let mut db = Db::create(unique_temp_path("coalesce_fail")).unwrap();
+        db.create_table("sparse", &clustered_schema()).unwrap();
+        db.create_table("junk", &clustered_schema()).unwrap();
+        let payload = |i: u32| Value::String(format!("{:0>200}", i));
+        for i in 0..200u32 {
+            db.insert_row("sparse", &vec![Value::I64(i as i64), payload(i)]).unwrap();
+        }
+        for i in 0..100u32 {
+            db.insert_row("junk", &vec![Value::I64(i as i64), payload(i)]).unwrap();
         }
+        for pk in 1..=200u32 {
+            if pk % 2 != 0 {
+                db.delete_row("sparse", pk).unwrap();
+            }
+        }
+        for pk in 1..=100u32 {
+            db.delete_row("junk", pk).unwrap();
+        }
+        let before = db.scan_table("sparse").unwrap();
 
-        let err = Db::open(&path).unwrap_err();
+        // The second free page is broken, so the rewrite fails once its
+        // first new page is full.
+        let free = db.pager.free_pages().unwrap();
+        assert!(free.len() >= 2);
+        db.pager.get_page_mut(free[1]).unwrap().as_bytes_mut()[0] = config::ROW_PAGE_KIND;
         assert!(matches!(
-            err,
-            InvError::Corruption {
-                context: "btree.leaf_cycle",
-                ..
-            }
+            db.coalesce_row_pages("sparse"),
+            Err(InvError::Corruption { context: "free_list.kind", .. })
         ));
+        assert_eq!(db.scan_table("sparse").unwrap(), before);
+        assert_eq!(db.get_row_by_pk("sparse", 2).unwrap(), Some(before[0].1.clone()));
+    }
+
+    #[test]
+    fn content_checksum_ignores_layout_but_not_rows() {
+        let src_path = unique_temp_path("checksum_src");
+        let dest_path = unique_temp_path("checksum_dest");
+        let mut db = Db::create(&src_path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        db.create_table_u64_pk("wide", &clustered_schema()).unwrap();
+        for i in 0..300i64 {
+            db.insert_row("t", &vec![Value::I64(i), Value::String(format!("r{}", i))]).unwrap();
+        }
+        for i in 0..20i64 {
+            db.insert_row_u64("wide", &vec![Value::I64(i), Value::String(String::new())]).unwrap();
+        }
+        for pk in (1..=300u32).step_by(3) {
+            db.delete_row("t", pk).unwrap();
+        }
+        db.flush().unwrap();
+        let checksum = db.content_checksum().unwrap();
+        assert_eq!(db.content_checksum().unwrap(), checksum);
+        db.compact_to(&dest_path).unwrap();
+
+        let mut copy = Db::open(&dest_path).unwrap();
+        assert_eq!(copy.content_checksum().unwrap(), checksum);
+        copy.set_value("t", 2, "payload", Value::String("changed".into())).unwrap();
+        assert_ne!(copy.content_checksum().unwrap(), checksum);
+        copy.set_value("t", 2, "payload", Value::String("r1".into())).unwrap();
+        assert_eq!(copy.content_checksum().unwrap(), checksum);
+        copy.delete_row("t", 2).unwrap();
+        assert_ne!(copy.content_checksum().unwrap(), checksum);
     }
 
     #[test]
-    fn open_valid_database_passes() {
-        let path = unique_temp_path("open_valid");
-        let schema = Schema::new(vec![Column {
-            name: "v".to_string(),
-            ty: ColType::U32,
-            nullable: false,
-        }])
-        .unwrap();
-        {
-            let mut db = Db::create(&path).unwrap();
-            db.create_table("t", &schema).unwrap();
-            db.insert_row("t", &vec![Value::U32(42)]).unwrap();
-            db.flush().unwrap();
+    fn dump_btree_shows_two_level_structure() {
+        let path = unique_temp_path("dump_btree");
+        let mut db = Db::create(&path).unwrap();
+        let max = crate::btree::node::max_leaf_keys() as u32;
+        for key in 0..=max {
+            db.put_u64(key, key as u64).unwrap();
         }
+        let root = db.pager.root_page_id();
+        let page_count = db.pager.page_count();
+        let internal = match Node::decode(db.pager.get_page(root).unwrap(), page_count).unwrap() {
+            Node::Internal(internal) => internal,
+            Node::Leaf(_) => panic!("tree did not split"),
+        };
+        assert_eq!(internal.children.len(), 2);
 
-        let mut db = Db::open(&path).unwrap();
-        let row = db.get_row_by_pk("t", 1).unwrap().unwrap();
-        assert_eq!(row, vec![Value::U32(42)]);
+        let dump = db.dump_btree().unwrap();
+        assert!(!dump.truncated);
+        assert_eq!(dump.root.page, root);
+        assert_eq!(
+            dump.root.kind,
+            NodeDumpKind::Internal { keys: internal.keys.clone(), child_pages: internal.children.clone() }
+        );
+        let pages: Vec<PageId> = dump.root.children.iter().map(|child| child.page).collect();
+        assert_eq!(pages, internal.children);
+        let mut total = 0u32;
+        for (i, child) in dump.root.children.iter().enumerate() {
+            let NodeDumpKind::Leaf { key_count, next_leaf } = child.kind else {
+                panic!("child {} is not a leaf", i);
+            };
+            total += key_count as u32;
+            assert_eq!(next_leaf, internal.children.get(i + 1).copied().unwrap_or(PageId(0)));
+        }
+        assert_eq!(total, max + 1);
+
+        let shallow = db.dump_btree_limited(0, 100).unwrap();
+        assert!(shallow.truncated && shallow.root.children.is_empty());
+        assert_eq!(shallow.root.kind, dump.root.kind);
+        let few = db.dump_btree_limited(8, 2).unwrap();
+        assert!(few.truncated);
+        assert_eq!(few.root.children, dump.root.children[..1]);
     }
 
     #[test]
-    fn schema_validation_rejects_duplicates() {
-        let cols = vec![
-            Column {
-                name: "id".to_string(),
-                ty: ColType::U32,
-                nullable: false,
-            },
-            Column {
-                name: "id".to_string(),
-                ty: ColType::U64,
-                nullable: false,
-            },
-        ];
-        let err = Schema::new(cols).unwrap_err();
-        assert!(matches!(err, InvError::InvalidArgument { name: "column.name", .. }));
+    fn reopen_picks_up_header_changes_from_another_handle() {
+        let path = unique_temp_path("reopen");
+        let mut db = Db::create(&path).unwrap();
+        db.put_u64(1, 10).unwrap();
+        db.flush().unwrap();
+        assert_eq!(db.pager.root_page_id(), ROOT_PAGE_ID);
+
+        let (root, page_count) = {
+            let mut other = Db::open(&path).unwrap();
+            for key in 0..5_000u32 {
+                other.put_u64(key, key as u64 + 1).unwrap();
+            }
+            other.create_table("late", &clustered_schema()).unwrap();
+            other.flush().unwrap();
+            (other.pager.root_page_id(), other.pager.page_count())
+        };
+        assert_ne!(root, ROOT_PAGE_ID);
+        assert_eq!(db.pager.page_count(), 3);
+
+        db.reopen().unwrap();
+        assert_eq!(db.pager.root_page_id(), root);
+        assert_eq!(db.pager.page_count(), page_count);
+        assert!(db.was_validation_complete());
+        assert_eq!(db.get_u64(4_999).unwrap(), Some(5_000));
+        assert!(db.get_table("late").unwrap().is_some());
+
+        db.put_u64(6_000, 1).unwrap();
+        db.reopen().unwrap();
+        assert_eq!(db.get_u64(6_000).unwrap(), Some(1));
+        drop(db);
+        assert_eq!(Db::open(&path).unwrap().get_u64(6_000).unwrap(), Some(1));
     }
 
     #[test]
-    fn row_roundtrip_basic() {
+    fn uuid_tables_generate_unique_retrievable_keys() {
+        let src_path = unique_temp_path("uuid_src");
+        let dest_path = unique_temp_path("uuid_dest");
         let schema = Schema::new(vec![
             Column {
                 name: "id".to_string(),
-                ty: ColType::U32,
-                nullable: false,
-            },
-            Column {
-                name: "score".to_string(),
-                ty: ColType::U64,
+                ty: ColType::Bytes,
                 nullable: false,
             },
             Column {
-                name: "ok".to_string(),
-                ty: ColType::Bool,
+                name: "n".to_string(),
+                ty: ColType::U32,
                 nullable: false,
             },
         ])
         .unwrap();
-        let row = vec![Value::U32(7), Value::U64(9001), Value::Bool(true)];
-        let bytes = encode_row(&schema, &row).unwrap();
-        let decoded = decode_row(&schema, &bytes).unwrap();
-        assert_eq!(row, decoded);
+        let mut db = Db::create(&src_path).unwrap();
+        db.create_table_with_uuid("opaque", &schema).unwrap();
+        let mut keys = Vec::new();
+        for n in 0..2_000u32 {
+            keys.push(db.insert_row_uuid("opaque", &vec![Value::Null, Value::U32(n)]).unwrap());
+        }
+        let distinct: HashSet<[u8; 16]> = keys.iter().copied().collect();
+        assert_eq!(distinct.len(), keys.len());
+        for (n, key) in keys.iter().enumerate() {
+            let row = db.get_row_by_uuid("opaque", key).unwrap().expect("row by key");
+            assert_eq!(row, vec![Value::Bytes(key.to_vec()), Value::U32(n as u32)]);
+        }
+        let mut unknown = keys[0];
+        unknown[15] ^= 1;
+        assert_eq!(db.get_row_by_uuid("opaque", &unknown).unwrap(), None);
+
+        assert!(matches!(
+            db.insert_row("opaque", &vec![Value::Bytes(vec![0; 16]), Value::U32(0)]),
+            Err(InvError::InvalidArgument { name: "table", .. })
+        ));
+        assert!(matches!(
+            db.insert_row_uuid("opaque", &vec![Value::Bytes(vec![0; 16]), Value::U32(0)]),
+            Err(InvError::InvalidArgument { name: "row", .. })
+        ));
+        assert!(matches!(
+            db.create_table_with_uuid("bad", &clustered_schema()),
+            Err(InvError::InvalidArgument { name: "uuid_column", .. })
+        ));
+
+        db.delete_row("opaque", 1).unwrap();
+        assert_eq!(db.get_row_by_uuid("opaque", &keys[0]).unwrap(), None);
+        db.flush().unwrap();
+        db.compact_to(&dest_path).unwrap();
+        drop(db);
+
+        let mut copy = Db::open(&dest_path).unwrap();
+        assert_eq!(copy.get_row_by_uuid("opaque", &keys[0]).unwrap(), None);
+        assert_eq!(copy.get_row_by_uuid("opaque", &keys[7]).unwrap().unwrap()[1], Value::U32(7));
+        let key = copy.insert_row_uuid("opaque", &vec![Value::Null, Value::U32(9_999)]).unwrap();
+        assert_eq!(copy.get_row_by_uuid("opaque", &key).unwrap().unwrap()[1], Value::U32(9_999));
+        copy.check_integrity().unwrap();
     }
 
-    #[test]
-    fn row_roundtrip_bytes_string() {
-        let schema = Schema::new(vec![
+    fn uuid_schema() -> Schema {
+        Schema::new(vec![
             Column {
-                name: "payload".to_string(),
+                name: "id".to_string(),
                 ty: ColType::Bytes,
                 nullable: false,
             },
             Column {
-                name: "name".to_string(),
-                ty: ColType::String,
-                nullable: true,
+                name: "n".to_string(),
+                ty: ColType::U32,
+                nullable: false,
             },
         ])
-        .unwrap();
-        let row = vec![Value::Bytes(vec![1, 2, 3]), Value::String("abc".to_string())];
-        let bytes = encode_row(&schema, &row).unwrap();
-        let decoded = decode_row(&schema, &bytes).unwrap();
-        assert_eq!(row, decoded);
+        .unwrap()
     }
 
     #[test]
-    fn row_rejects_null_for_nonnullable() {
-        let schema = Schema::new(vec![Column {
-            name: "x".to_string(),
-            ty: ColType::U64,
-            nullable: false,
-        }])
-        .unwrap();
-        let row = vec![Value::Null];
-        let err = encode_row(&schema, &row).unwrap_err();
-        assert!(matches!(err, InvError::InvalidArgument { name: "row.null", .. }));
+    fn vacuum_into_reports_monotonic_progress() {
+        let src_path = unique_temp_path("vacuum_progress_src");
+        let dest_path = unique_temp_path("vacuum_progress_dest");
+        let mut db = Db::create(&src_path).unwrap();
+        db.create_table("a", &clustered_schema()).unwrap();
+        db.create_table("b", &clustered_schema()).unwrap();
+        db.create_table("c", &clustered_schema()).unwrap();
+        for i in 0..2500i64 {
+            db.insert_row("a", &vec![Value::I64(i), Value::String(format!("a{}", i))]).unwrap();
+        }
+        for i in 0..30i64 {
+            db.insert_row("c", &vec![Value::I64(i), Value::String(format!("c{}", i))]).unwrap();
+        }
+        db.flush().unwrap();
+
+        let mut reports = Vec::new();
+        db.vacuum_into(&dest_path, |p| reports.push(p)).unwrap();
+        assert!(reports.len() >= 5, "{:?}", reports);
+        for pair in reports.windows(2) {
+            assert!(pair[0].tables_done <= pair[1].tables_done);
+            assert!(pair[0].rows_copied <= pair[1].rows_copied);
+        }
+        assert!(reports.iter().all(|p| p.tables_total == 3));
+        let last = reports.last().unwrap();
+        assert_eq!(last.tables_done, 3);
+        assert_eq!(last.rows_copied, 2530);
+        drop(db);
+
+        let mut copy = Db::open(&dest_path).unwrap();
+        copy.check_integrity().unwrap();
+        assert_eq!(copy.get_row_by_pk("a", 2500).unwrap().unwrap()[1], Value::String("a2499".to_string()));
     }
 
     #[test]
-    fn decode_rejects_bad_magic() {
-        let schema = Schema::new(vec![Column {
-            name: "x".to_string(),
-            ty: ColType::U64,
-            nullable: false,
-        }])
-        .unwrap();
-        let row = vec![Value::U64(1)];
-        let mut bytes = encode_row(&schema, &row).unwrap();
-        bytes[0] ^= 0xFF;
-        let err = decode_row(&schema, &bytes).unwrap_err();
-        assert!(matches!(err, InvError::Corruption { context: "row.magic", .. }));
+    fn warm_on_open_root_caches_root_and_children() {
+        let path = unique_temp_path("warm_root");
+        let mut db = Db::create(&path).unwrap();
+        // Enough full leaves to need a third level.
+        let pairs: Vec<(u32, u64)> = (0..400_000u32).map(|k| (k, k as u64)).collect();
+        db.put_u64_batch(&pairs).unwrap();
+        db.flush().unwrap();
+        drop(db);
+
+        let open = |warm_on_open| {
+            let options = DbOptions {
+                warm_on_open,
+                validate_on_open: ValidationLevel::None,
+                ..DbOptions::default()
+            };
+            Db::open_with_options(&path, options).unwrap()
+        };
+        let dump = open(WarmPolicy::None).dump_btree().unwrap();
+        let children: Vec<PageId> = dump.root.children.iter().map(|c| c.page).collect();
+        let grandchildren: Vec<PageId> =
+            dump.root.children.iter().flat_map(|c| c.children.iter().map(|g| g.page)).collect();
+        assert!(!children.is_empty() && !grandchildren.is_empty());
+
+        let cold = open(WarmPolicy::None);
+        assert!(!cold.pager.is_cached(dump.root.page));
+        drop(cold);
+
+        let warm = open(WarmPolicy::Root);
+        assert!(warm.pager.is_cached(dump.root.page));
+        assert!(children.iter().all(|&page| warm.pager.is_cached(page)));
+        assert!(grandchildren.iter().all(|&page| !warm.pager.is_cached(page)));
+        drop(warm);
+
+        let full = open(WarmPolicy::Full);
+        assert!(grandchildren.iter().all(|&page| full.pager.is_cached(page)));
+        drop(full);
     }
 
     #[test]
-    fn decode_rejects_trailing_bytes() {
-        let schema = Schema::new(vec![Column {
-            name: "x".to_string(),
-            ty: ColType::U32,
-            nullable: false,
-        }])
-        .unwrap();
-        let row = vec![Value::U32(5)];
-        let mut bytes = encode_row(&schema, &row).unwrap();
-        bytes.push(0xAA);
-        let err = decode_row(&schema, &bytes).unwrap_err();
-        assert!(matches!(err, InvError::Corruption { context: "row.trailing", .. }));
+    fn delete_u64_range_removes_a_window_and_frees_leaves() {
+        let path = unique_temp_path("delete_range");
+        let mut db = Db::create(&path).unwrap();
+        for key in pseudo_shuffle(5000) {
+            db.put_u64(key, key as u64 * 3).unwrap();
+        }
+        let leaves_before = collect_leaf_chain(&mut db.pager).len();
+        let free_before = db.pager.free_pages().unwrap().len();
+
+        assert_eq!(db.delete_u64_range(1200, 3700).unwrap(), 2500);
+        assert_eq!(db.delete_u64_range(1200, 3700).unwrap(), 0);
+        assert_eq!(db.delete_u64_range(10, 10).unwrap(), 0);
+
+        let survivors: Vec<(u32, u64)> = db.iter_u64().unwrap().map(Result::unwrap).collect();
+        let expected: Vec<(u32, u64)> =
+            (1..=5000u32).filter(|k| !(1200..3700).contains(k)).map(|k| (k, k as u64 * 3)).collect();
+        assert_eq!(survivors, expected);
+        assert_eq!(db.get_u64(1199).unwrap(), Some(3597));
+        assert_eq!(db.get_u64(1200).unwrap(), None);
+        assert_eq!(db.get_u64(3699).unwrap(), None);
+        assert_eq!(db.get_u64(3700).unwrap(), Some(11100));
+
+        let chain = collect_leaf_chain(&mut db.pager);
+        assert!(chain.len() < leaves_before);
+        assert!(db.pager.free_pages().unwrap().len() > free_before);
+        db.check_integrity().unwrap();
+
+        db.put_u64(2000, 7).unwrap();
+        assert_eq!(db.get_u64(2000).unwrap(), Some(7));
+        db.flush().unwrap();
+        drop(db);
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.iter_u64().unwrap().count(), 2501);
     }
 
     #[test]
-    fn decode_rejects_bad_bool() {
-        let schema = Schema::new(vec![Column {
-            name: "b".to_string(),
-            ty: ColType::Bool,
-            nullable: false,
-        }])
-        .unwrap();
-        // Manually craft bytes: magic + count + tag + invalid bool byte
-        let mut bytes = Vec::new();
-        bytes.extend_from_slice(b"ROW1");
-        crate::encoding::write_var_u64(&mut bytes, 1);
-        bytes.push(0x04);
-        bytes.push(2);
-        let err = decode_row(&schema, &bytes).unwrap_err();
-        assert!(matches!(err, InvError::Corruption { context: "row.bool", .. }));
+    fn find_orphan_pages_reports_unlinked_pages() {
+        let path = unique_temp_path("orphan_pages");
+        let dest_path = unique_temp_path("orphan_pages_dest");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        db.create_clustered_table("c", &clustered_schema(), "ts").unwrap();
+        for i in 0..600i64 {
+            db.insert_row("t", &vec![Value::I64(i), Value::String(format!("row {}", i))]).unwrap();
+            db.insert_row("c", &vec![Value::I64(i % 50), Value::String(format!("c{}", i))]).unwrap();
+        }
+        for pk in 1..=300u32 {
+            db.delete_row("t", pk).unwrap();
+        }
+        for key in pseudo_shuffle(2000) {
+            db.put_u64(key, key as u64).unwrap();
+        }
+        db.put_blob(7, &[3; 200]).unwrap();
+        db.put_blob(7, &[4; 200]).unwrap();
+        db.delete_u64_range(100, 1500).unwrap();
+        assert_eq!(db.find_orphan_pages().unwrap(), Vec::<PageId>::new());
+
+        let stray_row = db.pager.allocate_row_page().unwrap();
+        let stray_node = db.pager.allocate_btree_page().unwrap();
+        assert_eq!(db.find_orphan_pages().unwrap(), vec![stray_row, stray_node]);
+        db.flush().unwrap();
+        drop(db);
+
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.find_orphan_pages().unwrap(), vec![stray_row, stray_node]);
+        db.compact_to(&dest_path).unwrap();
+        let mut copy = Db::open(&dest_path).unwrap();
+        assert!(copy.find_orphan_pages().unwrap().is_empty());
     }
 
     #[test]
-    fn display_formats_without_panic() {
-        let err = InvError::Overflow {
-            context: "test overflow",
+    fn reclaim_orphans_frees_pages_for_reuse() {
+        let path = unique_temp_path("reclaim_orphans");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        for i in 0..200i64 {
+            db.insert_row("t", &vec![Value::I64(i), Value::String(format!("row {}", i))]).unwrap();
+        }
+        assert_eq!(db.reclaim_orphans().unwrap(), 0);
+
+        let orphan = db.pager.allocate_row_page().unwrap();
+        db.flush().unwrap();
+        drop(db);
+
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.find_orphan_pages().unwrap(), vec![orphan]);
+        assert_eq!(db.reclaim_orphans().unwrap(), 1);
+        assert!(db.find_orphan_pages().unwrap().is_empty());
+        assert_eq!(db.next_allocation_id(), orphan);
+        assert_eq!(db.pager.allocate_btree_page().unwrap(), orphan);
+        db.check_integrity().unwrap();
+        assert_eq!(db.get_row_by_pk("t", 200).unwrap().unwrap()[0], Value::I64(199));
+    }
+
+    #[test]
+    fn estimate_inserts_matches_sequential_appends() {
+        let path = unique_temp_path("estimate_inserts");
+        let mut db = Db::create(&path).unwrap();
+        assert_eq!(db.estimate_inserts(0).unwrap(), InsertEstimate::default());
+        let nearly_full = crate::btree::node::max_leaf_keys() as u32 - 1;
+        for key in 1..=nearly_full {
+            db.put_u64(key, 0).unwrap();
+        }
+        assert_eq!(db.estimate_inserts(1).unwrap(), InsertEstimate::default());
+        let next = db.estimate_inserts(2).unwrap();
+        assert_eq!(next.leaf_splits, 1);
+        assert_eq!(next.new_pages, 2);
+
+        let mut last = nearly_full;
+        for count in [2u32, 5000, 100_000] {
+            let estimate = db.estimate_inserts(count as usize).unwrap();
+            let splits = db.pager.btree_stats().splits;
+            let pages = db.pager.page_count();
+            for key in last + 1..=last + count {
+                db.put_u64(key, key as u64).unwrap();
+            }
+            last += count;
+            assert_eq!(estimate.leaf_splits + estimate.internal_splits, db.pager.btree_stats().splits - splits);
+            assert_eq!(estimate.new_pages, (db.pager.page_count() - pages) as u64);
+            assert_eq!(estimate.internal_splits > 0, count == 100_000);
+        }
+        db.check_integrity().unwrap();
+        assert_eq!(db.iter_u64().unwrap().count(), last as usize);
+    }
+
+    #[test]
+    fn nonzero_header_reserved_opens_only_when_allowed() {
+        use std::io::{Seek, SeekFrom, Write};
+        let path = unique_temp_path("header_reserved");
+        let mut db = Db::create(&path).unwrap();
+        db.put_u64(5, 50).unwrap();
+        db.flush().unwrap();
+        drop(db);
+        let mut file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(crate::layout::HEADER_RESERVED as u64)).unwrap();
+        file.write_all(&[0x01, 0, 0, 0x7f]).unwrap();
+        drop(file);
+
+        assert!(matches!(
+            Db::open(&path),
+            Err(InvError::Unsupported { feature: "header.reserved_nonzero" })
+        ));
+        let lenient = DbOptions {
+            ignore_unknown_header_reserved: true,
+            ..DbOptions::default()
         };
-        let _ = format!("{}", err);
+        let mut db = Db::open_with_options(&path, lenient.clone()).unwrap();
+        assert_eq!(db.ignored_header_reserved(), Some(0x7f00_0001));
+        assert_eq!(db.get_u64(5).unwrap(), Some(50));
+        db.reopen().unwrap();
+        assert_eq!(db.ignored_header_reserved(), Some(0x7f00_0001));
+        assert_eq!(db.get_u64(5).unwrap(), Some(50));
+        drop(db);
+
+        // Writing the header cleared the field.
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.ignored_header_reserved(), None);
+        assert_eq!(db.get_u64(5).unwrap(), Some(50));
     }
-}
 
-#[cfg(test)]
-impl Db {
-    fn pager_mut_for_tests(&mut self) -> &mut Pager {
-        &mut self.pager
+    #[test]
+    fn range_u64_respects_bounds_and_stops_early() {
+        use std::ops::Bound::{Excluded, Included, Unbounded};
+        let path = unique_temp_path("range_u64");
+        let mut db = Db::create(&path).unwrap();
+        for key in pseudo_shuffle(3000) {
+            db.put_u64(key * 2, key as u64).unwrap();
+        }
+        let root = db.pager.root_page_id();
+        let range = |db: &mut Db, start, end| {
+            crate::btree::search::range_u64(&mut db.pager, root, start, end)
+                .unwrap()
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<u32>>()
+        };
+        assert_eq!(range(&mut db, Included(10), Included(20)), vec![10, 12, 14, 16, 18, 20]);
+        assert_eq!(range(&mut db, Excluded(10), Excluded(20)), vec![12, 14, 16, 18]);
+        assert_eq!(range(&mut db, Included(11), Excluded(16)), vec![12, 14]);
+        assert_eq!(range(&mut db, Unbounded, Excluded(7)), vec![2, 4, 6]);
+        assert_eq!(range(&mut db, Excluded(5994), Unbounded), vec![5996, 5998, 6000]);
+        assert_eq!(range(&mut db, Unbounded, Unbounded).len(), 3000);
+        assert!(range(&mut db, Included(30), Excluded(30)).is_empty());
+        assert!(range(&mut db, Excluded(u32::MAX), Unbounded).is_empty());
+        let entries = crate::btree::search::range_u64(&mut db.pager, root, Included(4000), Included(4004)).unwrap();
+        assert_eq!(entries, vec![(4000, 2000), (4002, 2001), (4004, 2002)]);
+
+        // Only the leaves up to the end bound are read.
+        let last_leaf = crate::btree::search::find_leaf(&mut db.pager, root, 6000).unwrap();
+        db.flush().unwrap();
+        db.pager.reload().unwrap();
+        db.pager.watch_page(last_leaf);
+        range(&mut db, Included(2), Included(40));
+        assert!(db.pager.take_page_log().is_empty());
+    }
+
+    #[test]
+    fn rejected_insert_leaves_next_pk_untouched() {
+        let path = unique_temp_path("insert_next_pk");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        db.create_clustered_table("c", &clustered_schema(), "ts").unwrap();
+        assert_eq!(db.insert_row("t", &vec![Value::I64(1), Value::String("a".into())]).unwrap(), 1);
+        assert_eq!(db.insert_row("t", &vec![Value::I64(2), Value::String("b".into())]).unwrap(), 2);
+
+        let mut cat = db.pager.read_catalog().unwrap();
+        let table = crate::table::find_table_mut(&mut cat, "t").unwrap();
+        assert!(crate::table::insert_into_table(&mut db.pager, table, &vec![Value::I64(3)]).is_err());
+        let wrong_type = vec![Value::String("x".into()), Value::String("c".into())];
+        assert!(crate::table::insert_into_table(&mut db.pager, table, &wrong_type).is_err());
+        assert_eq!(table.next_pk, 3);
+        let clustered = crate::table::find_table_mut(&mut cat, "c").unwrap();
+        assert!(crate::table::insert_into_table(&mut db.pager, clustered, &vec![Value::Null, Value::Null]).is_err());
+        assert_eq!(clustered.next_pk, 1);
+
+        assert!(db.insert_row("t", &vec![Value::I64(3)]).is_err());
+        assert_eq!(db.insert_row("t", &vec![Value::I64(3), Value::String("c".into())]).unwrap(), 3);
+        db.flush().unwrap();
+        drop(db);
+
+        let mut db = Db::open(&path).unwrap();
+        assert_eq!(db.insert_row("t", &vec![Value::I64(4), Value::String("d".into())]).unwrap(), 4);
+        assert_eq!(db.insert_row("c", &vec![Value::I64(4), Value::String("d".into())]).unwrap(), 1);
+    }
+
+    #[test]
+    fn leaf_cursor_streams_a_range_and_stops_on_cycles() {
+        use std::ops::Bound::{Excluded, Included, Unbounded};
+        let path = unique_temp_path("leaf_cursor");
+        let mut db = Db::create(&path).unwrap();
+        for key in pseudo_shuffle(4000) {
+            db.put_u64(key, key as u64 + 1).unwrap();
+        }
+        let root = db.pager.root_page_id();
+        let mut cursor = crate::btree::search::LeafCursor::new(&mut db.pager, root, Excluded(100), Included(3500)).unwrap();
+        assert_eq!(cursor.next().unwrap().unwrap(), (101, 102));
+        let rest: Vec<(u32, u64)> = cursor.map(Result::unwrap).collect();
+        assert_eq!(rest.len(), 3399);
+        assert_eq!(rest.last(), Some(&(3500, 3501)));
+        let all = crate::btree::search::LeafCursor::new(&mut db.pager, root, Unbounded, Unbounded).unwrap();
+        assert_eq!(all.count(), 4000);
+
+        // Point the last leaf back at the first: the cursor must give up.
+        let chain = collect_leaf_chain(&mut db.pager);
+        let last = *chain.last().unwrap();
+        let page_count = db.pager.page_count();
+        let Node::Leaf(mut leaf) = Node::decode(db.pager.get_page(last).unwrap(), page_count).unwrap() else {
+            panic!("expected a leaf");
+        };
+        leaf.next_leaf = chain[0];
+        db.pager.encode_leaf_into_page(last, &leaf).unwrap();
+        let results: Vec<_> = crate::btree::search::LeafCursor::new(&mut db.pager, root, Unbounded, Unbounded)
+            .unwrap()
+            .collect();
+        assert!(matches!(
+            results.last(),
+            Some(Err(InvError::Corruption { context: "btree.leaf_cycle", .. }))
+        ));
+        assert!(results.len() < 4000 * 3);
+        drop(db);
+    }
+
+    #[test]
+    fn delete_u64_removes_raw_keys_and_persists() {
+        let path = unique_temp_path("delete_u64");
+        let mut db = Db::create(&path).unwrap();
+        db.put_u64(9, 90).unwrap();
+        assert!(db.delete_u64(9).unwrap());
+        assert!(!db.delete_u64(9).unwrap());
+        assert_eq!(db.get_u64(9).unwrap(), None);
+        assert_eq!(db.iter_u64().unwrap().count(), 0);
+
+        for key in pseudo_shuffle(3000) {
+            db.put_u64(key, key as u64).unwrap();
+        }
+        for key in (1..=3000u32).filter(|k| k % 3 == 0) {
+            assert!(db.delete_u64(key).unwrap());
+        }
+        assert!(!db.delete_u64(3001).unwrap());
+        db.flush().unwrap();
+        drop(db);
+
+        let options = DbOptions {
+            min_fill_ratio: 0.0,
+            ..DbOptions::default()
+        };
+        let mut db = Db::open_with_options(&path, options).unwrap();
+        let keys: Vec<u32> = db.iter_u64().unwrap().map(|entry| entry.unwrap().0).collect();
+        assert_eq!(keys, (1..=3000u32).filter(|k| k % 3 != 0).collect::<Vec<_>>());
+        for key in keys {
+            assert!(db.delete_u64(key).unwrap());
+        }
+        assert_eq!(db.iter_u64().unwrap().count(), 0);
+        db.check_integrity().unwrap();
+    }
+
+    #[test]
+    fn table_delete_row_hides_rows_from_lookups_and_scans() {
+        let path = unique_temp_path("table_delete_row");
+        let mut db = Db::create(&path).unwrap();
+        db.create_table("t", &clustered_schema()).unwrap();
+        db.create_clustered_table("c", &clustered_schema(), "ts").unwrap();
+        for i in 1..=40i64 {
+            db.insert_row("t", &vec![Value::I64(i), Value::String(format!("t{}", i))]).unwrap();
+            db.insert_row("c", &vec![Value::I64(i % 7), Value::String(format!("c{}", i))]).unwrap();
+        }
+
+        let mut cat = db.pager.read_catalog().unwrap();
+        for table in ["t", "c"] {
+            for pk in (1..=40u32).filter(|pk| pk % 4 == 1) {
+                assert!(crate::table::delete_row(&mut db.pager, &mut cat, table, pk).unwrap());
+            }
+            assert!(!crate::table::delete_row(&mut db.pager, &mut cat, table, 1).unwrap());
+            assert!(!crate::table::delete_row(&mut db.pager, &mut cat, table, 41).unwrap());
+        }
+        db.pager.write_catalog(&cat).unwrap();
+
+        for table in ["t", "c"] {
+            assert_eq!(db.get_row_by_pk(table, 5).unwrap(), None);
+            assert!(db.get_row_by_pk(table, 6).unwrap().is_some());
+            let pks: Vec<u32> = db.scan_table(table).unwrap().into_iter().map(|(pk, _)| pk).collect();
+            assert_eq!(pks, (1..=40u32).filter(|pk| pk % 4 != 1).collect::<Vec<_>>());
+        }
+        db.check_integrity().unwrap();
+    }
+
+    #[test]
+    fn direct_io_create_and_read_cycle() {
+        let path = unique_temp_path("direct_io");
+        let options = DbOptions {
+            direct_io: true,
+            ..DbOptions::default()
+        };
+        let mut db = Db::create_with_options(&path, options.clone()).unwrap();
+        // The temp dir may sit on a file system without direct IO, in which
+        // case the handle falls back to buffered IO.
+        let direct = db.is_direct_io();
+        db.create_table("t", &clustered_schema()).unwrap();
+        for i in 0..500i64 {
+            db.insert_row("t", &vec![Value::I64(i), Value::String(format!("row {}", i))]).unwrap();
+        }
+        for key in pseudo_shuffle(2000) {
+            db.put_u64(key, key as u64 * 7).unwrap();
+        }
+        db.flush().unwrap();
+        let writes = db.io_stats().writes;
+        assert!(writes > 0);
+        drop(db);
+
+        let mut db = Db::open_with_options(&path, options).unwrap();
+        assert_eq!(db.is_direct_io(), direct);
+        db.check_integrity().unwrap();
+        assert_eq!(db.get_u64(1999).unwrap(), Some(1999 * 7));
+        assert_eq!(db.get_row_by_pk("t", 500).unwrap().unwrap()[1], Value::String("row 499".to_string()));
+        assert_eq!(db.scan_table("t").unwrap().len(), 500);
+        drop(db);
+
+        let mut buffered = Db::open(&path).unwrap();
+        assert!(!buffered.is_direct_io());
+        assert_eq!(buffered.get_u64(1).unwrap(), Some(7));
+        assert_eq!(buffered.scan_table("t").unwrap().len(), 500);
     }
 }
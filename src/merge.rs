@@ -0,0 +1,94 @@
+//! Merging the tables of one database into another.
+
+use crate::catalog::TableDef;
+use crate::error::InvResult;
+use crate::pager::Pager;
+use crate::rowkey;
+use crate::table;
+
+/// How [`crate::Db::merge_from`] treats a source table whose name already
+/// exists in the destination with the same schema.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Append the source rows to the existing table under fresh pks.
+    AppendRows,
+    /// Leave the existing table untouched.
+    SkipExisting,
+}
+
+/// Outcome of a merge, listing table names by what happened to them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Tables that did not exist in the destination and were created.
+    pub created: Vec<String>,
+    /// Existing tables the source rows were appended to.
+    pub appended: Vec<String>,
+    /// Existing tables left untouched under [`MergePolicy::SkipExisting`].
+    pub skipped: Vec<String>,
+    /// Tables whose schema or kind (pk width, clustering, generated keys or
+    /// row checksums) differs between the two databases.
+    pub conflicts: Vec<String>,
+    /// Total number of rows copied into the destination.
+    pub rows_copied: u64,
+}
+
+/// Copy every table of `src` into `dst` according to `policy`.
+///
+/// Rows are re-inserted, so they receive fresh pks in the destination; rows
+/// of tables with generated keys keep their keys. Every source row is read,
+/// and every copied key checked against the destination, before the first
+/// row is written, so a source that cannot be read or a key that is already
+/// taken leaves `dst` untouched. The destination catalog is written once at
+/// the end; a failure while writing the rows is not rolled back.
+pub(crate) fn merge_into(dst: &mut Pager, src: &mut Pager, policy: MergePolicy) -> InvResult<MergeReport> {
+    let src_cat = src.read_catalog()?;
+    let mut cat = dst.read_catalog()?;
+    let mut report = MergeReport::default();
+    let mut staged = Vec::new();
+    for src_table in &src_cat.tables {
+        let existing = cat.get_by_name(&src_table.name);
+        match existing {
+            Some(existing) if !compatible(existing, src_table) => {
+                report.conflicts.push(src_table.name.clone());
+                continue;
+            }
+            Some(_) if policy == MergePolicy::SkipExisting => {
+                report.skipped.push(src_table.name.clone());
+                continue;
+            }
+            Some(_) => report.appended.push(src_table.name.clone()),
+            None => report.created.push(src_table.name.clone()),
+        }
+        let rows = table::scan_all_rows(src, src_table, false)?;
+        if let Some(existing) = existing.filter(|t| t.uuid_root.is_some()) {
+            for (_, row) in &rows {
+                rowkey::check_row_key(dst, existing, row)?;
+            }
+        }
+        staged.push((src_table, existing.is_none(), rows));
+    }
+
+    for (src_table, create, rows) in staged {
+        if create {
+            table::create_table_like(dst, &mut cat, &src_table.name, src_table)?;
+        }
+        for (_, row) in rows {
+            if src_table.has_u64_pk() {
+                table::insert_row_u64(dst, &mut cat, &src_table.name, &row)?;
+            } else if src_table.uuid_root.is_some() {
+                let def = table::find_table_mut(&mut cat, &src_table.name)?;
+                rowkey::insert_row_keyed(dst, def, &row)?;
+            } else {
+                table::insert_row(dst, &mut cat, &src_table.name, &row)?;
+            }
+            report.rows_copied += 1;
+        }
+    }
+    dst.write_catalog(&cat)?;
+    Ok(report)
+}
+
+/// Whether rows of `b` can be appended to `a`: same schema and same kind.
+fn compatible(a: &TableDef, b: &TableDef) -> bool {
+    a.schema == b.schema && a.kind() == b.kind()
+}
@@ -0,0 +1,144 @@
+//! Runtime options controlling optional engine behavior.
+//! Options are not persisted; they apply to a single open handle.
+
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Options applied when creating or opening a database.
+#[derive(Clone, Debug)]
+pub struct DbOptions {
+    /// Re-verify every decoded row against its schema and canonical encoding,
+    /// surfacing any inconsistency as [`crate::InvError::Corruption`].
+    pub strict_reads: bool,
+    /// What inserts do with rows larger than [`crate::rowstore::MAX_INLINE_ROW`].
+    pub large_row_policy: LargeRowPolicy,
+    /// How page reads and writes retry transient IO errors.
+    pub retry_policy: RetryPolicy,
+    /// How much of the file `open` checks before returning.
+    pub validate_on_open: ValidationLevel,
+    /// Directory for the intermediate files of operations that write a new
+    /// database, such as [`crate::Db::compact_to`]; `None` uses the directory
+    /// of the database file.
+    pub scratch_dir: Option<PathBuf>,
+    /// Fraction of a btree node's capacity below which [`crate::Db::delete_row`]
+    /// merges the node with a sibling; clamped to `0.0..=0.5`, and `0.0`
+    /// leaves deletes lazy.
+    pub min_fill_ratio: f32,
+    /// Most pages a [`ValidationLevel::Full`] open visits past the header,
+    /// catalog and root; `None` checks everything.
+    pub validate_page_budget: Option<u64>,
+    /// Index pages `open` reads into the cache after validating.
+    pub warm_on_open: WarmPolicy,
+    /// Open files whose reserved header field is non-zero, such as those
+    /// written by development builds, instead of failing with
+    /// `Unsupported { feature: "header.reserved_nonzero" }`. The ignored
+    /// value is reported by [`crate::Db::ignored_header_reserved`].
+    pub ignore_unknown_header_reserved: bool,
+    /// Read and write pages with direct IO (`O_DIRECT` on Linux), bypassing
+    /// the OS page cache so it does not duplicate the engine's own cache.
+    /// Silently falls back to buffered IO where unsupported; see
+    /// [`crate::Db::is_direct_io`].
+    pub direct_io: bool,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        Self {
+            strict_reads: false,
+            large_row_policy: LargeRowPolicy::default(),
+            retry_policy: RetryPolicy::default(),
+            validate_on_open: ValidationLevel::default(),
+            scratch_dir: None,
+            min_fill_ratio: 0.5,
+            validate_page_budget: None,
+            warm_on_open: WarmPolicy::default(),
+            ignore_unknown_header_reserved: false,
+            direct_io: false,
+        }
+    }
+}
+
+/// Depth of the structural checks run when a database is opened.
+///
+/// Lighter levels make opening large files cheap; the skipped checks can be
+/// run later with [`crate::Db::check_integrity`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValidationLevel {
+    /// Header, root node, catalog, each table's last row page and the whole
+    /// leaf chain.
+    #[default]
+    Full,
+    /// Header and root node only.
+    Header,
+    /// Nothing beyond the header page checks every open performs.
+    None,
+}
+
+/// Index pages pre-read into the cache when a database is opened.
+///
+/// Warming moves the cold-start reads of the first lookups into `open`.
+/// The page cache is unbounded, so warmed pages stay cached until the
+/// handle is dropped or reopened.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WarmPolicy {
+    /// Read nothing beyond what validation reads.
+    #[default]
+    None,
+    /// The root node and its children.
+    Root,
+    /// Every node of the index, level by level from the root.
+    Full,
+}
+
+/// Handling of rows too large to store inline in a row page.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LargeRowPolicy {
+    /// Fail with `Unsupported { feature: "row.too_large" }`.
+    #[default]
+    Error,
+    /// Spill the row to overflow pages. Overflow pages are not implemented
+    /// yet, so this fails with `Unsupported { feature: "row.overflow" }`.
+    Overflow,
+    /// Fail with `InvalidArgument { name: "row.too_large", .. }` naming the
+    /// column that pushed the row over the limit.
+    RejectWithDetails,
+}
+
+/// Bounded retry of page IO that fails with a transient error.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first failed attempt; `0` disables retrying.
+    pub max_retries: u32,
+    /// Error kinds treated as transient. Any other error surfaces at once.
+    pub kinds: Vec<ErrorKind>,
+    /// Sleep before the first retry, doubled before each further one.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            kinds: Vec::new(),
+            backoff: Duration::ZERO,
+        }
+    }
+
+    /// Whether a failure of `kind` after `retries` retries should be retried.
+    pub fn should_retry(&self, kind: ErrorKind, retries: u32) -> bool {
+        retries < self.max_retries && self.kinds.contains(&kind)
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Three retries of `Interrupted` and `WouldBlock`, starting at 1 ms.
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            kinds: vec![ErrorKind::Interrupted, ErrorKind::WouldBlock],
+            backoff: Duration::from_millis(1),
+        }
+    }
+}
@@ -1,9 +1,39 @@
 //! In-memory page buffer with fixed layout invariants.
 
-use crate::config::PAGE_SIZE;
+use crate::config::{FREE_PAGE_KIND, META_PAGE_KIND, PAGE_SIZE, ROW_PAGE_KIND};
 use crate::error::{InvError, InvResult};
+use crate::layout::{PAGE_CRC32, PAGE_FLAGS, PAGE_ID, PAGE_KIND, PAGE_RESERVED, PAGE_RESERVED2};
 use crate::types::PageId;
 
+/// What a page holds, as recorded in its [`PAGE_KIND`] byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageKind {
+    /// The file header, page 0, which starts with the file magic instead of
+    /// a kind byte.
+    Header,
+    /// A btree node, leaf or internal.
+    Btree,
+    /// The catalog or a cluster directory.
+    Meta,
+    /// Row storage.
+    Row,
+    /// A page on the free list.
+    Free,
+}
+
+impl PageKind {
+    /// Map a non-header page's kind byte, or `None` for an unknown kind.
+    pub fn from_byte(kind: u8) -> Option<Self> {
+        match kind {
+            2 => Some(Self::Btree),
+            META_PAGE_KIND => Some(Self::Meta),
+            ROW_PAGE_KIND => Some(Self::Row),
+            FREE_PAGE_KIND => Some(Self::Free),
+            _ => None,
+        }
+    }
+}
+
 /// Page buffer storing exactly `PAGE_SIZE` bytes.
 #[derive(Debug)]
 pub struct Page {
@@ -37,25 +67,25 @@ impl Page {
 
     /// Initialize the per-page header to a known kind with clean flags.
     pub fn init_header(&mut self, kind: u8) -> InvResult<()> {
-        self.write_u8(0, kind);
-        self.write_u8(1, 0);
-        self.write_u16(2, 0);
-        self.write_u32(4, 0);
-        self.write_u32(8, self.id.0);
-        self.write_u32(12, 0);
+        self.write_u8(PAGE_KIND, kind);
+        self.write_u8(PAGE_FLAGS, 0);
+        self.write_u16(PAGE_RESERVED, 0);
+        self.write_u32(PAGE_CRC32, 0);
+        self.write_u32(PAGE_ID, self.id.0);
+        self.write_u32(PAGE_RESERVED2, 0);
         Ok(())
     }
 
     /// Validate the per-page header invariants for non-header pages.
     pub fn validate_header(&self) -> InvResult<()> {
-        let flags = self.read_u8(1);
+        let flags = self.read_u8(PAGE_FLAGS);
         if flags != 0 {
             return Err(InvError::Unsupported {
                 feature: "page.flags",
             });
         }
 
-        let reserved = self.read_u16(2);
+        let reserved = self.read_u16(PAGE_RESERVED);
         if reserved != 0 {
             return Err(InvError::Corruption {
                 context: "page.reserved",
@@ -63,14 +93,14 @@ impl Page {
             });
         }
 
-        let crc32 = self.read_u32(4);
+        let crc32 = self.read_u32(PAGE_CRC32);
         if crc32 != 0 {
             return Err(InvError::Unsupported {
                 feature: "page.crc32",
             });
         }
 
-        let stored_page_id = self.read_u32(8);
+        let stored_page_id = self.read_u32(PAGE_ID);
         if stored_page_id != self.id.0 {
             return Err(InvError::Corruption {
                 context: "page.page_id",
@@ -78,7 +108,7 @@ impl Page {
             });
         }
 
-        let reserved2 = self.read_u32(12);
+        let reserved2 = self.read_u32(PAGE_RESERVED2);
         if reserved2 != 0 {
             return Err(InvError::Corruption {
                 context: "page.reserved2",
@@ -2,19 +2,75 @@
 
 use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
-use std::path::Path;
+use std::panic::Location;
+use std::path::{Path, PathBuf};
 
 use crate::btree::node::{encode_into_page, InternalNode, LeafNode, Node};
 
 use crate::config::{
-    CATALOG_PAGE_ID, FILE_FORMAT_VERSION, FILE_MAGIC, HEADER_PAGE_ID, META_PAGE_KIND, PAGE_SIZE,
-    ROOT_PAGE_ID, ROW_PAGE_KIND,
+    usable_payload, CATALOG_PAGE_ID, FILE_FORMAT_VERSION, FILE_MAGIC, FREE_PAGE_KIND, HEADER_PAGE_ID, META_PAGE_KIND, PAGE_SIZE,
+    ROOT_PAGE_ID, ROW_PAGE_FORMAT_VERSION, ROW_PAGE_KIND,
 };
+use crate::encoding;
 use crate::error::{InvError, InvResult};
-use crate::file::DbFile;
-use crate::page::Page;
+use crate::file::{DbFile, IoStats};
+use crate::layout::{
+    read_u16_le, read_u32_le, CATALOG_ENTRY_COUNT, CATALOG_MAGIC, CATALOG_NEXT_TABLE_ID, CATALOG_RESERVED,
+    CATALOG_VERSION, FREE_PAGE_NEXT, HEADER_FREE_LIST, HEADER_MAGIC, HEADER_PAGE_COUNT, HEADER_PAGE_SIZE,
+    HEADER_RESERVED, HEADER_ROOT_PAGE_ID, HEADER_VERSION, NODE_FLAGS, NODE_KIND, NODE_KIND_LEAF, NODE_NEXT_LEAF,
+    NODE_NUM_KEYS, NODE_RESERVED, NODE_RESERVED2, PAGE_PAYLOAD, ROW_PAGE_DATA, ROW_PAGE_FREE_OFFSET, ROW_PAGE_MAGIC,
+    ROW_PAGE_NEXT, ROW_PAGE_RESERVED2, ROW_PAGE_VERSION,
+};
+use crate::page::{Page, PageKind};
 use crate::types::{DbVersion, PageId};
 
+/// Upper bound on pages combined into one write during flush.
+const MAX_COALESCED_PAGES: usize = 256;
+
+/// Counters for the btree node splits and merges made through a pager.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BtreeStats {
+    /// Number of leaf and internal node splits.
+    pub splits: u64,
+    /// Number of sibling merges made by rebalancing deletes.
+    pub merges: u64,
+}
+
+/// Pages written by [`Pager::flush_reporting`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FlushReport {
+    /// Ids of the flushed pages in ascending order. The header page is
+    /// rewritten by every flush and not listed.
+    pub pages: Vec<PageId>,
+    /// CRC-32 of each flushed page as read back from disk, by index into
+    /// `pages`. Pages carry no checksum of their own, so this is computed
+    /// over the whole image.
+    pub checksums: Vec<u32>,
+}
+
+/// How a watched page was touched, see [`Pager::watch_page`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageAccessKind {
+    /// Borrowed through [`Pager::get_page`].
+    Read,
+    /// Borrowed through [`Pager::get_page_mut`], marking it dirty.
+    Modify,
+    /// Loaded from the file.
+    DiskRead,
+    /// Written to the file.
+    DiskWrite,
+}
+
+/// One recorded access to a watched page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageAccess {
+    pub page: PageId,
+    pub kind: PageAccessKind,
+    /// Source location that asked for the access, as reported by
+    /// `#[track_caller]`.
+    pub caller: &'static Location<'static>,
+}
+
 /// Pager with in-memory cache and dirty tracking.
 #[derive(Debug)]
 pub struct Pager {
@@ -24,40 +80,44 @@ pub struct Pager {
     root_page_id: PageId,
     page_count: u32,
     version: DbVersion,
+    read_only: bool,
+    /// Whether a non-zero reserved header field is accepted on read.
+    allow_header_reserved: bool,
+    /// Reserved header field ignored by the last open or reload, 0 if none.
+    ignored_header_reserved: u32,
+    catalog_writes: u64,
+    /// First page of the free list, 0 when empty; persisted in the header.
+    free_list_head: u32,
+    /// Whether root changes wait for [`Pager::end_header_deferral`].
+    defer_header: bool,
+    /// Whether the on-disk header lags behind the in-memory fields.
+    header_stale: bool,
+    btree_stats: BtreeStats,
+    /// Pages whose accesses are appended to `page_log`.
+    watched: HashSet<PageId>,
+    page_log: Vec<PageAccess>,
+    /// Row owning each btree key inserted through this pager since it was
+    /// opened or reloaded, kept in debug builds to catch composite key
+    /// collisions.
+    #[cfg(debug_assertions)]
+    key_owners: HashMap<u32, (u32, u64)>,
 }
 
 impl Pager {
     /// Create a new database file with initialized header and root pages.
+    ///
+    /// The pages are written to [`staging_path`] and renamed over `path`
+    /// once synced, so `path` is either left as it was or holds a complete
+    /// empty database, even after a crash.
     pub fn create(path: &Path) -> InvResult<Self> {
-        let mut file = DbFile::create_new(path)?;
-
-        let mut header_buf = [0u8; PAGE_SIZE];
-        encode_header_page(
-            &mut header_buf,
-            FILE_FORMAT_VERSION,
-            ROOT_PAGE_ID,
-            3, // header + root + catalog
-        )?;
-        file.write_page(HEADER_PAGE_ID, &header_buf)?;
-
-        let mut root_page = Page::new_zeroed(ROOT_PAGE_ID);
-        root_page.init_header(2)?;
-        initialize_empty_leaf_payload(root_page.as_bytes_mut());
-        let root_arr: &[u8; PAGE_SIZE] = root_page
-            .as_bytes()
-            .try_into()
-            .expect("page buffer length must equal PAGE_SIZE");
-        file.write_page(ROOT_PAGE_ID, root_arr)?;
-
-        // Catalog page
-        let mut cat_page = Page::new_zeroed(CATALOG_PAGE_ID);
-        cat_page.init_header(META_PAGE_KIND)?;
-        initialize_empty_catalog_payload(cat_page.as_bytes_mut());
-        let cat_arr: &[u8; PAGE_SIZE] = cat_page
-            .as_bytes()
-            .try_into()
-            .expect("page buffer length must equal PAGE_SIZE");
-        file.write_page(CATALOG_PAGE_ID, cat_arr)?;
+        let staging = staging_path(path);
+        let staged = write_initial_pages(&staging)
+            .and_then(|()| std::fs::rename(&staging, path).map_err(|e| InvError::io("create_rename", e)));
+        if let Err(e) = staged {
+            let _ = std::fs::remove_file(&staging);
+            return Err(e);
+        }
+        let file = DbFile::open_existing(path)?;
 
         Ok(Self {
             file,
@@ -66,35 +126,46 @@ impl Pager {
             root_page_id: ROOT_PAGE_ID,
             page_count: 3,
             version: DbVersion(FILE_FORMAT_VERSION),
+            read_only: false,
+            allow_header_reserved: false,
+            ignored_header_reserved: 0,
+            catalog_writes: 0,
+            free_list_head: 0,
+            defer_header: false,
+            header_stale: false,
+            btree_stats: BtreeStats::default(),
+            watched: HashSet::new(),
+            page_log: Vec::new(),
+            #[cfg(debug_assertions)]
+            key_owners: HashMap::new(),
         })
     }
 
     /// Open an existing database file, validating the header.
     pub fn open(path: &Path) -> InvResult<Self> {
-        let mut file = DbFile::open_existing(path)?;
-
-        let mut header_buf = [0u8; PAGE_SIZE];
-        file.read_page(HEADER_PAGE_ID, &mut header_buf)?;
-        let (version, root_page_id, page_count) = decode_and_validate_header_page(&header_buf)?;
+        Self::open_file(DbFile::open_existing(path)?, false, false)
+    }
 
-        let actual_count = file.page_count()?;
-        if actual_count != page_count {
-            return Err(InvError::Corruption {
-                context: "header.page_count",
-                details: format!(
-                    "header page_count {} != file page_count {}",
-                    page_count, actual_count
-                ),
-            });
-        }
+    /// [`Pager::open`], accepting a non-zero reserved header field.
+    ///
+    /// Files written by builds that use the field then open, reporting the
+    /// value through [`Pager::ignored_header_reserved`]; writing the header
+    /// stores zero in the field again.
+    pub fn open_allowing_header_reserved(path: &Path) -> InvResult<Self> {
+        Self::open_file(DbFile::open_existing(path)?, false, true)
+    }
 
-        if page_count < 3 {
-            return Err(InvError::Corruption {
-                context: "catalog.missing",
-                details: "catalog page missing".to_string(),
-            });
-        }
+    /// Open an existing database file without write access.
+    ///
+    /// Any operation that would modify a page fails with
+    /// [`InvError::Unsupported`] and dropping the pager writes nothing.
+    pub fn open_read_only(path: &Path) -> InvResult<Self> {
+        Self::open_file(DbFile::open_read_only(path)?, true, false)
+    }
 
+    fn open_file(mut file: DbFile, read_only: bool, allow_header_reserved: bool) -> InvResult<Self> {
+        let (version, root_page_id, page_count, free_list_head, ignored_header_reserved) =
+            read_header(&mut file, read_only, allow_header_reserved)?;
         Ok(Self {
             file,
             cache: HashMap::new(),
@@ -102,11 +173,59 @@ impl Pager {
             root_page_id,
             page_count,
             version,
+            read_only,
+            allow_header_reserved,
+            ignored_header_reserved,
+            catalog_writes: 0,
+            free_list_head,
+            defer_header: false,
+            header_stale: false,
+            btree_stats: BtreeStats::default(),
+            watched: HashSet::new(),
+            page_log: Vec::new(),
+            #[cfg(debug_assertions)]
+            key_owners: HashMap::new(),
         })
     }
 
+    /// Flush, then drop every cached page and reload the header from disk,
+    /// keeping the file handle.
+    ///
+    /// Picks up changes made to the file by other handles since it was
+    /// opened. Nothing is written when this pager has no pending changes;
+    /// otherwise they are flushed first, header included, overwriting what
+    /// other handles wrote. Counters and watched pages are kept.
+    /// # Errors
+    /// - [`InvError::Corruption`] if the header on disk is invalid or does
+    ///   not match the file length.
+    pub fn reload(&mut self) -> InvResult<()> {
+        if !self.dirty.is_empty() || self.header_stale {
+            self.flush()?;
+        }
+        self.cache.clear();
+        self.dirty.clear();
+        #[cfg(debug_assertions)]
+        self.key_owners.clear();
+        let (version, root_page_id, page_count, free_list_head, ignored_header_reserved) =
+            read_header(&mut self.file, self.read_only, self.allow_header_reserved)?;
+        self.version = version;
+        self.ignored_header_reserved = ignored_header_reserved;
+        self.root_page_id = root_page_id;
+        self.page_count = page_count;
+        self.free_list_head = free_list_head;
+        self.header_stale = false;
+        Ok(())
+    }
+
     /// Fetch a page by id, validating the header for non-header pages.
+    #[track_caller]
     pub fn get_page(&mut self, id: PageId) -> InvResult<&Page> {
+        self.note_access(id, PageAccessKind::Read);
+        self.load_page(id)
+    }
+
+    #[track_caller]
+    fn load_page(&mut self, id: PageId) -> InvResult<&Page> {
         if id.0 >= self.page_count {
             return Err(InvError::InvalidArgument {
                 name: "page_id",
@@ -115,6 +234,7 @@ impl Pager {
         }
 
         if !self.cache.contains_key(&id) {
+            self.note_access(id, PageAccessKind::DiskRead);
             let mut page = Page::new_zeroed(id);
             let buf: &mut [u8; PAGE_SIZE] = page
                 .as_bytes_mut()
@@ -134,35 +254,156 @@ impl Pager {
     }
 
     /// Fetch a mutable page, marking it dirty.
+    #[track_caller]
     pub fn get_page_mut(&mut self, id: PageId) -> InvResult<&mut Page> {
+        self.ensure_writable()?;
+        self.note_access(id, PageAccessKind::Modify);
         // Ensure cached and validated.
         if !self.cache.contains_key(&id) {
-            self.get_page(id)?;
+            self.load_page(id)?;
         }
         self.dirty.insert(id);
         Ok(self.cache.get_mut(&id).expect("page must exist in cache"))
     }
 
     /// Flush all dirty pages and header metadata to disk.
+    #[track_caller]
     pub fn flush(&mut self) -> InvResult<()> {
+        if self.read_only {
+            return Ok(());
+        }
         // Always write header to ensure counts are persisted.
         self.rewrite_header()?;
 
         let mut dirty_ids: Vec<PageId> = self.dirty.iter().copied().collect();
         dirty_ids.sort();
+        for &id in &dirty_ids {
+            self.note_access(id, PageAccessKind::DiskWrite);
+        }
+        // Coalesce runs of adjacent dirty pages into a single write each.
+        let mut run_start = PageId(0);
+        let mut run: Vec<&[u8; PAGE_SIZE]> = Vec::new();
         for id in dirty_ids {
-            if let Some(page) = self.cache.get(&id) {
-                let data: &[u8; PAGE_SIZE] = page
-                    .as_bytes()
-                    .try_into()
-                    .expect("page buffer length must equal PAGE_SIZE");
-                self.file.write_page(id, data)?;
+            let Some(page) = self.cache.get(&id) else { continue };
+            let data: &[u8; PAGE_SIZE] = page
+                .as_bytes()
+                .try_into()
+                .expect("page buffer length must equal PAGE_SIZE");
+            let contiguous = !run.is_empty() && run_start.0 + run.len() as u32 == id.0;
+            if !contiguous || run.len() >= MAX_COALESCED_PAGES {
+                if !run.is_empty() {
+                    self.file.write_pages(run_start, &run)?;
+                }
+                run.clear();
+                run_start = id;
             }
+            run.push(data);
+        }
+        if !run.is_empty() {
+            self.file.write_pages(run_start, &run)?;
         }
         self.dirty.clear();
         Ok(())
     }
 
+    /// Flush like [`Pager::flush`], then read every flushed page back and
+    /// report its id and checksum.
+    /// # Errors
+    /// - [`InvError::Corruption`] with context `flush.verify` if a page read
+    ///   back differs from the image written.
+    #[track_caller]
+    pub fn flush_reporting(&mut self) -> InvResult<FlushReport> {
+        if self.read_only {
+            return Ok(FlushReport::default());
+        }
+        let pages = self.dirty_page_ids();
+        let written: Vec<u32> = pages
+            .iter()
+            .map(|id| self.cache.get(id).map_or(0, |page| encoding::crc32(page.as_bytes())))
+            .collect();
+        self.flush()?;
+        let mut checksums = Vec::with_capacity(pages.len());
+        for (&id, &expected) in pages.iter().zip(&written) {
+            let crc = encoding::crc32(self.read_page_from_disk(id)?.as_bytes());
+            if crc != expected {
+                return Err(InvError::Corruption {
+                    context: "flush.verify",
+                    details: format!("page {} read back with crc {:08x}, wrote {:08x}", id.0, crc, expected),
+                });
+            }
+            checksums.push(crc);
+        }
+        Ok(FlushReport { pages, checksums })
+    }
+
+    /// Write the given pages from the cache if dirty, leaving other dirty pages cached.
+    #[track_caller]
+    pub(crate) fn flush_pages(&mut self, ids: &[PageId]) -> InvResult<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        self.commit_page_count()?;
+        let mut ids: Vec<PageId> = ids.iter().copied().filter(|id| self.dirty.contains(id)).collect();
+        ids.sort();
+        ids.dedup();
+        for id in ids {
+            self.note_access(id, PageAccessKind::DiskWrite);
+            let Some(page) = self.cache.get(&id) else { continue };
+            let data: &[u8; PAGE_SIZE] = page
+                .as_bytes()
+                .try_into()
+                .expect("page buffer length must equal PAGE_SIZE");
+            self.file.write_page(id, data)?;
+            self.dirty.remove(&id);
+        }
+        Ok(())
+    }
+
+    /// Whether page `id` is held in the cache.
+    #[cfg(test)]
+    pub(crate) fn is_cached(&self, id: PageId) -> bool {
+        self.cache.contains_key(&id)
+    }
+
+    /// Return the ids of dirty cached pages in ascending order.
+    pub(crate) fn dirty_page_ids(&self) -> Vec<PageId> {
+        let mut ids: Vec<PageId> = self.dirty.iter().copied().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Read a page as currently stored in the file, bypassing the cache.
+    #[track_caller]
+    pub(crate) fn read_page_from_disk(&mut self, id: PageId) -> InvResult<Page> {
+        if id.0 >= self.page_count {
+            return Err(InvError::InvalidArgument {
+                name: "page_id",
+                details: format!("{} out of bounds (page_count={})", id.0, self.page_count),
+            });
+        }
+        self.note_access(id, PageAccessKind::DiskRead);
+        let mut page = Page::new_zeroed(id);
+        let buf: &mut [u8; PAGE_SIZE] = page
+            .as_bytes_mut()
+            .try_into()
+            .expect("page buffer length must equal PAGE_SIZE");
+        self.file.read_page(id, buf)?;
+        Ok(page)
+    }
+
+    /// Write a page image straight to the file without touching the cache.
+    #[track_caller]
+    pub(crate) fn write_page_image(&mut self, page: &Page) -> InvResult<()> {
+        self.ensure_writable()?;
+        self.commit_page_count()?;
+        self.note_access(page.id(), PageAccessKind::DiskWrite);
+        let data: &[u8; PAGE_SIZE] = page
+            .as_bytes()
+            .try_into()
+            .expect("page buffer length must equal PAGE_SIZE");
+        self.file.write_page(page.id(), data)
+    }
+
     /// Return the root page identifier.
     pub fn root_page_id(&self) -> PageId {
         self.root_page_id
@@ -178,34 +419,156 @@ impl Pager {
         self.page_count
     }
 
+    /// Return the head of the free list, 0 when no page is free.
+    pub fn free_list_head(&self) -> u32 {
+        self.free_list_head
+    }
+
     /// Return the database path.
     pub fn path(&self) -> &Path {
         self.file.path()
     }
 
-    /// Allocate a new btree page by appending to the file.
-    pub fn allocate_btree_page(&mut self) -> InvResult<PageId> {
-        if self.page_count == u32::MAX {
-            return Err(InvError::Overflow {
-                context: "pager.allocate.page_count",
+    /// Retry page reads and writes that fail transiently according to `policy`.
+    pub fn set_retry_policy(&mut self, policy: crate::options::RetryPolicy) {
+        self.file.set_retry_policy(policy);
+    }
+
+    /// Return the I/O counters of the underlying file.
+    pub fn io_stats(&self) -> IoStats {
+        self.file.stats()
+    }
+
+    /// Record every later access to `id` until the log is taken with
+    /// [`Pager::take_page_log`].
+    ///
+    /// Accesses to unwatched pages cost a single set lookup.
+    pub fn watch_page(&mut self, id: PageId) {
+        self.watched.insert(id);
+    }
+
+    /// Return and clear the accesses recorded for watched pages, oldest first.
+    pub fn take_page_log(&mut self) -> Vec<PageAccess> {
+        std::mem::take(&mut self.page_log)
+    }
+
+    #[track_caller]
+    fn note_access(&mut self, id: PageId, kind: PageAccessKind) {
+        if !self.watched.is_empty() && self.watched.contains(&id) {
+            self.page_log.push(PageAccess {
+                page: id,
+                kind,
+                caller: Location::caller(),
             });
         }
-        let new_id = PageId(self.page_count);
-        let mut page = Page::new_zeroed(new_id);
-        page.init_header(2)?;
-        initialize_empty_leaf_payload(page.as_bytes_mut());
-        let data: &[u8; PAGE_SIZE] = page
-            .as_bytes()
-            .try_into()
-            .expect("page buffer length must equal PAGE_SIZE");
-        self.file.write_page(new_id, data)?;
-        self.page_count += 1;
-        self.rewrite_header()?;
-        Ok(new_id)
+    }
+
+    /// Return the btree split and merge counters accumulated by this pager.
+    pub fn btree_stats(&self) -> BtreeStats {
+        self.btree_stats
+    }
+
+    pub(crate) fn note_split(&mut self) {
+        self.btree_stats.splits += 1;
+    }
+
+    pub(crate) fn note_merge(&mut self) {
+        self.btree_stats.merges += 1;
+    }
+
+    /// Allocate a new btree page by appending to the file.
+    pub fn allocate_btree_page(&mut self) -> InvResult<PageId> {
+        self.allocate_page(2, initialize_empty_leaf_payload)
     }
 
     /// Allocate a new row page by appending to the file.
     pub fn allocate_row_page(&mut self) -> InvResult<PageId> {
+        self.allocate_page(ROW_PAGE_KIND, initialize_empty_row_page_payload)
+    }
+
+    /// Allocate a new meta page with a zeroed payload by appending to the file.
+    pub fn allocate_meta_page(&mut self) -> InvResult<PageId> {
+        self.allocate_page(META_PAGE_KIND, |_| {})
+    }
+
+    /// Release a page to the free list so a later allocation can reuse it.
+    ///
+    /// The caller must already have dropped every reference to the page. The
+    /// new list head reaches the header on the next flush.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] for the header, root, catalog or an
+    ///   out-of-bounds page.
+    pub fn free_page(&mut self, id: PageId) -> InvResult<()> {
+        self.ensure_writable()?;
+        if id == HEADER_PAGE_ID || id == CATALOG_PAGE_ID || id == self.root_page_id || id.0 >= self.page_count {
+            return Err(InvError::InvalidArgument {
+                name: "page_id",
+                details: format!("page {} cannot be freed", id.0),
+            });
+        }
+        let next = self.free_list_head;
+        let page = self.get_page_mut(id)?;
+        page.as_bytes_mut().fill(0);
+        page.init_header(FREE_PAGE_KIND)?;
+        page.as_bytes_mut()[FREE_PAGE_NEXT..FREE_PAGE_NEXT + 4].copy_from_slice(&next.to_le_bytes());
+        self.free_list_head = id.0;
+        Ok(())
+    }
+
+    /// List the pages on the free list, head first.
+    pub fn free_pages(&mut self) -> InvResult<Vec<PageId>> {
+        let mut pages = Vec::new();
+        let mut seen = HashSet::new();
+        let mut current = self.free_list_head;
+        while current != 0 {
+            if !seen.insert(current) {
+                return Err(InvError::Corruption {
+                    context: "free_list.cycle",
+                    details: format!("cycle detected at {}", current),
+                });
+            }
+            pages.push(PageId(current));
+            current = self.read_free_page_next(PageId(current))?;
+        }
+        Ok(pages)
+    }
+
+    fn read_free_page_next(&mut self, id: PageId) -> InvResult<u32> {
+        let page_count = self.page_count;
+        let buf = self.get_page(id)?.as_bytes();
+        if buf[0] != FREE_PAGE_KIND {
+            return Err(InvError::Corruption {
+                context: "free_list.kind",
+                details: format!("page {} on the free list has kind {}", id.0, buf[0]),
+            });
+        }
+        let next = read_u32_le(buf, FREE_PAGE_NEXT);
+        if next == CATALOG_PAGE_ID.0 || next >= page_count {
+            return Err(InvError::Corruption {
+                context: "free_list.next",
+                details: format!("page {} links to invalid page {}", id.0, next),
+            });
+        }
+        Ok(next)
+    }
+
+    fn allocate_page(&mut self, kind: u8, init_payload: fn(&mut [u8])) -> InvResult<PageId> {
+        self.ensure_writable()?;
+        if self.free_list_head != 0 {
+            let new_id = PageId(self.free_list_head);
+            let next = self.read_free_page_next(new_id)?;
+            let mut page = Page::new_zeroed(new_id);
+            page.init_header(kind)?;
+            init_payload(page.as_bytes_mut());
+            if cfg!(debug_assertions) {
+                if let Err(e) = validate_fresh_page(&page, kind, self.page_count) {
+                    panic!("allocated page {} of kind {} is not valid: {}", new_id.0, kind, e);
+                }
+            }
+            self.get_page_mut(new_id)?.as_bytes_mut().copy_from_slice(page.as_bytes());
+            self.free_list_head = next;
+            return Ok(new_id);
+        }
         if self.page_count == u32::MAX {
             return Err(InvError::Overflow {
                 context: "pager.allocate.page_count",
@@ -213,20 +576,29 @@ impl Pager {
         }
         let new_id = PageId(self.page_count);
         let mut page = Page::new_zeroed(new_id);
-        page.init_header(ROW_PAGE_KIND)?;
-        initialize_empty_row_page_payload(page.as_bytes_mut());
+        page.init_header(kind)?;
+        init_payload(page.as_bytes_mut());
+        if cfg!(debug_assertions) {
+            if let Err(e) = validate_fresh_page(&page, kind, self.page_count + 1) {
+                panic!("allocated page {} of kind {} is not valid: {}", new_id.0, kind, e);
+            }
+        }
         let data: &[u8; PAGE_SIZE] = page
             .as_bytes()
             .try_into()
             .expect("page buffer length must equal PAGE_SIZE");
+        self.note_access(new_id, PageAccessKind::DiskWrite);
         self.file.write_page(new_id, data)?;
+        // The header catches up on the next flush; until then the file may
+        // hold more pages than the header counts, which `open` trims.
         self.page_count += 1;
-        self.rewrite_header()?;
+        self.header_stale = true;
         Ok(new_id)
     }
 
     /// Update root page id and persist header.
     pub fn set_root_page_id(&mut self, new_root: PageId) -> InvResult<()> {
+        self.ensure_writable()?;
         if new_root.0 == 0 || new_root.0 >= self.page_count {
             return Err(InvError::Corruption {
                 context: "header.root_page_id",
@@ -237,6 +609,40 @@ impl Pager {
             });
         }
         self.root_page_id = new_root;
+        self.header_changed()
+    }
+
+    /// Hold back the header writes of root changes until
+    /// [`Pager::end_header_deferral`] or the next flush, for bulk work that
+    /// would otherwise rewrite the header at every root split.
+    pub(crate) fn begin_header_deferral(&mut self) {
+        self.defer_header = true;
+    }
+
+    /// Resume immediate header writes, writing the header once if a deferred
+    /// change is pending.
+    pub(crate) fn end_header_deferral(&mut self) -> InvResult<()> {
+        self.defer_header = false;
+        if self.header_stale {
+            self.rewrite_header()?;
+        }
+        Ok(())
+    }
+
+    /// Write a stale header before pages that may point at newly allocated
+    /// pages reach the file, so they are never trimmed on open.
+    fn commit_page_count(&mut self) -> InvResult<()> {
+        if self.header_stale {
+            self.rewrite_header()?;
+        }
+        Ok(())
+    }
+
+    fn header_changed(&mut self) -> InvResult<()> {
+        if self.defer_header {
+            self.header_stale = true;
+            return Ok(());
+        }
         self.rewrite_header()
     }
 
@@ -244,38 +650,127 @@ impl Pager {
     pub fn read_catalog(&mut self) -> InvResult<crate::catalog::Catalog> {
         let page = self.get_page(CATALOG_PAGE_ID)?;
         let buf = page.as_bytes();
-        if buf.get(0) != Some(&META_PAGE_KIND) {
+        if buf.first() != Some(&META_PAGE_KIND) {
             return Err(InvError::Corruption {
                 context: "catalog.page_kind",
-                details: format!("expected {} got {}", META_PAGE_KIND, buf.get(0).copied().unwrap_or(255)),
+                details: format!("expected {} got {}", META_PAGE_KIND, buf.first().copied().unwrap_or(255)),
             });
         }
         // validate header invariants
         page.validate_header()?;
-        let payload = &buf[16..];
+        let payload = &buf[PAGE_PAYLOAD..];
         crate::catalog::decode_catalog(payload)
     }
 
     /// Write catalog to disk (marks page dirty; flush persists).
     pub fn write_catalog(&mut self, cat: &crate::catalog::Catalog) -> InvResult<()> {
-        let encoded = crate::catalog::encode_catalog(cat)?;
-        if encoded.len() > PAGE_SIZE - 16 {
-            return Err(InvError::Unsupported {
-                feature: "catalog.page_overflow",
-            });
-        }
+        let encoded = encode_catalog_payload(cat)?;
         let page = self.get_page_mut(CATALOG_PAGE_ID)?;
-        let buf = page.as_bytes_mut();
-        if buf.get(0) != Some(&META_PAGE_KIND) {
+        store_catalog_payload(page.as_bytes_mut(), &encoded)?;
+        self.catalog_writes += 1;
+        Ok(())
+    }
+
+    /// Read the catalog as currently stored in the file, bypassing the cache.
+    pub(crate) fn read_catalog_from_disk(&mut self) -> InvResult<crate::catalog::Catalog> {
+        let page = self.read_page_from_disk(CATALOG_PAGE_ID)?;
+        if page.as_bytes().first() != Some(&META_PAGE_KIND) {
             return Err(InvError::Corruption {
                 context: "catalog.page_kind",
                 details: "wrong page kind for catalog".to_string(),
             });
         }
-        for b in &mut buf[16..] {
-            *b = 0;
+        page.validate_header()?;
+        crate::catalog::decode_catalog(&page.as_bytes()[PAGE_PAYLOAD..])
+    }
+
+    /// Write a catalog straight to the file, leaving the cached catalog page as is.
+    pub(crate) fn write_catalog_to_disk(&mut self, cat: &crate::catalog::Catalog) -> InvResult<()> {
+        let encoded = encode_catalog_payload(cat)?;
+        let mut page = self.read_page_from_disk(CATALOG_PAGE_ID)?;
+        store_catalog_payload(page.as_bytes_mut(), &encoded)?;
+        self.write_page_image(&page)
+    }
+
+    /// Number of [`Pager::write_catalog`] calls made through this pager.
+    pub fn catalog_writes(&self) -> u64 {
+        self.catalog_writes
+    }
+
+    /// Record that btree `key` now indexes row `pk` of table `table_id`.
+    ///
+    /// The registry only covers keys inserted through this pager since it was
+    /// opened or last reloaded, and release builds keep none.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] in debug builds if the key was already
+    ///   recorded for a different row, since the insert would silently
+    ///   replace that row's index entry.
+    pub(crate) fn note_key_owner(&mut self, key: u32, table_id: u32, pk: u64) -> InvResult<()> {
+        #[cfg(debug_assertions)]
+        match self.key_owners.get(&key) {
+            Some(&prev) if prev != (table_id, pk) => {
+                return Err(InvError::InvalidArgument {
+                    name: "pk",
+                    details: format!(
+                        "composite key collision: key {} maps to table {} pk {} and table {} pk {}",
+                        key, prev.0, prev.1, table_id, pk
+                    ),
+                });
+            }
+            Some(_) => {}
+            None => {
+                self.key_owners.insert(key, (table_id, pk));
+            }
         }
-        buf[16..16 + encoded.len()].copy_from_slice(&encoded);
+        #[cfg(not(debug_assertions))]
+        let _ = (key, table_id, pk);
+        Ok(())
+    }
+
+    /// Drop the owner recorded for a btree key whose entry was deleted.
+    pub(crate) fn forget_key_owner(&mut self, key: u32) {
+        #[cfg(debug_assertions)]
+        self.key_owners.remove(&key);
+        #[cfg(not(debug_assertions))]
+        let _ = key;
+    }
+
+    /// Switch the file to direct IO, bypassing the OS page cache, and return
+    /// whether it now uses it; see [`DbFile::enable_direct_io`].
+    pub fn enable_direct_io(&mut self) -> InvResult<bool> {
+        self.file.enable_direct_io(!self.read_only)
+    }
+
+    /// Whether page IO bypasses the OS page cache.
+    pub fn is_direct_io(&self) -> bool {
+        self.file.is_direct_io()
+    }
+
+    /// The non-zero reserved header field accepted by the last open or
+    /// reload, if any.
+    pub fn ignored_header_reserved(&self) -> Option<u32> {
+        Some(self.ignored_header_reserved).filter(|&reserved| reserved != 0)
+    }
+
+    /// Whether the pager was opened with [`Pager::open_read_only`].
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn ensure_writable(&self) -> InvResult<()> {
+        if self.read_only {
+            return Err(InvError::Unsupported {
+                feature: "pager.read_only",
+            });
+        }
+        Ok(())
+    }
+
+    /// Re-read the header page from disk and validate it.
+    pub fn validate_header_page(&mut self) -> InvResult<()> {
+        let mut buf = [0u8; PAGE_SIZE];
+        self.file.read_page(HEADER_PAGE_ID, &mut buf)?;
+        decode_and_validate_header_page(&buf, self.allow_header_reserved)?;
         Ok(())
     }
 
@@ -286,8 +781,12 @@ impl Pager {
             self.version.0,
             self.root_page_id,
             self.page_count,
+            self.free_list_head,
         )?;
-        self.file.write_page(HEADER_PAGE_ID, &header_buf)
+        self.note_access(HEADER_PAGE_ID, PageAccessKind::DiskWrite);
+        self.file.write_page(HEADER_PAGE_ID, &header_buf)?;
+        self.header_stale = false;
+        Ok(())
     }
 
     pub(crate) fn encode_leaf_into_page(
@@ -327,61 +826,186 @@ impl Drop for Pager {
     }
 }
 
+fn encode_catalog_payload(cat: &crate::catalog::Catalog) -> InvResult<Vec<u8>> {
+    let encoded = crate::catalog::encode_catalog(cat)?;
+    if encoded.len() > usable_payload(PageKind::Meta) {
+        return Err(InvError::Unsupported {
+            feature: "catalog.page_overflow",
+        });
+    }
+    Ok(encoded)
+}
+
+/// Sibling of `path` that [`Pager::create`] builds a new file in before
+/// renaming it into place.
+pub(crate) fn staging_path(path: &Path) -> PathBuf {
+    let mut staging = path.as_os_str().to_owned();
+    staging.push(".creating");
+    PathBuf::from(staging)
+}
+
+/// Write the header, empty root leaf and empty catalog of a new database.
+fn write_initial_pages(path: &Path) -> InvResult<()> {
+    let mut file = DbFile::create_new(path)?;
+
+    let mut header_buf = [0u8; PAGE_SIZE];
+    encode_header_page(
+        &mut header_buf,
+        FILE_FORMAT_VERSION,
+        ROOT_PAGE_ID,
+        3, // header + root + catalog
+        0,
+    )?;
+    file.write_page(HEADER_PAGE_ID, &header_buf)?;
+
+    let mut root_page = Page::new_zeroed(ROOT_PAGE_ID);
+    root_page.init_header(2)?;
+    initialize_empty_leaf_payload(root_page.as_bytes_mut());
+    let root_arr: &[u8; PAGE_SIZE] = root_page
+        .as_bytes()
+        .try_into()
+        .expect("page buffer length must equal PAGE_SIZE");
+    file.write_page(ROOT_PAGE_ID, root_arr)?;
+
+    // Catalog page
+    let mut cat_page = Page::new_zeroed(CATALOG_PAGE_ID);
+    cat_page.init_header(META_PAGE_KIND)?;
+    initialize_empty_catalog_payload(cat_page.as_bytes_mut());
+    let cat_arr: &[u8; PAGE_SIZE] = cat_page
+        .as_bytes()
+        .try_into()
+        .expect("page buffer length must equal PAGE_SIZE");
+    file.write_page(CATALOG_PAGE_ID, cat_arr)?;
+    file.sync()
+}
+
+fn store_catalog_payload(buf: &mut [u8], encoded: &[u8]) -> InvResult<()> {
+    if buf.first() != Some(&META_PAGE_KIND) {
+        return Err(InvError::Corruption {
+            context: "catalog.page_kind",
+            details: "wrong page kind for catalog".to_string(),
+        });
+    }
+    for b in &mut buf[PAGE_PAYLOAD..] {
+        *b = 0;
+    }
+    buf[PAGE_PAYLOAD..PAGE_PAYLOAD + encoded.len()].copy_from_slice(encoded);
+    Ok(())
+}
+
 fn encode_header_page(
     buf: &mut [u8; PAGE_SIZE],
     version: u16,
     root: PageId,
     page_count: u32,
+    free_list_head: u32,
 ) -> InvResult<()> {
     // zero-fill entire buffer first
     buf.fill(0);
 
-    buf[0..8].copy_from_slice(&FILE_MAGIC);
-    buf[8..10].copy_from_slice(&version.to_le_bytes());
+    buf[HEADER_MAGIC..HEADER_MAGIC + 8].copy_from_slice(&FILE_MAGIC);
+    buf[HEADER_VERSION..HEADER_VERSION + 2].copy_from_slice(&version.to_le_bytes());
 
     let ps: u16 = PAGE_SIZE
         .try_into()
         .map_err(|_| InvError::Overflow {
             context: "PAGE_SIZE exceeds u16::MAX",
         })?;
-    buf[10..12].copy_from_slice(&ps.to_le_bytes());
-    buf[12..16].copy_from_slice(&root.0.to_le_bytes());
-    buf[16..20].copy_from_slice(&page_count.to_le_bytes());
-    // reserved [20..24) stays zero; non-zero indicates forward-compat
+    buf[HEADER_PAGE_SIZE..HEADER_PAGE_SIZE + 2].copy_from_slice(&ps.to_le_bytes());
+    buf[HEADER_ROOT_PAGE_ID..HEADER_ROOT_PAGE_ID + 4].copy_from_slice(&root.0.to_le_bytes());
+    buf[HEADER_PAGE_COUNT..HEADER_PAGE_COUNT + 4].copy_from_slice(&page_count.to_le_bytes());
+    // reserved u32 at HEADER_RESERVED stays zero; non-zero indicates forward-compat
+    buf[HEADER_FREE_LIST..HEADER_FREE_LIST + 4].copy_from_slice(&free_list_head.to_le_bytes());
     Ok(())
 }
 
+/// Run the kind-specific validation a reader applies to a freshly initialized page.
+fn validate_fresh_page(page: &Page, kind: u8, page_count: u32) -> InvResult<()> {
+    page.validate_header()?;
+    match kind {
+        2 => Node::decode(page, page_count).map(|_| ()),
+        ROW_PAGE_KIND => crate::rowstore::validate_row_page_header(page.as_bytes()),
+        _ => Ok(()),
+    }
+}
+
 fn initialize_empty_leaf_payload(buf: &mut [u8]) {
-    let base = 16;
-    buf[base] = 1; // node_kind leaf
-    buf[base + 1] = 0; // node_flags
-    buf[base + 2..base + 4].copy_from_slice(&0u16.to_le_bytes()); // num_keys
-    buf[base + 4..base + 8].copy_from_slice(&0u32.to_le_bytes()); // reserved
-    buf[base + 8..base + 12].copy_from_slice(&0u32.to_le_bytes()); // next_leaf
-    buf[base + 12..base + 16].copy_from_slice(&0u32.to_le_bytes()); // reserved2
+    buf[NODE_KIND] = NODE_KIND_LEAF;
+    buf[NODE_FLAGS] = 0;
+    buf[NODE_NUM_KEYS..NODE_NUM_KEYS + 2].copy_from_slice(&0u16.to_le_bytes());
+    buf[NODE_RESERVED..NODE_RESERVED + 4].copy_from_slice(&0u32.to_le_bytes());
+    buf[NODE_NEXT_LEAF..NODE_NEXT_LEAF + 4].copy_from_slice(&0u32.to_le_bytes());
+    buf[NODE_RESERVED2..NODE_RESERVED2 + 4].copy_from_slice(&0u32.to_le_bytes());
 }
 
 fn initialize_empty_catalog_payload(buf: &mut [u8]) {
-    let base = 16;
-    buf[base..base + 4].copy_from_slice(b"CAT1");
-    buf[base + 4..base + 6].copy_from_slice(&1u16.to_le_bytes());
-    buf[base + 6..base + 8].copy_from_slice(&0u16.to_le_bytes()); // entry_count
-    buf[base + 8..base + 12].copy_from_slice(&1u32.to_le_bytes()); // next_table_id
-    buf[base + 12..base + 16].copy_from_slice(&0u32.to_le_bytes()); // reserved
+    let base = PAGE_PAYLOAD;
+    buf[base + CATALOG_MAGIC..base + CATALOG_MAGIC + 4].copy_from_slice(b"CAT1");
+    buf[base + CATALOG_VERSION..base + CATALOG_VERSION + 2]
+        .copy_from_slice(&crate::catalog::CATALOG_VERSION.to_le_bytes());
+    buf[base + CATALOG_ENTRY_COUNT..base + CATALOG_ENTRY_COUNT + 2].copy_from_slice(&0u16.to_le_bytes());
+    buf[base + CATALOG_NEXT_TABLE_ID..base + CATALOG_NEXT_TABLE_ID + 4].copy_from_slice(&1u32.to_le_bytes());
+    buf[base + CATALOG_RESERVED..base + CATALOG_RESERVED + 4].copy_from_slice(&0u32.to_le_bytes());
 }
 
 fn initialize_empty_row_page_payload(buf: &mut [u8]) {
-    let base = 16;
-    buf[base..base + 4].copy_from_slice(b"ROWP");
-    buf[base + 4..base + 6].copy_from_slice(&1u16.to_le_bytes());
-    buf[base + 6..base + 8].copy_from_slice(&32u16.to_le_bytes()); // free_offset absolute
-    buf[base + 8..base + 12].copy_from_slice(&0u32.to_le_bytes()); // reserved
-    buf[base + 12..base + 16].copy_from_slice(&0u32.to_le_bytes()); // reserved2
+    buf[ROW_PAGE_MAGIC..ROW_PAGE_MAGIC + 4].copy_from_slice(b"ROWP");
+    buf[ROW_PAGE_VERSION..ROW_PAGE_VERSION + 2].copy_from_slice(&ROW_PAGE_FORMAT_VERSION.to_le_bytes());
+    buf[ROW_PAGE_FREE_OFFSET..ROW_PAGE_FREE_OFFSET + 2].copy_from_slice(&(ROW_PAGE_DATA as u16).to_le_bytes());
+    buf[ROW_PAGE_NEXT..ROW_PAGE_NEXT + 4].copy_from_slice(&0u32.to_le_bytes());
+    buf[ROW_PAGE_RESERVED2..ROW_PAGE_RESERVED2 + 4].copy_from_slice(&0u32.to_le_bytes());
 }
 
-fn decode_and_validate_header_page(buf: &[u8; PAGE_SIZE]) -> InvResult<(DbVersion, PageId, u32)> {
+/// Read and validate the header page, returning its version, root page,
+/// page count, free list head and the reserved field it ignored (0 if none).
+///
+/// Pages past the header's count are truncated away unless `read_only`.
+fn read_header(
+    file: &mut DbFile,
+    read_only: bool,
+    allow_reserved: bool,
+) -> InvResult<(DbVersion, PageId, u32, u32, u32)> {
+    let mut header_buf = [0u8; PAGE_SIZE];
+    file.read_page(HEADER_PAGE_ID, &mut header_buf)?;
+    let (version, root_page_id, page_count, free_list_head) =
+        decode_and_validate_header_page(&header_buf, allow_reserved)?;
+    let ignored_reserved = read_u32_le(&header_buf, HEADER_RESERVED);
+
+    let actual_count = file.page_count()?;
+    // Pages past the header's count were allocated after the last header
+    // write and are not referenced by anything on disk.
+    if actual_count > page_count && !read_only {
+        file.truncate_pages(page_count)?;
+    }
+    if actual_count < page_count {
+        return Err(InvError::Corruption {
+            context: "header.page_count",
+            details: format!(
+                "header page_count {} != file page_count {}",
+                page_count, actual_count
+            ),
+        });
+    }
+
+    if page_count < 3 {
+        return Err(InvError::Corruption {
+            context: "catalog.missing",
+            details: "catalog page missing".to_string(),
+        });
+    }
+
+    Ok((version, root_page_id, page_count, free_list_head, ignored_reserved))
+}
+
+/// Decode the header page; a non-zero reserved field is an error unless
+/// `allow_reserved`, in which case it is ignored here and the caller reports
+/// it (see [`Pager::ignored_header_reserved`]).
+fn decode_and_validate_header_page(
+    buf: &[u8; PAGE_SIZE],
+    allow_reserved: bool,
+) -> InvResult<(DbVersion, PageId, u32, u32)> {
     let mut found_magic = [0u8; 8];
-    found_magic.copy_from_slice(&buf[0..8]);
+    found_magic.copy_from_slice(&buf[HEADER_MAGIC..HEADER_MAGIC + 8]);
     if found_magic != FILE_MAGIC {
         return Err(InvError::InvalidMagic {
             expected: FILE_MAGIC,
@@ -389,10 +1013,10 @@ fn decode_and_validate_header_page(buf: &[u8; PAGE_SIZE]) -> InvResult<(DbVersio
         });
     }
 
-    let version = u16::from_le_bytes([buf[8], buf[9]]);
+    let version = read_u16_le(buf, HEADER_VERSION);
     crate::config::validate_version(version)?;
 
-    let page_size = u16::from_le_bytes([buf[10], buf[11]]);
+    let page_size = read_u16_le(buf, HEADER_PAGE_SIZE);
     if page_size as usize != PAGE_SIZE {
         return Err(InvError::Corruption {
             context: "header.page_size",
@@ -400,11 +1024,10 @@ fn decode_and_validate_header_page(buf: &[u8; PAGE_SIZE]) -> InvResult<(DbVersio
         });
     }
 
-    let root_page_id_raw = u32::from_le_bytes([buf[12], buf[13], buf[14], buf[15]]);
-    let page_count = u32::from_le_bytes([buf[16], buf[17], buf[18], buf[19]]);
+    let root_page_id_raw = read_u32_le(buf, HEADER_ROOT_PAGE_ID);
+    let page_count = read_u32_le(buf, HEADER_PAGE_COUNT);
 
-    let reserved = u32::from_le_bytes([buf[20], buf[21], buf[22], buf[23]]);
-    if reserved != 0 {
+    if read_u32_le(buf, HEADER_RESERVED) != 0 && !allow_reserved {
         return Err(InvError::Unsupported {
             feature: "header.reserved_nonzero",
         });
@@ -427,5 +1050,16 @@ fn decode_and_validate_header_page(buf: &[u8; PAGE_SIZE]) -> InvResult<(DbVersio
         });
     }
 
-    Ok((DbVersion(version), PageId(root_page_id_raw), page_count))
+    let free_list_head = read_u32_le(buf, HEADER_FREE_LIST);
+    if free_list_head == CATALOG_PAGE_ID.0 || free_list_head >= page_count {
+        return Err(InvError::Corruption {
+            context: "header.free_list",
+            details: format!(
+                "free list head {} invalid for page_count {}",
+                free_list_head, page_count
+            ),
+        });
+    }
+
+    Ok((DbVersion(version), PageId(root_page_id_raw), page_count, free_list_head))
 }
@@ -0,0 +1,112 @@
+//! Per-table index of 64-bit pks.
+//!
+//! A table created with [`crate::Db::create_table_u64_pk`] indexes its rows in
+//! its own two-level btree instead of the shared one: the outer tree, rooted
+//! at [`crate::catalog::TableDef::pk_root`], maps the high 32 bits of a pk to
+//! the root of an inner tree, which maps the low 32 bits to the row's packed
+//! pointer. Every pk has a key of its own, so entries never collide with
+//! another pk, another table or the raw u64 store.
+
+use crate::btree;
+use crate::error::{InvError, InvResult};
+use crate::pager::Pager;
+use crate::types::PageId;
+
+fn split(pk: u64) -> (u32, u32) {
+    ((pk >> 32) as u32, pk as u32)
+}
+
+/// Look up the value stored for `pk` in the index rooted at `root`.
+///
+/// A `root` of 0 is an index without entries.
+pub(crate) fn search(pager: &mut Pager, root: u32, pk: u64) -> InvResult<Option<u64>> {
+    if root == 0 {
+        return Ok(None);
+    }
+    let (high, low) = split(pk);
+    let Some(inner) = btree::search_u64(pager, PageId(root), high)? else {
+        return Ok(None);
+    };
+    btree::search_u64(pager, inner_root(inner)?, low)
+}
+
+/// Insert or replace the value for `pk`, returning the (possibly new) root.
+///
+/// A `root` of 0 allocates the outer tree.
+pub(crate) fn insert(pager: &mut Pager, root: u32, pk: u64, value: u64) -> InvResult<u32> {
+    let root = match root {
+        0 => pager.allocate_btree_page()?,
+        root => PageId(root),
+    };
+    let (high, low) = split(pk);
+    let inner = match btree::search_u64(pager, root, high)? {
+        Some(inner) => inner_root(inner)?,
+        None => pager.allocate_btree_page()?,
+    };
+    let new_inner = btree::insert_u64(pager, inner, low, value)?;
+    if btree::search_u64(pager, root, high)? == Some(new_inner.0 as u64) {
+        return Ok(root.0);
+    }
+    Ok(btree::insert_u64(pager, root, high, new_inner.0 as u64)?.0)
+}
+
+/// Build an index from entries sorted by strictly ascending pk, returning its
+/// root, or 0 for no entries.
+pub(crate) fn bulk_load(pager: &mut Pager, entries: &[(u64, u64)]) -> InvResult<u32> {
+    if entries.is_empty() {
+        return Ok(0);
+    }
+    let mut outer = Vec::new();
+    for group in entries.chunk_by(|a, b| a.0 >> 32 == b.0 >> 32) {
+        let keys: Vec<(u32, u64)> = group.iter().map(|&(pk, value)| (pk as u32, value)).collect();
+        let empty = pager.allocate_btree_page()?;
+        let inner = btree::bulk_load_u64(pager, empty, &keys)?;
+        outer.push(((group[0].0 >> 32) as u32, inner.0 as u64));
+    }
+    let empty = pager.allocate_btree_page()?;
+    Ok(btree::bulk_load_u64(pager, empty, &outer)?.0)
+}
+
+/// Every `(pk, value)` entry of the index, in ascending pk order.
+pub(crate) fn entries(pager: &mut Pager, root: u32) -> InvResult<Vec<(u64, u64)>> {
+    let mut entries = Vec::new();
+    for (high, inner) in inner_roots(pager, root)? {
+        btree::search::walk_from(pager, inner, 0, |low, value| {
+            entries.push(((high as u64) << 32 | low as u64, value));
+            Ok(true)
+        })?;
+    }
+    Ok(entries)
+}
+
+/// Every node page of the outer tree and of each inner tree.
+pub(crate) fn pages(pager: &mut Pager, root: u32) -> InvResult<Vec<PageId>> {
+    if root == 0 {
+        return Ok(Vec::new());
+    }
+    let mut pages = btree::search::tree_pages(pager, PageId(root))?;
+    for (_, inner) in inner_roots(pager, root)? {
+        pages.extend(btree::search::tree_pages(pager, inner)?);
+    }
+    Ok(pages)
+}
+
+/// `(high word, inner root)` of every inner tree, in ascending order.
+fn inner_roots(pager: &mut Pager, root: u32) -> InvResult<Vec<(u32, PageId)>> {
+    if root == 0 {
+        return Ok(Vec::new());
+    }
+    let mut inner = Vec::new();
+    btree::search::walk_from(pager, PageId(root), 0, |high, value| {
+        inner.push((high, value));
+        Ok(true)
+    })?;
+    inner.into_iter().map(|(high, value)| Ok((high, inner_root(value)?))).collect()
+}
+
+fn inner_root(value: u64) -> InvResult<PageId> {
+    u32::try_from(value).ok().filter(|&page| page != 0).map(PageId).ok_or(InvError::Corruption {
+        context: "pktree.inner_root",
+        details: format!("outer entry holds page {}", value),
+    })
+}
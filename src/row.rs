@@ -1,8 +1,10 @@
 //! Row value representation and deterministic encoding/decoding.
 
+use std::sync::Arc;
+
 use crate::encoding;
 use crate::error::{InvError, InvResult};
-use crate::schema::{ColType, Schema};
+use crate::schema::{ColType, Column, Schema};
 
 /// Logical value types supported by the row codec.
 #[derive(Clone, Debug, PartialEq)]
@@ -19,10 +21,193 @@ pub enum Value {
 /// A row is a sequence of values matching a schema.
 pub type Row = Vec<Value>;
 
+/// Comparison applied by [`crate::Db::pks_where`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    /// Whether `lhs op rhs` holds.
+    ///
+    /// Values of different variants, including `Null` on either side, never
+    /// compare, so every operator is false for them. `Bytes` and `String`
+    /// compare bytewise.
+    pub fn eval(self, lhs: &Value, rhs: &Value) -> bool {
+        let ord = match (lhs, rhs) {
+            (Value::U32(a), Value::U32(b)) => a.cmp(b),
+            (Value::U64(a), Value::U64(b)) => a.cmp(b),
+            (Value::I64(a), Value::I64(b)) => a.cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Bytes(a), Value::Bytes(b)) => a.cmp(b),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            _ => return false,
+        };
+        match self {
+            CmpOp::Eq => ord.is_eq(),
+            CmpOp::Ne => ord.is_ne(),
+            CmpOp::Lt => ord.is_lt(),
+            CmpOp::Le => ord.is_le(),
+            CmpOp::Gt => ord.is_gt(),
+            CmpOp::Ge => ord.is_ge(),
+        }
+    }
+}
+
+/// A row returned with its pk and a shared handle to its table's schema, so
+/// values can be read by column name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryRow {
+    pk: u32,
+    schema: Arc<Schema>,
+    values: Row,
+}
+
+impl QueryRow {
+    /// Wrap a decoded row of a table with `schema`.
+    pub fn new(pk: u32, schema: Arc<Schema>, values: Row) -> Self {
+        Self { pk, schema, values }
+    }
+
+    /// Primary key of the row.
+    pub fn pk(&self) -> u32 {
+        self.pk
+    }
+
+    /// Value of the column named `column`, or `None` if there is no such column.
+    pub fn get(&self, column: &str) -> Option<&Value> {
+        self.values.get(self.schema.column_index(column)?)
+    }
+
+    /// Schema the values follow.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Values in schema column order.
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// Take the values, dropping the schema handle.
+    pub fn into_values(self) -> Row {
+        self.values
+    }
+}
+
+/// Wire tag of [`Value::Null`].
+pub const TAG_NULL: u8 = 0x00;
+/// Wire tag of [`Value::U32`].
+pub const TAG_U32: u8 = 0x01;
+/// Wire tag of [`Value::U64`].
+pub const TAG_U64: u8 = 0x02;
+/// Wire tag of [`Value::I64`].
+pub const TAG_I64: u8 = 0x03;
+/// Wire tag of [`Value::Bool`].
+pub const TAG_BOOL: u8 = 0x04;
+/// Wire tag of [`Value::Bytes`].
+pub const TAG_BYTES: u8 = 0x05;
+/// Wire tag of [`Value::String`].
+pub const TAG_STRING: u8 = 0x06;
+
+/// Return the tag byte `value` is encoded with by [`encode_row`].
+pub fn value_tag(value: &Value) -> u8 {
+    match value {
+        Value::Null => TAG_NULL,
+        Value::U32(_) => TAG_U32,
+        Value::U64(_) => TAG_U64,
+        Value::I64(_) => TAG_I64,
+        Value::Bool(_) => TAG_BOOL,
+        Value::Bytes(_) => TAG_BYTES,
+        Value::String(_) => TAG_STRING,
+    }
+}
+
+impl Value {
+    /// Return the tag byte this value is encoded with; see [`value_tag`].
+    pub fn encoded_tag(&self) -> u8 {
+        value_tag(self)
+    }
+}
+
 const ROW_MAGIC: &[u8; 4] = b"ROW1";
 const MAX_VAR_LEN: usize = 1_048_576; // 1 MiB guard
 
+/// Derive a schema from a sample row, one column per name.
+///
+/// Each column takes the type of its sample value and is not nullable.
+/// # Errors
+/// - [`InvError::InvalidArgument`] if the counts differ, a sample is `Null`
+///   (its type is ambiguous), or the names do not form a valid schema.
+pub fn infer_schema(column_names: &[&str], row: &Row) -> InvResult<Schema> {
+    if column_names.len() != row.len() {
+        return Err(InvError::InvalidArgument {
+            name: "row",
+            details: format!("{} column names for {} values", column_names.len(), row.len()),
+        });
+    }
+    let mut columns = Vec::with_capacity(row.len());
+    for (name, value) in column_names.iter().zip(row) {
+        let ty = match value {
+            Value::Null => {
+                return Err(InvError::InvalidArgument {
+                    name: "row.null",
+                    details: format!("cannot infer a type for '{}' from Null", name),
+                })
+            }
+            Value::U32(_) => ColType::U32,
+            Value::U64(_) => ColType::U64,
+            Value::I64(_) => ColType::I64,
+            Value::Bool(_) => ColType::Bool,
+            Value::Bytes(_) => ColType::Bytes,
+            Value::String(_) => ColType::String,
+        };
+        columns.push(Column {
+            name: name.to_string(),
+            ty,
+            nullable: false,
+        });
+    }
+    Schema::new(columns)
+}
+
+/// Encode values as a composite index key whose byte order matches value order.
+///
+/// Each value is a tag byte followed by a self-delimiting payload: integers
+/// are big-endian (`I64` with its sign bit flipped), and `Bytes`/`String` use
+/// [`encoding::write_order_preserving`]. `Null` sorts before any other value,
+/// including an empty `Bytes` or `String`, and keys sharing a prefix compare
+/// on their next component.
+pub fn encode_index_key(values: &[Value]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for value in values {
+        out.push(value_tag(value));
+        match value {
+            Value::Null => {}
+            Value::U32(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Value::U64(v) => out.extend_from_slice(&v.to_be_bytes()),
+            Value::I64(v) => out.extend_from_slice(&((*v as u64) ^ (1 << 63)).to_be_bytes()),
+            Value::Bool(b) => out.push(*b as u8),
+            Value::Bytes(bytes) => encoding::write_order_preserving(&mut out, bytes),
+            Value::String(s) => encoding::write_order_preserving(&mut out, s.as_bytes()),
+        }
+    }
+    out
+}
+
 /// Encode a row according to the provided schema.
+///
+/// Every value is a tag byte followed by its payload. `Bytes` and `String`
+/// payloads are length-prefixed, so an empty value still carries its tag and a
+/// zero length and never decodes as `Null`, which is the tag alone.
+///
+/// A `String` containing a NUL byte is rejected with `row.string_nul` when
+/// its column is set by [`Schema::reject_interior_nul`].
 pub fn encode_row(schema: &Schema, row: &Row) -> InvResult<Vec<u8>> {
     if schema.len() != row.len() {
         return Err(InvError::InvalidArgument {
@@ -48,31 +233,37 @@ pub fn encode_row(schema: &Schema, row: &Row) -> InvResult<Vec<u8>> {
                 });
             }
             (ColType::U32, Value::U32(v)) => {
-                out.push(0x01);
+                out.push(TAG_U32);
                 encoding::write_u32_le(&mut out, *v);
             }
             (ColType::U64, Value::U64(v)) => {
-                out.push(0x02);
+                out.push(TAG_U64);
                 encoding::write_u64_le(&mut out, *v);
             }
             (ColType::I64, Value::I64(v)) => {
-                out.push(0x03);
+                out.push(TAG_I64);
                 out.extend_from_slice(&v.to_le_bytes());
             }
             (ColType::Bool, Value::Bool(b)) => {
-                out.push(0x04);
+                out.push(TAG_BOOL);
                 out.push(if *b { 1 } else { 0 });
             }
             (ColType::Bytes, Value::Bytes(bytes)) => {
-                out.push(0x05);
+                out.push(TAG_BYTES);
                 encoding::write_bytes(&mut out, bytes);
             }
             (ColType::String, Value::String(s)) => {
-                out.push(0x06);
+                if schema.rejects_interior_nul(idx) && s.contains('\0') {
+                    return Err(InvError::InvalidArgument {
+                        name: "row.string_nul",
+                        details: format!("column '{}' rejects NUL bytes", col.name),
+                    });
+                }
+                out.push(TAG_STRING);
                 encoding::write_string(&mut out, s);
             }
             (_, Value::Null) => {
-                out.push(0x00);
+                out.push(TAG_NULL);
             }
             _ => {
                 return Err(InvError::InvalidArgument {
@@ -86,8 +277,71 @@ pub fn encode_row(schema: &Schema, row: &Row) -> InvResult<Vec<u8>> {
     Ok(out)
 }
 
+/// Number of bytes `value` occupies in [`encode_row`] output (tag included).
+pub(crate) fn encoded_value_len(value: &Value) -> usize {
+    let var_len = |n: usize| {
+        let mut prefix = Vec::new();
+        encoding::write_var_u64(&mut prefix, n as u64);
+        1 + prefix.len() + n
+    };
+    match value {
+        Value::Null => 1,
+        Value::U32(_) => 5,
+        Value::U64(_) | Value::I64(_) => 9,
+        Value::Bool(_) => 2,
+        Value::Bytes(b) => var_len(b.len()),
+        Value::String(s) => var_len(s.len()),
+    }
+}
+
 /// Decode bytes into a row according to the schema.
 pub fn decode_row(schema: &Schema, bytes: &[u8]) -> InvResult<Row> {
+    let mut row = Vec::with_capacity(schema.len());
+    decode_row_reuse(schema, bytes, &mut row)?;
+    Ok(row)
+}
+
+/// Decode bytes into a caller-provided row, reusing its allocations.
+///
+/// `out` is overwritten with the decoded values. Its capacity is kept, and the
+/// buffers of existing `Bytes`/`String` values are recycled when a value of the
+/// same variant is decoded at the same position. On error the contents of
+/// `out` are unspecified.
+pub fn decode_row_reuse(schema: &Schema, bytes: &[u8], out: &mut Row) -> InvResult<()> {
+    decode_values(schema, bytes, out, false, schema.len())
+}
+
+/// [`decode_row_reuse`] for rows that may carry values of dropped trailing columns.
+pub(crate) fn decode_row_reuse_lenient(schema: &Schema, bytes: &[u8], out: &mut Row) -> InvResult<()> {
+    decode_values(schema, bytes, out, true, schema.len())
+}
+
+/// Decode only the first `columns` values of a row into `out`, leaving the
+/// rest of the bytes unread and unchecked.
+pub(crate) fn decode_row_prefix(schema: &Schema, bytes: &[u8], columns: usize, out: &mut Row) -> InvResult<()> {
+    decode_values(schema, bytes, out, true, columns.min(schema.len()))
+}
+
+/// Decode a row that may still carry values for columns since dropped from
+/// the end of the schema.
+///
+/// The first `schema.len()` values are decoded as usual; any further stored
+/// values are checked for well-formedness and discarded.
+pub fn decode_row_lenient(schema: &Schema, bytes: &[u8]) -> InvResult<Row> {
+    let mut row = Vec::with_capacity(schema.len());
+    decode_values(schema, bytes, &mut row, true, schema.len())?;
+    Ok(row)
+}
+
+/// Number of values an encoded row was written with, read from its header
+/// without decoding any value.
+pub(crate) fn stored_column_count(bytes: &[u8]) -> InvResult<usize> {
+    read_row_header(bytes).map(|(count, _)| count)
+}
+
+/// Check the row magic and read the value count, returning it with the
+/// offset of the first value.
+fn read_row_header(bytes: &[u8]) -> InvResult<(usize, usize)> {
     if bytes.len() < ROW_MAGIC.len() {
         return Err(InvError::Corruption {
             context: "row.magic",
@@ -101,26 +355,44 @@ pub fn decode_row(schema: &Schema, bytes: &[u8]) -> InvResult<Row> {
         });
     }
     let mut pos = 4;
-    let col_count = encoding::read_var_u64(bytes, &mut pos)? as usize;
-    if col_count != schema.len() {
+    let col_count = encoding::read_var_u64(bytes, &mut pos).map_err(at_offset(4))? as usize;
+    Ok((col_count, pos))
+}
+
+/// Decode the values of the first `columns` schema columns; the rest of the
+/// row is only skipped and checked when every column is decoded.
+fn decode_values(
+    schema: &Schema,
+    bytes: &[u8],
+    out: &mut Row,
+    allow_trailing: bool,
+    columns: usize,
+) -> InvResult<()> {
+    let (col_count, mut pos) = read_row_header(bytes)?;
+    if col_count != schema.len() && !(allow_trailing && col_count > schema.len()) {
         return Err(InvError::Corruption {
             context: "row.column_count",
-            details: format!("expected {} got {}", schema.len(), col_count),
+            details: format!("expected {} got {} at offset 4", schema.len(), col_count),
         });
     }
 
-    let mut row = Vec::with_capacity(col_count);
-    for col in &schema.columns {
+    out.truncate(columns);
+    for (idx, col) in schema.columns.iter().enumerate().take(columns) {
         if pos >= bytes.len() {
             return Err(InvError::Corruption {
                 context: "row.tag",
-                details: "unexpected eof reading tag".to_string(),
+                details: format!("unexpected eof reading tag at offset {}", pos),
             });
         }
+        let recycled = match out.get_mut(idx) {
+            Some(slot) => std::mem::replace(slot, Value::Null),
+            None => Value::Null,
+        };
+        let tag_pos = pos;
         let tag = bytes[pos];
         pos += 1;
         let value = match tag {
-            0x00 => {
+            TAG_NULL => {
                 if !col.nullable {
                     return Err(InvError::InvalidArgument {
                         name: "row.null",
@@ -129,23 +401,23 @@ pub fn decode_row(schema: &Schema, bytes: &[u8]) -> InvResult<Row> {
                 }
                 Value::Null
             }
-            0x01 => {
-                let v = encoding::read_u32_le(bytes, &mut pos)?;
+            TAG_U32 => {
+                let v = encoding::read_u32_le(bytes, &mut pos).map_err(at_offset(pos))?;
                 Value::U32(v)
             }
-            0x02 => {
-                let v = encoding::read_u64_le(bytes, &mut pos)?;
+            TAG_U64 => {
+                let v = encoding::read_u64_le(bytes, &mut pos).map_err(at_offset(pos))?;
                 Value::U64(v)
             }
-            0x03 => {
-                let v = encoding::read_u64_le(bytes, &mut pos)?;
+            TAG_I64 => {
+                let v = encoding::read_u64_le(bytes, &mut pos).map_err(at_offset(pos))?;
                 Value::I64(i64::from_le_bytes(v.to_le_bytes()))
             }
-            0x04 => {
+            TAG_BOOL => {
                 if pos >= bytes.len() {
                     return Err(InvError::Corruption {
                         context: "row.bool",
-                        details: "missing bool payload".to_string(),
+                        details: format!("missing bool payload at offset {}", pos),
                     });
                 }
                 let b = bytes[pos];
@@ -156,23 +428,40 @@ pub fn decode_row(schema: &Schema, bytes: &[u8]) -> InvResult<Row> {
                     _ => {
                         return Err(InvError::Corruption {
                             context: "row.bool",
-                            details: format!("invalid bool byte {}", b),
+                            details: format!("invalid bool byte {} at offset {}", b, pos - 1),
                         })
                     }
                 }
             }
-            0x05 => {
-                let data = encoding::read_bytes(bytes, &mut pos, MAX_VAR_LEN)?;
-                Value::Bytes(data)
+            TAG_BYTES => {
+                let data = encoding::read_bytes_ref(bytes, &mut pos, MAX_VAR_LEN).map_err(at_offset(pos))?;
+                let mut buf = match recycled {
+                    Value::Bytes(buf) => buf,
+                    _ => Vec::new(),
+                };
+                buf.clear();
+                buf.extend_from_slice(data);
+                Value::Bytes(buf)
             }
-            0x06 => {
-                let s = encoding::read_string(bytes, &mut pos, MAX_VAR_LEN)?;
-                Value::String(s)
+            TAG_STRING => {
+                let start = pos;
+                let data = encoding::read_bytes_ref(bytes, &mut pos, MAX_VAR_LEN).map_err(at_offset(start))?;
+                let s = std::str::from_utf8(data).map_err(|e| InvError::Corruption {
+                    context: "encoding.string.utf8",
+                    details: format!("{} in string at offset {}", e, start),
+                })?;
+                let mut buf = match recycled {
+                    Value::String(buf) => buf,
+                    _ => String::new(),
+                };
+                buf.clear();
+                buf.push_str(s);
+                Value::String(buf)
             }
             _ => {
                 return Err(InvError::Corruption {
                     context: "row.tag",
-                    details: format!("unknown tag {}", tag),
+                    details: format!("unknown tag {} at offset {}", tag, tag_pos),
                 })
             }
         };
@@ -189,20 +478,128 @@ pub fn decode_row(schema: &Schema, bytes: &[u8]) -> InvResult<Row> {
             _ => {
                 return Err(InvError::Corruption {
                     context: "row.type",
-                    details: format!("decoded value does not match schema for '{}'", col.name),
+                    details: format!("decoded value does not match schema for '{}' at offset {}", col.name, tag_pos),
                 });
             }
         }
 
-        row.push(value);
+        match out.get_mut(idx) {
+            Some(slot) => *slot = value,
+            None => out.push(value),
+        }
+    }
+
+    if columns < schema.len() {
+        return Ok(());
+    }
+    for _ in schema.len()..col_count {
+        skip_value(bytes, &mut pos)?;
     }
 
     if pos != bytes.len() {
         return Err(InvError::Corruption {
             context: "row.trailing",
-            details: "extra trailing bytes".to_string(),
+            details: format!("extra trailing bytes at offset {}", pos),
         });
     }
 
-    Ok(row)
+    Ok(())
+}
+
+/// Append the byte offset a decode step started at to a corruption error.
+fn at_offset(pos: usize) -> impl Fn(InvError) -> InvError {
+    move |e| match e {
+        InvError::Corruption { context, details } => InvError::Corruption {
+            context,
+            details: format!("{} at offset {}", details, pos),
+        },
+        other => other,
+    }
+}
+
+/// Skip over one encoded value without a schema, using its tag alone.
+fn skip_value(bytes: &[u8], pos: &mut usize) -> InvResult<()> {
+    let tag_pos = *pos;
+    let tag = *bytes.get(*pos).ok_or(InvError::Corruption {
+        context: "row.tag",
+        details: format!("unexpected eof reading tag at offset {}", tag_pos),
+    })?;
+    *pos += 1;
+    match tag {
+        TAG_NULL => {}
+        TAG_U32 => {
+            encoding::read_u32_le(bytes, pos).map_err(at_offset(tag_pos + 1))?;
+        }
+        TAG_U64 | TAG_I64 => {
+            encoding::read_u64_le(bytes, pos).map_err(at_offset(tag_pos + 1))?;
+        }
+        TAG_BOOL => match bytes.get(*pos) {
+            Some(0) | Some(1) => *pos += 1,
+            Some(b) => {
+                return Err(InvError::Corruption {
+                    context: "row.bool",
+                    details: format!("invalid bool byte {} at offset {}", b, tag_pos + 1),
+                })
+            }
+            None => {
+                return Err(InvError::Corruption {
+                    context: "row.bool",
+                    details: format!("missing bool payload at offset {}", tag_pos + 1),
+                })
+            }
+        },
+        TAG_BYTES | TAG_STRING => {
+            encoding::read_bytes_ref(bytes, pos, MAX_VAR_LEN).map_err(at_offset(tag_pos + 1))?;
+        }
+        _ => {
+            return Err(InvError::Corruption {
+                context: "row.tag",
+                details: format!("unknown tag {} at offset {}", tag, tag_pos),
+            })
+        }
+    }
+    Ok(())
+}
+
+/// Strictly re-verify a decoded row against its schema and stored bytes.
+///
+/// Beyond the checks in [`decode_row`], this asserts the value count, the
+/// exact type and nullability of every value, and that the stored bytes are
+/// the canonical encoding of the row (e.g. no overlong varints).
+pub fn verify_row_strict(schema: &Schema, row: &Row, bytes: &[u8]) -> InvResult<()> {
+    if row.len() != schema.len() {
+        return Err(InvError::Corruption {
+            context: "row.strict",
+            details: format!("expected {} values got {}", schema.len(), row.len()),
+        });
+    }
+    for (col, val) in schema.columns.iter().zip(row.iter()) {
+        let ok = match (&col.ty, val) {
+            (_, Value::Null) => col.nullable,
+            (ColType::U32, Value::U32(_))
+            | (ColType::U64, Value::U64(_))
+            | (ColType::I64, Value::I64(_))
+            | (ColType::Bool, Value::Bool(_))
+            | (ColType::Bytes, Value::Bytes(_))
+            | (ColType::String, Value::String(_)) => true,
+            _ => false,
+        };
+        if !ok {
+            return Err(InvError::Corruption {
+                context: "row.strict",
+                details: format!("value does not match column '{}'", col.name),
+            });
+        }
+    }
+    let canonical = encode_row(schema, row).map_err(|e| InvError::Corruption {
+        context: "row.strict",
+        details: e.to_string(),
+    })?;
+    if canonical != bytes {
+        return Err(InvError::Corruption {
+            context: "row.strict",
+            details: "stored bytes are not the canonical encoding".to_string(),
+        });
+    }
+    Ok(())
 }
@@ -0,0 +1,214 @@
+//! Engine-generated 16-byte row keys.
+//!
+//! A table created with [`crate::Db::create_table_with_uuid`] has a
+//! non-nullable `Bytes` column named [`UUID_COLUMN`] that the engine fills
+//! with a random key on insert. Keys are indexed in the table's own btree,
+//! keyed by their first four bytes and mapping to the row's pk: generation
+//! retries until that prefix is unused, so a prefix names one row, and reads
+//! confirm the whole key against the stored column.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::btree;
+use crate::catalog::{Catalog, TableDef, TableId};
+use crate::error::{InvError, InvResult};
+use crate::options::LargeRowPolicy;
+use crate::pager::Pager;
+use crate::row::{Row, Value};
+use crate::schema::{ColType, Schema};
+use crate::table;
+use crate::types::PageId;
+
+/// Column holding a row's generated key.
+pub const UUID_COLUMN: &str = "id";
+
+/// Create a table whose rows get a generated key in [`UUID_COLUMN`].
+/// # Errors
+/// - [`InvError::InvalidArgument`] with name `uuid_column` if the schema has
+///   no non-nullable `Bytes` column named [`UUID_COLUMN`], or as
+///   [`Catalog::create_table`].
+pub(crate) fn create_table_with_uuid(
+    pager: &mut Pager,
+    catalog: &mut Catalog,
+    name: &str,
+    schema: &Schema,
+) -> InvResult<TableId> {
+    uuid_column_index(schema)?;
+    let id = catalog.create_table_with_uuid(name, schema, 0)?;
+    let root = pager.allocate_btree_page()?;
+    if let Some(table) = catalog.tables.last_mut() {
+        table.uuid_root = Some(root.0);
+    }
+    Ok(id)
+}
+
+/// Insert `row`, whose [`UUID_COLUMN`] must be `Null`, under a freshly
+/// generated key and return the key.
+pub(crate) fn insert_row_uuid(
+    pager: &mut Pager,
+    catalog: &mut Catalog,
+    table_name: &str,
+    row: &Row,
+    policy: LargeRowPolicy,
+) -> InvResult<[u8; 16]> {
+    let table = table::find_table_mut(catalog, table_name)?;
+    let root = uuid_root(table)?;
+    let idx = uuid_column_index(&table.schema)?;
+    if row.get(idx) != Some(&Value::Null) {
+        return Err(InvError::InvalidArgument {
+            name: "row",
+            details: format!("column {} must be Null; its key is generated", UUID_COLUMN),
+        });
+    }
+    let key = loop {
+        let key = random_key();
+        if btree::search::search_u64(pager, root, key_prefix(&key))?.is_none() {
+            break key;
+        }
+    };
+    let mut keyed = row.clone();
+    keyed[idx] = Value::Bytes(key.to_vec());
+    let pk = table::insert_into_table_with_policy(pager, table, &keyed, policy)?;
+    let root = btree::insert::insert_u64(pager, root, key_prefix(&key), pk as u64)?;
+    table.uuid_root = Some(root.0);
+    Ok(key)
+}
+
+/// Fetch the row stored under a generated key.
+pub(crate) fn get_row_by_uuid(pager: &mut Pager, table: &TableDef, key: &[u8; 16], strict: bool) -> InvResult<Option<Row>> {
+    let root = uuid_root(table)?;
+    let idx = uuid_column_index(&table.schema)?;
+    let Some(pk) = btree::search::search_u64(pager, root, key_prefix(key))? else {
+        return Ok(None);
+    };
+    let pk = u32::try_from(pk).map_err(|_| InvError::Corruption {
+        context: "uuid.pk",
+        details: format!("key index holds pk {}", pk),
+    })?;
+    let Some(row) = table::fetch_row(pager, table, pk, strict)? else {
+        return Ok(None);
+    };
+    match &row[idx] {
+        Value::Bytes(stored) if stored[..] == key[..] => Ok(Some(row)),
+        Value::Bytes(stored) if stored.len() == 16 => Ok(None),
+        _ => Err(InvError::Corruption {
+            context: "uuid.key",
+            details: format!("row {} holds no 16-byte key", pk),
+        }),
+    }
+}
+
+/// Drop the key index entry of a row about to be deleted from `table`.
+pub(crate) fn forget_row_key(pager: &mut Pager, table: &mut TableDef, pk: u32, row: &Row) -> InvResult<()> {
+    let Some(root) = table.uuid_root else {
+        return Ok(());
+    };
+    let Some(Value::Bytes(key)) = uuid_column_index(&table.schema).ok().and_then(|idx| row.get(idx)) else {
+        return Ok(());
+    };
+    let Some(prefix) = key.get(0..4) else {
+        return Ok(());
+    };
+    let prefix = u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]);
+    if btree::search::search_u64(pager, PageId(root), prefix)? == Some(pk as u64) {
+        let (root, _) = btree::delete::delete_u64(pager, PageId(root), prefix)?;
+        table.uuid_root = Some(root.0);
+    }
+    Ok(())
+}
+
+/// Insert `row`, whose [`UUID_COLUMN`] already holds a generated key, such
+/// as one copied from another database, under a fresh pk and index the key.
+/// # Errors
+/// - [`InvError::InvalidArgument`] if the row holds no 16-byte key or the
+///   key's prefix already names a row of `table`.
+pub(crate) fn insert_row_keyed(pager: &mut Pager, table: &mut TableDef, row: &Row) -> InvResult<u32> {
+    let prefix = check_row_key(pager, table, row)?;
+    let pk = table::insert_into_table_with_policy(pager, table, row, LargeRowPolicy::Error)?;
+    let root = btree::insert::insert_u64(pager, uuid_root(table)?, prefix, pk as u64)?;
+    table.uuid_root = Some(root.0);
+    Ok(pk)
+}
+
+/// Check that [`insert_row_keyed`] would accept `row`, returning the
+/// prefix its key is indexed under.
+/// # Errors
+/// - As [`insert_row_keyed`].
+pub(crate) fn check_row_key(pager: &mut Pager, table: &TableDef, row: &Row) -> InvResult<u32> {
+    let root = uuid_root(table)?;
+    let prefix = row_key_prefix(&table.schema, row).ok_or(InvError::InvalidArgument {
+        name: "row",
+        details: format!("column {} must hold a 16-byte key", UUID_COLUMN),
+    })?;
+    if btree::search::search_u64(pager, root, prefix)?.is_some() {
+        return Err(InvError::InvalidArgument {
+            name: "row",
+            details: format!("a row of {} already has a key with prefix {:#010x}", table.name, prefix),
+        });
+    }
+    Ok(prefix)
+}
+
+/// Index `(prefix, pk)` pairs of rows already stored in `table` into its
+/// empty key btree.
+pub(crate) fn index_keys(pager: &mut Pager, table: &mut TableDef, mut keys: Vec<(u32, u64)>) -> InvResult<()> {
+    let root = uuid_root(table)?;
+    keys.sort_unstable_by_key(|&(prefix, _)| prefix);
+    table.uuid_root = Some(btree::bulk_load_u64(pager, root, &keys)?.0);
+    Ok(())
+}
+
+/// Btree key of the generated key `row` holds, if it holds one.
+pub(crate) fn row_key_prefix(schema: &Schema, row: &Row) -> Option<u32> {
+    match row.get(uuid_column_index(schema).ok()?)? {
+        Value::Bytes(key) if key.len() == 16 => Some(key_prefix(key)),
+        _ => None,
+    }
+}
+
+/// Btree key a generated key is indexed under.
+pub(crate) fn key_prefix(key: &[u8]) -> u32 {
+    u32::from_le_bytes([key[0], key[1], key[2], key[3]])
+}
+
+fn uuid_root(table: &TableDef) -> InvResult<PageId> {
+    table.uuid_root.map(PageId).ok_or(InvError::InvalidArgument {
+        name: "table",
+        details: format!("table {} does not generate row keys", table.name),
+    })
+}
+
+fn uuid_column_index(schema: &Schema) -> InvResult<usize> {
+    schema
+        .column_index(UUID_COLUMN)
+        .filter(|&idx| schema.columns[idx].ty == ColType::Bytes && !schema.columns[idx].nullable)
+        .ok_or(InvError::InvalidArgument {
+            name: "uuid_column",
+            details: format!("schema needs a non-nullable Bytes column named {}", UUID_COLUMN),
+        })
+}
+
+/// 16 random bytes laid out like a version 4 UUID.
+///
+/// Seeded from the standard library's per-process random hasher keys, the
+/// clock and a counter; unpredictable enough to hide row counts, not meant
+/// for cryptographic use.
+fn random_key() -> [u8; 16] {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let state = RandomState::new();
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    let mut key = [0u8; 16];
+    for (half, chunk) in key.chunks_mut(8).enumerate() {
+        let mut hasher = state.build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u128(nanos);
+        hasher.write_usize(half);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    key[6] = (key[6] & 0x0f) | 0x40;
+    key[8] = (key[8] & 0x3f) | 0x80;
+    key
+}
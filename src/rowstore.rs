@@ -1,10 +1,19 @@
 //! Row storage primitives for appending and reading variable-length rows.
 
-use crate::config::{PAGE_SIZE, ROW_PAGE_KIND};
+use crate::config::{PAGE_SIZE, ROW_PAGE_FORMAT_VERSION, ROW_PAGE_KIND};
+use crate::encoding::crc32;
 use crate::error::{InvError, InvResult};
+use crate::layout::{
+    read_u16_le, read_u32_le, ROW_PAGE_DATA, ROW_PAGE_FREE_OFFSET, ROW_PAGE_MAGIC, ROW_PAGE_NEXT,
+    ROW_PAGE_RESERVED2, ROW_PAGE_VERSION, ROW_SLOT_CRC_FLAG, ROW_SLOT_CRC_LEN,
+};
+use crate::page::Page;
 use crate::pager::Pager;
 use crate::types::PageId;
 
+/// Largest stored row (pk prefix included) that fits inline in a row page.
+pub const MAX_INLINE_ROW: usize = 3500;
+
 /// Pointer to a stored row (page, offset, length).
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct RowPtr {
@@ -36,7 +45,7 @@ impl RowPtr {
                 details: "page_id is 0".to_string(),
             });
         }
-        if self.offset < 32 {
+        if (self.offset as usize) < ROW_PAGE_DATA {
             return Err(InvError::Corruption {
                 context: "rowptr.invalid",
                 details: format!("offset {} too small", self.offset),
@@ -64,12 +73,35 @@ pub struct RowStore;
 
 impl RowStore {
     /// Append a row and return its pointer and updated last_row_page value.
+    ///
+    /// A page allocated because `table_last_row_page` is full is linked from
+    /// it, see [`RowStore::next_row_page`].
     pub fn append_row(
         pager: &mut Pager,
         table_last_row_page: u32,
         row_bytes: &[u8],
     ) -> InvResult<(RowPtr, u32)> {
-        if row_bytes.len() > 3500 {
+        Self::append_slot(pager, table_last_row_page, row_bytes, false)
+    }
+
+    /// Append a row prefixed with its CRC-32, verified by every [`RowStore::read_row`].
+    ///
+    /// The pointer covers the checksum, so its `len` is 4 more than the row's.
+    pub fn append_row_with_crc(
+        pager: &mut Pager,
+        table_last_row_page: u32,
+        row_bytes: &[u8],
+    ) -> InvResult<(RowPtr, u32)> {
+        Self::append_slot(pager, table_last_row_page, row_bytes, true)
+    }
+
+    fn append_slot(
+        pager: &mut Pager,
+        table_last_row_page: u32,
+        row_bytes: &[u8],
+        with_crc: bool,
+    ) -> InvResult<(RowPtr, u32)> {
+        if row_bytes.len() > MAX_INLINE_ROW {
             return Err(InvError::Unsupported {
                 feature: "row.too_large",
             });
@@ -81,18 +113,21 @@ impl RowStore {
             table_last_row_page
         };
 
+        let slot_len = row_bytes.len() + if with_crc { ROW_SLOT_CRC_LEN } else { 0 };
         // Try appending to current page; if not enough space, allocate new.
         {
             let free_offset = Self::read_free_offset(pager, PageId(target_page_id))?;
-            let needed = 2 + row_bytes.len();
+            let needed = 2 + slot_len;
             if (free_offset as usize + needed) > PAGE_SIZE {
+                let full = PageId(target_page_id);
                 target_page_id = pager.allocate_row_page()?.0;
+                Self::set_next_row_page(pager, full, target_page_id)?;
             }
         }
 
         let page_id = PageId(target_page_id);
         let free_offset = Self::read_free_offset(pager, page_id)?;
-        let needed = 2 + row_bytes.len();
+        let needed = 2 + slot_len;
         if (free_offset as usize + needed) > PAGE_SIZE {
             return Err(InvError::Corruption {
                 context: "rowpage.free_offset",
@@ -103,16 +138,20 @@ impl RowStore {
         let page = pager.get_page_mut(page_id)?;
         let buf = page.as_bytes_mut();
         // Write length
-        let len_u16: u16 = row_bytes
-            .len()
+        let len_u16: u16 = slot_len
             .try_into()
             .map_err(|_| InvError::Unsupported {
                 feature: "row.too_large",
             })?;
+        let len_field = if with_crc { len_u16 | ROW_SLOT_CRC_FLAG } else { len_u16 };
         buf[free_offset as usize..free_offset as usize + 2]
-            .copy_from_slice(&len_u16.to_le_bytes());
-        // Write row bytes
-        let row_start = free_offset as usize + 2;
+            .copy_from_slice(&len_field.to_le_bytes());
+        // Write checksum and row bytes
+        let mut row_start = free_offset as usize + 2;
+        if with_crc {
+            buf[row_start..row_start + ROW_SLOT_CRC_LEN].copy_from_slice(&crc32(row_bytes).to_le_bytes());
+            row_start += ROW_SLOT_CRC_LEN;
+        }
         buf[row_start..row_start + row_bytes.len()].copy_from_slice(row_bytes);
 
         let new_free = free_offset as usize + needed;
@@ -120,21 +159,41 @@ impl RowStore {
 
         let ptr = RowPtr {
             page_id: page_id.0,
-            offset: (free_offset + 2) as u16,
+            offset: free_offset + 2,
             len: len_u16,
         };
         Ok((ptr, page_id.0))
     }
 
     /// Read row bytes from a pointer.
+    ///
+    /// A checksummed slot is verified and returned without its checksum.
+    /// # Errors
+    /// - [`InvError::Corruption`] with context `row.crc` if the checksum does
+    ///   not match the stored bytes.
     pub fn read_row(pager: &mut Pager, ptr: RowPtr) -> InvResult<Vec<u8>> {
         ptr.validate()?;
-        let page = pager.get_page(PageId(ptr.page_id))?;
+        Self::read_row_in(pager.get_page(PageId(ptr.page_id))?, ptr)
+    }
+
+    /// [`RowStore::read_row`] from an already loaded image of the row's page,
+    /// such as its on-disk copy.
+    pub(crate) fn read_row_in(page: &Page, ptr: RowPtr) -> InvResult<Vec<u8>> {
+        let (with_crc, slot) = Self::read_slot(page, ptr)?;
+        if with_crc {
+            return verify_slot_crc(ptr, &slot).map(|row| row.to_vec());
+        }
+        Ok(slot)
+    }
+
+    /// Read a slot's raw bytes and whether they start with a checksum.
+    fn read_slot(page: &Page, ptr: RowPtr) -> InvResult<(bool, Vec<u8>)> {
+        ptr.validate()?;
         let buf = page.as_bytes();
-        if buf.get(0) != Some(&ROW_PAGE_KIND) {
+        if buf.first() != Some(&ROW_PAGE_KIND) {
             return Err(InvError::Corruption {
                 context: "rowpage.kind",
-                details: format!("expected {} got {}", ROW_PAGE_KIND, buf.get(0).copied().unwrap_or(255)),
+                details: format!("expected {} got {}", ROW_PAGE_KIND, buf.first().copied().unwrap_or(255)),
             });
         }
         page.validate_header()?;
@@ -150,7 +209,8 @@ impl RowStore {
                 details: "length field out of bounds".to_string(),
             });
         }
-        let stored_len = u16::from_le_bytes([buf[len_offset], buf[len_offset + 1]]);
+        let len_field = u16::from_le_bytes([buf[len_offset], buf[len_offset + 1]]);
+        let stored_len = len_field & !ROW_SLOT_CRC_FLAG;
         if stored_len != ptr.len {
             return Err(InvError::Corruption {
                 context: "rowpage.len_mismatch",
@@ -165,22 +225,172 @@ impl RowStore {
                 details: "row extends beyond page".to_string(),
             });
         }
-        Ok(buf[start..end].to_vec())
+        Ok((len_field & ROW_SLOT_CRC_FLAG != 0, buf[start..end].to_vec()))
     }
 
-    fn read_free_offset(pager: &mut Pager, page_id: PageId) -> InvResult<u16> {
+    /// Rewrite a row page keeping only the `live` slots, packed from the payload start.
+    ///
+    /// Returns the relocated pointer for each entry of `live`, in the same order.
+    pub fn compact_page(pager: &mut Pager, page_id: PageId, live: &[RowPtr]) -> InvResult<Vec<RowPtr>> {
+        Self::read_free_offset(pager, page_id)?;
+        let mut rows = Vec::with_capacity(live.len());
+        for ptr in live {
+            if ptr.page_id != page_id.0 {
+                return Err(InvError::InvalidArgument {
+                    name: "row_ptr",
+                    details: format!("pointer to page {} while compacting {}", ptr.page_id, page_id.0),
+                });
+            }
+            let (with_crc, slot) = Self::read_slot(pager.get_page(page_id)?, *ptr)?;
+            if with_crc {
+                verify_slot_crc(*ptr, &slot)?;
+            }
+            rows.push((with_crc, slot));
+        }
+
+        // Keep surviving rows in their original relative order.
+        let mut order: Vec<usize> = (0..live.len()).collect();
+        order.sort_by_key(|&i| live[i].offset);
+
+        let page = pager.get_page_mut(page_id)?;
+        let buf = page.as_bytes_mut();
+        for b in &mut buf[ROW_PAGE_DATA..] {
+            *b = 0;
+        }
+        let mut moved = live.to_vec();
+        let mut free = ROW_PAGE_DATA;
+        let mut prev: Option<(u16, RowPtr)> = None;
+        for i in order {
+            if let Some((old_offset, new_ptr)) = prev {
+                if old_offset == live[i].offset {
+                    moved[i] = new_ptr;
+                    continue;
+                }
+            }
+            let (with_crc, row) = &rows[i];
+            let len_u16 = live[i].len;
+            let len_field = if *with_crc { len_u16 | ROW_SLOT_CRC_FLAG } else { len_u16 };
+            buf[free..free + 2].copy_from_slice(&len_field.to_le_bytes());
+            buf[free + 2..free + 2 + row.len()].copy_from_slice(row);
+            let new_ptr = RowPtr {
+                page_id: page_id.0,
+                offset: (free + 2) as u16,
+                len: len_u16,
+            };
+            moved[i] = new_ptr;
+            prev = Some((live[i].offset, new_ptr));
+            free += 2 + row.len();
+        }
+        Self::write_free_offset(page, free as u16)?;
+        Ok(moved)
+    }
+
+    /// List every slot of a row page, dead or alive, in on-page order.
+    ///
+    /// Checksummed slots are verified and listed without their checksum.
+    pub fn page_rows(pager: &mut Pager, page_id: PageId) -> InvResult<Vec<(RowPtr, Vec<u8>)>> {
+        let free = Self::read_free_offset(pager, page_id)? as usize;
+        let buf = pager.get_page(page_id)?.as_bytes();
+        let mut rows = Vec::new();
+        let mut pos = ROW_PAGE_DATA;
+        while pos < free {
+            if pos + 2 > free {
+                return Err(InvError::Corruption {
+                    context: "rowpage.len_mismatch",
+                    details: format!("truncated slot length at {}", pos),
+                });
+            }
+            let len_field = u16::from_le_bytes([buf[pos], buf[pos + 1]]);
+            let len = len_field & !ROW_SLOT_CRC_FLAG;
+            let start = pos + 2;
+            let end = start + len as usize;
+            if len == 0 || end > free {
+                return Err(InvError::Corruption {
+                    context: "rowpage.len_mismatch",
+                    details: format!("slot at {} with length {} overruns free offset {}", pos, len, free),
+                });
+            }
+            let ptr = RowPtr {
+                page_id: page_id.0,
+                offset: start as u16,
+                len,
+            };
+            let slot = &buf[start..end];
+            let row = if len_field & ROW_SLOT_CRC_FLAG != 0 {
+                verify_slot_crc(ptr, slot)?
+            } else {
+                slot
+            };
+            rows.push((ptr, row.to_vec()));
+            pos = end;
+        }
+        Ok(rows)
+    }
+
+    /// Replace the contents of a row page with `rows`, packed in the given order.
+    ///
+    /// Returns the new pointer of each row.
+    pub fn rewrite_page(pager: &mut Pager, page_id: PageId, rows: &[&[u8]]) -> InvResult<Vec<RowPtr>> {
+        Self::read_free_offset(pager, page_id)?;
+        let needed: usize = rows.iter().map(|r| 2 + r.len()).sum();
+        if ROW_PAGE_DATA + needed > PAGE_SIZE {
+            return Err(InvError::InvalidArgument {
+                name: "rows",
+                details: format!("{} bytes do not fit in a row page", needed),
+            });
+        }
+        let page = pager.get_page_mut(page_id)?;
+        let buf = page.as_bytes_mut();
+        for b in &mut buf[ROW_PAGE_DATA..] {
+            *b = 0;
+        }
+        let mut ptrs = Vec::with_capacity(rows.len());
+        let mut free = ROW_PAGE_DATA;
+        for row in rows {
+            let len_u16: u16 = row.len().try_into().map_err(|_| InvError::Unsupported {
+                feature: "row.too_large",
+            })?;
+            buf[free..free + 2].copy_from_slice(&len_u16.to_le_bytes());
+            buf[free + 2..free + 2 + row.len()].copy_from_slice(row);
+            ptrs.push(RowPtr {
+                page_id: page_id.0,
+                offset: (free + 2) as u16,
+                len: len_u16,
+            });
+            free += 2 + row.len();
+        }
+        Self::write_free_offset(page, free as u16)?;
+        Ok(ptrs)
+    }
+
+    /// The row page appended after `page_id` by the same owner, or 0 for the
+    /// owner's last page.
+    pub fn next_row_page(pager: &mut Pager, page_id: PageId) -> InvResult<u32> {
+        Self::read_free_offset(pager, page_id)?;
+        Ok(read_u32_le(pager.get_page(page_id)?.as_bytes(), ROW_PAGE_NEXT))
+    }
+
+    /// Point `page_id`'s next pointer at `next`, 0 to end the chain.
+    pub(crate) fn set_next_row_page(pager: &mut Pager, page_id: PageId, next: u32) -> InvResult<()> {
+        Self::read_free_offset(pager, page_id)?;
+        let buf = pager.get_page_mut(page_id)?.as_bytes_mut();
+        buf[ROW_PAGE_NEXT..ROW_PAGE_NEXT + 4].copy_from_slice(&next.to_le_bytes());
+        Ok(())
+    }
+
+    pub(crate) fn read_free_offset(pager: &mut Pager, page_id: PageId) -> InvResult<u16> {
         let page = pager.get_page(page_id)?;
         let buf = page.as_bytes();
-        if buf.get(0) != Some(&ROW_PAGE_KIND) {
+        if buf.first() != Some(&ROW_PAGE_KIND) {
             return Err(InvError::Corruption {
                 context: "rowpage.kind",
-                details: format!("expected {} got {}", ROW_PAGE_KIND, buf.get(0).copied().unwrap_or(255)),
+                details: format!("expected {} got {}", ROW_PAGE_KIND, buf.first().copied().unwrap_or(255)),
             });
         }
         page.validate_header()?;
         validate_row_page_header(buf)?;
-        let free = u16::from_le_bytes([buf[22], buf[23]]);
-        if free < 32 || free as usize > PAGE_SIZE {
+        let free = read_u16_le(buf, ROW_PAGE_FREE_OFFSET);
+        if (free as usize) < ROW_PAGE_DATA || free as usize > PAGE_SIZE {
             return Err(InvError::Corruption {
                 context: "rowpage.free_offset",
                 details: format!("invalid free_offset {}", free),
@@ -197,49 +407,59 @@ impl RowStore {
             });
         }
         let buf = page.as_bytes_mut();
-        buf[22..24].copy_from_slice(&free.to_le_bytes());
+        buf[ROW_PAGE_FREE_OFFSET..ROW_PAGE_FREE_OFFSET + 2].copy_from_slice(&free.to_le_bytes());
         Ok(())
     }
 }
 
+/// Check a checksummed slot and return the row bytes after the checksum.
+fn verify_slot_crc(ptr: RowPtr, slot: &[u8]) -> InvResult<&[u8]> {
+    if slot.len() < ROW_SLOT_CRC_LEN {
+        return Err(InvError::Corruption {
+            context: "row.crc",
+            details: format!("slot at page {} offset {} too small for a checksum", ptr.page_id, ptr.offset),
+        });
+    }
+    let (stored, row) = slot.split_at(ROW_SLOT_CRC_LEN);
+    let expected = read_u32_le(stored, 0);
+    let actual = crc32(row);
+    if expected != actual {
+        return Err(InvError::Corruption {
+            context: "row.crc",
+            details: format!(
+                "row at page {} offset {}: checksum {:#010x}, computed {:#010x}",
+                ptr.page_id, ptr.offset, expected, actual
+            ),
+        });
+    }
+    Ok(row)
+}
+
 pub(crate) fn validate_row_page_header(buf: &[u8]) -> InvResult<()> {
-    let base = 16;
-    if &buf[base..base + 4] != b"ROWP" {
+    if &buf[ROW_PAGE_MAGIC..ROW_PAGE_MAGIC + 4] != b"ROWP" {
         return Err(InvError::Corruption {
             context: "rowpage.magic",
             details: "invalid row page magic".to_string(),
         });
     }
-    let version = u16::from_le_bytes([buf[base + 4], buf[base + 5]]);
-    if version != 1 {
+    let version = read_u16_le(buf, ROW_PAGE_VERSION);
+    if version == 0 || version > ROW_PAGE_FORMAT_VERSION {
         return Err(InvError::Unsupported {
             feature: "rowpage.version",
         });
     }
-    let reserved = u32::from_le_bytes([
-        buf[base + 8],
-        buf[base + 9],
-        buf[base + 10],
-        buf[base + 11],
-    ]);
-    if reserved != 0 {
+    if version == 1 && read_u32_le(buf, ROW_PAGE_NEXT) != 0 {
         return Err(InvError::Unsupported {
             feature: "rowpage.reserved",
         });
     }
-    let reserved2 = u32::from_le_bytes([
-        buf[base + 12],
-        buf[base + 13],
-        buf[base + 14],
-        buf[base + 15],
-    ]);
-    if reserved2 != 0 {
+    if read_u32_le(buf, ROW_PAGE_RESERVED2) != 0 {
         return Err(InvError::Unsupported {
             feature: "rowpage.reserved2",
         });
     }
-    let free_offset = u16::from_le_bytes([buf[base + 6], buf[base + 7]]);
-    if free_offset < 32 || free_offset as usize > PAGE_SIZE {
+    let free_offset = read_u16_le(buf, ROW_PAGE_FREE_OFFSET);
+    if (free_offset as usize) < ROW_PAGE_DATA || free_offset as usize > PAGE_SIZE {
         return Err(InvError::Corruption {
             context: "rowpage.free_offset",
             details: format!("invalid free_offset {}", free_offset),
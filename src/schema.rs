@@ -18,6 +18,9 @@ pub enum ColType {
 pub struct Column {
     pub name: String,
     pub ty: ColType,
+    /// Whether the column accepts `Null`. Columns carry no uniqueness
+    /// constraint and are not indexed, so any number of rows may hold `Null`
+    /// or equal values in a column; a row is unique only by its pk.
     pub nullable: bool,
 }
 
@@ -25,6 +28,10 @@ pub struct Column {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Schema {
     pub columns: Vec<Column>,
+    /// Per column, whether `String` values containing a NUL byte are
+    /// rejected on write, see [`crate::row::encode_row`]. Set through
+    /// [`Schema::reject_interior_nul`].
+    pub(crate) reject_interior_nul: Vec<bool>,
 }
 
 impl Schema {
@@ -65,7 +72,29 @@ impl Schema {
 
         // TODO: constraints, indexes, defaults.
 
-        Ok(Self { columns })
+        let reject_interior_nul = vec![false; columns.len()];
+        Ok(Self {
+            columns,
+            reject_interior_nul,
+        })
+    }
+
+    /// Reject `String` values containing a NUL byte in `column` on write.
+    /// Ignored for columns of other types.
+    /// # Errors
+    /// - [`InvError::InvalidArgument`] if there is no such column.
+    pub fn reject_interior_nul(mut self, column: &str) -> InvResult<Self> {
+        let idx = self.column_index(column).ok_or(InvError::InvalidArgument {
+            name: "column",
+            details: format!("no column named {}", column),
+        })?;
+        self.reject_interior_nul[idx] = true;
+        Ok(self)
+    }
+
+    /// Whether column `idx` rejects `String` values containing a NUL byte.
+    pub fn rejects_interior_nul(&self, idx: usize) -> bool {
+        self.reject_interior_nul.get(idx).copied().unwrap_or(false)
     }
 
     /// Number of columns.
@@ -77,4 +106,9 @@ impl Schema {
     pub fn is_empty(&self) -> bool {
         self.columns.is_empty()
     }
+
+    /// Position of the column named `name`, if any.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.name == name)
+    }
 }
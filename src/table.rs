@@ -1,13 +1,33 @@
 //! Table-level operations using catalog, row store, and global btree.
 
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::blob;
 use crate::btree;
-use crate::catalog::{Catalog, TableDef};
+use crate::btree::node::{encode_into_page, max_leaf_keys, LeafNode, Node};
+use crate::cluster;
+use crate::catalog::{Catalog, TableDef, TableId};
 use crate::error::{InvError, InvResult};
 use crate::pager::Pager;
-use crate::row::{decode_row, encode_row, Row};
-use crate::rowstore::{RowPtr, RowStore};
+use crate::options::LargeRowPolicy;
+use crate::page::Page;
+use crate::pktree;
+use crate::row::{decode_row_lenient, decode_row_prefix, decode_row_reuse_lenient, stored_column_count, CmpOp, encode_row, encoded_value_len, verify_row_strict, Row, Value};
+use crate::rowkey;
+use crate::rowstore::{RowPtr, RowStore, MAX_INLINE_ROW};
+use crate::schema::{ColType, Schema};
+use crate::config::{CATALOG_PAGE_ID, HEADER_PAGE_ID, PAGE_SIZE, ROW_PAGE_KIND};
+use crate::types::PageId;
 
 /// Mix table_id and pk into a composite u32 key.
+///
+/// All arithmetic wraps, so every `(table_id, pk)` pair yields a key without
+/// overflow. For a fixed table the mapping is a bijection over pks, so
+/// [`composite_pk`] can recover the pk a key would belong to; pairs of
+/// different tables can still collide on one key. Debug builds reject an
+/// insert that would reuse a key held by another row. Colliding rows are never
+/// chained under one entry: a key indexes at most one row, so there is no
+/// chain order for reads or rebuilds to depend on.
 pub fn composite_key(table_id: u32, pk: u32) -> u32 {
     let mut x = table_id ^ 0x9E3779B9;
     x = x.wrapping_mul(0x85EBCA6B);
@@ -16,7 +36,62 @@ pub fn composite_key(table_id: u32, pk: u32) -> u32 {
     x ^ (x >> 16)
 }
 
-fn find_table_mut<'a>(cat: &'a mut Catalog, name: &str) -> InvResult<&'a mut TableDef> {
+/// Invert [`composite_key`] for a table, returning the pk that maps to `key`.
+///
+/// Every key maps to some pk, so callers must still confirm ownership (e.g.
+/// via the stored pk prefix) before trusting the result.
+pub fn composite_pk(table_id: u32, key: u32) -> u32 {
+    let mut x = key ^ (key >> 16);
+    x = x.wrapping_mul(mod_inverse(0x27D4EB2F));
+    let mixed = (table_id ^ 0x9E3779B9).wrapping_mul(0x85EBCA6B);
+    (x ^ mixed).wrapping_sub(0xC2B2AE35)
+}
+
+/// Multiplicative inverse modulo 2^32 of an odd constant (Newton iteration).
+const fn mod_inverse(a: u32) -> u32 {
+    let mut x = a;
+    let mut i = 0;
+    while i < 5 {
+        x = x.wrapping_mul(2u32.wrapping_sub(a.wrapping_mul(x)));
+        i += 1;
+    }
+    x
+}
+
+pub(crate) fn find_table_mut<'a>(cat: &'a mut Catalog, name: &str) -> InvResult<&'a mut TableDef> {
+    let table = find_any_table_mut(cat, name)?;
+    require_u32_pk(table)?;
+    Ok(table)
+}
+
+pub(crate) fn find_table<'a>(cat: &'a Catalog, name: &str) -> InvResult<&'a TableDef> {
+    let table = find_any_table(cat, name)?;
+    require_u32_pk(table)?;
+    Ok(table)
+}
+
+/// Create an empty table named `name` of the same kind as `like`: same
+/// schema, pk width, cluster column, generated keys and row checksums.
+pub(crate) fn create_table_like(pager: &mut Pager, catalog: &mut Catalog, name: &str, like: &TableDef) -> InvResult<TableId> {
+    let id = if like.has_u64_pk() {
+        catalog.create_table_u64_pk(name, &like.schema)?
+    } else if like.uuid_root.is_some() {
+        rowkey::create_table_with_uuid(pager, catalog, name, &like.schema)?
+    } else if let Some(cluster) = like.cluster {
+        let column = &like.schema.columns[cluster.column as usize].name;
+        cluster::create_clustered_table(pager, catalog, name, &like.schema, column)?
+    } else if like.row_crc {
+        return catalog.create_table_with_row_crc(name, &like.schema);
+    } else {
+        return catalog.create_table(name, &like.schema);
+    };
+    if like.row_crc {
+        find_any_table_mut(catalog, name)?.row_crc = true;
+    }
+    Ok(id)
+}
+
+fn find_any_table_mut<'a>(cat: &'a mut Catalog, name: &str) -> InvResult<&'a mut TableDef> {
     cat.tables
         .iter_mut()
         .find(|t| t.name == name)
@@ -26,7 +101,7 @@ fn find_table_mut<'a>(cat: &'a mut Catalog, name: &str) -> InvResult<&'a mut Tab
         })
 }
 
-fn find_table<'a>(cat: &'a Catalog, name: &str) -> InvResult<&'a TableDef> {
+fn find_any_table<'a>(cat: &'a Catalog, name: &str) -> InvResult<&'a TableDef> {
     cat.tables
         .iter()
         .find(|t| t.name == name)
@@ -36,37 +111,141 @@ fn find_table<'a>(cat: &'a Catalog, name: &str) -> InvResult<&'a TableDef> {
         })
 }
 
+/// The u32-pk operations address rows by `u32`; u64-pk tables use the `_u64` variants.
+fn require_u32_pk(table: &TableDef) -> InvResult<()> {
+    if table.has_u64_pk() {
+        return Err(InvError::Unsupported {
+            feature: "table.pk_u64",
+        });
+    }
+    Ok(())
+}
+
+fn require_u64_pk(table: &TableDef) -> InvResult<()> {
+    if !table.has_u64_pk() {
+        return Err(InvError::InvalidArgument {
+            name: "table",
+            details: format!("table {} does not use u64 pks", table.name),
+        });
+    }
+    Ok(())
+}
+
+/// Root of a u64-pk table's pk tree, 0 while it has no entries.
+/// # Errors
+/// - [`InvError::Unsupported`] with feature `table.pk_u64_shared_index` for a
+///   table whose rows are still indexed in the shared btree, which
+///   [`migrate_shared_u64_pks`] moves on a writable open.
+fn pk_tree_root(table: &TableDef) -> InvResult<u32> {
+    table.pk_root.ok_or(InvError::Unsupported {
+        feature: "table.pk_u64_shared_index",
+    })
+}
+
 /// Insert a row and return its primary key.
 pub fn insert_row(
     pager: &mut Pager,
     catalog: &mut Catalog,
     table_name: &str,
     row: &Row,
+) -> InvResult<u32> {
+    insert_row_with_policy(pager, catalog, table_name, row, LargeRowPolicy::Error)
+}
+
+/// Insert a row, handling oversized rows per `policy`, and return its primary key.
+pub(crate) fn insert_row_with_policy(
+    pager: &mut Pager,
+    catalog: &mut Catalog,
+    table_name: &str,
+    row: &Row,
+    policy: LargeRowPolicy,
 ) -> InvResult<u32> {
     let table = find_table_mut(catalog, table_name)?;
+    reject_generated_keys(table)?;
+    insert_into_table_with_policy(pager, table, row, policy)
+}
 
-    let pk = table
-        .next_pk
-        .checked_add(0)
-        .ok_or(InvError::Overflow {
-            context: "table.next_pk",
-        })?;
-    table.next_pk = table
-        .next_pk
-        .checked_add(1)
-        .ok_or(InvError::Overflow {
-            context: "table.next_pk",
-        })?;
+/// Insert a row into an already located table definition and return its
+/// primary key. The caller is responsible for persisting `table`.
+pub fn insert_into_table(pager: &mut Pager, table: &mut TableDef, row: &Row) -> InvResult<u32> {
+    reject_generated_keys(table)?;
+    insert_into_table_with_policy(pager, table, row, LargeRowPolicy::Error)
+}
+
+/// Refuse plain inserts into a table whose rows need a generated key, see
+/// [`crate::rowkey`].
+fn reject_generated_keys(table: &TableDef) -> InvResult<()> {
+    if table.uuid_root.is_some() {
+        return Err(InvError::InvalidArgument {
+            name: "table",
+            details: format!("table {} generates row keys; use insert_row_uuid", table.name),
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn insert_into_table_with_policy(
+    pager: &mut Pager,
+    table: &mut TableDef,
+    row: &Row,
+    policy: LargeRowPolicy,
+) -> InvResult<u32> {
+    insert_encoded(pager, table, row, policy).map(|(pk, _)| pk)
+}
+
+/// Insert a row and return its pk along with the row as stored, decoded from
+/// the bytes just written.
+pub(crate) fn insert_row_returning(
+    pager: &mut Pager,
+    catalog: &mut Catalog,
+    table_name: &str,
+    row: &Row,
+    policy: LargeRowPolicy,
+) -> InvResult<(u32, Row)> {
+    let table = find_table_mut(catalog, table_name)?;
+    reject_generated_keys(table)?;
+    let (pk, encoded_row) = insert_encoded(pager, table, row, policy)?;
+    Ok((pk, decode_row_lenient(&table.schema, &encoded_row)?))
+}
 
+/// Insert a row, returning its pk and encoded bytes (without the pk prefix).
+fn insert_encoded(
+    pager: &mut Pager,
+    table: &mut TableDef,
+    row: &Row,
+    policy: LargeRowPolicy,
+) -> InvResult<(u32, Vec<u8>)> {
+    require_u32_pk(table)?;
+    let pk = table.next_pk;
+    let next = pk.checked_add(1).ok_or(InvError::Overflow {
+        context: "table.next_pk",
+    })?;
+
+    // Validate everything before consuming the pk, so a rejected row leaves
+    // `next_pk` as it was.
     let encoded_row = encode_row(&table.schema, row)?;
     let mut stored = Vec::with_capacity(4 + encoded_row.len());
     stored.extend_from_slice(&pk.to_le_bytes());
     stored.extend_from_slice(&encoded_row);
-
-    let (ptr, new_last_page) = RowStore::append_row(pager, table.last_row_page, &stored)?;
-    table.last_row_page = new_last_page;
+    check_row_size(&table.schema, row, stored.len(), policy)?;
+    let cluster_key = match table.cluster {
+        Some(cluster) => Some(cluster::cluster_key(&row[cluster.column as usize]).ok_or(InvError::InvalidArgument {
+            name: "row",
+            details: "cluster column value must be an integer".to_string(),
+        })?),
+        None => None,
+    };
+    table.next_pk = next;
 
     let composite = composite_key(table.id.0, pk);
+    pager.note_key_owner(composite, table.id.0, pk as u64)?;
+    if let (Some(cluster), Some(key)) = (table.cluster, cluster_key) {
+        cluster::insert_row(pager, table, cluster, key, composite, stored)?;
+        return Ok((pk, encoded_row));
+    }
+
+    let ptr = append_stored_row(pager, table, &stored)?;
+
     let packed = ptr.pack();
     let root = pager.root_page_id();
     let new_root = btree::insert::insert_u64(pager, root, composite, packed)?;
@@ -74,7 +253,218 @@ pub fn insert_row(
         pager.set_root_page_id(new_root)?;
     }
 
-    Ok(pk)
+    Ok((pk, encoded_row))
+}
+
+/// Append a stored row to the table's row pages, with a checksum if the
+/// table asks for one, and advance `last_row_page`.
+///
+/// The table's first row page starts its chain; a table created before row
+/// pages were chained starts one only if it has never stored a row.
+pub(crate) fn append_stored_row(pager: &mut Pager, table: &mut TableDef, stored: &[u8]) -> InvResult<RowPtr> {
+    let (ptr, new_last_page) = if table.row_crc {
+        RowStore::append_row_with_crc(pager, table.last_row_page, stored)?
+    } else {
+        RowStore::append_row(pager, table.last_row_page, stored)?
+    };
+    if table.last_row_page == 0 {
+        table.first_row_page = Some(new_last_page);
+    }
+    table.last_row_page = new_last_page;
+    Ok(ptr)
+}
+
+/// The row pages of a table in the order they were appended to.
+///
+/// Follows the chain from [`TableDef::first_row_page`]; a clustered table
+/// lists its pages in directory order instead.
+/// # Errors
+/// - [`InvError::Unsupported`] with feature `table.row_chain` for a table
+///   created before row pages were chained; [`crate::Db::compact_to`]
+///   rebuilds its chain.
+/// - [`InvError::Corruption`] if the chain loops, leaves the file, or does
+///   not end at the table's last row page.
+pub fn row_pages(pager: &mut Pager, table: &TableDef) -> InvResult<Vec<PageId>> {
+    if let Some(c) = table.cluster {
+        let dir = cluster::read_directory(pager, PageId(c.directory_page))?;
+        return Ok(dir.into_iter().map(|(page, _)| PageId(page)).collect());
+    }
+    let Some(first) = table.first_row_page else {
+        if table.last_row_page == 0 {
+            return Ok(Vec::new());
+        }
+        return Err(InvError::Unsupported {
+            feature: "table.row_chain",
+        });
+    };
+    let mut pages = Vec::new();
+    let mut seen = HashSet::new();
+    let mut current = first;
+    while current != 0 {
+        if current >= pager.page_count() || !seen.insert(current) {
+            return Err(InvError::Corruption {
+                context: "table.row_chain",
+                details: format!("table {} links to page {} twice or out of bounds", table.name, current),
+            });
+        }
+        pages.push(PageId(current));
+        current = RowStore::next_row_page(pager, PageId(current))?;
+    }
+    if pages.last().map_or(0, |p| p.0) != table.last_row_page {
+        return Err(InvError::Corruption {
+            context: "table.row_chain",
+            details: format!("chain of table {} does not end at last row page {}", table.name, table.last_row_page),
+        });
+    }
+    Ok(pages)
+}
+
+/// Estimate a table's row count from its row pages without decoding rows.
+///
+/// The first page's average slot size gives the rows per full page, which is
+/// multiplied by the number of pages before the last; the last page's slots
+/// are counted exactly. For uniformly sized rows the estimate is within one
+/// row per page of the number of slots, but slots of deleted or rewritten rows
+/// are counted until their page is compacted.
+pub fn approx_row_count(pager: &mut Pager, table: &TableDef) -> InvResult<u64> {
+    let pages = row_pages(pager, table)?;
+    let Some((&last, full)) = pages.split_last() else {
+        return Ok(0);
+    };
+    let last_slots = RowStore::page_rows(pager, last)?.len() as u64;
+    let Some(&sample) = full.first() else {
+        return Ok(last_slots);
+    };
+    let slots = RowStore::page_rows(pager, sample)?.len() as u64;
+    let used = (RowStore::read_free_offset(pager, sample)? as usize - crate::layout::ROW_PAGE_DATA) as u64;
+    let per_page = (PAGE_SIZE - crate::layout::ROW_PAGE_DATA) as u64 * slots / used.max(1);
+    Ok(per_page * full.len() as u64 + last_slots)
+}
+
+/// Apply the large-row policy to a stored row of `stored_len` bytes.
+fn check_row_size(schema: &Schema, row: &Row, stored_len: usize, policy: LargeRowPolicy) -> InvResult<()> {
+    if stored_len <= MAX_INLINE_ROW {
+        return Ok(());
+    }
+    match policy {
+        LargeRowPolicy::Error => Err(InvError::Unsupported {
+            feature: "row.too_large",
+        }),
+        LargeRowPolicy::Overflow => Err(InvError::Unsupported {
+            feature: "row.overflow",
+        }),
+        LargeRowPolicy::RejectWithDetails => {
+            // Start from the pk prefix and row header, then add columns in order.
+            let values_len: usize = row.iter().map(encoded_value_len).sum();
+            let mut used = stored_len - values_len;
+            for (col, value) in schema.columns.iter().zip(row) {
+                used += encoded_value_len(value);
+                if used > MAX_INLINE_ROW {
+                    return Err(InvError::InvalidArgument {
+                        name: "row.too_large",
+                        details: format!(
+                            "column '{}' brings the row to {} bytes (limit {}, total {})",
+                            col.name, used, MAX_INLINE_ROW, stored_len
+                        ),
+                    });
+                }
+            }
+            Err(InvError::InvalidArgument {
+                name: "row.too_large",
+                details: format!("row of {} bytes exceeds limit {}", stored_len, MAX_INLINE_ROW),
+            })
+        }
+    }
+}
+
+/// Store an already-encoded row under an explicit pk, raising the table's
+/// next pk past it. Used to restore logical backups.
+pub(crate) fn restore_row(pager: &mut Pager, table: &mut TableDef, pk: u64, encoded_row: &[u8]) -> InvResult<()> {
+    let row = decode_row_lenient(&table.schema, encoded_row)?;
+    if let Some(next) = table.next_pk_u64 {
+        if pk == 0 || pk == u64::MAX {
+            return Err(InvError::InvalidArgument {
+                name: "pk",
+                details: format!("pk {} out of range", pk),
+            });
+        }
+        let root = pk_tree_root(table)?;
+        if pktree::search(pager, root, pk)?.is_some() {
+            return Err(InvError::InvalidArgument {
+                name: "pk",
+                details: format!("pk {} already present in {}", pk, table.name),
+            });
+        }
+        table.next_pk_u64 = Some(next.max(pk + 1));
+        let mut stored = pk.to_le_bytes().to_vec();
+        stored.extend_from_slice(encoded_row);
+        let ptr = append_stored_row(pager, table, &stored)?;
+        table.pk_root = Some(pktree::insert(pager, root, pk, ptr.pack())?);
+        return Ok(());
+    }
+
+    let pk = u32::try_from(pk)
+        .ok()
+        .filter(|pk| (1..u32::MAX).contains(pk))
+        .ok_or(InvError::InvalidArgument {
+            name: "pk",
+            details: format!("pk {} out of range", pk),
+        })?;
+    table.next_pk = table.next_pk.max(pk + 1);
+    let mut stored = pk.to_le_bytes().to_vec();
+    stored.extend_from_slice(encoded_row);
+    let composite = composite_key(table.id.0, pk);
+
+    let root = pager.root_page_id();
+    if btree::search::search_u64(pager, root, composite)?.is_some() {
+        return Err(InvError::InvalidArgument {
+            name: "pk",
+            details: format!("pk {} already present in {}", pk, table.name),
+        });
+    }
+    pager.note_key_owner(composite, table.id.0, pk as u64)?;
+    if let Some(cluster) = table.cluster {
+        let key = cluster::cluster_key(&row[cluster.column as usize]).ok_or(InvError::Corruption {
+            context: "cluster.key",
+            details: format!("row {} has a non-integer cluster value", pk),
+        })?;
+        return cluster::insert_row(pager, table, cluster, key, composite, stored);
+    }
+    let ptr = append_stored_row(pager, table, &stored)?;
+    let new_root = btree::insert::insert_u64(pager, root, composite, ptr.pack())?;
+    if new_root != root {
+        pager.set_root_page_id(new_root)?;
+    }
+    Ok(())
+}
+
+/// Advance a table's next pk by `count` without inserting, returning the first
+/// reserved pk. The caller is responsible for persisting `catalog`.
+pub(crate) fn reserve_pk_block(catalog: &mut Catalog, table_name: &str, count: u32) -> InvResult<u32> {
+    let table = find_table_mut(catalog, table_name)?;
+    let first = table.next_pk;
+    table.next_pk = first.checked_add(count).ok_or(InvError::Overflow {
+        context: "table.next_pk",
+    })?;
+    Ok(first)
+}
+
+/// Insert a row under an explicit pk, such as one from [`reserve_pk_block`].
+///
+/// A pk at or past the table's next pk raises it like [`restore_row`].
+pub(crate) fn insert_row_with_pk(
+    pager: &mut Pager,
+    catalog: &mut Catalog,
+    table_name: &str,
+    pk: u32,
+    row: &Row,
+    policy: LargeRowPolicy,
+) -> InvResult<()> {
+    let table = find_table_mut(catalog, table_name)?;
+    reject_generated_keys(table)?;
+    let encoded_row = encode_row(&table.schema, row)?;
+    check_row_size(&table.schema, row, 4 + encoded_row.len(), policy)?;
+    restore_row(pager, table, pk as u64, &encoded_row)
 }
 
 /// Fetch a row by primary key.
@@ -85,6 +475,50 @@ pub fn get_row_by_pk(
     pk: u32,
 ) -> InvResult<Option<Row>> {
     let table = find_table(catalog, table_name)?;
+    fetch_row(pager, table, pk, false)
+}
+
+/// Whether `table_name` exists and holds a row with `pk`.
+///
+/// A missing table yields `Ok(false)`; the row bytes are read and their pk
+/// verified but not decoded.
+pub fn row_present(pager: &mut Pager, catalog: &Catalog, table_name: &str, pk: u32) -> InvResult<bool> {
+    let Some(table) = catalog.tables.iter().find(|t| t.name == table_name) else {
+        return Ok(false);
+    };
+    require_u32_pk(table)?;
+    Ok(read_stored_row(pager, table, pk)?.is_some())
+}
+
+/// Fetch a row of an already-resolved table, optionally verifying it strictly.
+pub(crate) fn fetch_row(
+    pager: &mut Pager,
+    table: &TableDef,
+    pk: u32,
+    strict: bool,
+) -> InvResult<Option<Row>> {
+    let Some(stored) = read_stored_row(pager, table, pk)? else { return Ok(None); };
+    let mut row = Vec::with_capacity(table.schema.len());
+    decode_stored_row(table, &stored[4..], strict, &mut row)?;
+    Ok(Some(row))
+}
+
+/// Number of columns a row was stored with, read from its header alone.
+///
+/// Schemas only evolve by dropping trailing columns, which leaves existing
+/// rows carrying their old values until rewritten, so this count is the
+/// width of the schema the row was written under.
+pub(crate) fn row_schema_version(pager: &mut Pager, table: &TableDef, pk: u32) -> InvResult<Option<u32>> {
+    let Some(stored) = read_stored_row(pager, table, pk)? else { return Ok(None); };
+    let count = stored_column_count(&stored[4..])?;
+    u32::try_from(count).map(Some).map_err(|_| InvError::Corruption {
+        context: "row.column_count",
+        details: format!("row {} claims {} columns", pk, count),
+    })
+}
+
+/// Read the stored bytes (pk prefix included) of a row, verifying the pk prefix.
+fn read_stored_row(pager: &mut Pager, table: &TableDef, pk: u32) -> InvResult<Option<Vec<u8>>> {
     let composite = composite_key(table.id.0, pk);
     let root = pager.root_page_id();
     let ptr_val = btree::search::search_u64(pager, root, composite)?;
@@ -100,17 +534,127 @@ pub fn get_row_by_pk(
         });
     }
     let stored_pk = u32::from_le_bytes([stored[0], stored[1], stored[2], stored[3]]);
+    if !(1..table.next_pk).contains(&stored_pk) {
+        return Err(InvError::Corruption {
+            context: "table.pk_out_of_range",
+            details: format!("stored pk {} outside 1..{} for pk {}", stored_pk, table.next_pk, pk),
+        });
+    }
     if stored_pk != pk {
         return Err(InvError::Corruption {
             context: "table.pk_mismatch",
             details: format!("expected {} got {}", pk, stored_pk),
         });
     }
-    let row_bytes = &stored[4..];
-    let row = decode_row(&table.schema, row_bytes)?;
+    Ok(Some(stored))
+}
+
+/// Decode stored row bytes (pk prefix stripped) into `out`.
+///
+/// Values of columns dropped from the end of the schema are skipped; strict
+/// mode rejects them along with any other non-canonical encoding.
+pub(crate) fn decode_stored_row(table: &TableDef, row_bytes: &[u8], strict: bool, out: &mut Row) -> InvResult<()> {
+    if !strict {
+        return decode_row_reuse_lenient(&table.schema, row_bytes, out);
+    }
+    decode_row_reuse_lenient(&table.schema, row_bytes, out).map_err(|e| match e {
+        InvError::InvalidArgument { .. } => InvError::Corruption {
+            context: "row.strict",
+            details: e.to_string(),
+        },
+        other => other,
+    })?;
+    verify_row_strict(&table.schema, out, row_bytes)
+}
+
+/// Insert a row into a u64-pk table and return its primary key.
+///
+/// The row is indexed under its full pk in the table's own index, see
+/// [`crate::pktree`].
+pub fn insert_row_u64(
+    pager: &mut Pager,
+    catalog: &mut Catalog,
+    table_name: &str,
+    row: &Row,
+) -> InvResult<u64> {
+    insert_row_u64_with_policy(pager, catalog, table_name, row, LargeRowPolicy::Error)
+}
+
+/// [`insert_row_u64`] handling oversized rows per `policy`.
+pub(crate) fn insert_row_u64_with_policy(
+    pager: &mut Pager,
+    catalog: &mut Catalog,
+    table_name: &str,
+    row: &Row,
+    policy: LargeRowPolicy,
+) -> InvResult<u64> {
+    let table = find_any_table_mut(catalog, table_name)?;
+    require_u64_pk(table)?;
+
+    let encoded_row = encode_row(&table.schema, row)?;
+    let pk = table.next_pk_u64.unwrap_or(1);
+    let next = pk.checked_add(1).ok_or(InvError::Overflow {
+        context: "table.next_pk",
+    })?;
+
+    let mut stored = Vec::with_capacity(8 + encoded_row.len());
+    stored.extend_from_slice(&pk.to_le_bytes());
+    stored.extend_from_slice(&encoded_row);
+    check_row_size(&table.schema, row, stored.len(), policy)?;
+
+    let root = pk_tree_root(table)?;
+    let ptr = append_stored_row(pager, table, &stored)?;
+    table.next_pk_u64 = Some(next);
+    table.pk_root = Some(pktree::insert(pager, root, pk, ptr.pack())?);
+    Ok(pk)
+}
+
+/// Fetch a row of a u64-pk table by primary key.
+pub fn get_row_by_pk_u64(
+    pager: &mut Pager,
+    catalog: &Catalog,
+    table_name: &str,
+    pk: u64,
+    strict: bool,
+) -> InvResult<Option<Row>> {
+    let table = find_any_table(catalog, table_name)?;
+    require_u64_pk(table)?;
+    let Some(raw_ptr) = pktree::search(pager, pk_tree_root(table)?, pk)? else {
+        return Ok(None);
+    };
+    let ptr = RowPtr::unpack(raw_ptr);
+    ptr.validate()?;
+    let stored = read_stored_row_u64(pager, table, pk, ptr)?;
+    let mut row = Vec::with_capacity(table.schema.len());
+    decode_stored_row(table, &stored[8..], strict, &mut row)?;
     Ok(Some(row))
 }
 
+/// Read the stored bytes (pk prefix included) a u64-pk table indexes under
+/// `pk`, verifying the pk prefix.
+fn read_stored_row_u64(pager: &mut Pager, table: &TableDef, pk: u64, ptr: RowPtr) -> InvResult<Vec<u8>> {
+    let stored = RowStore::read_row(pager, ptr)?;
+    let prefix: [u8; 8] = stored.get(0..8).and_then(|p| p.try_into().ok()).ok_or(InvError::Corruption {
+        context: "table.pk_mismatch",
+        details: "stored row too small".to_string(),
+    })?;
+    let stored_pk = u64::from_le_bytes(prefix);
+    let next = table.next_pk_u64.unwrap_or(1);
+    if !(1..next).contains(&stored_pk) {
+        return Err(InvError::Corruption {
+            context: "table.pk_out_of_range",
+            details: format!("stored pk {} outside 1..{} for pk {}", stored_pk, next, pk),
+        });
+    }
+    if stored_pk != pk {
+        return Err(InvError::Corruption {
+            context: "table.pk_mismatch",
+            details: format!("expected {} got {}", pk, stored_pk),
+        });
+    }
+    Ok(stored)
+}
+
 /// Naive full scan by iterating pk range.
 pub fn scan_table(
     pager: &mut Pager,
@@ -118,15 +662,905 @@ pub fn scan_table(
     table_name: &str,
 ) -> InvResult<Vec<(u32, Row)>> {
     let table = find_table(catalog, table_name)?;
+    scan_rows(pager, table, false)
+}
+
+/// Scan an already-resolved table, optionally verifying every row strictly.
+///
+/// A table with a row page chain only looks up the pks found in its own
+/// pages; clustered tables and tables created before chaining probe every
+/// pk below `next_pk`.
+pub(crate) fn scan_rows(
+    pager: &mut Pager,
+    table: &TableDef,
+    strict: bool,
+) -> InvResult<Vec<(u32, Row)>> {
+    let mut rows = Vec::new();
+    for pk in candidate_pks(pager, table)? {
+        if let Some(row) = fetch_row(pager, table, pk, strict)? {
+            rows.push((pk, row));
+        }
+    }
+    Ok(rows)
+}
+
+/// Pks that may hold a live row of the table, in ascending order.
+///
+/// Read from the slots of the table's row pages, or from the shared btree for
+/// a table created before row pages were chained.
+fn candidate_pks(pager: &mut Pager, table: &TableDef) -> InvResult<Vec<u32>> {
+    if table.first_row_page.is_none() && table.cluster.is_none() && table.last_row_page != 0 {
+        return index_pks(pager, table);
+    }
+    Ok(chain_pks(pager, table)?.into_iter().collect())
+}
+
+/// Pks in `1..next_pk` whose composite key has an entry in the shared btree.
+///
+/// Keys of other tables and of the key-value store map to pks of this table
+/// too, so the caller must still verify the stored pk prefix, as
+/// [`read_stored_row`] does.
+fn index_pks(pager: &mut Pager, table: &TableDef) -> InvResult<Vec<u32>> {
+    let root = pager.root_page_id();
+    let mut pks = Vec::new();
+    for entry in btree::search::iter_from(pager, root, 0)? {
+        let pk = composite_pk(table.id.0, entry?.0);
+        if (1..table.next_pk).contains(&pk) {
+            pks.push(pk);
+        }
+    }
+    pks.sort_unstable();
+    Ok(pks)
+}
+
+/// Pks with at least one slot, live or dead, in the table's row page chain.
+///
+/// Every slot was written with a pk below `next_pk`, so any other prefix is
+/// reported like [`read_stored_row`] does.
+fn chain_pks(pager: &mut Pager, table: &TableDef) -> InvResult<BTreeSet<u32>> {
+    let mut pks = BTreeSet::new();
+    for page in row_pages(pager, table)? {
+        for (ptr, stored) in RowStore::page_rows(pager, page)? {
+            let Some(prefix) = stored.get(0..4) else {
+                return Err(InvError::Corruption {
+                    context: "table.pk_mismatch",
+                    details: format!("row at page {} offset {} too small", ptr.page_id, ptr.offset),
+                });
+            };
+            let pk = u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]);
+            if !(1..table.next_pk).contains(&pk) {
+                return Err(InvError::Corruption {
+                    context: "table.pk_out_of_range",
+                    details: format!("stored pk {} outside 1..{} at page {}", pk, table.next_pk, ptr.page_id),
+                });
+            }
+            pks.insert(pk);
+        }
+    }
+    Ok(pks)
+}
+
+/// Collect one column's value from every row in pk order.
+pub(crate) fn column_values(pager: &mut Pager, table: &TableDef, column: &str, strict: bool) -> InvResult<Vec<Value>> {
+    let idx = table.schema.column_index(column).ok_or(InvError::InvalidArgument {
+        name: "column",
+        details: format!("no column named {}", column),
+    })?;
+    Ok(scan_rows(pager, table, strict)?
+        .into_iter()
+        .map(|(_, mut row)| row.swap_remove(idx))
+        .collect())
+}
+
+/// The pks, in ascending order, of rows whose `column` satisfies `op` against
+/// `value`.
+///
+/// Each row is decoded only up to the column, unless `strict` asks for every
+/// row to be verified in full.
+/// # Errors
+/// - [`InvError::InvalidArgument`] if the column does not exist or `value` is
+///   not a non-null value of its type.
+pub(crate) fn pks_where(
+    pager: &mut Pager,
+    table: &TableDef,
+    column: &str,
+    op: CmpOp,
+    value: &Value,
+    strict: bool,
+) -> InvResult<Vec<u32>> {
+    let idx = table.schema.column_index(column).ok_or(InvError::InvalidArgument {
+        name: "column",
+        details: format!("no column named {}", column),
+    })?;
+    let matches_type = matches!(
+        (&table.schema.columns[idx].ty, value),
+        (ColType::U32, Value::U32(_))
+            | (ColType::U64, Value::U64(_))
+            | (ColType::I64, Value::I64(_))
+            | (ColType::Bool, Value::Bool(_))
+            | (ColType::Bytes, Value::Bytes(_))
+            | (ColType::String, Value::String(_))
+    );
+    if !matches_type {
+        return Err(InvError::InvalidArgument {
+            name: "value",
+            details: format!("value must be a non-null value of column {}'s type", column),
+        });
+    }
+    let mut pks = Vec::new();
+    let mut row = Vec::with_capacity(idx + 1);
+    for pk in 1..table.next_pk {
+        let Some(stored) = read_stored_row(pager, table, pk)? else { continue; };
+        if strict {
+            decode_stored_row(table, &stored[4..], true, &mut row)?;
+        } else {
+            decode_row_prefix(&table.schema, &stored[4..], idx + 1, &mut row)?;
+        }
+        if op.eval(&row[idx], value) {
+            pks.push(pk);
+        }
+    }
+    Ok(pks)
+}
+
+/// Stream rows in pk order into a callback, reusing one row buffer throughout.
+///
+/// Stops at and returns the first error produced by the callback.
+pub(crate) fn for_each_row<F>(pager: &mut Pager, table: &TableDef, strict: bool, mut f: F) -> InvResult<()>
+where
+    F: FnMut(u32, &Row) -> InvResult<()>,
+{
+    let mut row = Vec::with_capacity(table.schema.len());
+    for pk in 1..table.next_pk {
+        if let Some(stored) = read_stored_row(pager, table, pk)? {
+            decode_stored_row(table, &stored[4..], strict, &mut row)?;
+            f(pk, &row)?;
+        }
+    }
+    Ok(())
+}
+
+/// Re-encode every row of a table against its current schema and store back
+/// those whose bytes change, returning how many were stored back.
+pub fn rewrite_rows(pager: &mut Pager, catalog: &mut Catalog, table_name: &str) -> InvResult<u64> {
+    let table = find_table_mut(catalog, table_name)?;
+    let mut rewritten = 0u64;
+    for pk in 1..table.next_pk {
+        let Some(stored) = read_stored_row(pager, table, pk)? else { continue; };
+        let row = decode_row_lenient(&table.schema, &stored[4..])?;
+        let encoded_row = encode_row(&table.schema, &row)?;
+        if encoded_row == stored[4..] {
+            continue;
+        }
+        store_row_back(pager, table, pk, &row, &encoded_row)?;
+        rewritten += 1;
+    }
+    Ok(rewritten)
+}
+
+/// Rewrite a table's live rows densely into as few row pages as they fit
+/// in, returning how many row pages were freed.
+///
+/// Rows are read into memory and appended in pk order to a fresh chain of
+/// row pages; only once every btree entry points into the new chain are the
+/// old pages freed, so a failure partway leaves every row readable. The
+/// caller is responsible for persisting `catalog`.
+/// # Errors
+/// - [`InvError::Unsupported`] with feature `cluster.coalesce` for a
+///   clustered table, whose pages follow its cluster order, and as
+///   [`row_pages`] for a table whose row pages are not chained.
+pub fn coalesce_row_pages(pager: &mut Pager, catalog: &mut Catalog, table_name: &str) -> InvResult<u64> {
+    let table = find_any_table_mut(catalog, table_name)?;
+    if table.cluster.is_some() {
+        return Err(InvError::Unsupported {
+            feature: "cluster.coalesce",
+        });
+    }
+    let old_pages = row_pages(pager, table)?;
     let mut rows = Vec::new();
+    for (key, ptr) in table_keys(pager, table)? {
+        rows.push((key, RowStore::read_row(pager, ptr)?));
+    }
+    table.first_row_page = Some(0);
+    table.last_row_page = 0;
+    let mut moved = Vec::with_capacity(rows.len());
+    for (key, stored) in rows {
+        moved.push((key, append_stored_row(pager, table, &stored)?));
+    }
+    for (key, ptr) in moved {
+        repoint_row(pager, table, key, ptr)?;
+    }
+    for &page in &old_pages {
+        pager.free_page(page)?;
+    }
+    let new_pages = row_pages(pager, table)?.len();
+    Ok(old_pages.len().saturating_sub(new_pages) as u64)
+}
+
+/// Replace one column of a stored row and store the row back, freeing the
+/// old row page if that left it without live rows.
+/// # Errors
+/// - [`InvError::InvalidArgument`] if the column or row does not exist, or
+///   `value` does not fit the column's type or nullability.
+pub(crate) fn set_value(
+    pager: &mut Pager,
+    catalog: &mut Catalog,
+    table_name: &str,
+    pk: u32,
+    column: &str,
+    value: Value,
+    policy: LargeRowPolicy,
+) -> InvResult<()> {
+    let table = find_table_mut(catalog, table_name)?;
+    require_u32_pk(table)?;
+    let idx = table.schema.column_index(column).ok_or(InvError::InvalidArgument {
+        name: "column",
+        details: format!("no column '{}' in {}", column, table.name),
+    })?;
+    if table.uuid_root.is_some() && column == rowkey::UUID_COLUMN {
+        return Err(InvError::InvalidArgument {
+            name: "column",
+            details: format!("generated keys of {} cannot be changed", table.name),
+        });
+    }
+    let stored = read_stored_row(pager, table, pk)?.ok_or(InvError::InvalidArgument {
+        name: "pk",
+        details: format!("pk {} not found in {}", pk, table.name),
+    })?;
+    let mut row = decode_row_lenient(&table.schema, &stored[4..])?;
+    row[idx] = value;
+    let encoded_row = encode_row(&table.schema, &row)?;
+    check_row_size(&table.schema, &row, 4 + encoded_row.len(), policy)?;
+    let root = pager.root_page_id();
+    let old = btree::search::search_u64(pager, root, composite_key(table.id.0, pk))?.map(RowPtr::unpack);
+    store_row_back(pager, table, pk, &row, &encoded_row)?;
+    if let Some(old) = old {
+        release_dead_row_page(pager, table, PageId(old.page_id))?;
+    }
+    Ok(())
+}
+
+/// Store a re-encoded row under its existing pk, pointing the index at the
+/// new bytes; the old bytes become dead space.
+fn store_row_back(pager: &mut Pager, table: &mut TableDef, pk: u32, row: &Row, encoded_row: &[u8]) -> InvResult<()> {
+    let mut fresh = Vec::with_capacity(4 + encoded_row.len());
+    fresh.extend_from_slice(&pk.to_le_bytes());
+    fresh.extend_from_slice(encoded_row);
+    let composite = composite_key(table.id.0, pk);
+    if let Some(cluster) = table.cluster {
+        let key = cluster::cluster_key(&row[cluster.column as usize]).ok_or(InvError::Corruption {
+            context: "cluster.key",
+            details: format!("row {} has a non-integer cluster value", pk),
+        })?;
+        return cluster::insert_row(pager, table, cluster, key, composite, fresh);
+    }
+    let ptr = append_stored_row(pager, table, &fresh)?;
+    let root = pager.root_page_id();
+    let new_root = btree::insert::insert_u64(pager, root, composite, ptr.pack())?;
+    if new_root != root {
+        pager.set_root_page_id(new_root)?;
+    }
+    Ok(())
+}
+
+/// Delete a row by primary key, returning whether a row existed.
+///
+/// Only the btree entry is removed; the row bytes stay behind as dead space in
+/// their row page until the page is compacted, unless the delete leaves the
+/// page without live rows, see [`release_dead_row_page`]. Leaves are never
+/// merged, see [`delete_row_with_fill`].
+pub fn delete_row(
+    pager: &mut Pager,
+    catalog: &mut Catalog,
+    table_name: &str,
+    pk: u32,
+) -> InvResult<bool> {
+    delete_row_with_fill(pager, catalog, table_name, pk, 0.0)
+}
+
+/// Like [`delete_row`], but merges btree nodes left below `min_fill_ratio`
+/// of their capacity with a sibling, see
+/// [`btree::delete::delete_u64_rebalancing`].
+pub fn delete_row_with_fill(
+    pager: &mut Pager,
+    catalog: &mut Catalog,
+    table_name: &str,
+    pk: u32,
+    min_fill_ratio: f32,
+) -> InvResult<bool> {
+    let table = find_table_mut(catalog, table_name)?;
+    let composite = composite_key(table.id.0, pk);
+    let root = pager.root_page_id();
+    let Some(raw_ptr) = btree::search::search_u64(pager, root, composite)? else {
+        return Ok(false);
+    };
+    let ptr = RowPtr::unpack(raw_ptr);
+    ptr.validate()?;
+    let stored = RowStore::read_row(pager, ptr)?;
+    if stored.len() < 4 || stored[0..4] != pk.to_le_bytes() {
+        return Err(InvError::Corruption {
+            context: "table.pk_mismatch",
+            details: format!("entry for pk {} holds a different row", pk),
+        });
+    }
+    if table.uuid_root.is_some() {
+        let row = decode_row_lenient(&table.schema, &stored[4..])?;
+        rowkey::forget_row_key(pager, table, pk, &row)?;
+    }
+    let (new_root, removed) = btree::delete::delete_u64_rebalancing(pager, root, composite, min_fill_ratio)?;
+    if new_root != root {
+        pager.set_root_page_id(new_root)?;
+    }
+    pager.forget_key_owner(composite);
+    release_dead_row_page(pager, table, PageId(ptr.page_id))?;
+    Ok(removed)
+}
+
+/// Free a row page of the table's chain once none of its slots is indexed,
+/// returning whether it was freed.
+///
+/// The page is unlinked from the chain first, moving
+/// [`TableDef::first_row_page`] if it was the head. The last row page stays
+/// as the append target, and clustered tables and tables created before row
+/// pages were chained keep all their pages.
+pub(crate) fn release_dead_row_page(pager: &mut Pager, table: &mut TableDef, page: PageId) -> InvResult<bool> {
+    let Some(first) = table.first_row_page else {
+        return Ok(false);
+    };
+    if table.cluster.is_some() || page.0 == table.last_row_page {
+        return Ok(false);
+    }
+    for (ptr, stored) in RowStore::page_rows(pager, page)? {
+        let Some(prefix) = stored.get(0..4) else { continue; };
+        let pk = u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]);
+        let root = pager.root_page_id();
+        if btree::search::search_u64(pager, root, composite_key(table.id.0, pk))? == Some(ptr.pack()) {
+            return Ok(false);
+        }
+    }
+    let next = RowStore::next_row_page(pager, page)?;
+    if first == page.0 {
+        table.first_row_page = Some(next);
+    } else {
+        let pages = row_pages(pager, table)?;
+        let prev = pages
+            .iter()
+            .position(|&p| p == page)
+            .and_then(|i| i.checked_sub(1))
+            .ok_or(InvError::Corruption {
+                context: "table.row_chain",
+                details: format!("page {} is not in the chain of table {}", page.0, table.name),
+            })?;
+        RowStore::set_next_row_page(pager, pages[prev], next)?;
+    }
+    pager.free_page(page)?;
+    Ok(true)
+}
+
+/// Return the live pks of a table in ascending order without decoding rows.
+///
+/// Looks up the same candidate pks as [`scan_rows`], reading each row's bytes
+/// only to verify its pk prefix.
+pub fn list_pks(pager: &mut Pager, table: &TableDef) -> InvResult<Vec<u32>> {
+    let mut pks = Vec::new();
+    for pk in candidate_pks(pager, table)? {
+        if read_stored_row(pager, table, pk)?.is_some() {
+            pks.push(pk);
+        }
+    }
+    Ok(pks)
+}
+
+/// Return the pks in `1..next_pk` that have no live row, in ascending order.
+///
+/// Probes every pk of the range, so a row whose stored prefix disagrees with
+/// its pk surfaces as an error rather than as a gap.
+pub fn missing_pks(pager: &mut Pager, table: &TableDef) -> InvResult<Vec<u32>> {
+    let mut missing = Vec::new();
     for pk in 1..table.next_pk {
-        if let Some(row) = get_row_by_pk(pager, catalog, table_name, pk)? {
+        if read_stored_row(pager, table, pk)?.is_none() {
+            missing.push(pk);
+        }
+    }
+    Ok(missing)
+}
+
+/// Return the smallest and largest live pks of a table, or `None` if it is empty.
+///
+/// Probes upward from pk 1 and downward from `next_pk - 1`, stopping at the
+/// first live row in each direction.
+pub fn pk_range(pager: &mut Pager, table: &TableDef) -> InvResult<Option<(u32, u32)>> {
+    let mut min = None;
+    for pk in 1..table.next_pk {
+        if read_stored_row(pager, table, pk)?.is_some() {
+            min = Some(pk);
+            break;
+        }
+    }
+    let Some(min) = min else { return Ok(None); };
+    for pk in (min..table.next_pk).rev() {
+        if read_stored_row(pager, table, pk)?.is_some() {
+            return Ok(Some((min, pk)));
+        }
+    }
+    Ok(Some((min, min)))
+}
+
+/// Return up to `n` distinct rows picked pseudo-randomly from the table's pk
+/// range, in pk order.
+///
+/// Picks are driven by an LCG seeded with `seed`, so equal seeds over equal
+/// data give equal samples. Deleted pks are retried, with at most
+/// `8 * n + 64` probes in total.
+pub fn sample_rows(
+    pager: &mut Pager,
+    table: &TableDef,
+    n: usize,
+    seed: u64,
+    strict: bool,
+) -> InvResult<Vec<(u32, Row)>> {
+    let Some((min, max)) = pk_range(pager, table)? else { return Ok(Vec::new()); };
+    let span = (max - min) as u64 + 1;
+    let mut tried = std::collections::HashSet::new();
+    let mut rows = Vec::new();
+    let mut state = seed;
+    let mut probes = n.saturating_mul(8).saturating_add(64);
+    while rows.len() < n && probes > 0 && (tried.len() as u64) < span {
+        probes -= 1;
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let pk = min + ((state >> 33) % span) as u32;
+        if !tried.insert(pk) {
+            continue;
+        }
+        if let Some(row) = fetch_row(pager, table, pk, strict)? {
             rows.push((pk, row));
         }
     }
+    rows.sort_by_key(|(pk, _)| *pk);
+    Ok(rows)
+}
+
+/// Bytes a table occupies, as reported by [`table_storage`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TableStorage {
+    /// Slots of live rows plus their btree entries.
+    pub live_bytes: u64,
+    /// Used portion of the table's row pages (dead slots included) plus its
+    /// btree entries.
+    pub used_bytes: u64,
+    /// Whole pages owned by the table plus its share of btree pages,
+    /// proportional to its number of entries.
+    pub allocated_bytes: u64,
+}
+
+/// Size of one leaf entry: a u32 key and a u64 value.
+const BTREE_ENTRY_BYTES: u64 = 12;
+
+/// A table's definition together with the stats [`table_info`] computes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableInfo {
+    pub def: TableDef,
+    /// Row pages owned by the table, plus the directory of a clustered
+    /// table and the index of a u64-pk table; pages of the shared btree are
+    /// not counted.
+    pub owned_pages: u64,
+    /// Number of live rows.
+    pub live_rows: u64,
+    pub storage: TableStorage,
+}
+
+/// Account for the storage of one table.
+pub fn table_storage(pager: &mut Pager, table: &TableDef) -> InvResult<TableStorage> {
+    let live = table_keys(pager, table)?;
+    let owned = owned_pages(pager, table, &live)?;
+    storage_of(pager, table, &live, &owned)
+}
+
+/// Gather a table's definition, owned pages, live rows and storage from a
+/// single walk of its rows.
+pub fn table_info(pager: &mut Pager, table: &TableDef) -> InvResult<TableInfo> {
+    let live = table_keys(pager, table)?;
+    let owned = owned_pages(pager, table, &live)?;
+    Ok(TableInfo {
+        def: table.clone(),
+        owned_pages: owned.len() as u64,
+        live_rows: live.len() as u64,
+        storage: storage_of(pager, table, &live, &owned)?,
+    })
+}
+
+/// [`TableStorage`] of a table whose live rows and owned pages are known.
+///
+/// A share of the shared btree's pages proportional to the table's entries
+/// is added to `allocated_bytes`; a u64-pk table's index is owned outright.
+fn storage_of(pager: &mut Pager, table: &TableDef, live: &[(u64, RowPtr)], owned: &HashSet<PageId>) -> InvResult<TableStorage> {
+    let index_bytes = live.len() as u64 * BTREE_ENTRY_BYTES;
+
+    let mut storage = TableStorage {
+        live_bytes: index_bytes + live.iter().map(|(_, ptr)| 2 + ptr.len as u64).sum::<u64>(),
+        used_bytes: index_bytes,
+        allocated_bytes: owned.len() as u64 * PAGE_SIZE as u64,
+    };
+    for &page in owned {
+        if pager.get_page(page)?.as_bytes().first() == Some(&ROW_PAGE_KIND) {
+            storage.used_bytes += (RowStore::read_free_offset(pager, page)? as usize - crate::layout::ROW_PAGE_DATA) as u64;
+        }
+    }
+    if table.pk_root.is_some() {
+        return Ok(storage);
+    }
+    let root = pager.root_page_id();
+    let (tree_pages, tree_keys) = btree::search::tree_counts(pager, root)?;
+    let tree_bytes = tree_pages as u64 * PAGE_SIZE as u64;
+    storage.allocated_bytes += (tree_bytes * live.len() as u64).checked_div(tree_keys).unwrap_or(0);
+    Ok(storage)
+}
+
+/// Pages owned by a table: those holding its live rows, its append target
+/// and the rest of its row page chain, for clustered tables the directory
+/// and every listed page, and for u64-pk tables the nodes of their index.
+fn owned_pages(pager: &mut Pager, table: &TableDef, live: &[(u64, RowPtr)]) -> InvResult<HashSet<PageId>> {
+    let mut owned: HashSet<PageId> = live.iter().map(|(_, ptr)| PageId(ptr.page_id)).collect();
+    if table.last_row_page != 0 {
+        owned.insert(PageId(table.last_row_page));
+    }
+    if table.first_row_page.is_some() && table.cluster.is_none() {
+        owned.extend(row_pages(pager, table)?);
+    }
+    if let Some(c) = table.cluster {
+        owned.insert(PageId(c.directory_page));
+        for (page, _) in cluster::read_directory(pager, PageId(c.directory_page))? {
+            owned.insert(PageId(page));
+        }
+    }
+    if let Some(root) = table.pk_root {
+        owned.extend(pktree::pages(pager, root)?);
+    }
+    Ok(owned)
+}
+
+/// Pages below `page_count` that nothing in the file references: not the
+/// header or catalog, not a node of the index, blob or key-index btrees,
+/// not owned by a table as counted by [`table_info`], not holding a live
+/// blob and not on the free list. Returned in ascending order.
+pub(crate) fn orphan_pages(pager: &mut Pager, catalog: &Catalog) -> InvResult<Vec<PageId>> {
+    let mut reachable: HashSet<PageId> = [HEADER_PAGE_ID, CATALOG_PAGE_ID].into_iter().collect();
+    let root = pager.root_page_id();
+    reachable.extend(btree::search::tree_pages(pager, root)?);
+    for table in &catalog.tables {
+        let live = table_keys(pager, table)?;
+        reachable.extend(owned_pages(pager, table, &live)?);
+        if let Some(uuid_root) = table.uuid_root {
+            reachable.extend(btree::search::tree_pages(pager, PageId(uuid_root))?);
+        }
+    }
+    if catalog.blob_root != 0 {
+        reachable.extend(btree::search::tree_pages(pager, PageId(catalog.blob_root))?);
+        reachable.insert(PageId(catalog.blob_last_row_page));
+        btree::search::walk_from(pager, PageId(catalog.blob_root), 0, |_, value| {
+            reachable.insert(PageId(RowPtr::unpack(value).page_id));
+            Ok(true)
+        })?;
+    }
+    reachable.extend(pager.free_pages()?);
+    Ok((0..pager.page_count()).map(PageId).filter(|page| !reachable.contains(page)).collect())
+}
+
+/// Reinsert index entries for a table's rows found by scanning row pages,
+/// returning the number of entries added.
+///
+/// Row pages carry no owner, so every row page not held by another table or
+/// the blob store is scanned, and a slot is taken as the table's when its pk
+/// prefix lies in `1..next_pk` and the rest decodes under the table's schema.
+/// Pks that are still indexed keep their entry. Dead slots left by deletes or
+/// rewrites are not told apart from live rows: such a pk comes back with its
+/// last copy in page and offset order.
+pub(crate) fn rebuild_table_index(pager: &mut Pager, catalog: &Catalog, table_name: &str) -> InvResult<u64> {
+    let table = find_table(catalog, table_name)?;
+    if table.cluster.is_some() {
+        return Err(InvError::Unsupported {
+            feature: "table.rebuild_clustered",
+        });
+    }
+    let mut claimed = HashSet::new();
+    for other in catalog.tables.iter().filter(|t| t.id != table.id) {
+        let live = table_keys(pager, other)?;
+        claimed.extend(owned_pages(pager, other, &live)?);
+    }
+    if catalog.blob_root != 0 {
+        claimed.insert(PageId(catalog.blob_last_row_page));
+        btree::search::walk_from(pager, PageId(catalog.blob_root), 0, |_, value| {
+            claimed.insert(PageId(RowPtr::unpack(value).page_id));
+            Ok(true)
+        })?;
+    }
+
+    let mut found = BTreeMap::new();
+    for page in 1..pager.page_count() {
+        let page = PageId(page);
+        if claimed.contains(&page) || pager.get_page(page)?.as_bytes().first() != Some(&ROW_PAGE_KIND) {
+            continue;
+        }
+        for (ptr, stored) in RowStore::page_rows(pager, page)? {
+            let Some(prefix) = stored.get(0..4) else { continue; };
+            let pk = u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]);
+            if (1..table.next_pk).contains(&pk) && decode_row_lenient(&table.schema, &stored[4..]).is_ok() {
+                found.insert(pk, ptr);
+            }
+        }
+    }
+
+    let mut rebuilt = 0u64;
+    for (pk, ptr) in found {
+        let composite = composite_key(table.id.0, pk);
+        let root = pager.root_page_id();
+        if btree::search::search_u64(pager, root, composite)?.is_some() {
+            continue;
+        }
+        pager.note_key_owner(composite, table.id.0, pk as u64)?;
+        let new_root = btree::insert::insert_u64(pager, root, composite, ptr.pack())?;
+        if new_root != root {
+            pager.set_root_page_id(new_root)?;
+        }
+        rebuilt += 1;
+    }
+    Ok(rebuilt)
+}
+
+/// Persist one table's dirty row pages, btree entries and catalog entry,
+/// leaving other tables' unflushed changes in the cache.
+///
+/// Row pages belong to a single table, but btree leaves and the catalog page
+/// are shared, so those are written as their on-disk image with only this
+/// table's entries replaced. An entry counts as the table's only if it points
+/// at one of its row slots whose pk maps back to the entry's key, so raw u64
+/// entries and other tables' keys keep their on-disk value. If the btree changed shape since the last flush,
+/// or a merged leaf would not fit, the whole database is flushed instead.
+pub(crate) fn flush_table(pager: &mut Pager, table_name: &str) -> InvResult<()> {
+    if pager.is_read_only() {
+        return Ok(());
+    }
+    let cat = pager.read_catalog()?;
+    let table = find_any_table(&cat, table_name)?;
+
+    let live = table_keys(pager, table)?;
+    let owned = owned_pages(pager, table, &live)?;
+
+    // A u64-pk table's index is among its owned pages; it has no entries in
+    // the shared btree.
+    let shared_dirty = if table.pk_root.is_some() { Vec::new() } else { pager.dirty_page_ids() };
+    let mut disk_pages: HashMap<u32, Option<Page>> = HashMap::new();
+    let mut leaves = Vec::new();
+    for id in shared_dirty {
+        if owned.contains(&id) {
+            continue;
+        }
+        let page_count = pager.page_count();
+        let cached = pager.get_page(id)?;
+        if cached.as_bytes().first() != Some(&2) {
+            continue;
+        }
+        let Node::Leaf(cached_leaf) = Node::decode(cached, page_count)? else {
+            return pager.flush();
+        };
+        let mut image = pager.read_page_from_disk(id)?;
+        let disk_leaf = match Node::decode(&image, page_count) {
+            Ok(Node::Leaf(leaf)) if leaf.next_leaf == cached_leaf.next_leaf => leaf,
+            _ => return pager.flush(),
+        };
+        let mut merged: BTreeMap<u32, u64> = BTreeMap::new();
+        for (&key, &value) in disk_leaf.keys.iter().zip(&disk_leaf.values) {
+            let page = RowPtr::unpack(value).page_id;
+            let image = disk_pages
+                .entry(page)
+                .or_insert_with(|| pager.read_page_from_disk(PageId(page)).ok());
+            if !indexes_row_of(table, image.as_ref(), key, value) {
+                merged.insert(key, value);
+            }
+        }
+        for (&key, &value) in cached_leaf.keys.iter().zip(&cached_leaf.values) {
+            let page = PageId(RowPtr::unpack(value).page_id);
+            if owned.contains(&page) && indexes_row_of(table, pager.get_page(page).ok(), key, value) {
+                merged.insert(key, value);
+            }
+        }
+        if merged.len() > max_leaf_keys() {
+            return pager.flush();
+        }
+        let node = Node::Leaf(LeafNode {
+            num_keys: merged.len() as u16,
+            next_leaf: cached_leaf.next_leaf,
+            keys: merged.keys().copied().collect(),
+            values: merged.values().copied().collect(),
+        });
+        encode_into_page(&node, &mut image)?;
+        leaves.push(image);
+    }
+
+    let mut disk_cat = pager.read_catalog_from_disk()?;
+    match disk_cat.tables.iter_mut().find(|t| t.id == table.id) {
+        Some(slot) => *slot = table.clone(),
+        None => disk_cat.tables.push(table.clone()),
+    }
+    disk_cat.next_table_id = disk_cat.next_table_id.max(table.id.0 + 1);
+
+    let owned: Vec<PageId> = owned.into_iter().collect();
+    pager.flush_pages(&owned)?;
+    for leaf in &leaves {
+        pager.write_page_image(leaf)?;
+    }
+    pager.write_catalog_to_disk(&disk_cat)
+}
+
+/// Whether a shared btree entry indexes a row of `table`, given the image of
+/// the page its value points into: the pointer must land on a slot whose
+/// stored pk lies below `next_pk` and maps back to `key`.
+///
+/// A pk maps to a different key in every table, so no other table's entry
+/// passes, and a raw u64 value only if it points at such a slot.
+fn indexes_row_of(table: &TableDef, page: Option<&Page>, key: u32, value: u64) -> bool {
+    let Some(stored) = page.and_then(|page| RowStore::read_row_in(page, RowPtr::unpack(value)).ok()) else {
+        return false;
+    };
+    let Some(prefix) = stored.get(0..4) else {
+        return false;
+    };
+    let pk = u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]);
+    (1..table.next_pk).contains(&pk) && composite_key(table.id.0, pk) == key
+}
+
+/// Collect `(pk, pointer)` pairs for every live row of a table by probing its pk range.
+pub(crate) fn table_entries(pager: &mut Pager, table: &TableDef) -> InvResult<Vec<(u32, RowPtr)>> {
+    let mut entries = Vec::new();
+    for pk in 1..table.next_pk {
+        let composite = composite_key(table.id.0, pk);
+        let root = pager.root_page_id();
+        if let Some(raw_ptr) = btree::search::search_u64(pager, root, composite)? {
+            let ptr = RowPtr::unpack(raw_ptr);
+            ptr.validate()?;
+            entries.push((pk, ptr));
+        }
+    }
+    Ok(entries)
+}
+
+/// Collect `(index key, pointer)` pairs for every live row of a table.
+///
+/// The key is the btree key for u32-pk tables, probed over their pk range,
+/// and the pk itself for u64-pk tables; [`repoint_row`] takes it back.
+fn table_keys(pager: &mut Pager, table: &TableDef) -> InvResult<Vec<(u64, RowPtr)>> {
+    if table.has_u64_pk() {
+        return u64_entries(pager, table);
+    }
+    Ok(table_entries(pager, table)?
+        .into_iter()
+        .map(|(pk, ptr)| (composite_key(table.id.0, pk) as u64, ptr))
+        .collect())
+}
+
+/// Point the index entry for `key`, as returned by [`table_keys`], at `ptr`.
+fn repoint_row(pager: &mut Pager, table: &mut TableDef, key: u64, ptr: RowPtr) -> InvResult<()> {
+    if let Some(root) = table.pk_root {
+        table.pk_root = Some(pktree::insert(pager, root, key, ptr.pack())?);
+        return Ok(());
+    }
+    let root = pager.root_page_id();
+    let new_root = btree::insert::insert_u64(pager, root, key as u32, ptr.pack())?;
+    if new_root != root {
+        pager.set_root_page_id(new_root)?;
+    }
+    Ok(())
+}
+
+/// Collect `(pk, pointer)` for every live row of a u64-pk table, in pk order.
+fn u64_entries(pager: &mut Pager, table: &TableDef) -> InvResult<Vec<(u64, RowPtr)>> {
+    pktree::entries(pager, pk_tree_root(table)?)?
+        .into_iter()
+        .map(|(pk, value)| {
+            let ptr = RowPtr::unpack(value);
+            ptr.validate()?;
+            Ok((pk, ptr))
+        })
+        .collect()
+}
+
+/// Scan a u64-pk table in pk order, optionally verifying every row strictly.
+pub(crate) fn scan_rows_u64(pager: &mut Pager, table: &TableDef, strict: bool) -> InvResult<Vec<(u64, Row)>> {
+    require_u64_pk(table)?;
+    let mut rows = Vec::new();
+    for (pk, ptr) in u64_entries(pager, table)? {
+        let stored = read_stored_row_u64(pager, table, pk, ptr)?;
+        let mut row = Vec::with_capacity(table.schema.len());
+        decode_stored_row(table, &stored[8..], strict, &mut row)?;
+        rows.push((pk, row));
+    }
     Ok(rows)
 }
 
+/// Move the rows of u64-pk tables written before they had a pk tree of their
+/// own out of the shared btree and into one, persisting the catalog.
+///
+/// Such a table keyed each row under the composite key of its pk's low 32
+/// bits; an entry is taken as the table's if the row it points at carries a
+/// pk in `1..next_pk` with those low bits, as that format itself decided.
+pub(crate) fn migrate_shared_u64_pks(pager: &mut Pager) -> InvResult<()> {
+    let mut cat = pager.read_catalog()?;
+    if !cat.tables.iter().any(|t| t.has_u64_pk() && t.pk_root.is_none()) {
+        return Ok(());
+    }
+    let root = pager.root_page_id();
+    let shared: Vec<(u32, u64)> = btree::search::iter_from(pager, root, 0)?.collect::<InvResult<_>>()?;
+    for table in cat.tables.iter_mut().filter(|t| t.has_u64_pk() && t.pk_root.is_none()) {
+        let next = table.next_pk_u64.unwrap_or(1);
+        let mut entries = Vec::new();
+        for &(key, value) in &shared {
+            let ptr = RowPtr::unpack(value);
+            if ptr.validate().is_err() {
+                continue;
+            }
+            let Ok(stored) = RowStore::read_row(pager, ptr) else { continue; };
+            let Some(prefix) = stored.get(0..8) else { continue; };
+            let pk = u64::from_le_bytes(prefix.try_into().expect("8-byte prefix"));
+            if (1..next).contains(&pk) && pk as u32 == composite_pk(table.id.0, key) {
+                entries.push((key, pk, value));
+            }
+        }
+        let mut root = pager.root_page_id();
+        for &(key, _, _) in &entries {
+            root = btree::delete_u64(pager, root, key)?.0;
+        }
+        if root != pager.root_page_id() {
+            pager.set_root_page_id(root)?;
+        }
+        let mut pks: Vec<(u64, u64)> = entries.into_iter().map(|(_, pk, value)| (pk, value)).collect();
+        pks.sort_unstable_by_key(|&(pk, _)| pk);
+        table.pk_root = Some(pktree::bulk_load(pager, &pks)?);
+    }
+    pager.write_catalog(&cat)
+}
+
+/// Scan any table in pk order, widening u32 pks to u64.
+pub(crate) fn scan_all_rows(pager: &mut Pager, table: &TableDef, strict: bool) -> InvResult<Vec<(u64, Row)>> {
+    if table.has_u64_pk() {
+        return scan_rows_u64(pager, table, strict);
+    }
+    Ok(scan_rows(pager, table, strict)?
+        .into_iter()
+        .map(|(pk, row)| (pk as u64, row))
+        .collect())
+}
+
+/// Compact a single row page, dropping dead slots and repointing the index
+/// entries of the rows and blobs that moved. The caller is responsible for
+/// persisting `catalog`.
+pub fn defragment_row_page(pager: &mut Pager, catalog: &mut Catalog, page_id: PageId) -> InvResult<()> {
+    let mut live = Vec::new();
+    for (idx, table) in catalog.tables.iter().enumerate() {
+        for (key, ptr) in table_keys(pager, table)? {
+            if ptr.page_id == page_id.0 {
+                live.push((Some(idx), key, ptr));
+            }
+        }
+    }
+    for (key, ptr) in blob::entries(pager, catalog)? {
+        if ptr.page_id == page_id.0 {
+            live.push((None, key as u64, ptr));
+        }
+    }
+    let ptrs: Vec<RowPtr> = live.iter().map(|&(_, _, ptr)| ptr).collect();
+    let moved = RowStore::compact_page(pager, page_id, &ptrs)?;
+    for ((owner, key, old), new) in live.into_iter().zip(moved) {
+        if old == new {
+            continue;
+        }
+        match owner {
+            Some(idx) => repoint_row(pager, &mut catalog.tables[idx], key, new)?,
+            None => blob::repoint(pager, catalog, key as u32, new)?,
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 pub(crate) fn composite_for_tests(table_id: u32, pk: u32) -> u32 {
     composite_key(table_id, pk)
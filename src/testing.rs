@@ -0,0 +1,85 @@
+//! Helpers for tests: deterministic-encoding assertions and IO fault
+//! injection. Available with the `testing` cargo feature.
+//!
+//! Each roundtrip helper encodes its input twice, decodes it, re-encodes the
+//! decoded value, and panics unless every encoding is byte-identical and the
+//! decoded value equals the input.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::catalog::{decode_catalog, decode_schema, encode_catalog, encode_schema, Catalog};
+use crate::row::{decode_row, encode_row, Row};
+use crate::file::IoOp;
+use crate::schema::Schema;
+
+/// Pending injected faults: file path, call kind, error kind, calls left.
+static IO_FAULTS: Mutex<Vec<(PathBuf, IoOp, ErrorKind, u32)>> = Mutex::new(Vec::new());
+
+/// Assert that `schema` has a canonical encoding that round-trips.
+///
+/// Returns the canonical bytes.
+pub fn assert_canonical_roundtrip_schema(schema: &Schema) -> Vec<u8> {
+    let first = encode_schema(schema).expect("schema must encode");
+    let second = encode_schema(schema).expect("schema must encode");
+    assert_eq!(first, second, "schema encoding is not deterministic");
+    let decoded = decode_schema(&first).expect("schema bytes must decode");
+    assert_eq!(&decoded, schema, "schema changed across a round trip");
+    let reencoded = encode_schema(&decoded).expect("decoded schema must encode");
+    assert_eq!(first, reencoded, "schema re-encoding differs from the original bytes");
+    first
+}
+
+/// Assert that `row` has a canonical encoding under `schema` that round-trips.
+///
+/// Returns the canonical bytes.
+pub fn assert_canonical_roundtrip_row(schema: &Schema, row: &Row) -> Vec<u8> {
+    let first = encode_row(schema, row).expect("row must encode");
+    let second = encode_row(schema, row).expect("row must encode");
+    assert_eq!(first, second, "row encoding is not deterministic");
+    let decoded = decode_row(schema, &first).expect("row bytes must decode");
+    assert_eq!(&decoded, row, "row changed across a round trip");
+    let reencoded = encode_row(schema, &decoded).expect("decoded row must encode");
+    assert_eq!(first, reencoded, "row re-encoding differs from the original bytes");
+    first
+}
+
+/// Assert that `catalog` has a canonical encoding that round-trips.
+///
+/// Returns the canonical bytes.
+pub fn assert_canonical_roundtrip_catalog(catalog: &Catalog) -> Vec<u8> {
+    let first = encode_catalog(catalog).expect("catalog must encode");
+    let second = encode_catalog(catalog).expect("catalog must encode");
+    assert_eq!(first, second, "catalog encoding is not deterministic");
+    let decoded = decode_catalog(&first).expect("catalog bytes must decode");
+    assert_eq!(decoded.list(), catalog.list(), "catalog tables changed across a round trip");
+    let reencoded = encode_catalog(&decoded).expect("decoded catalog must encode");
+    assert_eq!(first, reencoded, "catalog re-encoding differs from the original bytes");
+    first
+}
+
+/// Make the next `count` `op` calls against the database file at `path` fail
+/// with `kind` before reaching the file.
+pub fn inject_io_faults(path: &Path, op: IoOp, kind: ErrorKind, count: u32) {
+    let mut faults = IO_FAULTS.lock().unwrap_or_else(|e| e.into_inner());
+    faults.push((path.to_path_buf(), op, kind, count));
+}
+
+/// Drop any faults still pending for the file at `path`.
+pub fn clear_io_faults(path: &Path) {
+    let mut faults = IO_FAULTS.lock().unwrap_or_else(|e| e.into_inner());
+    faults.retain(|(p, ..)| p != path);
+}
+
+/// Consume one pending fault for an `op` call on `path`, if any.
+pub(crate) fn take_io_fault(path: &Path, op: IoOp) -> Option<ErrorKind> {
+    let mut faults = IO_FAULTS.lock().unwrap_or_else(|e| e.into_inner());
+    let idx = faults.iter().position(|(p, o, _, left)| p == path && *o == op && *left > 0)?;
+    let kind = faults[idx].2;
+    faults[idx].3 -= 1;
+    if faults[idx].3 == 0 {
+        faults.remove(idx);
+    }
+    Some(kind)
+}